@@ -0,0 +1,79 @@
+use crate::{CONFIG, runs};
+use chrono::Local;
+use colored::Colorize;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/**
+ * Appends an operation to the audit log and the journal of the run directory, if
+ * they are configured.
+ *
+ * Each operation is written as a single tab-separated line containing the local
+ * timestamp, the operation, the absolute path before and after the operation,
+ * and the outcome. Nothing is written during a dry run.
+ *
+ * @param operation The name of the operation (e.g. `move`, `copy`, `delete`).
+ * @param from The path before the operation.
+ * @param to The path after the operation. Empty if the path was removed.
+ * @param error The error message if the operation failed.
+ */
+pub fn record(operation: &str, from: &Path, to: &Path, error: Option<&str>) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let journal = runs::journal();
+    if cfg.dry_run || (cfg.audit_log.is_none() && journal.is_none()) {
+        return;
+    }
+
+    let outcome = match error {
+        Some(err) => format!("failed: {}", err.replace(['\t', '\n'], " ")),
+        None => String::from("ok"),
+    };
+    let line = format!(
+        "{}\t{}\t{}\t{}\t{}\n",
+        Local::now().format("%Y-%m-%dT%H:%M:%S%:z"),
+        operation,
+        absolute(from).display(),
+        absolute(to).display(),
+        outcome
+    );
+
+    for log in cfg.audit_log.iter().chain(journal.as_ref()) {
+        if let Err(err) = append(log, &line) {
+            eprintln!(
+                "{} '{}'. {}",
+                "Error: Could not write to the audit log".red(),
+                log.display(),
+                err
+            );
+        }
+    }
+}
+
+/**
+ * Appends a line to a log file, creating the file and its directory if needed.
+ *
+ * @param log The path of the log file.
+ * @param line The line to append.
+ */
+fn append(log: &Path, line: &str) -> io::Result<()> {
+    log.parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(log))
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+}
+
+/**
+ * Makes a path absolute, so the log stays useful regardless of the working
+ * directory aborg was run from. Empty paths are kept empty.
+ *
+ * @param path The path to make absolute.
+ * @return The absolute path.
+ */
+fn absolute(path: &Path) -> PathBuf {
+    if path.as_os_str().is_empty() {
+        return PathBuf::new();
+    }
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
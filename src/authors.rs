@@ -0,0 +1,597 @@
+use crate::config::FileConfig;
+use crate::fsutil::{is_hidden, remove_junk};
+use crate::schema::Schema;
+use crate::summary::Summary;
+use crate::{
+    ActionOpt, Args, CONFIG, Command, Plan, build_config, confirm, database, execute,
+    is_audio_file, plan,
+};
+use colored::Colorize;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The shortest name (without spaces and punctuation) that is compared with a typo
+/// allowed, so short names like "Tom Holt" and "Tim Holt" are not taken for each other.
+const FUZZY_MIN_LENGTH: usize = 10;
+
+/// Represents author directories in a destination root that are likely the same
+/// author, e.g. `Neil Gaiman` and `Gaiman, Neil`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuthorGroup {
+    /// The directory the other directories should be merged into, the one with the
+    /// most books.
+    pub canonical: PathBuf,
+    /// The other directories of the author.
+    pub others: Vec<PathBuf>,
+}
+
+impl AuthorGroup {
+    /**
+     * Gets the command that merges the other directories into the canonical one.
+     */
+    pub fn remediation(&self) -> String {
+        let dirs: Vec<String> = self
+            .others
+            .iter()
+            .chain(std::iter::once(&self.canonical))
+            .map(|dir| shell_quote(&dir.display().to_string()))
+            .collect();
+        format!("aborg merge-authors {}", dirs.join(" "))
+    }
+
+    /**
+     * Checks whether the names of the directories only differ by case, spacing,
+     * punctuation or name order, and not by a typo.
+     */
+    pub fn is_exact(&self) -> bool {
+        let key = |dir: &PathBuf| {
+            author_key(
+                &dir.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+            )
+        };
+        let canonical = key(&self.canonical);
+        self.others.iter().all(|dir| key(dir) == canonical)
+    }
+}
+
+/**
+ * Gets the destination roots whose directories are author directories, i.e. the
+ * roots a book was placed on with its author as the first directory of its path.
+ *
+ * @param plans The plans of the run.
+ * @return The roots, in the order of the plans.
+ */
+pub fn author_roots(plans: &[Plan]) -> Vec<String> {
+    let mut roots: Vec<String> = Vec::new();
+    for plan in plans {
+        let Some(author) = &plan.metadata.author else {
+            continue;
+        };
+        let first = Path::new(&plan.to)
+            .strip_prefix(&plan.root)
+            .ok()
+            .and_then(|path| path.iter().next())
+            .map(|name| name.to_string_lossy().to_string());
+        if first.is_some_and(|name| author_key(&name) == author_key(author))
+            && !roots.contains(&plan.root)
+        {
+            roots.push(plan.root.clone());
+        }
+    }
+    roots
+}
+
+/**
+ * Finds the author directories of destination roots whose names differ only by
+ * case, spacing, punctuation, name order (`Gaiman, Neil`) or a single typo.
+ *
+ * @param roots The destination roots, whose directories are author directories.
+ * @param file_ext The audio file extensions, used to count the books of each author.
+ * @return The groups of similar directories, in the order of the roots and names.
+ */
+pub fn find_similar_authors(roots: &[String], file_ext: &[String]) -> Vec<AuthorGroup> {
+    let mut groups = Vec::new();
+    for root in roots {
+        let Ok(entries) = fs::read_dir(root) else {
+            continue;
+        };
+        let mut dirs: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir() && !is_hidden(path))
+            .collect();
+        dirs.sort();
+        let names: Vec<String> = dirs
+            .iter()
+            .map(|dir| {
+                dir.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        for indexes in group_similar(&names) {
+            let mut members: Vec<(usize, &PathBuf, &String)> = indexes
+                .into_iter()
+                .map(|i| (count_books(&dirs[i], file_ext), &dirs[i], &names[i]))
+                .collect();
+            // Most books first, then the name in its plain "First Last" form
+            members.sort_by_key(|(books, _, name)| {
+                let plain = !name.contains(',') && !name.contains("  ") && name.trim() == *name;
+                (std::cmp::Reverse(*books), !plain, name.to_string())
+            });
+            groups.push(AuthorGroup {
+                canonical: members[0].1.clone(),
+                others: members[1..]
+                    .iter()
+                    .map(|(_, dir, _)| (*dir).clone())
+                    .collect(),
+            });
+        }
+    }
+    groups
+}
+
+/**
+ * Checks that author directories can be merged: they must all exist, be different
+ * and be in the same library directory.
+ *
+ * @param from The directories to merge.
+ * @param into The directory they are merged into.
+ * @return `Ok(())`, or the reason the directories can't be merged.
+ */
+pub fn check_merge(from: &[String], into: &str) -> Result<(), String> {
+    let library = Path::new(into).parent();
+    for dir in from.iter().map(String::as_str).chain(std::iter::once(into)) {
+        if !Path::new(dir).is_dir() {
+            return Err(format!("'{}' is not a directory", dir));
+        }
+    }
+    for dir in from {
+        if fs::canonicalize(dir).ok() == fs::canonicalize(into).ok() {
+            return Err(format!("'{}' can't be merged into itself", dir));
+        }
+        if Path::new(dir).parent() != library {
+            return Err(format!(
+                "'{}' and '{}' are not in the same library directory",
+                dir, into
+            ));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Merges author directories into the directory of the same author. The books are
+ * planned again with the author of that directory, so their paths and file names are
+ * rendered like new books of the author, and moved there. The source directory of
+ * each book is deleted once its files are verified, like with `--action 2`.
+ *
+ * A book that already exists under the author is handled by the conflict policy,
+ * and by default left where it is. Emptied author directories are removed, and the
+ * records of the moved books are updated in the library database.
+ *
+ * @param schema The schema the library was organized with.
+ * @param summary The summary that collects the results.
+ */
+pub fn merge_authors(schema: &Schema, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let author = cfg.author.as_deref().unwrap_or_default();
+    for dir in &cfg.sources {
+        println!(
+            "{} '{}' into '{}'",
+            "Merging:".green(),
+            dir,
+            Path::new(&cfg.to).join(author).display()
+        );
+    }
+
+    let plans = plan(schema, &cfg.sources, summary);
+    execute(plans, &cfg.sources, summary);
+    if cfg.dry_run {
+        return;
+    }
+
+    for dir in &cfg.sources {
+        remove_junk(Path::new(dir), cfg.skip_hidden);
+        match fs::remove_dir(dir) {
+            Ok(_) => println!("{} '{}'", "Removed Author Directory:".green(), dir),
+            Err(_) => println!(
+                "{} '{}' (books were left in it)",
+                "Kept Author Directory:".yellow(),
+                dir
+            ),
+        }
+        if let Some(path) = &cfg.database
+            && let Err(err) = database::forget_moved_books(path, dir)
+        {
+            eprintln!("{} {}", "Error updating the library database:".red(), err);
+        }
+    }
+}
+
+/**
+ * Merges the groups of similar author directories in a library, as the summary of
+ * a run reports them. Each group is merged into its directory with the most books.
+ *
+ * Only the groups whose names differ by case, spacing, punctuation or name order
+ * are merged, once they are listed and confirmed (or with `--yes`). Names a typo
+ * apart can be different authors, so those groups are only listed with the command
+ * that merges them.
+ *
+ * @param args The command line arguments.
+ * @param file_config The settings of the config file.
+ * @param action The action option.
+ * @param schema The schema the library was organized with.
+ */
+pub fn merge_similar(args: &Args, file_config: &FileConfig, action: ActionOpt, schema: &Schema) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let groups = find_similar_authors(std::slice::from_ref(&cfg.to), &cfg.file_ext);
+    if groups.is_empty() {
+        println!("No similar author directories in '{}'", cfg.to);
+        return;
+    }
+
+    // e.g. `Mark Lawrence` and `Mary Lawrence` are different authors
+    let (groups, typos): (Vec<_>, Vec<_>) = groups.into_iter().partition(AuthorGroup::is_exact);
+    if !typos.is_empty() {
+        println!(
+            "{}",
+            format!(
+                "Not merging {} groups whose names differ by a typo, merge them by hand if they are the same author:",
+                typos.len()
+            )
+            .yellow()
+        );
+        for group in &typos {
+            println!("  {}", group.remediation());
+        }
+    }
+    if groups.is_empty() {
+        return;
+    }
+
+    println!(
+        "{}",
+        format!(
+            "The following {} groups of author directories will be merged:",
+            groups.len()
+        )
+        .yellow()
+    );
+    for group in &groups {
+        let others: Vec<String> = group
+            .others
+            .iter()
+            .map(|dir| format!("'{}'", dir.display()))
+            .collect();
+        println!(
+            "  '{}' <- {}",
+            group.canonical.display().to_string().yellow(),
+            others.join(", ")
+        );
+    }
+    let question = format!(
+        "Type 'yes' to merge {} groups of author directories: ",
+        groups.len()
+    );
+    if !cfg.dry_run && !args.yes && !confirm(&question) {
+        println!(
+            "{}",
+            "Not merging any author directories, use --yes to skip the confirmation.".yellow()
+        );
+        return;
+    }
+
+    for group in groups {
+        println!("--\n");
+        let dirs = group
+            .others
+            .iter()
+            .chain(std::iter::once(&group.canonical))
+            .map(|dir| dir.display().to_string())
+            .collect();
+        let mut group_args = args.clone();
+        if let Some(Command::MergeAuthors { on_conflict, .. }) = &args.command {
+            group_args.command = Some(Command::MergeAuthors {
+                dirs,
+                similar: None,
+                on_conflict: on_conflict.clone(),
+            });
+        }
+        match build_config(&group_args, file_config, action.clone()) {
+            Ok(config) => CONFIG.replace(config),
+            Err(err) => {
+                eprintln!("{} {}", "Error:".red(), err);
+                continue;
+            }
+        }
+        let mut summary = Summary::default();
+        merge_authors(schema, &mut summary);
+    }
+}
+
+/**
+ * Replaces the names of merged authors in the author of a book with the author they
+ * are merged into. The other names of a co-authored book (e.g. `Gaiman, Neil &
+ * Terry Pratchett`) are kept.
+ *
+ * @param author The author of the book.
+ * @param merged The names of the merged author directories.
+ * @param into The author the directories are merged into.
+ * @return The new author, or `None` if it names none of the merged authors.
+ */
+pub fn replace_merged_author(author: &str, merged: &[String], into: &str) -> Option<String> {
+    let keys: Vec<String> = merged.iter().map(|name| author_key(name)).collect();
+    let is_merged = |name: &str| {
+        let key = author_key(name);
+        !key.is_empty() && keys.iter().any(|merged| is_similar(merged, &key))
+    };
+    if is_merged(author) {
+        return Some(into.to_string());
+    }
+
+    // Co-authors are separated by `&`, `;`, `/` or `and`, a comma can be `Last, First`
+    let separator = Regex::new(r"(?i)\s*(?:&|;|/|\band\b)\s*").unwrap();
+    let mut replaced = String::new();
+    let mut found = false;
+    let mut start = 0;
+    let ends = separator
+        .find_iter(author)
+        .map(|m| (m.start(), m.end()))
+        .chain(std::iter::once((author.len(), author.len())));
+    for (end, next) in ends {
+        let name = &author[start..end];
+        if is_merged(name) {
+            replaced.push_str(into);
+            found = true;
+        } else {
+            replaced.push_str(name);
+        }
+        replaced.push_str(&author[end..next]);
+        start = next;
+    }
+    found.then_some(replaced)
+}
+
+/**
+ * Groups names that are likely the same author.
+ *
+ * @param names The directory names.
+ * @return The indexes of the names of each group with more than one name.
+ */
+fn group_similar(names: &[String]) -> Vec<Vec<usize>> {
+    let keys: Vec<String> = names.iter().map(|name| author_key(name)).collect();
+    let mut grouped = HashSet::new();
+    let mut groups = Vec::new();
+    for i in 0..names.len() {
+        if grouped.contains(&i) || keys[i].is_empty() {
+            continue;
+        }
+        let mut group = vec![i];
+        // Names are added to the group when they are similar to any of its names
+        let mut next = 0;
+        while next < group.len() {
+            let key = &keys[group[next]];
+            let similar: Vec<usize> = (i + 1..keys.len())
+                .filter(|j| !group.contains(j) && !grouped.contains(j))
+                .filter(|j| is_similar(key, &keys[*j]))
+                .collect();
+            group.extend(similar);
+            next += 1;
+        }
+        if group.len() > 1 {
+            grouped.extend(group.iter().copied());
+            group.sort();
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+/**
+ * Builds the comparison key of an author name: the lowercase words in alphabetical
+ * order, without spaces or punctuation, so `Gaiman, Neil`, `Neil  Gaiman` and
+ * `J. R. R. Tolkien` / `J.R.R. Tolkien` get the same key.
+ */
+fn author_key(name: &str) -> String {
+    let mut words: Vec<String> = name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    words.concat()
+}
+
+/**
+ * Checks whether two author keys are the same author, allowing a single typo in
+ * long names.
+ */
+fn is_similar(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    a.chars().count().min(b.chars().count()) >= FUZZY_MIN_LENGTH && strsim::levenshtein(a, b) <= 1
+}
+
+/**
+ * Counts the books below an author directory, i.e. the directories that directly
+ * hold audio files.
+ */
+fn count_books(dir: &Path, file_ext: &[String]) -> usize {
+    let books: HashSet<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && is_audio_file(entry.path(), file_ext))
+        .filter_map(|entry| entry.path().parent().map(Path::to_path_buf))
+        .collect();
+    books.len()
+}
+
+/**
+ * Quotes a path for a POSIX shell, so the suggested command can be pasted as is.
+ */
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_group_similar() {
+        let names: Vec<String> = [
+            "Brandon Sanderson",
+            "Gaiman, Neil",
+            "J. R. R. Tolkien",
+            "J.R.R. Tolkien",
+            "Neil  Gaiman",
+            "Neil Gaiman",
+            "Brandon Sandersen",
+            "Tim Holt",
+            "Tom Holt",
+        ]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+        assert_eq!(
+            group_similar(&names),
+            vec![vec![0, 6], vec![1, 4, 5], vec![2, 3]]
+        );
+        assert_eq!(
+            shell_quote("/library/Patrick O'Brian"),
+            r"'/library/Patrick O'\''Brian'"
+        );
+    }
+
+    #[test]
+    fn test_replace_merged_author() {
+        let merged = vec![String::from("Gaiman, Neil"), String::from("Neil Gaiman")];
+        let replace = |author: &str| replace_merged_author(author, &merged, "Neil Gaiman");
+        assert_eq!(replace("Gaiman, Neil").as_deref(), Some("Neil Gaiman"));
+        assert_eq!(
+            replace("Gaiman, Neil & Terry Pratchett").as_deref(),
+            Some("Neil Gaiman & Terry Pratchett")
+        );
+        assert_eq!(replace("Terry Pratchett"), None);
+    }
+
+    #[test]
+    fn test_check_merge() {
+        let library =
+            std::env::temp_dir().join(format!("aborg-check-merge-{}", std::process::id()));
+        let dir = |name: &str| library.join(name).display().to_string();
+        fs::create_dir_all(dir("Gaiman, Neil")).unwrap();
+        fs::create_dir_all(dir("Neil Gaiman")).unwrap();
+        fs::create_dir_all(dir("other/Neil Gaiman")).unwrap();
+
+        assert!(check_merge(&[dir("Gaiman, Neil")], &dir("Neil Gaiman")).is_ok());
+        assert!(check_merge(&[dir("Neil Gaiman")], &dir("Neil Gaiman")).is_err());
+        assert!(check_merge(&[dir("Missing")], &dir("Neil Gaiman")).is_err());
+        assert!(check_merge(&[dir("other/Neil Gaiman")], &dir("Neil Gaiman")).is_err());
+        fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn test_merge_authors() {
+        let _lock = crate::TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let library =
+            std::env::temp_dir().join(format!("aborg-merge-authors-{}", std::process::id()));
+        let book = |author: &str, title: &str, authors: &str| {
+            let dir = library.join(author).join(title);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("metadata.json"),
+                format!(r#"{{"title": "{}", "authors": [{}]}}"#, title, authors),
+            )
+            .unwrap();
+            fs::write(dir.join(format!("{}.mp3", title)), title).unwrap();
+        };
+        book("Gaiman, Neil", "Coraline", r#""Gaiman, Neil""#);
+        book(
+            "Gaiman, Neil",
+            "Good Omens",
+            r#""Gaiman, Neil & Terry Pratchett""#,
+        );
+        book("Neil Gaiman", "Stardust", r#""Neil Gaiman""#);
+        let dir = |name: &str| library.join(name).display().to_string();
+
+        let args = Args::try_parse_from([
+            "aborg",
+            "merge-authors",
+            &dir("Gaiman, Neil"),
+            &dir("Neil Gaiman"),
+        ])
+        .unwrap();
+        let config = build_config(&args, &FileConfig::default(), ActionOpt::All).unwrap();
+        CONFIG.replace(config);
+        let schema = Schema::new(
+            String::from("{{author}}/{{title}}"),
+            String::from("{{title}}"),
+        );
+        let mut summary = Summary::default();
+        merge_authors(&schema, &mut summary);
+
+        assert!(library.join("Neil Gaiman/Coraline/Coraline.mp3").is_file());
+        // The co-author is kept
+        assert!(
+            library
+                .join("Neil Gaiman & Terry Pratchett/Good Omens/Good Omens.mp3")
+                .is_file()
+        );
+        assert!(!library.join("Gaiman, Neil").exists());
+        // The merged directory isn't suggested to be merged again
+        assert!(summary.similar_authors.is_empty());
+        fs::remove_dir_all(library).unwrap();
+    }
+
+    #[test]
+    fn test_merge_similar() {
+        let _lock = crate::TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let library =
+            std::env::temp_dir().join(format!("aborg-merge-similar-{}", std::process::id()));
+        let book = |author: &str, title: &str| {
+            let dir = library.join(author).join(title);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(
+                dir.join("metadata.json"),
+                format!(r#"{{"title": "{}", "authors": ["{}"]}}"#, title, author),
+            )
+            .unwrap();
+            fs::write(dir.join(format!("{}.mp3", title)), title).unwrap();
+        };
+        book("Gaiman, Neil", "Coraline");
+        book("Neil Gaiman", "Stardust");
+        book("Neil Gaiman", "Neverwhere");
+        book("Mark Lawrence", "Prince of Thorns");
+        book("Mary Lawrence", "Death of an Assassin");
+        let library_dir = library.display().to_string();
+
+        let args =
+            Args::try_parse_from(["aborg", "--yes", "merge-authors", "--similar", &library_dir])
+                .unwrap();
+        let file_config = FileConfig::default();
+        let config = build_config(&args, &file_config, ActionOpt::All).unwrap();
+        CONFIG.replace(config);
+        let schema = Schema::new(
+            String::from("{{author}}/{{title}}"),
+            String::from("{{title}}"),
+        );
+        merge_similar(&args, &file_config, ActionOpt::All, &schema);
+
+        assert!(library.join("Neil Gaiman/Coraline/Coraline.mp3").is_file());
+        assert!(!library.join("Gaiman, Neil").exists());
+        // Names a typo apart are only listed
+        assert!(library.join("Mark Lawrence/Prince of Thorns").is_dir());
+        assert!(library.join("Mary Lawrence/Death of an Assassin").is_dir());
+        fs::remove_dir_all(library).unwrap();
+    }
+}
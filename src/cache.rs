@@ -0,0 +1,112 @@
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// An on-disk cache for the responses of online metadata lookups, so re-runs and
+/// dry runs don't query the same books again, and work offline once primed.
+///
+/// Every response is stored in its own file, named after the SHA-256 of its key.
+/// A response expires once its file is older than the TTL.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    /**
+     * Creates a cache.
+     *
+     * @param dir The cache directory, or `None` for the user's cache directory
+     * (`$XDG_CACHE_HOME/aborg` or `~/.cache/aborg`).
+     * @param ttl How long responses are used.
+     * @return The cache, or `None` if no cache directory could be found.
+     */
+    pub fn new(dir: Option<&str>, ttl: Duration) -> Option<ResponseCache> {
+        let dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => default_dir()?,
+        };
+        Some(ResponseCache { dir, ttl })
+    }
+
+    /**
+     * Gets a cached response.
+     *
+     * @param key The lookup key, e.g. the provider and the ASIN.
+     * @return The response, or `None` if it is not cached or has expired.
+     */
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.path(key);
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if age >= self.ttl {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    /**
+     * Stores a response.
+     *
+     * @param key The lookup key.
+     * @param response The response to store.
+     */
+    pub fn put(&self, key: &str, response: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Written to a temporary file first, so a crash never leaves a partial response
+        let path = self.path(key);
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, response)?;
+        fs::rename(temp, path)
+    }
+
+    /**
+     * Gets the file a response is stored in.
+     */
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+        self.dir.join(format!("{}.json", hash))
+    }
+}
+
+/**
+ * Gets the user's cache directory for aborg.
+ */
+fn default_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("aborg"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_expiry() {
+        let dir = env::temp_dir().join(format!("aborg-cache-test-{}", std::process::id()));
+        let cache = ResponseCache::new(dir.to_str(), Duration::from_secs(60)).unwrap();
+        assert_eq!(cache.get("asin:B0036I54I6"), None);
+
+        cache
+            .put("asin:B0036I54I6", "{\"title\":\"The Alloy of Law\"}")
+            .unwrap();
+        assert_eq!(
+            cache.get("asin:B0036I54I6").as_deref(),
+            Some("{\"title\":\"The Alloy of Law\"}")
+        );
+        assert_eq!(cache.get("asin:B07B8W2KPX"), None);
+
+        let expired = ResponseCache::new(dir.to_str(), Duration::ZERO).unwrap();
+        assert_eq!(expired.get("asin:B0036I54I6"), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+use crate::copy::hash_file;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The name of the checksum manifest written into each book directory.
+pub const MANIFEST_NAME: &str = "checksums.sha256";
+
+/**
+ * Writes the checksum manifest of a book directory, in the format `sha256sum`
+ * reads, so the files can later be checked with `sha256sum -c checksums.sha256`.
+ *
+ * Every file in the directory is listed, relative to the directory. Files hashed
+ * while they were copied are not read again.
+ *
+ * @param dir The book directory.
+ * @param known The SHA-256 of files that were already hashed, keyed by path.
+ * @param buffer_size The buffer size used to hash the other files.
+ * @return The path of the written manifest.
+ */
+pub fn write_manifest(
+    dir: &Path,
+    known: &HashMap<PathBuf, String>,
+    buffer_size: usize,
+) -> io::Result<PathBuf> {
+    let manifest = dir.join(MANIFEST_NAME);
+    let mut lines = Vec::new();
+
+    for entry in WalkDir::new(dir).sort_by_file_name() {
+        let entry = entry?;
+        let path = entry.path();
+        if !path.is_file() || path == manifest {
+            continue;
+        }
+
+        let hash = match known.get(path) {
+            Some(hash) => hash.clone(),
+            None => hash_file(path, buffer_size)?,
+        };
+        let relative = path.strip_prefix(dir).unwrap_or(path);
+        lines.push(format!("{}  {}\n", hash, relative.to_string_lossy()));
+    }
+
+    fs::write(&manifest, lines.concat())?;
+    Ok(manifest)
+}
+
+/**
+ * Reads a checksum manifest in the `sha256sum` format.
+ *
+ * @param manifest The path of the manifest.
+ * @return The expected SHA-256 of each listed file, with the paths resolved against
+ * the manifest's directory.
+ */
+pub fn read_manifest(manifest: &Path) -> io::Result<Vec<(PathBuf, String)>> {
+    let dir = manifest.parent().unwrap_or(Path::new(""));
+    let contents = fs::read_to_string(manifest)?;
+
+    let mut entries = Vec::new();
+    for line in contents.lines() {
+        // `sha256sum` separates the name with two spaces, or " *" in binary mode
+        let Some((hash, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let name = name.strip_prefix([' ', '*']).unwrap_or(name);
+        if hash.len() != 64 || name.is_empty() {
+            continue;
+        }
+        entries.push((dir.join(name), hash.to_lowercase()));
+    }
+
+    Ok(entries)
+}
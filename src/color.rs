@@ -0,0 +1,30 @@
+use clap::ValueEnum;
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// Represents when the output is colored.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum ColorMode {
+    /// Color the output if stdout is a terminal and `NO_COLOR` isn't set.
+    Auto,
+    /// Always color the output.
+    Always,
+    /// Never color the output.
+    Never,
+}
+
+/**
+ * Enables or disables colored output for the rest of the run.
+ *
+ * @param mode The color mode selected with `--color`.
+ */
+pub fn apply(mode: &ColorMode) {
+    let enabled = match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stdout().is_terminal()
+        }
+    };
+    colored::control::set_override(enabled);
+}
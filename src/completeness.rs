@@ -0,0 +1,95 @@
+use crate::FileOp;
+use crate::metadata::Metadata;
+use serde::{Deserialize, Serialize};
+
+/// The extensions of the files that count as a cover.
+const COVER_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+/// Represents how complete the metadata of a book is.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Completeness {
+    /// The share of the scored fields the book has, in percent.
+    pub score: u8,
+    /// The scored fields the book is missing, e.g. `narrator`.
+    pub missing: Vec<String>,
+}
+
+/**
+ * Scores the metadata of a book by the fields that are worth fixing by hand: the
+ * series, book number, year and narrator, and whether a cover image is organized
+ * with the book.
+ *
+ * @param metadata The metadata of the book.
+ * @param files The planned file operations of the book.
+ * @return The score and the missing fields.
+ */
+pub fn score(metadata: &Metadata, files: &[FileOp]) -> Completeness {
+    let has_cover = files.iter().any(|op| {
+        op.from
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                COVER_EXTENSIONS
+                    .iter()
+                    .any(|cover| ext.eq_ignore_ascii_case(cover))
+            })
+    });
+    let fields = [
+        ("series", metadata.series.is_some()),
+        ("book_number", metadata.book_number.is_some()),
+        ("published_year", metadata.published_year.is_some()),
+        ("narrator", metadata.narrator.is_some()),
+        ("cover", has_cover),
+    ];
+
+    let present = fields.iter().filter(|(_, present)| *present).count();
+    Completeness {
+        score: u8::try_from(present * 100 / fields.len()).unwrap_or(100),
+        missing: fields
+            .iter()
+            .filter(|(_, present)| !present)
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FileAction;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_score() {
+        let op = |from: &str| FileOp {
+            from: PathBuf::from(from),
+            to: String::new(),
+            bytes: 0,
+            action: FileAction::Copy,
+        };
+        let mut metadata = Metadata {
+            title: String::from("Dune"),
+            series: Some(String::from("Dune")),
+            book_number: Some(1),
+            narrator: Some(String::from("Scott Brick")),
+            ..Default::default()
+        };
+        let files = vec![op("/src/Dune/Dune.m4b"), op("/src/Dune/Cover.JPG")];
+        assert_eq!(
+            score(&metadata, &files),
+            Completeness {
+                score: 80,
+                missing: vec![String::from("published_year")],
+            }
+        );
+
+        metadata.series = None;
+        metadata.book_number = None;
+        let completeness = score(&metadata, &files[..1]);
+        assert_eq!(completeness.score, 20);
+        assert_eq!(
+            completeness.missing,
+            vec!["series", "book_number", "published_year", "cover"]
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use colored::Colorize;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::process::exit;
+
+/// Represents the settings that can be provided through a TOML config file.
+///
+/// Every field is optional, so a config file only needs to contain the settings
+/// that should differ from the built-in defaults.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// Ordered list of regexes used to split a series entry into a series name and
+    /// book number. Each regex must contain a `series` named group and may contain
+    /// a `number` named group. The first matching regex wins.
+    pub series_patterns: Vec<String>,
+
+    /// Keywords that mark the file number in a file name (e.g. `Kapitel` in
+    /// `Kapitel 5`). The keyword that comes first in a name wins. Replaces the
+    /// built-in keywords.
+    pub track_keywords: Vec<String>,
+
+    /// Other names of a series, keyed by the canonical series name. Applied after
+    /// the series patterns, so all books of a series end up in one directory.
+    pub series_aliases: HashMap<String, Vec<String>>,
+
+    /// Path of the append-only audit log. Overridden by `--audit-log`.
+    pub audit_log: Option<String>,
+
+    /// Path of the SQLite library database. Overridden by `--database`.
+    pub database: Option<String>,
+
+    /// Path of a Rhai script that can change each book's destination directory.
+    /// Overridden by `--naming-script`.
+    pub naming_script: Option<String>,
+
+    /// File name templates for non-audio files, keyed by a comma-separated list
+    /// of extensions. Files without a template keep their original name.
+    pub file_templates: HashMap<String, String>,
+
+    /// Computed template variables, keyed by name, e.g.
+    /// `decade = "{{slice published_year 0 3}}0s"`. Each is rendered from the book's
+    /// metadata and can be used in all templates like a field.
+    pub variables: HashMap<String, String>,
+
+    /// Directory of `.hbs` partials used in schemas as `{{> name}}`. Overridden by
+    /// `--templates`.
+    pub templates: Option<String>,
+
+    /// The metadata providers in order of precedence (`json`, `opf` and `tags`).
+    /// Fields missing from a provider are filled in by the providers after it.
+    /// Defaults to `["json", "opf"]`.
+    pub providers: Vec<String>,
+
+    /// The program and arguments run by the `command` provider. The book directory
+    /// is added as the last argument, and the command prints the book's metadata
+    /// as JSON on stdout.
+    pub metadata_command: Vec<String>,
+
+    /// How copy, move, link and delete operations are retried after transient
+    /// errors, e.g. on flaky network mounts.
+    pub retry: RetryConfig,
+
+    /// How the responses of online metadata lookups are cached.
+    pub cache: CacheConfig,
+
+    /// Where the journal, report, mapping and summary of each run are kept.
+    pub runs: RunsConfig,
+
+    /// Rules that pick the destination root of a book by its metadata. The first
+    /// matching rule wins, and books no rule matches go to `--destination`.
+    pub routes: Vec<RouteConfig>,
+}
+
+/// Represents a rule that routes matching books to another destination root.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Filters in the `--filter` syntax (`field=value` or `field~=regex`) that must
+    /// all match. A rule without filters matches every book.
+    #[serde(default)]
+    pub when: Vec<String>,
+
+    /// The destination root the matching books are organized into.
+    pub destination: String,
+}
+
+/// Represents the retry policy for file operations.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    /// How often an operation is tried before it fails. `1` disables retrying.
+    pub attempts: u32,
+
+    /// The delay before the first retry in milliseconds. The delay doubles after
+    /// every retry.
+    pub delay_ms: u64,
+
+    /// The names of the errors that are retried, e.g. `ESTALE`.
+    pub errors: Vec<String>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            attempts: 3,
+            delay_ms: 1000,
+            errors: ["EAGAIN", "EBUSY", "ESTALE", "ETIMEDOUT", "ECONNRESET"]
+                .iter()
+                .map(|e| e.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Represents the on-disk cache for online metadata lookups.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// Whether lookup responses are cached.
+    pub enabled: bool,
+
+    /// The cache directory. Defaults to `$XDG_CACHE_HOME/aborg` or `~/.cache/aborg`.
+    pub dir: Option<String>,
+
+    /// How long a cached response is used, e.g. `30d` or `12h`.
+    pub ttl: String,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            dir: None,
+            ttl: String::from("30d"),
+        }
+    }
+}
+
+/// Represents the per-run directories that keep the artifacts of each run.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunsConfig {
+    /// Whether every run writes its artifacts into a new timestamped directory.
+    pub enabled: bool,
+
+    /// The directory of the run directories. Defaults to `$XDG_DATA_HOME/aborg/runs`
+    /// or `~/.local/share/aborg/runs`.
+    pub dir: Option<String>,
+
+    /// How many of the newest run directories are kept. Older ones are removed.
+    pub keep: usize,
+}
+
+impl Default for RunsConfig {
+    fn default() -> Self {
+        RunsConfig {
+            enabled: false,
+            dir: None,
+            keep: 30,
+        }
+    }
+}
+
+/**
+ * Reads and parses a TOML config file.
+ *
+ * Exits the process if the file can not be read or is not valid.
+ *
+ * @param path The path of the config file.
+ * @return The parsed `FileConfig`.
+ */
+pub fn load_config(path: &str) -> FileConfig {
+    match read_config(path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{} {}", "Error:".red(), err);
+            exit(1);
+        }
+    }
+}
+
+/**
+ * Reads and parses a TOML config file.
+ *
+ * @param path The path of the config file.
+ * @return The parsed `FileConfig`, or an error message if the file can not be read
+ * or is not valid.
+ */
+pub fn read_config(path: &str) -> Result<FileConfig, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read the config file '{}'. {}", path, e))?;
+    toml::from_str::<FileConfig>(&contents)
+        .map_err(|e| format!("Failed to parse config file '{}'. {}", path, e))
+}
@@ -0,0 +1,300 @@
+use clap::ValueEnum;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Represents what happens when a destination file already exists, or when
+/// two planned files end up at the same destination.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Replace the existing file.
+    Overwrite,
+    /// Leave the existing file alone and skip the incoming file.
+    Skip,
+    /// Skip the whole book.
+    Fail,
+    /// Keep both files by adding a version suffix to the incoming file name.
+    Rename,
+}
+
+/// Represents how an incoming book is combined with a book that already exists
+/// in the destination.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum MergeStrategy {
+    /// Only add the files that don't exist yet, keeping the existing files.
+    AddMissing,
+    /// Remove the existing book's files and replace them with the incoming files.
+    ReplaceAll,
+    /// Keep whichever version of the book is larger in total.
+    KeepLarger,
+    /// Ask what to do for each existing book.
+    Prompt,
+}
+
+/// The decision made for a book that already exists in the destination.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeDecision {
+    AddMissing,
+    ReplaceAll,
+    KeepExisting,
+}
+
+/**
+ * Decides how an incoming book is merged into an existing destination book.
+ *
+ * @param strategy The configured merge strategy.
+ * @param destination The existing destination book directory.
+ * @param incoming_bytes The total size of the incoming book.
+ * @return The decision for this book.
+ */
+pub fn merge_decision(
+    strategy: &MergeStrategy,
+    destination: &str,
+    incoming_bytes: u64,
+) -> MergeDecision {
+    match strategy {
+        MergeStrategy::AddMissing => MergeDecision::AddMissing,
+        MergeStrategy::ReplaceAll => MergeDecision::ReplaceAll,
+        MergeStrategy::KeepLarger => {
+            if incoming_bytes > dir_size(destination) {
+                MergeDecision::ReplaceAll
+            } else {
+                MergeDecision::KeepExisting
+            }
+        }
+        MergeStrategy::Prompt => prompt_merge(destination, incoming_bytes),
+    }
+}
+
+/**
+ * Asks the user how an incoming book should be merged into an existing book.
+ *
+ * @param destination The existing destination book directory.
+ * @param incoming_bytes The total size of the incoming book.
+ * @return The decision for this book. Defaults to keeping the existing book.
+ */
+fn prompt_merge(destination: &str, incoming_bytes: u64) -> MergeDecision {
+    println!(
+        "'{}' already exists ({} existing, {} incoming).",
+        destination,
+        crate::fsutil::format_size(dir_size(destination)),
+        crate::fsutil::format_size(incoming_bytes)
+    );
+
+    loop {
+        print!("[a]dd missing files, [r]eplace all files, [k]eep existing book: ");
+        io::stdout().flush().unwrap_or(());
+
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+            return MergeDecision::KeepExisting;
+        }
+
+        match answer.trim().to_lowercase().as_str() {
+            "a" => return MergeDecision::AddMissing,
+            "r" => return MergeDecision::ReplaceAll,
+            "k" => return MergeDecision::KeepExisting,
+            _ => continue,
+        }
+    }
+}
+
+/**
+ * Sums the size of all files in a directory and its sub-directories.
+ *
+ * @param dir The directory to measure.
+ * @return The total size in bytes.
+ */
+fn dir_size(dir: &str) -> u64 {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Tracks every planned destination path, so paths that only differ by case
+/// can be detected between plans and against what already exists on disk.
+///
+/// On case-insensitive filesystems (exFAT, NTFS, APFS) such paths are the same
+/// directory or file, so they would otherwise silently merge or overwrite.
+#[derive(Debug, Default)]
+pub struct CollisionIndex {
+    dirs: HashMap<String, String>,
+    files: HashMap<String, String>,
+    merged: HashSet<String>,
+    books: HashSet<String>,
+}
+
+impl CollisionIndex {
+    /**
+     * Resolves the casing of a destination directory.
+     *
+     * Each component of `relative` is matched case-insensitively against the
+     * directories already planned and the directories that exist on disk, and the
+     * first casing seen is reused.
+     *
+     * @param root The destination root directory.
+     * @param relative The rendered directory, relative to the root.
+     * @return The full directory path, and whether the casing was changed.
+     */
+    pub fn resolve_dir(&mut self, root: &str, relative: &str) -> (String, bool) {
+        let mut path = root.to_string();
+        let mut changed = false;
+
+        for component in relative.split('/').filter(|c| !c.is_empty()) {
+            let candidate = format!("{}/{}", path, component);
+            let key = candidate.to_lowercase();
+            let resolved = match self.dirs.get(&key) {
+                Some(existing) => existing.clone(),
+                None => {
+                    let resolved = find_on_disk(&path, component)
+                        .map(|name| format!("{}/{}", path, name))
+                        .unwrap_or(candidate.clone());
+                    self.dirs.insert(key, resolved.clone());
+                    resolved
+                }
+            };
+
+            changed |= resolved != candidate;
+            path = resolved;
+        }
+
+        (path, changed)
+    }
+
+    /**
+     * Records that an incoming book is merged into an existing destination book.
+     *
+     * @param dir The existing destination book directory.
+     * @return True if this is the first book merged into `dir`, so a second book
+     *         planned into the same directory never replaces the first one.
+     */
+    pub fn claim_merge(&mut self, dir: &str) -> bool {
+        self.merged.insert(dir.to_lowercase())
+    }
+
+    /**
+     * Claims a destination book directory for a planned book.
+     *
+     * @param dir The destination book directory.
+     * @return True if no other planned book uses the directory (ignoring case).
+     */
+    pub fn claim_book(&mut self, dir: &str) -> bool {
+        self.books.insert(dir.to_lowercase())
+    }
+
+    /**
+     * Claims a destination file path for a planned file.
+     *
+     * @param path The full destination path of the file.
+     * @param check_disk If false, only other planned files are checked.
+     * @return The conflicting path if another planned file or an existing file
+     *         already uses this path (ignoring case), otherwise `None`.
+     */
+    pub fn claim_file(&mut self, path: &str, check_disk: bool) -> Option<String> {
+        let key = path.to_lowercase();
+        if let Some(existing) = self.files.get(&key) {
+            return Some(existing.clone());
+        }
+
+        self.files.insert(key, path.to_string());
+        if !check_disk {
+            return None;
+        }
+        let file = Path::new(path);
+        let parent = file.parent()?.to_str()?;
+        let name = file.file_name()?.to_str()?;
+        find_on_disk(parent, name).map(|existing| format!("{}/{}", parent, existing))
+    }
+
+    /**
+     * Claims a versioned variant of a conflicting destination file path.
+     *
+     * The suffix is added after the file stem with `{n}` replaced by the first
+     * version number that doesn't conflict, e.g. `Title (02).mp3` becomes
+     * `Title (02) (1).mp3` with the suffix ` ({n})`.
+     *
+     * @param path The conflicting destination path.
+     * @param suffix The suffix pattern, containing `{n}`.
+     * @return The versioned destination path.
+     */
+    pub fn claim_versioned(&mut self, path: &str, suffix: &str) -> String {
+        let file = Path::new(path);
+        let parent = file.parent().and_then(|p| p.to_str()).unwrap_or("");
+        let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        let extension = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| format!(".{}", e))
+            .unwrap_or_default();
+
+        let mut version = 1;
+        loop {
+            let candidate = format!(
+                "{}/{}{}{}",
+                parent,
+                stem,
+                suffix.replace("{n}", &version.to_string()),
+                extension
+            );
+            if self.claim_file(&candidate, true).is_none() {
+                return candidate;
+            }
+            version += 1;
+        }
+    }
+}
+
+/**
+ * Finds an entry in a directory whose name matches `name` ignoring case.
+ *
+ * @param dir The directory to search.
+ * @param name The name to look for.
+ * @return The name of the entry as it exists on disk.
+ */
+fn find_on_disk(dir: &str, name: &str) -> Option<String> {
+    let name = name.to_lowercase();
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .find(|entry| entry.to_lowercase() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_collisions_between_plans() {
+        let mut index = CollisionIndex::default();
+        let root = "/nonexistent-aborg-root";
+
+        let (first, changed) = index.resolve_dir(root, "Brandon Sanderson/Mistborn");
+        assert_eq!(first, format!("{}/Brandon Sanderson/Mistborn", root));
+        assert!(!changed);
+
+        let (second, changed) = index.resolve_dir(root, "Brandon SANDERSON/Elantris");
+        assert_eq!(second, format!("{}/Brandon Sanderson/Elantris", root));
+        assert!(changed);
+
+        assert_eq!(index.claim_file(&format!("{}/Book.m4b", first), true), None);
+        assert_eq!(
+            index.claim_file(&format!("{}/book.M4B", first), true),
+            Some(format!("{}/Book.m4b", first))
+        );
+        assert_eq!(
+            index.claim_versioned(&format!("{}/book.M4B", first), " ({n})"),
+            format!("{}/book (1).M4B", first)
+        );
+        assert_eq!(
+            index.claim_versioned(&format!("{}/Book.m4b", first), " ({n})"),
+            format!("{}/Book (2).m4b", first)
+        );
+    }
+}
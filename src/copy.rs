@@ -0,0 +1,173 @@
+use crate::fsutil::copy_xattrs;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// The result of copying a single file.
+#[derive(Debug)]
+pub struct CopyResult {
+    pub bytes: u64,
+    /// The SHA-256 of the copied data, only set when hashing was requested.
+    pub sha256: Option<String>,
+}
+
+/**
+ * Copies a file, preferring the fastest method available.
+ *
+ * Without hashing, the kernel does the copy (`copy_file_range`, then `sendfile`)
+ * on Linux, and `fs::copy` is used on other platforms. With hashing, the file is
+ * read once through a buffer of `buffer_size` bytes, and the SHA-256 is computed
+ * from the same pass that writes the destination. On macOS, `fs::copy` and the
+ * buffered copy both keep the extended attributes, such as Finder tags.
+ *
+ * @param from The file to copy.
+ * @param to The destination path.
+ * @param buffer_size The buffer size used for the buffered copy.
+ * @param hash If true, compute the SHA-256 of the data while copying.
+ * @return The number of bytes copied and the hash, if requested.
+ */
+pub fn copy_file(from: &Path, to: &Path, buffer_size: usize, hash: bool) -> io::Result<CopyResult> {
+    if !hash {
+        #[cfg(target_os = "linux")]
+        {
+            let reader = File::open(from)?;
+            let metadata = reader.metadata()?;
+            let writer = File::create(to)?;
+            if let Some(bytes) = kernel_copy(&reader, &writer, metadata.len())? {
+                fs::set_permissions(to, metadata.permissions())?;
+                return Ok(CopyResult {
+                    bytes,
+                    sha256: None,
+                });
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let bytes = fs::copy(from, to)?;
+            return Ok(CopyResult {
+                bytes,
+                sha256: None,
+            });
+        }
+    }
+
+    let mut reader = File::open(from)?;
+    let permissions = reader.metadata()?.permissions();
+    let mut writer = File::create(to)?;
+    let mut hasher = hash.then(Sha256::new);
+    let mut buffer = vec![0; buffer_size.max(4096)];
+    let mut bytes: u64 = 0;
+
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..read])?;
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.update(&buffer[..read]);
+        }
+        bytes += read as u64;
+    }
+
+    writer.flush()?;
+    fs::set_permissions(to, permissions)?;
+    copy_xattrs(from, to)?;
+
+    Ok(CopyResult {
+        bytes,
+        sha256: hasher.map(|h| format!("{:x}", h.finalize())),
+    })
+}
+
+/**
+ * Computes the SHA-256 of a file.
+ *
+ * @param path The file to hash.
+ * @param buffer_size The size of the read buffer.
+ * @return The hex encoded hash.
+ */
+pub fn hash_file(path: &Path, buffer_size: usize) -> io::Result<String> {
+    let mut reader = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; buffer_size.max(4096)];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => hasher.update(&buffer[..read]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/**
+ * Copies a file inside the kernel with `copy_file_range`, falling back to `sendfile`.
+ *
+ * @param reader The opened source file.
+ * @param writer The opened destination file.
+ * @param len The size of the source file.
+ * @return The number of bytes copied, or `None` if neither call is supported
+ *         for these files and a buffered copy should be used instead.
+ */
+#[cfg(target_os = "linux")]
+fn kernel_copy(reader: &File, writer: &File, len: u64) -> io::Result<Option<u64>> {
+    use std::os::unix::io::AsRawFd;
+
+    const CHUNK: u64 = 1 << 30;
+    let (fd_in, fd_out) = (reader.as_raw_fd(), writer.as_raw_fd());
+    let mut use_sendfile = false;
+    let mut copied: u64 = 0;
+
+    while copied < len {
+        let chunk = (len - copied).min(CHUNK) as usize;
+        // SAFETY: both descriptors are open for the lifetime of this call and null
+        // offsets make the kernel use and advance the file positions.
+        let result = unsafe {
+            if use_sendfile {
+                libc::sendfile(fd_out, fd_in, std::ptr::null_mut(), chunk)
+            } else {
+                libc::copy_file_range(
+                    fd_in,
+                    std::ptr::null_mut(),
+                    fd_out,
+                    std::ptr::null_mut(),
+                    chunk,
+                    0,
+                )
+            }
+        };
+
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            let unsupported = matches!(
+                err.raw_os_error(),
+                Some(libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM)
+            );
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            } else if unsupported && copied == 0 && !use_sendfile {
+                use_sendfile = true;
+                continue;
+            } else if unsupported && copied == 0 {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+
+        if result == 0 {
+            // The source got shorter while copying
+            break;
+        }
+        copied += result as u64;
+    }
+
+    Ok(Some(copied))
+}
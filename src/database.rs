@@ -0,0 +1,635 @@
+use crate::Plan;
+use crate::copy::hash_file;
+use crate::metadata::Metadata;
+use chrono::Local;
+use rusqlite::{Connection, OpenFlags, params};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use walkdir::WalkDir;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started_at TEXT NOT NULL,
+    source TEXT NOT NULL,
+    destination TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS books (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    identity TEXT NOT NULL UNIQUE,
+    author TEXT,
+    series TEXT,
+    book_number INTEGER,
+    title TEXT NOT NULL,
+    path TEXT NOT NULL,
+    root TEXT,
+    metadata TEXT NOT NULL,
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    updated_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    book_id INTEGER NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    bytes INTEGER NOT NULL,
+    sha256 TEXT,
+    PRIMARY KEY (book_id, path)
+);
+CREATE TABLE IF NOT EXISTS plans (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    position INTEGER NOT NULL,
+    plan TEXT NOT NULL,
+    PRIMARY KEY (run_id, position)
+);
+";
+
+/// Represents the SQLite database that records every organized book.
+///
+/// A book is identified by its normalized author, series, book number, title and
+/// narrator, so organizing the same book again updates its existing record, while
+/// editions read by different narrators are kept apart.
+pub struct Database {
+    conn: Connection,
+    run_id: i64,
+}
+
+impl Database {
+    /**
+     * Opens (or creates) the library database and starts a new run.
+     *
+     * @param path The path of the SQLite database file.
+     * @param sources The source roots of the run. Stored one per line.
+     * @param destinations The destination roots of the run. Stored one per line.
+     * @return The opened database.
+     */
+    pub fn open(
+        path: &str,
+        sources: &[String],
+        destinations: &[String],
+    ) -> rusqlite::Result<Database> {
+        let mut conn = Connection::open(path)?;
+        let source = sources
+            .iter()
+            .map(|s| absolute(s))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let destination = destinations
+            .iter()
+            .map(|d| absolute(d))
+            .collect::<Vec<_>>()
+            .join("\n");
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        // Databases created before disk pooling don't record the root of a book
+        if conn.prepare("SELECT root FROM books LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE books ADD COLUMN root TEXT;")?;
+        }
+        // Databases created before the narrator was part of the identity of a book
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < 1 {
+            migrate_identities(&mut conn)?;
+        }
+        conn.execute(
+            "INSERT INTO runs (started_at, source, destination) VALUES (?1, ?2, ?3)",
+            params![Local::now().to_rfc3339(), source, destination],
+        )?;
+        let run_id = conn.last_insert_rowid();
+
+        Ok(Database { conn, run_id })
+    }
+
+    /**
+     * Gets the ID of the run started when the database was opened.
+     */
+    pub fn run_id(&self) -> i64 {
+        self.run_id
+    }
+
+    /**
+     * Records the plans of the run, so the run can be verified or executed again with
+     * `--redo`. The paths of the plans are stored as absolute paths.
+     *
+     * @param plans The plans of the run, in the order they are executed.
+     */
+    pub fn record_plans(&mut self, plans: &[Plan]) -> Result<(), String> {
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        for (position, plan) in plans.iter().enumerate() {
+            let json = serde_json::to_string(&absolute_plan(plan)).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO plans (run_id, position, plan) VALUES (?1, ?2, ?3)",
+                params![self.run_id, position as i64, json],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /**
+     * Records an organized book, replacing any previous record of the same book.
+     *
+     * @param metadata The metadata of the book.
+     * @param path The final directory of the book. Stored as an absolute path.
+     * @param root The destination root the book was placed on.
+     * @param files The files of the book, from `book_files`.
+     */
+    pub fn record_book(
+        &mut self,
+        metadata: &Metadata,
+        path: &str,
+        root: &str,
+        files: &[BookFile],
+    ) -> Result<(), String> {
+        let (path, root) = (absolute(path), absolute(root));
+        let snapshot = serde_json::to_string(metadata).map_err(|e| e.to_string())?;
+        let tx = self.conn.transaction().map_err(|e| e.to_string())?;
+        let book_id: i64 = tx
+            .query_row(
+                "INSERT INTO books
+                    (identity, author, series, book_number, title, path, root, metadata, run_id, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT(identity) DO UPDATE SET
+                    author = excluded.author,
+                    series = excluded.series,
+                    book_number = excluded.book_number,
+                    title = excluded.title,
+                    path = excluded.path,
+                    root = excluded.root,
+                    metadata = excluded.metadata,
+                    run_id = excluded.run_id,
+                    updated_at = excluded.updated_at
+                 RETURNING id",
+                params![
+                    identity_key(metadata),
+                    metadata.author,
+                    metadata.series,
+                    metadata.book_number,
+                    metadata.title,
+                    path,
+                    root,
+                    snapshot,
+                    self.run_id,
+                    Local::now().to_rfc3339(),
+                ],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+
+        tx.execute("DELETE FROM files WHERE book_id = ?1", params![book_id])
+            .map_err(|e| e.to_string())?;
+        for file in files {
+            tx.execute(
+                "INSERT INTO files (book_id, path, bytes, sha256) VALUES (?1, ?2, ?3, ?4)",
+                params![book_id, file.path, file.bytes as i64, file.sha256],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+
+        tx.commit().map_err(|e| e.to_string())
+    }
+}
+
+/// Represents a file of an organized book, as it is recorded in the database.
+pub struct BookFile {
+    path: String,
+    bytes: u64,
+    sha256: Option<String>,
+}
+
+/**
+ * Lists every file in a book's directory with its size and SHA-256, for
+ * `Database::record_book`. This reads the whole book, so it's done before the
+ * database is locked.
+ *
+ * @param path The directory of the book.
+ * @param checksums The SHA-256 already computed while copying, keyed by destination.
+ *                  Only the other files are hashed.
+ * @param buffer_size The buffer size used to hash the files.
+ * @return The files of the book.
+ */
+pub fn book_files(
+    path: &str,
+    checksums: &HashMap<PathBuf, String>,
+    buffer_size: usize,
+) -> Vec<BookFile> {
+    let known: HashMap<PathBuf, &String> = checksums
+        .iter()
+        .map(|(file, sha256)| (std::path::absolute(file).unwrap_or(file.clone()), sha256))
+        .collect();
+    WalkDir::new(absolute(path))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| BookFile {
+            path: entry.path().display().to_string(),
+            bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+            sha256: match known.get(entry.path()) {
+                Some(sha256) => Some(sha256.to_string()),
+                None => hash_file(entry.path(), buffer_size).ok(),
+            },
+        })
+        .collect()
+}
+
+/**
+ * Rewrites the identities of the recorded books with the current identity key,
+ * which includes the narrator. A book that was already recorded again under its new
+ * identity keeps only the newer record.
+ *
+ * @param conn The connection to the database.
+ */
+fn migrate_identities(conn: &mut Connection) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    let books: Vec<(i64, String, String)> = tx
+        .prepare("SELECT id, identity, metadata FROM books")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, identity, snapshot) in books {
+        let Ok(metadata) = serde_json::from_str::<Metadata>(&snapshot) else {
+            continue;
+        };
+        let key = identity_key(&metadata);
+        if key == identity {
+            continue;
+        }
+        let taken: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM books WHERE identity = ?1)",
+            params![key],
+            |row| row.get(0),
+        )?;
+        if taken {
+            tx.execute("DELETE FROM books WHERE id = ?1", params![id])?;
+        } else {
+            tx.execute(
+                "UPDATE books SET identity = ?1 WHERE id = ?2",
+                params![key, id],
+            )?;
+        }
+    }
+    tx.execute_batch("PRAGMA user_version = 1;")?;
+    tx.commit()
+}
+
+/**
+ * Builds the key that identifies a book, independent of where it is stored.
+ * Editions read by different narrators are different books.
+ *
+ * @param metadata The metadata of the book.
+ * @return The lowercase author, series, book number, title and narrator joined with `|`.
+ */
+fn identity_key(metadata: &Metadata) -> String {
+    [
+        metadata.author.clone().unwrap_or_default(),
+        metadata.series.clone().unwrap_or_default(),
+        metadata
+            .book_number
+            .map(|n| n.to_string())
+            .unwrap_or_default(),
+        metadata.title.clone(),
+        metadata.narrator.clone().unwrap_or_default(),
+    ]
+    .iter()
+    .map(|s| s.trim().to_lowercase())
+    .collect::<Vec<String>>()
+    .join("|")
+}
+
+/**
+ * Reads the recorded SHA-256 of every file of the books in a library directory.
+ *
+ * @param path The path of the SQLite database file.
+ * @param library The library directory. Only files below it are returned.
+ * @return The recorded files, as (absolute path, SHA-256).
+ */
+pub fn file_checksums(path: &str, library: &str) -> rusqlite::Result<Vec<(String, String)>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let library = absolute(library);
+    let prefix = format!("{}/", library.trim_end_matches('/'));
+    let mut stmt = conn.prepare(
+        "SELECT path, sha256 FROM files
+         WHERE sha256 IS NOT NULL AND substr(path, 1, length(?1)) = ?1
+         ORDER BY path",
+    )?;
+
+    let rows = stmt.query_map(params![prefix], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/**
+ * Reads the destination root each author's books were last placed on, so a pool
+ * can keep an author's books on one drive across runs.
+ *
+ * @param path The path of the SQLite database file.
+ * @return The absolute root of each author, by lowercase author.
+ */
+pub fn author_roots(path: &str) -> rusqlite::Result<HashMap<String, String>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT author, root FROM books
+         WHERE author IS NOT NULL AND root IS NOT NULL
+         ORDER BY updated_at",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        let author: String = row.get(0)?;
+        Ok((author.trim().to_lowercase(), row.get(1)?))
+    })?;
+    rows.collect()
+}
+
+/**
+ * Removes the records of the books below a directory that no longer exist, e.g.
+ * after `merge-authors` moved them under another author. The moved books are
+ * recorded again under their new path.
+ *
+ * @param path The path of the SQLite database file.
+ * @param dir The directory the books were moved out of.
+ * @return The number of removed records.
+ */
+pub fn forget_moved_books(path: &str, dir: &str) -> rusqlite::Result<usize> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    let dir = absolute(dir);
+    let mut stmt =
+        conn.prepare("SELECT id, path FROM books WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'")?;
+    let books = stmt
+        .query_map(params![dir, format!("{}/%", escape_like(&dir))], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut removed = 0;
+    for (id, book_path) in books {
+        if !std::path::Path::new(&book_path).exists() {
+            removed += conn.execute("DELETE FROM books WHERE id = ?1", params![id])?;
+        }
+    }
+    Ok(removed)
+}
+
+/**
+ * Escapes the wildcards of a `LIKE` pattern, for `ESCAPE '\'`.
+ *
+ * @param value The literal text.
+ * @return The text with `%`, `_` and `\` escaped.
+ */
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/**
+ * Gets the directories of the recorded books below a directory that still exist,
+ * e.g. to export part of a library.
+ *
+ * @param path The path of the SQLite database file.
+ * @param dir The library directory.
+ * @return The book directories, sorted by author, series, book number and title.
+ */
+pub fn library_books(path: &str, dir: &str) -> rusqlite::Result<Vec<String>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let dir = absolute(dir);
+    let mut stmt = conn.prepare(
+        "SELECT path FROM books WHERE path = ?1 OR path LIKE ?2 ESCAPE '\\'
+         ORDER BY author, series, book_number, title",
+    )?;
+    let books = stmt
+        .query_map(params![dir, format!("{}/%", escape_like(&dir))], |row| {
+            row.get::<_, String>(0)
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(books
+        .into_iter()
+        .filter(|book| std::path::Path::new(book).is_dir())
+        .collect())
+}
+
+/**
+ * Reads the plans recorded for a run.
+ *
+ * @param path The path of the SQLite database file.
+ * @param run_id The ID of the run.
+ * @return The plans of the run, in the order they were executed. Empty if the run
+ *         is unknown or recorded no plans.
+ */
+pub fn run_plans(path: &str, run_id: i64) -> Result<Vec<Plan>, String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.to_string())?;
+    // Databases created before runs recorded their plans don't have the table
+    let Ok(mut stmt) = conn.prepare("SELECT plan FROM plans WHERE run_id = ?1 ORDER BY position")
+    else {
+        return Ok(Vec::new());
+    };
+
+    let rows = stmt
+        .query_map(params![run_id], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    rows.map(|json| {
+        let json = json.map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    })
+    .collect()
+}
+
+/**
+ * Makes the paths of a plan absolute, so a recorded run can be redone from any
+ * working directory.
+ */
+fn absolute_plan(plan: &Plan) -> Plan {
+    let mut plan = plan.clone();
+    for dir in [&mut plan.from, &mut plan.to, &mut plan.root]
+        .into_iter()
+        .chain(&mut plan.parts)
+    {
+        *dir = absolute(dir);
+    }
+    if !plan.source.is_empty() {
+        plan.source = absolute(&plan.source);
+    }
+    for op in &mut plan.files {
+        op.from = std::path::absolute(&op.from).unwrap_or(op.from.clone());
+        op.to = absolute(&op.to);
+    }
+    for file in &mut plan.left_out {
+        *file = std::path::absolute(&*file).unwrap_or(file.clone());
+    }
+    if let Some(file) = &mut plan.description_file {
+        *file = absolute(file);
+    }
+    if let Some(playlist) = &mut plan.playlist {
+        playlist.file = absolute(&playlist.file);
+    }
+    plan
+}
+
+/**
+ * Makes a path absolute, so the stored paths don't depend on the working directory.
+ *
+ * @param path The path to make absolute.
+ * @return The absolute path, or the path unchanged if it can't be resolved.
+ */
+fn absolute(path: &str) -> String {
+    std::path::absolute(path)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Represents the filters of a library database query. Text filters match any
+/// part of the field, ignoring case.
+#[derive(Debug, Default)]
+pub struct BookQuery {
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub title: Option<String>,
+    /// Only match books whose metadata snapshot doesn't have this field.
+    pub missing: Option<String>,
+}
+
+/// Represents a book recorded in the library database.
+#[derive(Debug, Serialize)]
+pub struct BookRecord {
+    pub path: String,
+    pub author: Option<String>,
+    pub series: Option<String>,
+    pub book_number: Option<u16>,
+    pub title: String,
+    pub metadata: serde_json::Value,
+}
+
+/**
+ * Finds the books in the library database matching a query.
+ *
+ * @param path The path of the SQLite database file.
+ * @param query The filters to apply.
+ * @return The matching books, sorted by author, series, book number and title.
+ */
+pub fn find_books(path: &str, query: &BookQuery) -> rusqlite::Result<Vec<BookRecord>> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT path, author, series, book_number, title, metadata FROM books
+         WHERE (?1 IS NULL OR author LIKE '%' || ?1 || '%')
+           AND (?2 IS NULL OR series LIKE '%' || ?2 || '%')
+           AND (?3 IS NULL OR title LIKE '%' || ?3 || '%')
+           AND (?4 IS NULL OR json_type(metadata, '$.' || ?4) IS NULL)
+         ORDER BY author, series, book_number, title",
+    )?;
+
+    let rows = stmt.query_map(
+        params![query.author, query.series, query.title, query.missing],
+        |row| {
+            let metadata: String = row.get(5)?;
+            Ok(BookRecord {
+                path: row.get(0)?,
+                author: row.get(1)?,
+                series: row.get(2)?,
+                book_number: row.get(3)?,
+                title: row.get(4)?,
+                metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+            })
+        },
+    )?;
+
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_forget_moved_books() {
+        let dir = std::env::temp_dir().join(format!("aborg-database-test-{}", std::process::id()));
+        let path = |name: &str| dir.join(name).display().to_string();
+        fs::create_dir_all(path("lib/Gaiman_ Neil/Kept")).unwrap();
+        fs::create_dir_all(path("lib/Gaiman, Neil")).unwrap();
+        let database = path("library.db");
+        let mut db = Database::open(&database, &[path("src")], &[path("lib")]).unwrap();
+        let record = |db: &mut Database, title: &str, book: &str| {
+            let metadata = Metadata {
+                title: title.to_string(),
+                ..Default::default()
+            };
+            let files = book_files(&path(book), &HashMap::new(), 4096);
+            db.record_book(&metadata, &path(book), &path("lib"), &files)
+                .unwrap();
+        };
+        record(&mut db, "Moved", "lib/Gaiman, Neil/Moved");
+        record(&mut db, "Other", "lib/Gaiman_ Neil/Gone");
+        record(&mut db, "Kept", "lib/Gaiman_ Neil/Kept");
+        drop(db);
+
+        // `_` is not a wildcard, so the books of `Gaiman, Neil` are not removed with it
+        assert_eq!(
+            forget_moved_books(&database, &path("lib/Gaiman_ Neil")).unwrap(),
+            1
+        );
+        assert_eq!(
+            forget_moved_books(&database, &path("lib/Gaiman, Neil")).unwrap(),
+            1
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_identities() {
+        let dir =
+            std::env::temp_dir().join(format!("aborg-database-migrate-{}", std::process::id()));
+        let path = |name: &str| dir.join(name).display().to_string();
+        fs::create_dir_all(path("lib/Mistborn")).unwrap();
+        let database = path("library.db");
+        let metadata = Metadata {
+            title: String::from("Mistborn"),
+            author: Some(String::from("Brandon Sanderson")),
+            narrator: Some(String::from("Michael Kramer")),
+            ..Default::default()
+        };
+        let mut db = Database::open(&database, &[path("src")], &[path("lib")]).unwrap();
+        db.record_book(&metadata, &path("lib/Mistborn"), &path("lib"), &[])
+            .unwrap();
+        // The identity the book had before the narrator was part of it
+        db.conn
+            .execute_batch(
+                "UPDATE books SET identity = 'brandon sanderson|||mistborn';
+                 PRAGMA user_version = 0;",
+            )
+            .unwrap();
+        drop(db);
+
+        let mut db = Database::open(&database, &[path("src")], &[path("lib")]).unwrap();
+        db.record_book(&metadata, &path("lib/Mistborn"), &path("lib"), &[])
+            .unwrap();
+        let identities: Vec<String> = db
+            .conn
+            .prepare("SELECT identity FROM books")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(identities, vec![identity_key(&metadata)]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_book_files() {
+        let dir = std::env::temp_dir().join(format!("aborg-database-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("01.mp3"), "copied").unwrap();
+        fs::write(dir.join("cover.jpg"), "written").unwrap();
+        // The checksum computed while copying is used as it is
+        let checksums = HashMap::from([(dir.join("01.mp3"), String::from("known"))]);
+
+        let mut files = book_files(&dir.display().to_string(), &checksums, 4096);
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].sha256.as_deref(), Some("known"));
+        assert_eq!(files[1].bytes, 7);
+        assert_eq!(
+            files[1].sha256,
+            hash_file(&dir.join("cover.jpg"), 4096).ok()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
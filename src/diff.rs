@@ -0,0 +1,80 @@
+use colored::Colorize;
+
+/**
+ * Splits two names into their common start, the parts that differ, and their
+ * common end.
+ *
+ * @param old The original name.
+ * @param new The new name.
+ * @return The common prefix, the changed part of each name, and the common suffix.
+ */
+fn split_changes<'a>(old: &'a str, new: &'a str) -> (&'a str, &'a str, &'a str, &'a str) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix: usize = old_rest
+        .chars()
+        .rev()
+        .zip(new_rest.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+
+    (
+        &old[..prefix],
+        &old_rest[..old_rest.len() - suffix],
+        &new_rest[..new_rest.len() - suffix],
+        &old_rest[old_rest.len() - suffix..],
+    )
+}
+
+/**
+ * Formats a file rename as the original and the new name side by side, with the
+ * changed part of each name highlighted.
+ *
+ * @param old The original name.
+ * @param new The new name.
+ * @param width The width the original name is padded to, so the new names of a
+ *        book line up.
+ * @return The formatted line.
+ */
+pub fn rename_line(old: &str, new: &str, width: usize) -> String {
+    let (prefix, old_changed, new_changed, suffix) = split_changes(old, new);
+    let padding = " ".repeat(width.saturating_sub(old.chars().count()));
+    format!(
+        "  {}{}{}{}  ->  {}{}{}",
+        prefix,
+        old_changed.red(),
+        suffix,
+        padding,
+        prefix,
+        new_changed.green(),
+        suffix
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_changes() {
+        assert_eq!(
+            split_changes("Track1.mp3", "Mistborn (001).mp3"),
+            ("", "Track1", "Mistborn (001)", ".mp3")
+        );
+        assert_eq!(
+            split_changes("Dune (1).mp3", "Dune (001).mp3"),
+            ("Dune (", "", "00", "1).mp3")
+        );
+        assert_eq!(
+            split_changes("Café 1.mp3", "Café 2.mp3"),
+            ("Café ", "1", "2", ".mp3")
+        );
+        assert_eq!(split_changes("a.mp3", "a.mp3"), ("a.mp3", "", "", ""));
+    }
+}
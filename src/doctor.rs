@@ -0,0 +1,360 @@
+use crate::schema::Schema;
+use crate::summary::Summary;
+use crate::{CONFIG, fsutil, is_metafile, is_skipped, read_book_metadata};
+use colored::Colorize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// The characters that are not allowed in file names on Windows, SMB shares and
+/// exFAT or FAT32 drives.
+const PROBED_CHARS: [char; 8] = ['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+/// The prefix of the probe files created in the destination.
+const PROBE_PREFIX: &str = ".aborg-doctor-";
+
+/// Collects the results of the checks and prints them as they are made.
+#[derive(Debug, Default)]
+pub struct Checks {
+    pub problems: usize,
+    pub warnings: usize,
+}
+
+impl Checks {
+    /**
+     * Reports a check that passed.
+     */
+    pub fn pass(&mut self, message: &str) {
+        println!("{} {}", "OK:".green(), message);
+    }
+
+    /**
+     * Reports a limitation that doesn't keep aborg from running, but may need a
+     * different setup.
+     */
+    pub fn warn(&mut self, message: &str) {
+        self.warnings += 1;
+        println!("{} {}", "Warning:".yellow(), message);
+    }
+
+    /**
+     * Reports a problem that keeps aborg from running.
+     */
+    pub fn fail(&mut self, message: &str) {
+        self.problems += 1;
+        println!("{} {}", "Error:".red(), message);
+    }
+
+    /**
+     * Prints the result of all checks.
+     *
+     * @return True if no problems were found. Warnings are allowed.
+     */
+    pub fn finish(&self) -> bool {
+        println!("--");
+        let warnings = count(self.warnings, "warning");
+        if self.problems == 0 {
+            println!("{} ({})", "No problems found.".green(), warnings);
+        } else {
+            let problems = count(self.problems, "problem");
+            println!("{}", format!("{} and {} found.", problems, warnings).red());
+        }
+        self.problems == 0
+    }
+}
+
+/**
+ * Formats a count with a singular or plural noun, e.g. "1 warning" or "2 warnings".
+ */
+fn count(count: usize, noun: &str) -> String {
+    match count {
+        1 => format!("1 {}", noun),
+        _ => format!("{} {}s", count, noun),
+    }
+}
+
+/// The limitations of a destination filesystem, found by creating probe files.
+#[derive(Debug, Default)]
+struct Limits {
+    /// The characters the filesystem doesn't allow in file names.
+    illegal_chars: Vec<char>,
+    /// The maximum length of a file name, in bytes.
+    max_name: Option<usize>,
+}
+
+/**
+ * Checks the setup before a run: the source and destination directories, the
+ * limitations of the destination filesystems, and a sample of the books, which are
+ * read and rendered with the schema. Nothing is changed, except for probe files
+ * that are removed right away.
+ *
+ * @param sources The source directories.
+ * @param destinations The destination directories.
+ * @param schema The schema, or `None` if it doesn't compile.
+ * @param sample The maximum number of books that are read.
+ * @param checks The results of the checks.
+ */
+pub fn doctor(
+    sources: &[String],
+    destinations: &[String],
+    schema: Option<&Schema>,
+    sample: usize,
+    checks: &mut Checks,
+) {
+    for source in sources {
+        check_source(Path::new(source), checks);
+    }
+
+    let mut limits = Limits::default();
+    for destination in destinations {
+        let found = check_destination(Path::new(destination), checks);
+        limits.illegal_chars.extend(found.illegal_chars);
+        limits.max_name = match (limits.max_name, found.max_name) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    if let Some(schema) = schema {
+        check_books(sources, schema, sample, &limits, checks);
+    }
+}
+
+/**
+ * Checks that a source directory exists and can be read.
+ */
+fn check_source(source: &Path, checks: &mut Checks) {
+    let name = source.display().to_string();
+    if !source.is_dir() {
+        checks.fail(&format!(
+            "The source '{}' is not a directory",
+            name.yellow()
+        ));
+        return;
+    }
+    match fs::read_dir(source) {
+        Ok(_) => checks.pass(&format!("The source '{}' can be read", name)),
+        Err(err) => checks.fail(&format!(
+            "The source '{}' can't be read. {}",
+            name.yellow(),
+            err
+        )),
+    }
+}
+
+/**
+ * Checks that a destination directory can be written to, and probes the
+ * limitations of its filesystem.
+ *
+ * @param destination The destination directory. If it doesn't exist yet, its closest
+ *        existing parent is checked, since the directory is created by the run.
+ * @param checks The results of the checks.
+ * @return The limitations of the filesystem.
+ */
+fn check_destination(destination: &Path, checks: &mut Checks) -> Limits {
+    let name = destination.display().to_string();
+    let dir = if destination.is_dir() {
+        destination.to_path_buf()
+    } else {
+        match fsutil::existing_ancestor(destination) {
+            Some(parent) if parent.is_dir() => {
+                checks.warn(&format!(
+                    "The destination '{}' doesn't exist yet, it will be created in '{}'",
+                    name.yellow(),
+                    parent.display()
+                ));
+                parent.to_path_buf()
+            }
+            _ => {
+                checks.fail(&format!(
+                    "The destination '{}' can't be created",
+                    name.yellow()
+                ));
+                return Limits::default();
+            }
+        }
+    };
+
+    let probe = dir.join(format!("{}write", PROBE_PREFIX));
+    if let Err(err) = File::create(&probe) {
+        checks.fail(&format!(
+            "The destination '{}' can't be written to. {}",
+            name.yellow(),
+            err
+        ));
+        return Limits::default();
+    }
+    fs::remove_file(&probe).unwrap_or(());
+    let free = fsutil::available_space(&dir)
+        .map(|bytes| format!(", {} free", fsutil::format_size(bytes)))
+        .unwrap_or_default();
+    checks.pass(&format!(
+        "The destination '{}' can be written to{}",
+        name, free
+    ));
+
+    if is_case_insensitive(&dir) {
+        checks.warn(&format!(
+            "The destination '{}' is case-insensitive, names that only differ by case are the same file",
+            name
+        ));
+    }
+
+    let illegal_chars = probe_illegal_chars(&dir);
+    if !illegal_chars.is_empty() {
+        let chars: String = illegal_chars.iter().map(|c| format!(" {}", c)).collect();
+        checks.warn(&format!(
+            "The destination '{}' doesn't allow these characters in file names:{}",
+            name, chars
+        ));
+    }
+
+    let max_name = fsutil::max_name_length(&dir);
+    if let Some(max) = max_name
+        && max < 255
+    {
+        checks.warn(&format!(
+            "The destination '{}' only allows file names of up to {} bytes",
+            name, max
+        ));
+    }
+
+    Limits {
+        illegal_chars,
+        max_name,
+    }
+}
+
+/**
+ * Checks whether a directory is on a case-insensitive filesystem, by creating a
+ * probe file and looking it up with a different case.
+ */
+fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(format!("{}case", PROBE_PREFIX));
+    if File::create(&probe).is_err() {
+        return false;
+    }
+    let insensitive = dir
+        .join(format!("{}CASE", PROBE_PREFIX.to_uppercase()))
+        .exists();
+    fs::remove_file(&probe).unwrap_or(());
+    insensitive
+}
+
+/**
+ * Finds the characters a directory doesn't allow in file names, by creating a probe
+ * file with each of them.
+ */
+fn probe_illegal_chars(dir: &Path) -> Vec<char> {
+    PROBED_CHARS
+        .into_iter()
+        .filter(|c| {
+            let probe = dir.join(format!("{}{}", PROBE_PREFIX, c));
+            let created = File::create(&probe).is_ok();
+            if created {
+                fs::remove_file(&probe).unwrap_or(());
+            }
+            !created
+        })
+        .collect()
+}
+
+/**
+ * Reads a sample of the books in the sources, renders their destination directory,
+ * and checks the rendered names against the limitations of the destination.
+ *
+ * @param sources The source directories.
+ * @param schema The schema the books are rendered with.
+ * @param sample The maximum number of books that are read.
+ * @param limits The limitations of the destination filesystems.
+ * @param checks The results of the checks.
+ */
+fn check_books(
+    sources: &[String],
+    schema: &Schema,
+    sample: usize,
+    limits: &Limits,
+    checks: &mut Checks,
+) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let metadata_paths: Vec<PathBuf> = sources
+        .iter()
+        .flat_map(|source| {
+            WalkDir::new(source)
+                .into_iter()
+                .filter_entry(|e| !is_skipped(e))
+                .filter_map(|e| e.ok())
+        })
+        .filter(|e| is_metafile(e.path()))
+        .map(|e| e.path().to_path_buf())
+        .take(sample)
+        .collect();
+    if metadata_paths.is_empty() {
+        checks.warn(&format!(
+            "No '{}' files were found in the sources",
+            cfg.metafile
+        ));
+        return;
+    }
+
+    let mut read = 0;
+    for path in &metadata_paths {
+        let name = path.display().to_string();
+        let mut summary = Summary::default();
+        let Some(mut metadata) = read_book_metadata(path, &mut summary) else {
+            checks.fail(&format!(
+                "The metadata of '{}' can't be read",
+                name.yellow()
+            ));
+            continue;
+        };
+        read += 1;
+        let Ok(rendered) = schema.fmt_path(&mut metadata) else {
+            checks.fail(&format!(
+                "The path schema can't be rendered for '{}', a required field is missing",
+                name.yellow()
+            ));
+            continue;
+        };
+        for component in rendered.split('/') {
+            if let Some(c) = component.chars().find(|c| limits.illegal_chars.contains(c)) {
+                checks.warn(&format!(
+                    "'{}' contains '{}', which the destination doesn't allow",
+                    component, c
+                ));
+            }
+            if let Some(max) = limits.max_name
+                && component.len() > max
+            {
+                checks.warn(&format!(
+                    "'{}' is longer than the {} bytes the destination allows",
+                    component, max
+                ));
+            }
+        }
+    }
+    checks.pass(&format!(
+        "{} of {} sampled metadata files were read",
+        read,
+        metadata_paths.len()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probes() {
+        let dir = std::env::temp_dir().join(format!("aborg-doctor-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Probing leaves nothing behind, whatever the filesystem allows
+        is_case_insensitive(&dir);
+        assert!(probe_illegal_chars(&dir).len() <= PROBED_CHARS.len());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir(dir).unwrap();
+
+        assert_eq!(count(1, "warning"), "1 warning");
+        assert_eq!(count(3, "problem"), "3 problems");
+    }
+}
@@ -0,0 +1,284 @@
+use crate::fingerprint::{Fingerprint, fingerprint};
+use crate::metadata::Metadata;
+use crate::pipeline::parallel_map;
+use crate::summary::Summary;
+use crate::{CONFIG, get_files, is_audio_file, is_metafile, read_book_metadata};
+use colored::Colorize;
+use lofty::file::AudioFile;
+use lofty::probe::Probe;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use walkdir::WalkDir;
+
+/// The share of matching frames from which two fingerprints are the same recording.
+const SAME_AUDIO: f64 = 0.85;
+
+/// Represents a book of the library, as seen by the duplicate detector.
+struct Book {
+    dir: PathBuf,
+    metadata: Metadata,
+    /// The total length of the book's audio files.
+    duration: Duration,
+    fingerprint: Option<Fingerprint>,
+}
+
+/**
+ * Finds books that are in the library more than once, and prints them in groups.
+ *
+ * Books with the same author and title (ignoring case and punctuation) are always
+ * duplicates. With fingerprinting, books with about the same length whose audio
+ * starts the same are duplicates too, even when their names, tags and bitrates
+ * differ. Nothing is changed.
+ *
+ * @param use_fingerprints If true, also compare the audio of the books.
+ */
+pub fn find_duplicates(use_fingerprints: bool) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    println!("Searching for duplicate books in '{}'...", cfg.from.green());
+
+    let metadata_paths: Vec<PathBuf> = WalkDir::new(&cfg.from)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| is_metafile(e.path()))
+        .map(|e| e.path().to_path_buf())
+        .collect();
+    let books: Vec<Book> = parallel_map(&metadata_paths, cfg.jobs, |path| {
+        let mut book_summary = Summary::default();
+        let metadata = read_book_metadata(path, &mut book_summary)?;
+        let dir = path.parent()?.to_path_buf();
+        let audio_files: Vec<PathBuf> = get_files(&dir.display().to_string())
+            .into_iter()
+            .filter(|file| is_audio_file(file, &cfg.file_ext))
+            .collect();
+        let duration = audio_files.iter().map(|file| audio_duration(file)).sum();
+        let fingerprint = match audio_files.iter().min() {
+            Some(file) if use_fingerprints => fingerprint(file).unwrap_or_else(|err| {
+                eprintln!(
+                    "{} '{}'. {}",
+                    "Warning: Could not fingerprint".yellow(),
+                    file.display(),
+                    err
+                );
+                None
+            }),
+            _ => None,
+        };
+        Some(Book {
+            dir,
+            metadata,
+            duration,
+            fingerprint,
+        })
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let mut groups = DisjointSet::new(books.len());
+    let mut by_title: HashMap<String, usize> = HashMap::new();
+    for (index, book) in books.iter().enumerate() {
+        let key = title_key(&book.metadata);
+        match by_title.get(&key) {
+            Some(&first) => groups.union(first, index),
+            None => {
+                by_title.insert(key, index);
+            }
+        }
+    }
+
+    let lengths: Vec<(usize, Duration)> = books
+        .iter()
+        .enumerate()
+        .filter(|(_, book)| book.fingerprint.is_some())
+        .map(|(index, book)| (index, book.duration))
+        .collect();
+    let mut matches = Vec::new();
+    for (i, j) in similar_length_pairs(lengths) {
+        let (Some(fa), Some(fb)) = (&books[i].fingerprint, &books[j].fingerprint) else {
+            continue;
+        };
+        if groups.find(i) == groups.find(j) {
+            continue;
+        }
+        let similarity = fa.similarity(fb);
+        if similarity >= SAME_AUDIO {
+            groups.union(i, j);
+            matches.push((i, j, similarity));
+        }
+    }
+
+    let mut grouped: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..books.len() {
+        grouped.entry(groups.find(index)).or_default().push(index);
+    }
+    let mut duplicates: Vec<Vec<usize>> = grouped
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    duplicates.sort();
+
+    if duplicates.is_empty() {
+        println!("{}", "No duplicate books found.".green());
+        return;
+    }
+    println!(
+        "{}",
+        format!("Duplicate books ({} groups):", duplicates.len()).yellow()
+    );
+    for group in &duplicates {
+        println!("--");
+        for &index in group {
+            let book = &books[index];
+            let matched = matches
+                .iter()
+                .find(|(i, j, _)| *j == index && group.contains(i))
+                .map(|(_, _, similarity)| format!(" (same audio, {:.0}%)", similarity * 100.0))
+                .unwrap_or_default();
+            println!(
+                "  '{}' - {}{}",
+                book.dir.display().to_string().yellow(),
+                book.metadata.title,
+                matched
+            );
+        }
+    }
+}
+
+/**
+ * Builds the key books with the same author and title share, ignoring case,
+ * punctuation and spacing.
+ */
+pub fn title_key(metadata: &Metadata) -> String {
+    let normalize = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    format!(
+        "{}|{}",
+        normalize(metadata.author.as_deref().unwrap_or_default()),
+        normalize(&metadata.title)
+    )
+}
+
+/**
+ * Checks whether two books have about the same length, allowing for different
+ * intros and outros. Books of unknown length are always compared.
+ */
+fn similar_length(a: Duration, b: Duration) -> bool {
+    if a.is_zero() || b.is_zero() {
+        return true;
+    }
+    let tolerance = (a.max(b) / 33).max(Duration::from_secs(90));
+    a.abs_diff(b) <= tolerance
+}
+
+/**
+ * Finds the pairs of books with about the same length, so only they have their
+ * audio compared. The books are sorted by length, and each book is paired with the
+ * books after it until the lengths are too far apart.
+ *
+ * @param lengths The index and length of each book.
+ * @return The pairs of book indexes, the smaller index first.
+ */
+fn similar_length_pairs(mut lengths: Vec<(usize, Duration)>) -> Vec<(usize, usize)> {
+    // Books of unknown length sort first, and are paired with every book
+    lengths.sort_by_key(|&(index, length)| (length, index));
+    let mut pairs = Vec::new();
+    for (position, &(a, a_length)) in lengths.iter().enumerate() {
+        for &(b, b_length) in &lengths[position + 1..] {
+            if !similar_length(a_length, b_length) {
+                break;
+            }
+            pairs.push((a.min(b), a.max(b)));
+        }
+    }
+    pairs
+}
+
+/**
+ * Reads the length of an audio file from its headers.
+ */
+pub fn audio_duration(path: &Path) -> Duration {
+    Probe::open(path)
+        .and_then(|p| p.read())
+        .map(|file| file.properties().duration())
+        .unwrap_or_default()
+}
+
+/// A union-find structure for grouping the duplicates.
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    /**
+     * Creates a set where every item is in a group of its own.
+     */
+    fn new(len: usize) -> Self {
+        DisjointSet {
+            parents: (0..len).collect(),
+        }
+    }
+
+    /**
+     * Gets the first item of an item's group.
+     */
+    fn find(&mut self, index: usize) -> usize {
+        let parent = self.parents[index];
+        if parent == index {
+            return index;
+        }
+        let root = self.find(parent);
+        self.parents[index] = root;
+        root
+    }
+
+    /**
+     * Merges the groups of two items.
+     */
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a.max(b)] = a.min(b);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_key_and_length() {
+        let book = |author: &str, title: &str| Metadata {
+            author: Some(author.to_string()),
+            title: title.to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            title_key(&book("Brandon Sanderson", "The Way of Kings")),
+            title_key(&book("brandon  sanderson", "The Way of Kings!"))
+        );
+        assert_ne!(
+            title_key(&book("Brandon Sanderson", "The Way of Kings")),
+            title_key(&book("Brandon Sanderson", "Words of Radiance"))
+        );
+
+        let hours = |h: f64| Duration::from_secs_f64(h * 3600.0);
+        assert!(similar_length(hours(45.5), hours(45.6)));
+        assert!(!similar_length(hours(45.5), hours(40.0)));
+        assert!(similar_length(Duration::ZERO, hours(40.0)));
+
+        let lengths = vec![
+            (0, hours(45.5)),
+            (1, hours(10.0)),
+            (2, Duration::ZERO),
+            (3, hours(45.6)),
+            (4, hours(10.1)),
+        ];
+        let mut pairs = similar_length_pairs(lengths);
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 2), (0, 3), (1, 2), (1, 4), (2, 3), (2, 4)]);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::schema::Schema;
+use crate::summary::Summary;
+use crate::{CONFIG, execute, plan};
+use colored::Colorize;
+
+/**
+ * Copies the books of an organized library that match the filters to another
+ * destination, e.g. a USB stick for a road trip. The books are planned from the
+ * library like from a source, so the copy is organized with the schemas of the
+ * export, which can be the ones of the library or a different, e.g. flatter, one.
+ *
+ * The library is only read: the books are always copied, metadata corrections are
+ * not written back, and the library database is not updated.
+ *
+ * @param schema The schema the exported books are organized with.
+ * @param summary The summary that collects the results.
+ */
+pub fn export(schema: &Schema, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    if cfg.filters.is_empty() && cfg.since.is_none() {
+        println!(
+            "{} No --filter is set, the whole library is exported",
+            "Warning:".yellow()
+        );
+    }
+    println!("{} '{}' to '{}'", "Exporting:".green(), cfg.from, cfg.to);
+
+    let plans = plan(schema, &cfg.sources, summary);
+    execute(plans, &cfg.sources, summary);
+}
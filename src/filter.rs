@@ -0,0 +1,191 @@
+use crate::metadata::Metadata;
+use crate::watch::parse_interval;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use regex::{Regex, RegexBuilder};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Represents a `--filter` matcher on a metadata field.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub field: String,
+    pub matcher: Matcher,
+}
+
+/// Represents how a metadata field is compared.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// `field=value`, the field equals the value, ignoring case.
+    Equals(String),
+    /// `field~=regex`, the field matches the regex, ignoring case.
+    Matches(Regex),
+}
+
+impl Filter {
+    /**
+     * Checks whether a book's metadata matches the filter.
+     *
+     * Numbers are compared by their text, so `book_number=4` works. Books without
+     * the field never match.
+     *
+     * @param metadata The parsed metadata of the book.
+     * @return True if the field matches.
+     */
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let value = match serde_json::to_value(metadata) {
+            Ok(Value::Object(fields)) => fields.get(&self.field).cloned(),
+            _ => None,
+        };
+        let text = match value {
+            Some(Value::String(s)) => s,
+            Some(Value::Number(n)) => n.to_string(),
+            Some(Value::Bool(b)) => b.to_string(),
+            _ => return false,
+        };
+
+        match &self.matcher {
+            Matcher::Equals(expected) => text.to_lowercase() == expected.to_lowercase(),
+            Matcher::Matches(regex) => regex.is_match(&text),
+        }
+    }
+}
+
+/**
+ * Parses a `--filter` argument, e.g. `author=Brandon Sanderson` or `title~=Mistborn`.
+ *
+ * @param filter The filter argument.
+ * @return The parsed filter, or an error message if the filter is not valid.
+ */
+pub fn parse_filter(filter: &str) -> Result<Filter, String> {
+    let (field, matcher) = if let Some((field, pattern)) = filter.split_once("~=") {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("invalid regex '{}': {}", pattern, e))?;
+        (field, Matcher::Matches(regex))
+    } else if let Some((field, value)) = filter.split_once('=') {
+        (field, Matcher::Equals(value.to_string()))
+    } else {
+        return Err("expected 'field=value' or 'field~=regex'".to_string());
+    };
+
+    let field = field.trim();
+    if field.is_empty() {
+        return Err("the field name is missing".to_string());
+    }
+
+    Ok(Filter {
+        field: field.to_string(),
+        matcher,
+    })
+}
+
+/**
+ * Parses a `--since` argument, either a local date (`2024-05-01`), date and time
+ * (`2024-05-01 18:30`, or RFC 3339), or a duration before now (`7d`, `12h`).
+ *
+ * @param since The `--since` argument.
+ * @return The point in time, or an error message if the argument is not valid.
+ */
+pub fn parse_since(since: &str) -> Result<SystemTime, String> {
+    let since = since.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(since) {
+        return Ok(time.into());
+    }
+    let local = NaiveDateTime::parse_from_str(since, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(since, "%Y-%m-%dT%H:%M"))
+        .ok()
+        .or_else(|| {
+            NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        });
+    if let Some(local) = local {
+        return local
+            .and_local_timezone(Local)
+            .earliest()
+            .map(SystemTime::from)
+            .ok_or_else(|| format!("'{}' is not a valid local time", since));
+    }
+
+    let duration = parse_interval(since)
+        .map_err(|_| format!("'{}' is not a date, time or duration (e.g. 7d)", since))?;
+    SystemTime::now()
+        .checked_sub(duration)
+        .ok_or_else(|| format!("'{}' is too far in the past", since))
+}
+
+/**
+ * Gets the time a book was last changed, which is the newest modification time of
+ * its metadata file and the other files in its directory.
+ *
+ * @param metadata_path The path of the book's metadata file.
+ * @return The modification time, or `None` if it could not be read.
+ */
+pub fn book_modified(metadata_path: &Path) -> Option<SystemTime> {
+    let modified = fs::metadata(metadata_path).and_then(|m| m.modified()).ok();
+    let files = metadata_path
+        .parent()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|m| m.is_file())
+        .filter_map(|m| m.modified().ok());
+
+    files.chain(modified).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matches() {
+        let metadata = Metadata {
+            author: Some("Brandon Sanderson".to_string()),
+            title: "The Alloy of Law".to_string(),
+            book_number: Some(4),
+            ..Default::default()
+        };
+
+        let matches = |filter: &str| parse_filter(filter).unwrap().matches(&metadata);
+        assert!(matches("author=brandon sanderson"));
+        assert!(!matches("author=Sanderson"));
+        assert!(matches("author~=Sanderson"));
+        assert!(matches("title~=^the alloy"));
+        assert!(!matches("title~=Mistborn"));
+        assert!(matches("book_number=4"));
+        assert!(!matches("series=Mistborn"));
+
+        assert!(parse_filter("author").is_err());
+        assert!(parse_filter("=Sanderson").is_err());
+        assert!(parse_filter("title~=(").is_err());
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let now = SystemTime::now();
+        let week = parse_since("7d").unwrap();
+        let elapsed = now.duration_since(week).unwrap().as_secs();
+        assert!((7 * 24 * 60 * 60 - 5..=7 * 24 * 60 * 60).contains(&elapsed));
+
+        let date = parse_since("2024-05-01").unwrap();
+        assert_eq!(
+            DateTime::<Local>::from(date)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            "2024-05-01 00:00"
+        );
+        let time = parse_since("2024-05-01 18:30").unwrap();
+        assert_eq!(
+            time.duration_since(date).unwrap().as_secs(),
+            (18 * 60 + 30) * 60
+        );
+        assert!(parse_since("2024-05-01T18:30:00Z").is_ok());
+
+        assert!(parse_since("last week").is_err());
+    }
+}
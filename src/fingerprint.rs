@@ -0,0 +1,182 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/// The length of a fingerprint frame, in frames per second.
+const FRAMES_PER_SECOND: usize = 10;
+/// How much of the start of a book is fingerprinted, in seconds.
+const LENGTH: usize = 180;
+/// How far two fingerprints may be shifted against each other, e.g. because one
+/// download has an extra intro, in frames.
+const MAX_SHIFT: usize = 30 * FRAMES_PER_SECOND;
+/// How many frames two fingerprints must overlap to be compared.
+const MIN_OVERLAP: usize = 60 * FRAMES_PER_SECOND;
+
+/// An acoustic fingerprint of the start of a book.
+///
+/// Every tenth of a second is reduced to whether it is louder than the previous
+/// one. The loudness envelope of speech survives re-encoding, so the same recording
+/// matches across file names, tags, formats and bitrates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    bits: Vec<bool>,
+}
+
+impl Fingerprint {
+    /**
+     * Builds a fingerprint from the loudness of each frame.
+     *
+     * @param energy The energy of each frame, in order.
+     * @return The fingerprint, or `None` if the audio is too short or too uniform
+     * (e.g. silence) to tell books apart.
+     */
+    fn from_energy(energy: &[f64]) -> Option<Fingerprint> {
+        let bits: Vec<bool> = energy.windows(2).map(|w| w[1] > w[0] * 1.05).collect();
+        let rising = bits.iter().filter(|&&bit| bit).count();
+        let varied = (bits.len() / 5..=bits.len() * 4 / 5).contains(&rising);
+        (bits.len() >= MIN_OVERLAP && varied).then_some(Fingerprint { bits })
+    }
+
+    /**
+     * Compares two fingerprints, trying every shift up to `MAX_SHIFT`.
+     *
+     * @param other The other fingerprint.
+     * @return The share of matching frames at the best shift, from 0 to 1. Unrelated
+     * recordings score about 0.5.
+     */
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let (a, b) = (&self.bits, &other.bits);
+        let mut best: f64 = 0.0;
+        for shift in 0..=MAX_SHIFT {
+            for (a, b) in [(a, b), (b, a)] {
+                let Some(a) = a.get(shift..) else {
+                    continue;
+                };
+                let overlap = a.len().min(b.len());
+                if overlap < MIN_OVERLAP {
+                    continue;
+                }
+                let matching = a.iter().zip(b.iter()).filter(|(x, y)| x == y).count();
+                best = best.max(matching as f64 / overlap as f64);
+            }
+        }
+        best
+    }
+}
+
+/**
+ * Fingerprints the start of an audio file.
+ *
+ * @param path The audio file.
+ * @return The fingerprint, `None` if the audio can't be told apart (e.g. silence or
+ * a very short file), or an error message if the file can't be decoded.
+ */
+pub fn fingerprint(path: &Path) -> Result<Option<Fingerprint>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|e| e.to_string())?;
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("no decodable audio track found")?;
+    let track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| e.to_string())?;
+
+    let mut energy = Vec::new();
+    let (mut frame_energy, mut frame_samples) = (0.0, 0);
+    let mut samples: Option<SampleBuffer<f32>> = None;
+    while energy.len() < LENGTH * FRAMES_PER_SECOND {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.to_string()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        let spec = *decoded.spec();
+        let channels = spec.channels.count().max(1);
+        let frame_len = (spec.rate as usize / FRAMES_PER_SECOND).max(1);
+        let buffer = match samples.as_mut() {
+            Some(buffer) if buffer.capacity() >= decoded.capacity() * channels => buffer,
+            _ => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+        };
+        buffer.copy_interleaved_ref(decoded);
+
+        for frame in buffer.samples().chunks(channels) {
+            let mono = frame.iter().sum::<f32>() as f64 / channels as f64;
+            frame_energy += mono * mono;
+            frame_samples += 1;
+            if frame_samples == frame_len {
+                energy.push(frame_energy);
+                (frame_energy, frame_samples) = (0.0, 0);
+            }
+        }
+    }
+
+    Ok(Fingerprint::from_energy(&energy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A deterministic pseudo-random loudness envelope.
+    fn envelope(seed: u64, len: usize) -> Vec<f64> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (state >> 33) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_similarity() {
+        let book = envelope(1, 1800);
+        let original = Fingerprint::from_energy(&book).unwrap();
+
+        // A quieter re-encode with a 5 second intro
+        let mut reencoded: Vec<f64> = envelope(7, 50);
+        reencoded.extend(book.iter().map(|e| e * 0.5));
+        let reencoded = Fingerprint::from_energy(&reencoded).unwrap();
+        assert!(original.similarity(&reencoded) > 0.95);
+        assert!(reencoded.similarity(&original) > 0.95);
+
+        let other = Fingerprint::from_energy(&envelope(2, 1800)).unwrap();
+        assert!(original.similarity(&other) < 0.7);
+
+        assert_eq!(Fingerprint::from_energy(&[0.0; 1800]), None);
+        assert_eq!(Fingerprint::from_energy(&envelope(1, 100)), None);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::CONFIG;
+use crate::checksum::{MANIFEST_NAME, read_manifest};
+use crate::database;
+use crate::summary::{Category, Summary};
+use colored::Colorize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+/**
+ * Re-hashes the files of an organized library and reports the files that are
+ * corrupted (their SHA-256 changed) or missing.
+ *
+ * The expected checksums are read from the `checksums.sha256` manifests in the
+ * library and from the library database, if one is set. When both list a file,
+ * the manifest wins.
+ *
+ * @param max_rate The maximum number of bytes read per second, or `None` to read
+ * as fast as possible.
+ * @param summary The summary that collects the results of the run.
+ */
+pub fn fsck(max_rate: Option<u64>, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let mut expected = BTreeMap::new();
+
+    if let Some(path) = &cfg.database {
+        match database::file_checksums(path, &cfg.from) {
+            Ok(files) => expected.extend(
+                files
+                    .into_iter()
+                    .map(|(file, hash)| (PathBuf::from(file), hash)),
+            ),
+            Err(err) => {
+                eprintln!("{} {}", "Error reading the library database:".red(), err);
+                exit(1)
+            }
+        }
+    }
+
+    for entry in WalkDir::new(&cfg.from).into_iter().filter_map(|e| e.ok()) {
+        if entry.file_name() != MANIFEST_NAME {
+            continue;
+        }
+        match read_manifest(entry.path()) {
+            Ok(files) => {
+                for (file, hash) in files {
+                    let file = std::path::absolute(&file).unwrap_or(file);
+                    expected.insert(file, hash);
+                }
+            }
+            Err(err) => summary.error(
+                Category::Metadata,
+                format!("'{}': {}", entry.path().display(), err),
+            ),
+        }
+    }
+
+    if expected.is_empty() {
+        eprintln!(
+            "{} '{}'",
+            "Error: No checksum manifests or database checksums found for".red(),
+            cfg.from.yellow()
+        );
+        exit(1)
+    }
+
+    println!(
+        "Checking {} files in '{}'...",
+        expected.len(),
+        cfg.from.green()
+    );
+
+    let mut throttle = Throttle::new(max_rate);
+    for (file, hash) in &expected {
+        print!("{} '{}'...", "Checking:".blue(), file.display());
+        if !file.exists() {
+            println!(" {}", "Missing".red());
+            summary.error(
+                Category::Verification,
+                format!("'{}': missing", file.display()),
+            );
+            continue;
+        }
+
+        match hash_file(file, cfg.buffer_size, &mut throttle) {
+            Ok((actual, bytes)) => {
+                summary.files += 1;
+                summary.bytes += bytes;
+                if &actual == hash {
+                    println!(" OK");
+                } else {
+                    println!(" {}", "Corrupted".red());
+                    summary.error(
+                        Category::Verification,
+                        format!(
+                            "'{}': corrupted, the checksum changed from {} to {}",
+                            file.display(),
+                            hash,
+                            actual
+                        ),
+                    );
+                }
+            }
+            Err(err) => {
+                println!(" {}", "Error".red());
+                summary.error(Category::Io, format!("'{}': {}", file.display(), err));
+            }
+        }
+    }
+}
+
+/// Limits how fast files are read, so a check doesn't starve other users of the disk.
+struct Throttle {
+    max_rate: Option<u64>,
+    started: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+    fn new(max_rate: Option<u64>) -> Self {
+        Throttle {
+            max_rate,
+            started: Instant::now(),
+            bytes: 0,
+        }
+    }
+
+    /**
+     * Records that bytes were read, and sleeps until reading them is within the rate.
+     *
+     * @param bytes The number of bytes read.
+     */
+    fn consume(&mut self, bytes: usize) {
+        let Some(max_rate) = self.max_rate else {
+            return;
+        };
+        self.bytes += bytes as u64;
+        let allowed = Duration::from_secs_f64(self.bytes as f64 / max_rate as f64);
+        if let Some(ahead) = allowed.checked_sub(self.started.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
+
+/**
+ * Computes the SHA-256 of a file, reading no faster than the throttle allows.
+ *
+ * @param path The file to hash.
+ * @param buffer_size The size of the read buffer.
+ * @param throttle The throttle shared by all files of the check.
+ * @return The hex encoded hash and the number of bytes read.
+ */
+fn hash_file(
+    path: &Path,
+    buffer_size: usize,
+    throttle: &mut Throttle,
+) -> io::Result<(String, u64)> {
+    let mut reader = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; buffer_size.max(4096)];
+    let mut bytes: u64 = 0;
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => {
+                hasher.update(&buffer[..read]);
+                bytes += read as u64;
+                throttle.consume(read);
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok((format!("{:x}", hasher.finalize()), bytes))
+}
+
+/**
+ * Parses a read rate like `500K`, `20M` or `1G` (bytes per second, in binary units).
+ *
+ * @param value The rate to parse. A number without a unit is in bytes.
+ * @return The rate in bytes per second, or an error message.
+ */
+pub fn parse_rate(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid rate", value))?;
+
+    let multiplier: u64 = match unit
+        .to_uppercase()
+        .trim_end_matches("/S")
+        .trim_end_matches('B')
+    {
+        "" => 1,
+        "K" | "KI" => 1 << 10,
+        "M" | "MI" => 1 << 20,
+        "G" | "GI" => 1 << 30,
+        _ => return Err(format!("unknown rate unit '{}', use K, M or G", unit)),
+    };
+    if number == 0 {
+        return Err(String::from("the rate must be greater than zero"));
+    }
+
+    Ok(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("512"), Ok(512));
+        assert_eq!(parse_rate("500K"), Ok(500 * 1024));
+        assert_eq!(parse_rate("20M"), Ok(20 * 1024 * 1024));
+        assert_eq!(parse_rate("20MiB/s"), Ok(20 * 1024 * 1024));
+        assert_eq!(parse_rate("1g"), Ok(1024 * 1024 * 1024));
+        assert!(parse_rate("0M").is_err());
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("10T").is_err());
+    }
+}
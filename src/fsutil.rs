@@ -0,0 +1,438 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Files and directories that macOS leaves behind, which never belong in the library.
+const JUNK_NAMES: [&str; 3] = [".DS_Store", ".AppleDouble", ".Spotlight-V100"];
+
+/// The extensions of files that are still being downloaded, by browsers
+/// (`.crdownload`, `.download`, `.part`) and torrent and download clients
+/// (qBittorrent `.!qB`, uTorrent `.!ut`, BitTorrent `.!bt`, BitComet `.bc!`,
+/// aria2 `.aria2`, `.partial`), in lowercase.
+const INCOMPLETE_EXTENSIONS: [&str; 9] = [
+    "part",
+    "partial",
+    "crdownload",
+    "download",
+    "!qb",
+    "!ut",
+    "!bt",
+    "bc!",
+    "aria2",
+];
+
+/**
+ * Finds the closest existing ancestor of a path (including the path itself).
+ *
+ * This is used for destination paths that have not been created yet.
+ *
+ * @param path The path to start from.
+ * @return The closest existing path, if any.
+ */
+pub fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .or_else(|| Some(Path::new(".")).filter(|_| path.is_relative()))
+}
+
+/**
+ * Checks whether two paths are on the same filesystem, so one can be renamed to
+ * the other instead of being copied.
+ *
+ * @param a The first path. It must exist.
+ * @param b The second path. It doesn't need to exist yet.
+ * @return True if both paths are on the same filesystem, or false if that can't
+ * be determined.
+ */
+#[cfg(unix)]
+pub fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let Some(b) = existing_ancestor(b) else {
+        return false;
+    };
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/**
+ * Gets the ID of the device holding a path, so work can be spread across drives.
+ *
+ * @param path The path. It doesn't need to exist yet.
+ * @return The device ID, or `None` if it can't be determined.
+ */
+#[cfg(unix)]
+pub fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(existing_ancestor(path)?).ok().map(|m| m.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/**
+ * Returns the number of bytes available to the current user on the filesystem
+ * holding the given path.
+ *
+ * @param path The path to check. It doesn't need to exist yet.
+ * @return The available space in bytes, or `None` if it can't be determined.
+ */
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = existing_ancestor(path)?;
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid nul terminated string and `stat` is a valid out pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/**
+ * Returns the maximum length of a file name, in bytes, on the filesystem holding
+ * the given path.
+ *
+ * @param path The path to check. It doesn't need to exist yet.
+ * @return The maximum length, or `None` if it can't be determined.
+ */
+#[cfg(unix)]
+pub fn max_name_length(path: &Path) -> Option<usize> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = existing_ancestor(path)?;
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid nul terminated string and `stat` is a valid out pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+
+    usize::try_from(stat.f_namemax).ok().filter(|&max| max > 0)
+}
+
+#[cfg(not(unix))]
+pub fn max_name_length(_path: &Path) -> Option<usize> {
+    None
+}
+
+/**
+ * Formats a number of bytes as a human readable size (e.g. "1.50 GiB").
+ *
+ * @param bytes The number of bytes.
+ * @return The formatted size.
+ */
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", size, UNITS[unit])
+    }
+}
+
+/**
+ * Checks whether a file or directory is junk left behind by macOS: Finder and
+ * Spotlight data, and AppleDouble `._*` files holding the extended attributes of
+ * files on filesystems without native support for them.
+ *
+ * @param path The file or directory to check.
+ * @return True if the path is junk.
+ */
+pub fn is_junk(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| JUNK_NAMES.contains(&name) || name.starts_with("._"))
+}
+
+/**
+ * Finds a file in a directory or its sub-directories that is still being
+ * downloaded, e.g. `Chapter 3.mp3.part` or `Chapter 3.mp3.!qB`.
+ *
+ * @param dir The directory to search.
+ * @return The first incomplete file, if the directory has any.
+ */
+pub fn find_incomplete_download(dir: &Path) -> Option<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .find(|path| {
+            path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| INCOMPLETE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+}
+
+/**
+ * Gets the time a directory, or anything in it, was last modified.
+ *
+ * @param dir The directory to check, including its sub-directories.
+ * @return The latest modification time, or `None` if the directory can't be read.
+ */
+pub fn last_modified(dir: &Path) -> Option<SystemTime> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+}
+
+/**
+ * Checks whether a file or directory is hidden, i.e. its name starts with a dot
+ * (e.g. `.stfolder` or `.unwanted`).
+ *
+ * @param path The file or directory to check.
+ * @return True if the path is hidden.
+ */
+pub fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.') && name != "." && name != "..")
+}
+
+/**
+ * Checks whether a directory is reached through a symbolic link below a source
+ * root, so deleting it would delete the data the link points to.
+ *
+ * @param path The directory to check.
+ * @param root The source root the directory was found in, or an empty path. The
+ *        root itself may be a link. Without a root, or for a path outside of it,
+ *        the directory and all of its parents are checked.
+ * @return True if the directory or one of its parents below the root is a link.
+ */
+pub fn through_symlink(path: &Path, root: &Path) -> bool {
+    if root.as_os_str().is_empty() || !path.starts_with(root) {
+        // The resolved path only differs if a link was followed on the way
+        return match (fs::canonicalize(path), std::path::absolute(path)) {
+            (Ok(real), Ok(absolute)) => real != absolute,
+            _ => false,
+        };
+    }
+    let is_link = |p: &Path| fs::symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink());
+    path.ancestors().take_while(|p| *p != root).any(is_link)
+}
+
+/**
+ * Removes the junk files and directories directly inside a directory, so it can be
+ * deleted once the book has been moved out of it.
+ *
+ * Hidden entries (e.g. `.stfolder` or `.git`) are only removed if nothing else is
+ * left in the directory, so a directory that still holds other books keeps them.
+ *
+ * @param dir The directory to clean up.
+ * @param hidden If true, hidden files and directories are removed too.
+ */
+pub fn remove_junk(dir: &Path, hidden: bool) {
+    let entries: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|entry| entry.path())
+        .collect();
+    let hidden = hidden && entries.iter().all(|path| is_junk(path) || is_hidden(path));
+    for path in entries
+        .iter()
+        .filter(|path| is_junk(path) || (hidden && is_hidden(path)))
+    {
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap_or(());
+        } else {
+            fs::remove_file(path).unwrap_or(());
+        }
+    }
+}
+
+/**
+ * Copies the extended attributes of a file, such as Finder tags and comments, to
+ * another file. Destinations that don't support extended attributes are skipped.
+ *
+ * @param from The file to copy the attributes from.
+ * @param to The file to copy the attributes to.
+ */
+#[cfg(target_os = "macos")]
+pub fn copy_xattrs(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+
+    // SAFETY: The paths are valid nul terminated strings, and every buffer is at least
+    // as large as the size passed with it.
+    let size = unsafe { libc::listxattr(from.as_ptr(), ptr::null_mut(), 0, libc::XATTR_NOFOLLOW) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut names = vec![0u8; size as usize];
+    let size = unsafe {
+        libc::listxattr(
+            from.as_ptr(),
+            names.as_mut_ptr().cast(),
+            names.len(),
+            libc::XATTR_NOFOLLOW,
+        )
+    };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    names.truncate(size as usize);
+
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name)?;
+        let len = unsafe {
+            libc::getxattr(
+                from.as_ptr(),
+                name.as_ptr(),
+                ptr::null_mut(),
+                0,
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut value = vec![0u8; len as usize];
+        let len = unsafe {
+            libc::getxattr(
+                from.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let result = unsafe {
+            libc::setxattr(
+                to.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                len as usize,
+                0,
+                libc::XATTR_NOFOLLOW,
+            )
+        };
+        if result != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOTSUP) {
+                return Ok(());
+            }
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn copy_xattrs(_from: &Path, _to: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_junk() {
+        assert!(is_junk(Path::new("book/.DS_Store")));
+        assert!(is_junk(Path::new("book/._Skyward.m4b")));
+        assert!(is_junk(Path::new("book/.AppleDouble")));
+        assert!(is_junk(Path::new("/Volumes/Library/.Spotlight-V100")));
+        assert!(!is_junk(Path::new("book/Skyward.m4b")));
+        assert!(!is_junk(Path::new("book/.hidden.m4b")));
+        assert!(is_hidden(Path::new("book/.hidden.m4b")));
+        assert!(is_hidden(Path::new("/library/.stfolder")));
+        assert!(!is_hidden(Path::new("book/Skyward.m4b")));
+        assert!(!is_hidden(Path::new("..")));
+    }
+
+    #[test]
+    fn test_find_incomplete_download() {
+        let dir =
+            std::env::temp_dir().join(format!("aborg-incomplete-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("CD2")).unwrap();
+        fs::write(dir.join("Chapter 1.mp3"), "").unwrap();
+        assert_eq!(find_incomplete_download(&dir), None);
+
+        fs::write(dir.join("CD2").join("Chapter 2.mp3.!qB"), "").unwrap();
+        assert_eq!(
+            find_incomplete_download(&dir),
+            Some(dir.join("CD2").join("Chapter 2.mp3.!qB"))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_junk_keeps_hidden_next_to_books() {
+        let dir = std::env::temp_dir().join(format!("aborg-junk-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".stfolder")).unwrap();
+        fs::create_dir_all(dir.join("Other Book")).unwrap();
+        fs::write(dir.join(".DS_Store"), "").unwrap();
+
+        remove_junk(&dir, true);
+        assert!(!dir.join(".DS_Store").exists());
+        assert!(dir.join(".stfolder").exists());
+
+        fs::remove_dir(dir.join("Other Book")).unwrap();
+        remove_junk(&dir, true);
+        assert!(!dir.join(".stfolder").exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_through_symlink() {
+        let dir = std::env::temp_dir().join(format!("aborg-symlink-test-{}", std::process::id()));
+        let (source, snapshot) = (dir.join("source"), dir.join("snapshot"));
+        fs::create_dir_all(snapshot.join("Dune")).unwrap();
+        fs::create_dir_all(source.join("Skyward")).unwrap();
+        std::os::unix::fs::symlink(&snapshot, source.join("link")).unwrap();
+
+        assert!(!through_symlink(&source.join("Skyward"), &source));
+        assert!(through_symlink(&source.join("link/Dune"), &source));
+        assert!(through_symlink(&source.join("link"), Path::new("")));
+        // Without a root, a link among the parents is found too
+        assert!(through_symlink(&source.join("link/Dune"), Path::new("")));
+        assert!(!through_symlink(&source.join("Skyward"), Path::new("")));
+        assert!(!through_symlink(
+            &source.join("link/Dune"),
+            &source.join("link")
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use crate::sniff;
+use lofty::error::ErrorKind as LoftyErrorKind;
+use lofty::file::AudioFile;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::Path;
+use symphonia::core::codecs::{CODEC_TYPE_NULL, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+/**
+ * Checks that an audio file is readable and not truncated or corrupt.
+ *
+ * The quick check reads the file headers and makes sure an audio stream with a
+ * duration is present. The deep check additionally decodes every packet of the
+ * file. Formats that can not be read by the checker are assumed to be fine.
+ *
+ * @param path The audio file to check.
+ * @param deep If true, fully decode the file instead of only reading the headers.
+ * @return `Ok(())` if the file looks fine, or an error describing the problem.
+ */
+pub fn check_audio(path: &Path, deep: bool) -> Result<(), String> {
+    match sniff::open(path).and_then(|p| p.read()) {
+        Ok(tagged_file) => {
+            if tagged_file.properties().duration().is_zero() {
+                return Err("no audio stream found".to_string());
+            }
+        }
+        Err(err) if matches!(err.kind(), LoftyErrorKind::UnknownFormat) => return Ok(()),
+        Err(err) => return Err(err.to_string()),
+    }
+
+    if deep {
+        decode_all(path)?;
+    }
+
+    Ok(())
+}
+
+/**
+ * Decodes every packet of the first audio track in a file.
+ *
+ * @param path The audio file to decode.
+ * @return `Ok(())` if the whole file decoded, or an error describing the problem.
+ */
+fn decode_all(path: &Path) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = match symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed,
+        Err(Error::Unsupported(_)) => return Ok(()),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or("no decodable audio track found")?;
+    let track_id = track.id;
+    let expected_frames = track.codec_params.n_frames;
+
+    let mut decoder = match symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+    {
+        Ok(decoder) => decoder,
+        Err(Error::Unsupported(_)) => return Ok(()),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let mut decoded_frames: u64 = 0;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.to_string()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(buffer) => decoded_frames += buffer.frames() as u64,
+            Err(err) => return Err(format!("decode error: {}", err)),
+        }
+    }
+
+    // Allow a small difference for encoder delay and padding
+    if let Some(expected) = expected_frames
+        && decoded_frames < expected.saturating_sub(expected / 100)
+    {
+        return Err(format!(
+            "truncated, decoded {} of {} frames",
+            decoded_frames, expected
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,111 @@
+/**
+ * Validates an ISBN and normalizes it to an ISBN-13 without hyphens, so the same
+ * book always has the same ISBN, e.g. `0-7653-2635-3` becomes `9780765326355`.
+ *
+ * Spaces, hyphens and an `ISBN` prefix are ignored.
+ *
+ * @param isbn The ISBN-10 or ISBN-13.
+ * @return The ISBN-13, or an error message if the ISBN is not valid.
+ */
+pub fn normalize(isbn: &str) -> Result<String, String> {
+    let mut rest = isbn.trim();
+    if rest
+        .get(..4)
+        .is_some_and(|p| p.eq_ignore_ascii_case("isbn"))
+    {
+        rest = &rest[4..];
+        rest = rest
+            .strip_prefix("-13")
+            .or_else(|| rest.strip_prefix("-10"))
+            .unwrap_or(rest);
+        rest = rest.trim_start_matches([':', ' ']);
+    }
+    let digits: String = rest
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if !digits.is_ascii() {
+        return Err(format!("'{}' is not an ISBN", isbn));
+    }
+
+    match digits.len() {
+        10 => {
+            let valid = digits[..9].chars().all(|c| c.is_ascii_digit())
+                && isbn10_check_digit(&digits[..9]) == digits.chars().last();
+            if !valid {
+                return Err(format!("'{}' is not a valid ISBN-10", isbn));
+            }
+            let isbn13 = format!("978{}", &digits[..9]);
+            let check = isbn13_check_digit(&isbn13);
+            Ok(format!("{}{}", isbn13, check))
+        }
+        13 => {
+            let valid = digits.chars().all(|c| c.is_ascii_digit())
+                && (digits.starts_with("978") || digits.starts_with("979"))
+                && digits.ends_with(isbn13_check_digit(&digits[..12]));
+            if !valid {
+                return Err(format!("'{}' is not a valid ISBN-13", isbn));
+            }
+            Ok(digits)
+        }
+        _ => Err(format!("'{}' is not an ISBN", isbn)),
+    }
+}
+
+/**
+ * Calculates the check digit of an ISBN-10 from its first 9 digits.
+ */
+fn isbn10_check_digit(digits: &str) -> Option<char> {
+    let sum: u32 = digits
+        .chars()
+        .zip((2..=10).rev())
+        .map(|(c, weight)| c.to_digit(10).unwrap_or(0) * weight)
+        .sum();
+    match (11 - sum % 11) % 11 {
+        10 => Some('X'),
+        check => char::from_digit(check, 10),
+    }
+}
+
+/**
+ * Calculates the check digit of an ISBN-13 from its first 12 digits.
+ */
+fn isbn13_check_digit(digits: &str) -> char {
+    let sum: u32 = digits
+        .chars()
+        .zip([1, 3].into_iter().cycle())
+        .map(|(c, weight)| c.to_digit(10).unwrap_or(0) * weight)
+        .sum();
+    char::from_digit((10 - sum % 10) % 10, 10).unwrap_or('0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("0-7653-2635-3"), Ok("9780765326355".to_string()));
+        assert_eq!(
+            normalize("978-0-7653-2635-5"),
+            Ok("9780765326355".to_string())
+        );
+        assert_eq!(
+            normalize("ISBN 0 7653 2635 3"),
+            Ok("9780765326355".to_string())
+        );
+        assert_eq!(
+            normalize("ISBN-13: 978-0-7653-2635-5"),
+            Ok("9780765326355".to_string())
+        );
+        assert_eq!(normalize("080442957X"), Ok("9780804429573".to_string()));
+        assert_eq!(normalize("080442957x"), Ok("9780804429573".to_string()));
+
+        assert!(normalize("0-7653-2635-4").is_err());
+        assert!(normalize("978-0-7653-2635-1").is_err());
+        assert!(normalize("123-4-5678-9012-8").is_err());
+        assert!(normalize("B0036I54I6").is_err());
+        assert!(normalize("12345").is_err());
+    }
+}
@@ -0,0 +1,4105 @@
+mod audit;
+mod authors;
+mod cache;
+mod checksum;
+mod color;
+mod completeness;
+mod config;
+mod conflict;
+mod copy;
+mod database;
+mod diff;
+mod doctor;
+mod dupes;
+mod export;
+mod filter;
+mod fingerprint;
+mod fsck;
+mod fsutil;
+mod integrity;
+mod isbn;
+mod link;
+mod mapping;
+mod metadata;
+mod numbers;
+mod one;
+mod overrides;
+mod parts;
+mod pipeline;
+mod pool;
+mod progress;
+mod provider;
+mod prune;
+mod quality;
+mod query;
+mod redo;
+mod reload;
+mod report;
+mod retag;
+mod retry;
+mod route;
+mod runs;
+mod sample;
+mod schema;
+mod script;
+mod seed;
+mod serve;
+mod shutdown;
+mod snapshot;
+mod sniff;
+mod summary;
+mod sync;
+mod track;
+mod watch;
+mod writeback;
+
+use cache::ResponseCache;
+use chrono::NaiveTime;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use color::ColorMode;
+use colored::Colorize;
+pub use completeness::Completeness;
+use config::{FileConfig, RunsConfig, load_config, read_config};
+use conflict::{CollisionIndex, ConflictPolicy, MergeDecision, MergeStrategy, merge_decision};
+use database::{BookQuery, Database};
+use filter::Filter;
+use fsutil::format_size;
+pub use metadata::Metadata;
+use metadata::{build_metadata, compile_series_patterns, series_alias_map};
+use overrides::{BookOverrides, OVERRIDE_FILE};
+use pool::{Placement, Pool};
+use provider::{
+    DEFAULT_PROVIDERS, MetadataProvider, build_providers, is_book_metafile, is_provider_file,
+    read_book,
+};
+use quality::{BookQuality, DuplicatePolicy};
+use query::QueryFormat;
+use regex::Regex;
+use reload::{Reloaded, Reloader};
+use retag::retag;
+use retry::{RetryPolicy, retry};
+use route::{Route, compile_routes};
+use schema::{DEFAULT_FILE_SCHEMA, DEFAULT_PATH_SCHEMA, Lenient, Preset, Schema, read_schema_arg};
+use script::{NamingScript, ScriptBooks};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use summary::{Category, Operation, Summary};
+use track::{compile_track_keywords, explain_track_number};
+use walkdir::WalkDir;
+use watch::Schedule;
+pub use writeback::WriteBack;
+
+// TODO:
+// - Add a "results" output at the end that prints total files touched, etc, also have it output a list of any errors
+// - Fix the bug where the source dir is not being deleted when empty
+// - Add chapter filtering from file name
+
+static CONFIG: ConfigCell = ConfigCell::new();
+
+/// The extension of the temporary name a file gets while files swap their names.
+const TEMPORARY_EXTENSION: &str = "aborg-rename";
+
+/// The extension of the name an existing book gets while a replacement is organized.
+const REPLACED_EXTENSION: &str = "aborg-replaced";
+
+/// The exit status of a dry run that found render failures, collisions or duplicate
+/// books, so a pre-check can keep the real run from starting.
+const DRY_RUN_PROBLEMS_STATUS: i32 = 3;
+
+/// Serializes the tests that set the global config.
+#[cfg(test)]
+static TEST_CONFIG: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Holds the global config.
+///
+/// Watch mode replaces the config when the config file changes. The replaced
+/// configs are never freed, so references to them stay valid for the rest of the
+/// process (reloads are rare, so this only costs a few KiB).
+struct ConfigCell(RwLock<Option<&'static Config>>);
+
+impl ConfigCell {
+    const fn new() -> Self {
+        ConfigCell(RwLock::new(None))
+    }
+
+    /**
+     * Gets the current config, or `None` if it was not set yet.
+     */
+    fn get(&self) -> Option<&'static Config> {
+        *self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /**
+     * Sets the config, unless it was already set.
+     *
+     * @param config The config.
+     * @return The config back, if a config was already set.
+     */
+    fn set(&self, config: Config) -> Result<(), Box<Config>> {
+        let config = Box::new(config);
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        if current.is_some() {
+            return Err(config);
+        }
+        *current = Some(Box::leak(config));
+        Ok(())
+    }
+
+    /**
+     * Replaces the config. Only the code that runs after this sees the new config.
+     *
+     * @param config The new config.
+     */
+    fn replace(&self, config: Config) {
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        *current = Some(Box::leak(Box::new(config)));
+    }
+
+    /**
+     * Swaps the config, so it can be restored later.
+     *
+     * @param config The new config, or `None` to unset it.
+     * @return The previous config.
+     */
+    fn swap(&self, config: Option<&'static Config>) -> Option<&'static Config> {
+        let mut current = self.0.write().unwrap_or_else(|e| e.into_inner());
+        std::mem::replace(&mut *current, config)
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
+/// Represents the command-line arguments for the application.
+///
+/// This struct is used to parse and store the arguments provided by the user,
+/// including source and destination directories, schemas, and other options.
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The directory containing the audiobook files you want to manage.
+    /// This is the source directory for the operation. Can be repeated to import
+    /// from several directories in one run. Use `-` to read the book directories
+    /// from stdin instead, see `--from-list`.
+    #[arg(short, long, required_unless_present_any = ["from_list", "redo"])]
+    source: Vec<String>,
+
+    /// Only process the book directories listed in this file, one per line, instead
+    /// of searching the whole source directory. Empty lines and lines starting with
+    /// `#` are ignored. Use `-` to read the list from stdin.
+    #[arg(long, value_name = "FILE")]
+    from_list: Option<String>,
+
+    /// The directory` where the managed files will be moved.
+    /// This is the destination directory for the operation. Can be repeated to
+    /// spread the library across several drives, see `--placement`.
+    #[arg(short, long, required_unless_present_any = ["rename_only", "redo"])]
+    destination: Vec<String>,
+
+    /// Rename the files of each book where they are, using the file schema, instead
+    /// of moving the books into a destination. No directories are created or moved.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["destination", "action", "merge_parts"])]
+    rename_only: bool,
+
+    /// How books are assigned to the destination roots when several `--destination`
+    /// roots are given. Books that already exist on a root stay there.
+    #[arg(long, value_enum, default_value_t = Placement::MostFreeSpace)]
+    placement: Placement,
+
+    /// The schema used to format the newly created destination directories.
+    /// This uses the Handlebar schema style. Use `@FILE` to read it from a file.
+    #[arg(short, long, default_value_t = String::from(DEFAULT_PATH_SCHEMA))]
+    path_schema: String,
+
+    /// Use a built-in directory layout instead of `--path-schema`. The `editions`
+    /// layout adds the narrator to the book directory, e.g. `The Way of Kings [Michael Kramer]`.
+    /// The `language` layout puts each language in its own library, e.g. `de/` and `en/`.
+    #[arg(long, value_enum, conflicts_with = "path_schema")]
+    preset: Option<Preset>,
+
+    /// Render missing fields as empty instead of skipping the book, and clean up the
+    /// dangling separators they leave behind (e.g. ` - ` or `()`). Applies to the
+    /// path schema, the file schema, or both (the default).
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "all")]
+    lenient: Option<Lenient>,
+
+    /// The schema used to format the files that are being moved.
+    /// This uses the Handlebar schema style. Use `@FILE` to read it from a file.
+    #[arg(short, long, default_value_t = String::from(DEFAULT_FILE_SCHEMA))]
+    file_schema: String,
+
+    /// Lowercase the extensions of renamed files, e.g. `.MP3` to `.mp3`.
+    #[arg(long, default_value_t = false)]
+    lowercase_extensions: bool,
+
+    /// Rename an extension when renaming files, e.g. `m4a=m4b` so audiobooks are
+    /// recognized as such. Can be repeated.
+    #[arg(long, value_name = "FROM=TO", value_parser = schema::parse_extension_alias)]
+    extension_alias: Vec<(String, String)>,
+
+    /// If set to true, the process will only display the actions that would be performed
+    /// without actually renaming, moving, or deleting any files.
+    #[arg(long, default_value_t = false, global = true)]
+    dry_run: bool,
+
+    /// Specifies the action option:
+    /// 0 = Copy files only.
+    /// 1 = Moves the files, keep directory.
+    /// 2 = Moves the files and deletes the directory.
+    /// 3 = Symlinks the files into the destination, keep the source.
+    /// 4 = Hardlinks the files into the destination, keep the source.
+    #[arg(long, default_value_t = 0)]
+    action: u8,
+
+    /// The name of the metadata file to look for in each directory.
+    /// Defaults to 'metadata.json'.
+    #[arg(long, default_value_t = String::from("metadata.json"), global = true)]
+    metafile: String,
+
+    /// A comma-separated list of audio file extensions to process.
+    /// Defaults to common audiobook formats.
+    #[arg(long, default_value_t = String::from("m4b,m4a,m4p,mp3,aa,aax,aac,ogg,wma,wav,flac,alac"), global = true)]
+    file_types: String,
+
+    /// Path to a TOML config file with additional settings, such as the
+    /// ordered `series_patterns` used to split series names and book numbers.
+    #[arg(short, long, global = true)]
+    config: Option<String>,
+
+    /// Print more details, e.g. where a metadata field came from when it isn't in
+    /// the metadata file.
+    #[arg(short, long, default_value_t = false, global = true)]
+    verbose: bool,
+
+    /// Print, for every audio file, where its file number came from (the track tag
+    /// or the file name rule that matched) and which numbers were ignored.
+    #[arg(long, default_value_t = false)]
+    explain_numbers: bool,
+
+    /// Detect the format of files with a wrong or missing extension (e.g. `.tmp`)
+    /// from their content, so audio files are recognized and get their canonical
+    /// extension (e.g. `.mp3` or `.m4b`) in the destination.
+    #[arg(long, default_value_t = false)]
+    detect_types: bool,
+
+    /// Check each book's audio files for truncation or corruption before they are
+    /// moved into the library. Books with bad files are skipped and listed in the summary.
+    #[arg(long, default_value_t = false)]
+    check_audio: bool,
+
+    /// Used with `--check-audio` to fully decode every audio file instead of only
+    /// reading the file headers. This is much slower but catches more problems.
+    #[arg(long, default_value_t = false, requires = "check_audio")]
+    deep: bool,
+
+    /// Directory that books failing `--check-audio` are moved into. If not set,
+    /// failing books are left where they are.
+    #[arg(long, requires = "check_audio")]
+    quarantine: Option<String>,
+
+    /// Verify every copied file by comparing the SHA-256 computed while copying
+    /// with the SHA-256 of the written destination file.
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+
+    /// With `--action 2`, keep a book's source directory when any of its operations
+    /// failed. Audio files without a verified copy in the destination always keep
+    /// the source directory.
+    #[arg(long, default_value_t = true, action = ArgAction::Set)]
+    keep_source_on_error: bool,
+
+    /// Skip hidden files and directories (e.g. `.sync`, `.stfolder` or `.unwanted`),
+    /// so they are not copied into the library. Hidden files left in an emptied
+    /// source directory are removed with it.
+    #[arg(long, default_value_t = true, action = ArgAction::Set, global = true)]
+    skip_hidden: bool,
+
+    /// Follow symbolic links to directories while searching the source, e.g. book
+    /// directories that link into a snapshot. Link loops are reported and skipped.
+    /// Directories reached through a link are never deleted by `--action 2`.
+    #[arg(long, default_value_t = false, global = true)]
+    follow_symlinks: bool,
+
+    /// The number of books processed at the same time on each destination drive.
+    /// Books on different drives are always processed side by side when this is
+    /// more than 1. Also the number of books whose metadata is read at the same time.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..), global = true)]
+    jobs: u16,
+
+    /// The number of books processed at the same time on each drive, counting the
+    /// drives books are read from as well as the drives they are written to, so two
+    /// books on the same spinning disk are never copied at once. `--jobs` is then the
+    /// number of books processed at the same time in total.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs_per_device: Option<u16>,
+
+    /// What to do when a destination file already exists, or when two files would
+    /// end up at the same destination. Paths that only differ by case are treated as
+    /// the same path, since they are on case-insensitive filesystems.
+    #[arg(long, value_enum, default_value_t = ConflictPolicy::Overwrite)]
+    on_conflict: ConflictPolicy,
+
+    /// The suffix added to the incoming file name when `--on-conflict rename` is used.
+    /// `{n}` is replaced by the first version number that doesn't conflict.
+    #[arg(long, default_value_t = String::from(" ({n})"))]
+    conflict_suffix: String,
+
+    /// How incoming files are combined with a book that already exists in the
+    /// destination. If not set, existing files are handled by `--on-conflict`.
+    #[arg(long, value_enum)]
+    merge_strategy: Option<MergeStrategy>,
+
+    /// What to do when an incoming book already exists in the destination. With
+    /// `keep-better`, the bitrate, codec and size of both editions are compared and
+    /// only the better one is kept. The decision is listed in the summary.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        conflicts_with = "merge_strategy"
+    )]
+    on_duplicate: Option<DuplicatePolicy>,
+
+    /// What is done with the non-audio files of a book, such as PDFs, covers, cue
+    /// sheets and text files: `copy` them with the book (using the action), `skip`
+    /// them, or `list` them in the summary without moving them. Metadata files
+    /// always travel with the book.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = OtherFiles::Copy)]
+    other_files: OtherFiles,
+
+    /// What is done with books that are still downloading, i.e. have `.part`, `.!qB`,
+    /// `.crdownload` or similar files: `defer` them to a later run, `skip` them and
+    /// report them with the skipped books, or `import` them anyway. In watch mode,
+    /// deferred and skipped books are checked again every batch.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = IncompleteDownloads::Defer)]
+    incomplete: IncompleteDownloads,
+
+    /// Leave out books whose files were modified within the last N seconds, so a
+    /// book a downloader is still writing is not imported half. They are handled
+    /// like incomplete downloads, see `--incomplete`.
+    #[arg(long, value_name = "SECONDS")]
+    settle: Option<u64>,
+
+    /// The order the books are processed in. Books are always ordered by author,
+    /// series, book number and title, so a partial run leaves a predictable part of
+    /// the library done. `largest-first` and `smallest-first` order them by size
+    /// first.
+    #[arg(long, value_enum, default_value_t = BookOrder::Library)]
+    order: BookOrder,
+
+    /// Merge books that were released as several directories (e.g. `The Stand Part 1`
+    /// and `The Stand Part 2`) into a single book, with continuous file numbering.
+    /// Without it, such directories are only reported.
+    #[arg(long, default_value_t = false)]
+    merge_parts: bool,
+
+    /// The buffer size, in KiB, used when copying and hashing files.
+    #[arg(long, default_value_t = 1024)]
+    buffer_size: usize,
+
+    /// Append a timestamped line for every operation (with the paths before and
+    /// after, and the outcome) to this file. Can also be set in the config file.
+    #[arg(long, global = true)]
+    audit_log: Option<String>,
+
+    /// Record every organized book (identity, final path, metadata and file
+    /// checksums) in this SQLite database. Can also be set in the config file.
+    #[arg(long, global = true)]
+    database: Option<String>,
+
+    /// Run this Rhai script for every book to change its destination directory.
+    /// Can also be set in the config file.
+    #[arg(long, global = true)]
+    naming_script: Option<String>,
+
+    /// Write a self-contained HTML report of the run, with the summary, a sortable
+    /// table of all file operations and the errors highlighted, to this file.
+    #[arg(long)]
+    report: Option<String>,
+
+    /// Write a CSV file that maps the original path of every moved, copied or linked
+    /// file to its new path, so other tools can update their references.
+    #[arg(long, value_name = "FILE")]
+    mapping: Option<String>,
+
+    /// Verify or execute the plans of a past run again, instead of planning a new run.
+    /// Books whose files are all in place are reported as done, the missing files of
+    /// the other books are copied or moved again. Requires `--database`, which records
+    /// the plans and ID of every run.
+    #[arg(long, value_name = "RUN_ID")]
+    redo: Option<i64>,
+
+    /// Before anything is changed, write a JSON record of the source directories of
+    /// the planned books, with the path and size of every file (and its SHA-256 with
+    /// `--verify`), to this file.
+    #[arg(long, value_name = "FILE")]
+    snapshot: Option<String>,
+
+    /// Write structured progress events, one JSON object per line, to this file or
+    /// stream (e.g. `/dev/fd/3`), so wrappers can show live progress.
+    #[arg(long, global = true)]
+    progress_json: Option<String>,
+
+    /// When to color the output. `auto` colors the output only when it is written
+    /// to a terminal and the `NO_COLOR` environment variable isn't set.
+    #[arg(long, value_enum, default_value_t = ColorMode::Auto, global = true)]
+    color: ColorMode,
+
+    /// Append the original path and library path of every imported file to this
+    /// manifest, so `restore-seed` can rebuild the original structure later.
+    #[arg(long, global = true)]
+    seed_manifest: Option<String>,
+
+    /// Write the book description to a text file in each book's directory. The
+    /// file name is a template, and defaults to `desc.txt` (e.g. `{{title}}.txt`).
+    #[arg(long, num_args = 0..=1, default_missing_value = "desc.txt", global = true)]
+    write_description: Option<String>,
+
+    /// Write an `.m3u8` playlist of the audio files, in file number order, into each
+    /// book's directory. The file name is a template, and defaults to `{{title}}.m3u8`.
+    #[arg(long, num_args = 0..=1, default_missing_value = "{{title}}.m3u8", global = true)]
+    write_playlist: Option<String>,
+
+    /// Write the series and book number back to the source `metadata.json` when
+    /// they were found elsewhere, e.g. in the folder name or the audio tags. The
+    /// original file is kept as `metadata.json.bak`.
+    #[arg(long, default_value_t = false)]
+    write_back: bool,
+
+    /// Register every `.hbs` file in this directory as a partial, used in schemas
+    /// as `{{> name}}`. Can also be set in the config file.
+    #[arg(long, global = true)]
+    templates: Option<String>,
+
+    /// Only process the first N books, e.g. for a cautious first run.
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Don't ask for confirmation before `--action 2` deletes the source directories,
+    /// or `merge-authors --similar` merges author directories.
+    #[arg(long, default_value_t = false)]
+    yes: bool,
+
+    /// Only process books whose metadata matches, e.g. `author=Brandon Sanderson`
+    /// (equal, ignoring case) or `title~=Mistborn` (regex, ignoring case). Can be
+    /// repeated, and a book must match all filters.
+    #[arg(long, value_parser = filter::parse_filter, global = true)]
+    filter: Vec<Filter>,
+
+    /// Only process books whose metadata file (or newest file) was modified after
+    /// this local date or time (`2024-05-01`, `2024-05-01 18:30`), or within this
+    /// duration (e.g. `7d` or `12h`).
+    #[arg(long, value_parser = filter::parse_since, global = true)]
+    since: Option<SystemTime>,
+
+    /// Write a `checksums.sha256` manifest of each book's files into its directory,
+    /// so the library can later be checked for bit rot with `sha256sum -c`. Copied
+    /// files are hashed while they are copied.
+    #[arg(long, default_value_t = false, global = true)]
+    checksums: bool,
+}
+
+/// Represents the optional subcommands. Without a subcommand, aborg organizes the
+/// `--source` directory into the `--destination` directory.
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Updates the embedded tags (album, artist, track number, series, etc.) of an
+    /// already organized library from its metadata files, without moving anything.
+    Retag {
+        /// The organized library directory to retag.
+        library: String,
+
+        /// Also write the series and book number into the movement tags (MP4
+        /// `©mvn`/`©mvi`, ID3 `MVNM`/`MVIN`) used by Apple Books.
+        #[arg(long, default_value_t = false)]
+        movement: bool,
+    },
+
+    /// Makes a mirror directory structurally match an organized library, copying
+    /// new and changed files. Useful for keeping a second copy of the library.
+    Sync {
+        /// The organized library to mirror.
+        library: String,
+
+        /// The mirror directory.
+        mirror: String,
+
+        /// Also delete files from the mirror whose source was removed. The deletions
+        /// are always listed first and must be confirmed.
+        #[arg(long, default_value_t = false)]
+        delete: bool,
+
+        /// Don't ask for confirmation before deleting files from the mirror.
+        #[arg(long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Answers questions about the organized library from the library database
+    /// (`--database`), printing the matching books.
+    Query {
+        /// Only list books whose author contains this text.
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Only list books whose series contains this text.
+        #[arg(long)]
+        series: Option<String>,
+
+        /// Only list books whose title contains this text.
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Only list books that are missing this metadata field (e.g. `book_number`).
+        #[arg(long)]
+        missing: Option<String>,
+
+        /// The output format.
+        #[arg(long, value_enum, default_value_t = QueryFormat::Paths)]
+        format: QueryFormat,
+    },
+
+    /// Runs an HTTP server that plans submitted source directories into the
+    /// destination, and executes the plans once they are approved.
+    Serve {
+        /// The directory that approved plans are organized into.
+        destination: String,
+
+        /// The address to listen on. Requests for another host are rejected.
+        #[arg(long, default_value_t = String::from("127.0.0.1:8080"))]
+        listen: String,
+
+        /// The token that API requests must send in the `X-Aborg-Token` header. A
+        /// random token is generated and printed if not set.
+        #[arg(long)]
+        token: Option<String>,
+    },
+
+    /// Keeps running, and processes the books that accumulated in the source
+    /// directory in batches on a schedule, rather than immediately on arrival.
+    Watch {
+        /// The directory new books arrive in.
+        source: String,
+
+        /// The directory the books are organized into.
+        destination: String,
+
+        /// Process a batch every interval, e.g. `30m`, `6h` or `1d`.
+        #[arg(long, value_parser = watch::parse_interval, required_unless_present = "at", conflicts_with = "at")]
+        interval: Option<Duration>,
+
+        /// Process a batch every day at this local time, e.g. `03:00`.
+        #[arg(long, value_parser = watch::parse_time)]
+        at: Option<NaiveTime>,
+    },
+
+    /// Rebuilds the original (torrent) structure recorded in a seed manifest by
+    /// hardlinking the library files back to their original paths.
+    RestoreSeed {
+        /// The seed manifest written with `--seed-manifest`.
+        manifest: String,
+    },
+
+    /// Re-hashes the files of an organized library and reports corrupted or missing
+    /// files, using the `checksums.sha256` manifests and the library database.
+    Fsck {
+        /// The organized library directory to check.
+        library: String,
+
+        /// The maximum read rate, e.g. `20M` for 20 MiB per second, so the check
+        /// doesn't slow down other users of the disk.
+        #[arg(long, value_parser = fsck::parse_rate)]
+        max_rate: Option<u64>,
+    },
+
+    /// Re-evaluates the file numbers of the books in an organized library, and
+    /// renames the audio files whose number is wrong (e.g. a date in the title that
+    /// was taken for the file number), without moving or re-importing anything.
+    FixNumbers {
+        /// The organized library directory to fix.
+        library: String,
+    },
+
+    /// Plans a single book directory and explains every decision: which provider
+    /// each metadata field came from, which series pattern matched, and where each
+    /// file number came from. Runs as a dry run unless `--execute` is given.
+    One {
+        /// The book directory to plan.
+        dir: String,
+
+        /// The directory the book is organized into.
+        destination: String,
+
+        /// Organize the book after explaining it, instead of only showing the plan.
+        #[arg(long, default_value_t = false)]
+        execute: bool,
+    },
+
+    /// Finds books that are in a library more than once, by author and title, and
+    /// optionally by their audio. Nothing is changed.
+    Dupes {
+        /// The library directory to search.
+        library: String,
+
+        /// Also compare acoustic fingerprints of the books, to find the same
+        /// recording under different names, tags and bitrates. This decodes the
+        /// first minutes of every book, so it is slower.
+        #[arg(long, default_value_t = false)]
+        fingerprint: bool,
+    },
+
+    /// Merges author directories of a library into the directory of the same
+    /// author, e.g. `Gaiman, Neil` into `Neil Gaiman`. The books are planned again
+    /// under the author of the last directory and moved into it.
+    MergeAuthors {
+        /// The author directories to merge, followed by the directory they are
+        /// merged into. All must be in the same library directory.
+        #[arg(num_args = 2.., required_unless_present = "similar")]
+        dirs: Vec<String>,
+
+        /// Merge every group of similar author directories in this library, as the
+        /// summary of a run reports them.
+        #[arg(long, conflicts_with = "dirs")]
+        similar: Option<String>,
+
+        /// What to do when a book already exists under the canonical author. By
+        /// default, the book is left in its directory and reported.
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Fail)]
+        on_conflict: ConflictPolicy,
+    },
+
+    /// Copies the books of an organized library that match `--filter` to another
+    /// destination, e.g. a USB stick, with the same or a different schema. With
+    /// `--database`, only the books recorded in the library database are exported.
+    Export {
+        /// The organized library to export from.
+        library: String,
+
+        /// The directory the books are copied to.
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Checks the setup before a run: the source and destination directories, the
+    /// schemas and config file, a sample of the metadata files, and the limitations
+    /// of the destination filesystems. Nothing is changed.
+    Doctor {
+        /// A source directory to check. Can be repeated.
+        #[arg(short, long)]
+        source: Vec<String>,
+
+        /// A destination directory to check. Can be repeated.
+        #[arg(short, long)]
+        destination: Vec<String>,
+
+        /// The maximum number of books whose metadata is read.
+        #[arg(long, default_value_t = 20)]
+        sample: usize,
+    },
+
+    /// Generates a sample source tree, with books in the supported metadata formats
+    /// and messy audio file names, to safely try out schemas and options on.
+    GenSample {
+        /// The directory to create the sample tree in. It must be empty.
+        directory: String,
+    },
+}
+
+/// Represents what is done with the non-audio files of a book, such as PDFs, covers,
+/// cue sheets and text files. Metadata files always travel with the book.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum OtherFiles {
+    /// Copy or move them with the book.
+    Copy,
+    /// Leave them in the source.
+    Skip,
+    /// Leave them in the source, and list them in the summary.
+    List,
+}
+
+/// Represents what is done with books that are still downloading.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum IncompleteDownloads {
+    /// Leave them out of this run, and list them as still downloading.
+    Defer,
+    /// Leave them out of this run, and report them with the skipped books.
+    Skip,
+    /// Import them anyway.
+    Import,
+}
+
+/// Represents the order the books of a run are processed in.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum BookOrder {
+    /// By author, series, book number and title.
+    Library,
+    /// The books with the most bytes first, in library order otherwise.
+    LargestFirst,
+    /// The books with the fewest bytes first, in library order otherwise.
+    SmallestFirst,
+}
+
+/// Represents the possible actions that can be performed on audiobook files.
+///
+/// This enum defines the options for copying, moving, or deleting files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActionOpt {
+    #[serde(rename = "copy")]
+    None = 0,
+    #[serde(rename = "move")]
+    Move = 1,
+    #[serde(rename = "move_and_delete")]
+    All = 2,
+    #[serde(rename = "symlink")]
+    Symlink = 3,
+    #[serde(rename = "hardlink")]
+    Hardlink = 4,
+}
+
+impl ActionOpt {
+    /**
+     * Gets the action selected with `--action`.
+     *
+     * @param value The value of `--action`.
+     * @return The action, or `None` if the value is not a known action.
+     */
+    fn from_arg(value: u8) -> Option<ActionOpt> {
+        match value {
+            0 => Some(ActionOpt::None),
+            1 => Some(ActionOpt::Move),
+            2 => Some(ActionOpt::All),
+            3 => Some(ActionOpt::Symlink),
+            4 => Some(ActionOpt::Hardlink),
+            _ => None,
+        }
+    }
+
+    /**
+     * Gets what is done with the files of a book for this action.
+     */
+    fn file_action(&self) -> FileAction {
+        match self {
+            ActionOpt::None => FileAction::Copy,
+            ActionOpt::Move | ActionOpt::All => FileAction::Move,
+            ActionOpt::Symlink => FileAction::Symlink,
+            ActionOpt::Hardlink => FileAction::Hardlink,
+        }
+    }
+}
+
+/// Represents the plan of a single book: where it goes, and what is done with each
+/// of its files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub from: String,
+    /// The source root the book was found in, if known.
+    #[serde(default)]
+    pub source: String,
+    pub to: String,
+    /// The destination root the book was placed on.
+    pub root: String,
+    pub action: ActionOpt,
+    /// The destination book already exists and its files are removed before the
+    /// incoming files are added.
+    pub replace_existing: bool,
+    pub files: Vec<FileOp>,
+    /// The file the book description is written to, if enabled.
+    pub description_file: Option<String>,
+    /// The playlist written into the book directory, if enabled.
+    pub playlist: Option<Playlist>,
+    /// The series and book number written back to the source metadata file, if
+    /// enabled and the file lacks them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_back: Option<WriteBack>,
+    pub metadata: Metadata,
+    /// The source directories of the book's other parts, which are merged into it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<String>,
+    /// The non-audio files that are left in the source with `--other-files skip` or
+    /// `list`. Their source directories are kept with `--action 2`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub left_out: Vec<PathBuf>,
+    /// How complete the metadata of the book is.
+    #[serde(default)]
+    pub completeness: Completeness,
+}
+
+/// Represents the playlist of a book.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Playlist {
+    pub file: String,
+    /// The audio files in playback order, relative to the book directory.
+    pub entries: Vec<String>,
+}
+
+/// Represents a single file operation of a book.
+///
+/// File operations are fully resolved during planning, so the dry run and the
+/// real run always produce identical results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileOp {
+    pub from: PathBuf,
+    pub to: String,
+    pub bytes: u64,
+    pub action: FileAction,
+}
+
+/// Represents what is done with a single file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileAction {
+    Copy,
+    Move,
+    Symlink,
+    Hardlink,
+    /// The destination already exists and the conflict policy is `skip`.
+    Skip,
+}
+
+impl FileAction {
+    /**
+     * Gets the name of the action, as shown in the report.
+     */
+    fn name(&self) -> &'static str {
+        match self {
+            FileAction::Copy => "copy",
+            FileAction::Move => "move",
+            FileAction::Symlink => "symlink",
+            FileAction::Hardlink => "hardlink",
+            FileAction::Skip => "skip",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Config {
+    from: String,
+    to: String,
+    action: ActionOpt,
+    dry_run: bool,
+    file_ext: Vec<String>,
+    metafile: String,
+    series_patterns: Vec<Regex>,
+    /// The keywords that mark the file number in a file name.
+    track_keywords: Vec<Regex>,
+    series_aliases: HashMap<String, String>,
+    providers: Vec<Box<dyn MetadataProvider>>,
+    filters: Vec<Filter>,
+    since: Option<SystemTime>,
+    check_audio: bool,
+    deep_check: bool,
+    quarantine: Option<String>,
+    verify: bool,
+    keep_source_on_error: bool,
+    skip_hidden: bool,
+    follow_symlinks: bool,
+    /// The number of books processed at the same time on each destination drive.
+    jobs: usize,
+    /// The number of books processed at the same time on each source and destination
+    /// drive, if limited.
+    jobs_per_device: Option<usize>,
+    checksums: bool,
+    buffer_size: usize,
+    on_conflict: ConflictPolicy,
+    conflict_suffix: String,
+    merge_strategy: Option<MergeStrategy>,
+    on_duplicate: Option<DuplicatePolicy>,
+    merge_parts: bool,
+    other_files: OtherFiles,
+    incomplete: IncompleteDownloads,
+    settle: Option<Duration>,
+    order: BookOrder,
+    verbose: bool,
+    explain_numbers: bool,
+    /// Planning prints nothing to stdout, for `plan_only`.
+    quiet: bool,
+    detect_types: bool,
+    write_back: bool,
+    /// Files are renamed inside their book directory, and books are not moved.
+    rename_only: bool,
+    audit_log: Option<PathBuf>,
+    /// The settings of the run directories, if each run gets one.
+    runs: Option<RunsConfig>,
+    database: Option<String>,
+    seed_manifest: Option<PathBuf>,
+    retry: RetryPolicy,
+    naming_script: Option<NamingScript>,
+    routes: Vec<Route>,
+    /// The source roots searched for books. `from` is the first one.
+    sources: Vec<String>,
+    /// The author every book is organized under, set by `merge-authors`.
+    author: Option<String>,
+    /// The book directories to process instead of searching the sources, if given.
+    source_list: Option<Vec<String>>,
+    /// The destination roots books are spread across. `to` is the first one.
+    pool: Vec<String>,
+    placement: Placement,
+}
+
+/**
+ * Runs aborg with the command line arguments of the process.
+ */
+pub fn run_cli() {
+    let args = Args::parse();
+    color::apply(&args.color);
+
+    let action = match ActionOpt::from_arg(args.action) {
+        Some(action) => action,
+        None => {
+            println!("Unknow delete option value of '{}' set!", args.action);
+            println!("Select one of the following options:");
+            println!("0 = Copy files only.");
+            println!("1 = Moves the files, keep directory.");
+            println!("2 = Moves the files and deletes the directory.");
+            println!("3 = Symlinks the files into the destination, keep the source.");
+            println!("4 = Hardlinks the files into the destination, keep the source.");
+            exit(1)
+        }
+    };
+
+    if !args.conflict_suffix.contains("{n}") {
+        eprintln!(
+            "{} '{}'",
+            "Error: The conflict suffix must contain '{n}':".red(),
+            args.conflict_suffix
+        );
+        exit(1)
+    }
+
+    // The doctor reports a broken config file or schema instead of exiting
+    if let Some(Command::Doctor { .. }) = &args.command {
+        let healthy = run_doctor(&args, action);
+        exit(if healthy { 0 } else { 1 })
+    }
+
+    let file_config = match &args.config {
+        Some(path) => load_config(path),
+        None => FileConfig::default(),
+    };
+
+    // Queries only read the database, and print nothing else so the output can be piped
+    if let Some(Command::Query {
+        author,
+        series,
+        title,
+        missing,
+        format,
+    }) = &args.command
+    {
+        let Some(database) = args.database.as_ref().or(file_config.database.as_ref()) else {
+            eprintln!(
+                "{}",
+                "Error: A library database must be set with --database or in the config file".red()
+            );
+            exit(1)
+        };
+        let book_query = BookQuery {
+            author: author.clone(),
+            series: series.clone(),
+            title: title.clone(),
+            missing: missing.clone(),
+        };
+        query::query(database, &book_query, format);
+        return;
+    }
+
+    if let Some(path) = &args.progress_json
+        && let Err(err) = progress::init(path)
+    {
+        eprintln!(
+            "{} '{}'. {}",
+            "Error: Could not open the progress stream".red(),
+            path.yellow(),
+            err
+        );
+        exit(1)
+    }
+
+    let config = match build_config(&args, &file_config, action.clone()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{} {}", "Error:".red(), err);
+            exit(1)
+        }
+    };
+    if CONFIG.set(config).is_err() {
+        eprintln!(
+            "{}",
+            "Error: Tried to set global config and it failed!".red()
+        );
+    }
+
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    if cfg.dry_run {
+        println!(
+            "{}",
+            "--->>> This is running as a dry-run, no changes will be made! <<<---"
+                .bold()
+                .underline()
+                .yellow()
+        );
+    }
+
+    let schema = match load_schema(&args, &file_config) {
+        Ok(schema) => schema,
+        Err(err) => {
+            eprintln!("{} {}", "Error: Invalid schema:".red(), err);
+            exit(1)
+        }
+    };
+
+    let mut status = 0;
+    match args.command {
+        Some(Command::Retag { movement, .. }) => retag(movement),
+        Some(Command::Sync { delete, yes, .. }) => {
+            let mut summary = Summary::default();
+            sync::sync(delete, yes, &mut summary);
+            summary.print();
+        }
+        Some(Command::Query { .. }) => unreachable!("queries return before the config is set"),
+        Some(Command::Doctor { .. }) => unreachable!("the doctor returns before the config is set"),
+        Some(Command::RestoreSeed { manifest }) => {
+            let mut summary = Summary::default();
+            seed::restore(&manifest, &mut summary);
+            summary.print();
+        }
+        Some(Command::GenSample { directory }) => {
+            let mut summary = Summary::default();
+            sample::generate(&directory, &mut summary);
+            summary.print();
+        }
+        Some(Command::Fsck { max_rate, .. }) => {
+            let mut summary = Summary::default();
+            fsck::fsck(max_rate, &mut summary);
+            summary.print();
+            if !summary.errors.is_empty() {
+                exit(1)
+            }
+        }
+        Some(Command::Dupes { fingerprint, .. }) => dupes::find_duplicates(fingerprint),
+        Some(Command::FixNumbers { .. }) => {
+            let mut summary = Summary::default();
+            numbers::fix_numbers(&schema, &mut summary);
+            summary.print();
+        }
+        Some(Command::One { .. }) => {
+            let mut summary = Summary::default();
+            one::one(&schema, &mut summary);
+        }
+        Some(Command::MergeAuthors {
+            similar: Some(_), ..
+        }) => authors::merge_similar(&args, &file_config, action, &schema),
+        Some(Command::MergeAuthors { .. }) => {
+            let mut summary = Summary::default();
+            authors::merge_authors(&schema, &mut summary);
+        }
+        Some(Command::Export { .. }) => {
+            let mut summary = Summary::default();
+            export::export(&schema, &mut summary);
+        }
+        Some(Command::Watch { interval, at, .. }) => {
+            let schedule = match (interval, at) {
+                (_, Some(time)) => Schedule::DailyAt(time),
+                (Some(interval), None) => Schedule::Every(interval),
+                (None, None) => unreachable!("clap requires --interval or --at"),
+            };
+            let reload_args = args.clone();
+            let load = move || {
+                let file_config = match &reload_args.config {
+                    Some(path) => read_config(path)?,
+                    None => FileConfig::default(),
+                };
+                Ok(Reloaded {
+                    config: build_config(&reload_args, &file_config, action.clone())?,
+                    schema: load_schema(&reload_args, &file_config)?,
+                    files: watched_files(&reload_args, &file_config),
+                })
+            };
+            let reloader = Reloader::new(watched_files(&args, &file_config), Box::new(load));
+            watch::watch(schema, &schedule, reloader);
+        }
+        Some(Command::Serve { listen, token, .. }) => {
+            serve::serve(&listen, token, schema);
+        }
+        None => {
+            // Define the move/rename schema
+            let mut summary = Summary::default();
+            let mut plan_list = match args.redo {
+                Some(run_id) => match redo::redo_plans(run_id, &mut summary) {
+                    Ok(plans) => plans,
+                    Err(err) => {
+                        eprintln!("{} {}", "Error:".red(), err);
+                        exit(1)
+                    }
+                },
+                None => plan(&schema, &cfg.sources, &mut summary),
+            };
+            if let Some(limit) = args.limit
+                && plan_list.len() > limit
+            {
+                println!(
+                    "{} Only processing the first {} of {} books",
+                    "Limit:".yellow(),
+                    limit,
+                    plan_list.len()
+                );
+                plan_list.truncate(limit);
+            }
+            if cfg.action == ActionOpt::All
+                && !cfg.dry_run
+                && !args.yes
+                && !plan_list.is_empty()
+                && !confirm_source_deletion(&plan_list)
+            {
+                println!(
+                    "{}",
+                    "Not processing any books, use --yes to skip the confirmation.".yellow()
+                );
+                exit(1)
+            }
+            if let Some(path) = &args.snapshot {
+                match snapshot::write_snapshot(path, &plan_list) {
+                    Ok(files) => println!(
+                        "{} '{}' ({} files)",
+                        "Snapshot written to".green(),
+                        path,
+                        files
+                    ),
+                    // Nothing is changed without the record of the input
+                    Err(err) => {
+                        eprintln!("{} {}", "Error writing the snapshot:".red(), err);
+                        exit(1)
+                    }
+                }
+            }
+            if let Some(config) = &cfg.runs {
+                runs::start(config);
+            }
+            execute(plan_list, &cfg.sources, &mut summary);
+
+            if let Some(report) = &args.report {
+                match report::write_report(report, &summary, cfg.dry_run) {
+                    Ok(_) => println!("{} '{}'", "Report written to".green(), report),
+                    Err(err) => eprintln!("{} {}", "Error writing the report:".red(), err),
+                }
+            }
+            if let Some(path) = &args.mapping {
+                match mapping::write_mapping(path, &summary) {
+                    Ok(_) => println!("{} '{}'", "Mapping written to".green(), path),
+                    Err(err) => eprintln!("{} {}", "Error writing the mapping:".red(), err),
+                }
+            }
+            if let Some(config) = &cfg.runs {
+                runs::finish(config, &summary);
+            }
+            let found = summary.potential_problems();
+            if cfg.dry_run && !found.is_empty() {
+                println!(
+                    "{} The dry run found {}, exiting with status {}",
+                    "Warning:".yellow(),
+                    found.join(", "),
+                    DRY_RUN_PROBLEMS_STATUS
+                );
+                status = DRY_RUN_PROBLEMS_STATUS;
+            }
+        }
+    }
+
+    println!("\n——————————————————————————————");
+    println!("{}", "Finished!".bold().blue());
+    if status != 0 {
+        exit(status)
+    }
+}
+
+/**
+ * Plans a run without executing it, for tools that want the resolved plans rather
+ * than the console output.
+ *
+ * The arguments are the same as on the command line, including the program name,
+ * e.g. `["aborg", "-s", "/downloads", "-d", "/library"]`. Planning only reads the
+ * source and destination, nothing is moved or written, and nothing is printed to
+ * stdout. Errors are still printed to stderr.
+ *
+ * The config of the call is only set while planning, and the previous config is
+ * restored afterwards. Like a config reload, every call leaks its config, which
+ * costs a few KiB.
+ *
+ * @param args The command line arguments.
+ * @return The plan of every book, or an error message if an argument, the config
+ *         file or the schema is not valid.
+ */
+pub fn plan_only<I, T>(args: I) -> Result<Vec<Plan>, String>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let args = Args::try_parse_from(args).map_err(|e| e.to_string())?;
+    let action = ActionOpt::from_arg(args.action)
+        .ok_or_else(|| format!("Unknown action '{}'", args.action))?;
+    let file_config = match &args.config {
+        Some(path) => read_config(path)?,
+        None => FileConfig::default(),
+    };
+    let mut config = build_config(&args, &file_config, action)?;
+    if config.merge_strategy == Some(MergeStrategy::Prompt) {
+        return Err("'--merge-strategy prompt' can't ask while planning only".to_string());
+    }
+    config.quiet = true;
+    let config: &'static Config = Box::leak(Box::new(config));
+
+    // Calls from several threads would see each other's config
+    static PLANNING: Mutex<()> = Mutex::new(());
+    let _planning = PLANNING.lock().unwrap_or_else(|e| e.into_inner());
+    let previous = CONFIG.swap(Some(config));
+    let plans = load_schema(&args, &file_config).map(|schema| {
+        let mut summary = Summary::default();
+        plan(&schema, &config.sources, &mut summary)
+    });
+    CONFIG.swap(previous);
+    plans
+}
+
+/**
+ * Asks the user to confirm that `--action 2` deletes the source directories of the
+ * planned books, so a mistyped `--source` doesn't wipe the wrong tree.
+ *
+ * @param actions The plans of the run.
+ * @return True if the user confirmed.
+ */
+fn confirm_source_deletion(actions: &[Plan]) -> bool {
+    let count: usize = actions.iter().map(|action| 1 + action.parts.len()).sum();
+    println!(
+        "{} {} source directories will be deleted once their books are moved",
+        "Warning:".yellow(),
+        count
+    );
+    let mut roots: Vec<&str> = actions
+        .iter()
+        .map(|action| action.source.as_str())
+        .filter(|root| !root.is_empty())
+        .collect();
+    roots.sort_unstable();
+    roots.dedup();
+    for root in roots {
+        println!("  from '{}'", root.yellow());
+    }
+    confirm(&format!(
+        "Type 'yes' to delete {} source directories: ",
+        count
+    ))
+}
+
+/**
+ * Asks the user a question that must be answered by typing `yes`.
+ *
+ * @param question The question, shown without a line break.
+ * @return True if the user typed `yes`.
+ */
+fn confirm(question: &str) -> bool {
+    print!("{}", question);
+    io::stdout().flush().unwrap_or(());
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).unwrap_or(0);
+    answer.trim() == "yes"
+}
+
+/**
+ * Dry runs or executes the plans, and reports the results.
+ *
+ * @param actions The plans to execute.
+ * @param sources The source roots the plans were generated from.
+ * @param summary The summary that collects the results of the run.
+ */
+fn execute(actions: Vec<Plan>, sources: &[String], summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let author_roots = if cfg.rename_only {
+        Vec::new()
+    } else {
+        authors::author_roots(&actions)
+    };
+
+    if cfg.dry_run {
+        dry_run(actions, summary);
+    } else {
+        let mut database = match &cfg.database {
+            Some(path) => match Database::open(path, sources, &cfg.pool) {
+                Ok(database) => Some(database),
+                Err(err) => {
+                    eprintln!("{} {}", "Error opening the library database:".red(), err);
+                    summary.error(
+                        Category::Io,
+                        format!("Could not open the library database: {}", err),
+                    );
+                    summary.print();
+                    return;
+                }
+            },
+            None => None,
+        };
+        if let Some(database) = &mut database {
+            println!("{} {}", "Run ID:".green(), database.run_id());
+            if let Err(err) = database.record_plans(&actions) {
+                eprintln!("{} {}", "Error recording the plans of the run:".red(), err);
+            }
+        }
+        run(actions, summary, database);
+    }
+
+    // merge-authors removes the merged directories after the run, so they would be
+    // reported as the similar authors they were
+    if cfg.author.is_none() {
+        summary.similar_authors = authors::find_similar_authors(&author_roots, &cfg.file_ext);
+    }
+    summary.print();
+    progress::emit(
+        "run_finished",
+        json!({
+            "books": summary.books,
+            "files": summary.files,
+            "bytes": summary.bytes,
+            "errors": summary.errors,
+        }),
+    );
+}
+
+/**
+ * Runs the `doctor` checks: the config file, the schemas and the settings, then the
+ * directories and books, see `doctor::doctor`.
+ *
+ * @param args The command line arguments.
+ * @param action The selected action.
+ * @return True if no problems were found.
+ */
+fn run_doctor(args: &Args, action: ActionOpt) -> bool {
+    let Some(Command::Doctor {
+        source,
+        destination,
+        sample,
+    }) = &args.command
+    else {
+        return false;
+    };
+    let mut checks = doctor::Checks::default();
+
+    let file_config = match &args.config {
+        Some(path) => match read_config(path) {
+            Ok(file_config) => {
+                checks.pass(&format!("The config file '{}' is valid", path));
+                file_config
+            }
+            Err(err) => {
+                checks.fail(&err);
+                FileConfig::default()
+            }
+        },
+        None => FileConfig::default(),
+    };
+    let schema = match load_schema(args, &file_config) {
+        Ok(schema) => {
+            checks.pass("The schemas and templates compile");
+            Some(schema)
+        }
+        Err(err) => {
+            checks.fail(&format!("Invalid schema: {}", err));
+            None
+        }
+    };
+    match build_config(args, &file_config, action) {
+        Ok(config) => {
+            if CONFIG.set(config).is_err() {
+                checks.fail("Tried to set global config and it failed!");
+                return checks.finish();
+            }
+        }
+        Err(err) => {
+            checks.fail(&err);
+            return checks.finish();
+        }
+    }
+
+    doctor::doctor(source, destination, schema.as_ref(), *sample, &mut checks);
+    checks.finish()
+}
+
+/**
+ * Builds the global config from the command line arguments and the config file.
+ *
+ * @param args The command line arguments.
+ * @param file_config The settings of the config file.
+ * @param action The selected action.
+ * @return The config, or an error message if a setting is not valid.
+ */
+fn build_config(
+    args: &Args,
+    file_config: &FileConfig,
+    action: ActionOpt,
+) -> Result<Config, String> {
+    let mut file_types: Vec<String> = args
+        .file_types
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
+
+    if file_types.is_empty() {
+        file_types = [
+            "m4b", "m4a", "m4p", "mp3", "aa", "aax", "aac", "ogg", "wma", "wav", "flac", "alac",
+        ]
+        .iter()
+        .map(|&s| s.to_string())
+        .collect();
+    }
+
+    let series_patterns = compile_series_patterns(&file_config.series_patterns)
+        .map_err(|err| format!("Invalid series pattern {}", err))?;
+    let retry_policy = RetryPolicy::from_config(&file_config.retry)
+        .map_err(|err| format!("Invalid retry setting: {}", err))?;
+
+    let provider_names = if file_config.providers.is_empty() {
+        DEFAULT_PROVIDERS.iter().map(|p| p.to_string()).collect()
+    } else {
+        file_config.providers.clone()
+    };
+    let cache = if file_config.cache.enabled {
+        let ttl = watch::parse_interval(&file_config.cache.ttl)
+            .map_err(|err| format!("Invalid cache ttl: {}", err))?;
+        ResponseCache::new(file_config.cache.dir.as_deref(), ttl)
+    } else {
+        None
+    };
+    let providers = build_providers(
+        &provider_names,
+        &args.metafile,
+        &file_types,
+        &file_config.metadata_command,
+        cache.as_ref(),
+    )
+    .map_err(|err| format!("Invalid providers setting: {}", err))?;
+
+    let naming_script = match args
+        .naming_script
+        .as_ref()
+        .or(file_config.naming_script.as_ref())
+    {
+        Some(path) => Some(
+            NamingScript::load(path)
+                .map_err(|err| format!("Invalid naming script '{}'. {}", path, err))?,
+        ),
+        None => None,
+    };
+
+    let routes = compile_routes(&file_config.routes)
+        .map_err(|err| format!("Invalid route filter {}", err))?;
+
+    let (from, to) = match &args.command {
+        Some(
+            Command::Retag { library, .. }
+            | Command::Fsck { library, .. }
+            | Command::Dupes { library, .. }
+            | Command::FixNumbers { library },
+        ) => (library.clone(), String::new()),
+        Some(Command::Sync {
+            library, mirror, ..
+        }) => (library.clone(), mirror.clone()),
+        Some(Command::Export { library, to }) => (library.clone(), to.clone()),
+        Some(Command::Serve { destination, .. }) => (String::new(), destination.clone()),
+        Some(Command::Doctor {
+            source,
+            destination,
+            ..
+        }) => (
+            source.first().cloned().unwrap_or_default(),
+            destination.first().cloned().unwrap_or_default(),
+        ),
+        Some(Command::Watch {
+            source,
+            destination,
+            ..
+        }) => (source.clone(), destination.clone()),
+        Some(Command::One {
+            dir, destination, ..
+        }) => (dir.clone(), destination.clone()),
+        Some(Command::MergeAuthors {
+            similar: Some(library),
+            ..
+        }) => (library.clone(), library.clone()),
+        Some(Command::MergeAuthors { dirs, .. }) => {
+            let (into, from) = dirs.split_last().ok_or("No author directories to merge")?;
+            authors::check_merge(from, into)?;
+            let library = Path::new(into).parent().unwrap_or(Path::new(""));
+            (from[0].clone(), library.display().to_string())
+        }
+        Some(Command::Query { .. } | Command::RestoreSeed { .. } | Command::GenSample { .. })
+        | None => (
+            args.source.first().cloned().unwrap_or_default(),
+            args.destination.first().cloned().unwrap_or_default(),
+        ),
+    };
+    let pool = match &args.command {
+        None if args.destination.len() > 1 => args.destination.clone(),
+        _ => vec![to.clone()],
+    };
+    let database = args.database.clone().or(file_config.database.clone());
+    let source_list = match (&args.command, &args.from_list) {
+        (None, Some(list)) => Some(read_source_list(list)?),
+        (None, None) if from == "-" => Some(read_source_list("-")?),
+        // The library database lists the books, so the library isn't searched
+        (Some(Command::Export { .. }), _) => match &database {
+            Some(path) => Some(
+                database::library_books(path, &from)
+                    .map_err(|err| format!("Could not read the library database. {}", err))?,
+            ),
+            None => None,
+        },
+        _ => None,
+    };
+    let from = match &source_list {
+        Some(_) if from == "-" => String::new(),
+        _ => from,
+    };
+    let sources = match &args.command {
+        None if args.source.len() > 1 => args.source.clone(),
+        Some(Command::MergeAuthors {
+            dirs,
+            similar: None,
+            ..
+        }) => dirs[..dirs.len() - 1].to_vec(),
+        _ => vec![from.clone()],
+    };
+    // Merged books are organized under the author of the last directory
+    let author = match &args.command {
+        Some(Command::MergeAuthors {
+            dirs,
+            similar: None,
+            ..
+        }) => dirs.last().and_then(|dir| {
+            Path::new(dir)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        }),
+        _ => None,
+    };
+
+    let is_export = matches!(args.command, Some(Command::Export { .. }));
+
+    Ok(Config {
+        from,
+        to,
+        // Renaming in place moves the files, but never deletes the book directory
+        action: if args.rename_only {
+            ActionOpt::Move
+        } else if matches!(args.command, Some(Command::MergeAuthors { .. })) {
+            ActionOpt::All
+        } else if matches!(args.command, Some(Command::Export { .. })) {
+            ActionOpt::None
+        } else {
+            action
+        },
+        // A single book is only explained, unless it is executed explicitly
+        dry_run: args.dry_run || matches!(args.command, Some(Command::One { execute: false, .. })),
+        file_ext: file_types,
+        metafile: args.metafile.clone(),
+        series_patterns,
+        track_keywords: compile_track_keywords(&file_config.track_keywords),
+        series_aliases: series_alias_map(&file_config.series_aliases),
+        providers,
+        filters: args.filter.clone(),
+        since: args.since,
+        check_audio: args.check_audio,
+        deep_check: args.deep,
+        quarantine: args.quarantine.clone(),
+        verify: args.verify,
+        keep_source_on_error: args.keep_source_on_error,
+        skip_hidden: args.skip_hidden,
+        follow_symlinks: args.follow_symlinks,
+        jobs: usize::from(args.jobs),
+        jobs_per_device: args.jobs_per_device.map(usize::from),
+        checksums: args.checksums,
+        buffer_size: args.buffer_size.saturating_mul(1024),
+        on_conflict: match &args.command {
+            Some(Command::MergeAuthors { on_conflict, .. }) => on_conflict.clone(),
+            _ => args.on_conflict.clone(),
+        },
+        conflict_suffix: args.conflict_suffix.clone(),
+        merge_strategy: args.merge_strategy.clone(),
+        on_duplicate: args.on_duplicate.clone(),
+        merge_parts: args.merge_parts,
+        other_files: args.other_files.clone(),
+        incomplete: args.incomplete.clone(),
+        settle: args.settle.map(Duration::from_secs),
+        order: args.order.clone(),
+        verbose: args.verbose || matches!(args.command, Some(Command::One { .. })),
+        explain_numbers: args.explain_numbers,
+        quiet: false,
+        detect_types: args.detect_types,
+        write_back: args.write_back && !is_export,
+        rename_only: args.rename_only,
+        audit_log: args
+            .audit_log
+            .clone()
+            .or(file_config.audit_log.clone())
+            .map(PathBuf::from),
+        // Only real organizing runs and watch batches get a run directory
+        runs: (file_config.runs.enabled
+            && !args.dry_run
+            && matches!(args.command, None | Some(Command::Watch { .. })))
+        .then(|| file_config.runs.clone()),
+        // An export is a copy of the library, it isn't recorded as the library
+        database: database.filter(|_| !is_export),
+        seed_manifest: args.seed_manifest.clone().map(PathBuf::from),
+        retry: retry_policy,
+        naming_script,
+        // Exported books all go to the export destination
+        routes: if is_export { Vec::new() } else { routes },
+        sources,
+        author,
+        source_list,
+        pool,
+        placement: args.placement.clone(),
+    })
+}
+
+/**
+ * Reads the list of book directories given with `--from-list` or `--source -`.
+ *
+ * @param path The file with one directory per line, or `-` for stdin.
+ * @return The directories, or an error message if the list could not be read.
+ */
+fn read_source_list(path: &str) -> Result<Vec<String>, String> {
+    let content = if path == "-" {
+        io::read_to_string(io::stdin())
+            .map_err(|err| format!("Could not read the source list from stdin: {}", err))?
+    } else {
+        fs::read_to_string(path)
+            .map_err(|err| format!("Could not read the source list '{}': {}", path, err))?
+    };
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/**
+ * Builds the schema from the schema arguments, template files and partials, and
+ * checks that all templates compile.
+ *
+ * @param args The command line arguments.
+ * @param file_config The settings of the config file.
+ * @return The schema, or an error message.
+ */
+fn load_schema(args: &Args, file_config: &FileConfig) -> Result<Schema, String> {
+    let path_schema = match &args.preset {
+        Some(preset) => preset.path_schema(),
+        None => read_schema_arg(&args.path_schema)?,
+    };
+    let mut schema = Schema::new(path_schema, read_schema_arg(&args.file_schema)?)
+        .with_extension_templates(&file_config.file_templates)
+        .with_variables(&file_config.variables)
+        .with_description_file(args.write_description.clone())
+        .with_playlist_file(args.write_playlist.clone())
+        .with_extension_rules(args.lowercase_extensions, &args.extension_alias)
+        .with_type_detection(args.detect_types)
+        .with_lenient(args.lenient.as_ref());
+    if let Some(dir) = args.templates.as_ref().or(file_config.templates.as_ref()) {
+        schema = schema.with_partials(Path::new(dir))?;
+    }
+
+    schema.validate()?;
+    Ok(schema)
+}
+
+/**
+ * Lists the files the config and schema are read from: the config file, schema
+ * template files, partials and the naming script.
+ *
+ * @param args The command line arguments.
+ * @param file_config The settings of the config file.
+ * @return The files, including ones that don't exist (yet).
+ */
+fn watched_files(args: &Args, file_config: &FileConfig) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = args.config.iter().map(PathBuf::from).collect();
+    for schema in [&args.path_schema, &args.file_schema] {
+        if let Some(path) = schema.strip_prefix('@') {
+            files.push(PathBuf::from(path));
+        }
+    }
+    if let Some(dir) = args.templates.as_ref().or(file_config.templates.as_ref()) {
+        // The directory changes when partials are added or removed
+        files.push(PathBuf::from(dir));
+        let partials = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok());
+        files.extend(
+            partials
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|e| e == "hbs")),
+        );
+    }
+    if let Some(script) = args
+        .naming_script
+        .as_ref()
+        .or(file_config.naming_script.as_ref())
+    {
+        files.push(PathBuf::from(script));
+    }
+
+    files
+}
+
+/**
+ * Generate a move/rename plan for the given path and schema.
+ *
+ * This function takes a path and a schema as input and returns a vector of plans.
+ * Each plan represents a move or rename operation that needs to be performed.
+ *
+ * @param schema - The schema to use for formatting the new file names.
+ * @param sources - The source roots to search for books, unless book directories
+ *        were listed with `--from-list`.
+ * @param summary - The summary that collects the results of the run.
+ * @return Vec<Plan> - A vector of plans representing the move/rename operations.
+ */
+fn plan(schema: &Schema, sources: &[String], summary: &mut Summary) -> Vec<Plan> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let target_file = &cfg.metafile;
+
+    // A list of book directories replaces the search of the whole source
+    let roots: Vec<&str> = match &cfg.source_list {
+        Some(dirs) => {
+            if !cfg.quiet {
+                println!(
+                    "Searching for '{}' in {} listed directories...",
+                    target_file.green(),
+                    dirs.len()
+                );
+            }
+            dirs.iter().map(String::as_str).collect()
+        }
+        None => {
+            for source in sources {
+                if !cfg.quiet {
+                    println!(
+                        "Searching for '{}' in '{}' and all sub-directories...",
+                        target_file.green(),
+                        source.green()
+                    );
+                }
+            }
+            sources.iter().map(String::as_str).collect()
+        }
+    };
+
+    let mut metadata_paths = Vec::new();
+    let mut seen = HashSet::new();
+    let entries = roots.into_iter().flat_map(|root| {
+        WalkDir::new(root)
+            .follow_links(cfg.follow_symlinks)
+            .into_iter()
+            .filter_entry(|e| !is_skipped(e))
+    });
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                // Listed directories can overlap, and followed links can lead to the
+                // same book twice, each book is only planned once. A path that can't
+                // be resolved is never the same as another one
+                if is_metafile(entry.path())
+                    && is_recent(entry.path())
+                    && fs::canonicalize(entry.path())
+                        .ok()
+                        .is_none_or(|real| seen.insert(real))
+                {
+                    metadata_paths.push(entry.path().to_path_buf());
+                }
+            }
+            Err(err) => {
+                eprintln!("{}{}", "Error: ".red(), err);
+            }
+        }
+    }
+
+    // Reading the metadata probes the audio files and can query providers, so
+    // several books are read at once
+    let results = pipeline::parallel_map(&metadata_paths, cfg.jobs, |path| {
+        let mut book_summary = Summary::default();
+        let metadata = read_book_metadata(path, &mut book_summary);
+        (metadata, book_summary)
+    });
+    let mut books = Vec::new();
+    for (path, (metadata, book_summary)) in metadata_paths.into_iter().zip(results) {
+        summary.merge(book_summary);
+        if let Some(metadata) = metadata {
+            books.push((path, metadata));
+        }
+    }
+
+    // The walk follows the order of the filesystem, the books are planned and
+    // processed in library order instead
+    books.sort_by_cached_key(|(path, metadata)| (library_order(metadata), path.clone()));
+
+    // The naming script can look at all books of the run, e.g. to count a series
+    let all_books = match cfg.naming_script {
+        Some(_) => {
+            let metadata: Vec<Metadata> = books.iter().map(|(_, m)| m.clone()).collect();
+            ScriptBooks::new(&metadata)
+        }
+        None => ScriptBooks::default(),
+    };
+
+    // Books released as one directory per part are merged into their first part
+    let mut merged_parts: HashMap<usize, Vec<PathBuf>> = HashMap::new();
+    for split_book in parts::find_split_books(&books) {
+        let names = split_book
+            .iter()
+            .filter_map(|&index| books[index].0.parent())
+            .map(|dir| format!("'{}'", dir.display()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !cfg.merge_parts {
+            if !cfg.quiet {
+                println!(
+                    "{} {} look like parts of one book, use --merge-parts to merge them",
+                    "Warning:".yellow(),
+                    names
+                );
+            }
+            continue;
+        }
+        if !cfg.quiet {
+            println!("{} {}", "Merging Parts:".green(), names);
+        }
+        let metadata_paths = split_book.iter().map(|&index| books[index].0.clone());
+        merged_parts.insert(split_book[0], metadata_paths.collect());
+        for &index in &split_book[1..] {
+            merged_parts.insert(index, Vec::new());
+        }
+    }
+
+    let mut actions = Vec::new();
+    let mut collisions = CollisionIndex::default();
+    let mut pool = new_pool();
+    for (index, (metadata_path, mut metadata)) in books.into_iter().enumerate() {
+        let metadata_paths = match merged_parts.remove(&index) {
+            // A later part, planned with the first part
+            Some(paths) if paths.is_empty() => continue,
+            Some(paths) => {
+                if let Some((title, _)) = parts::split_part(&metadata.title) {
+                    metadata.title = title;
+                }
+                paths
+            }
+            None => vec![metadata_path],
+        };
+        if let Some(mut plan) = plan_book(
+            schema,
+            &metadata_paths,
+            metadata,
+            &all_books,
+            &mut collisions,
+            &mut pool,
+            summary,
+        ) {
+            // Empty directories are only pruned up to the root the book was found in
+            plan.source = sources
+                .iter()
+                .find(|source| !source.is_empty() && Path::new(&plan.from).starts_with(source))
+                .cloned()
+                .unwrap_or_default();
+            actions.push(plan);
+        }
+    }
+
+    // The sort is stable, so books of the same size stay in library order
+    let book_size = |plan: &Plan| plan.files.iter().map(|op| op.bytes).sum::<u64>();
+    match cfg.order {
+        BookOrder::Library => {}
+        BookOrder::LargestFirst => actions.sort_by_key(|plan| Reverse(book_size(plan))),
+        BookOrder::SmallestFirst => actions.sort_by_key(book_size),
+    }
+
+    actions
+}
+
+/**
+ * Gets the key that orders books by author, series, book number and title,
+ * ignoring case. Books without a series come before the series of their author.
+ *
+ * @param metadata The metadata of the book.
+ * @return The sort key.
+ */
+fn library_order(metadata: &Metadata) -> (String, String, Option<u16>, String) {
+    let lowercase = |value: &Option<String>| value.as_deref().unwrap_or_default().to_lowercase();
+    (
+        lowercase(&metadata.author),
+        lowercase(&metadata.series),
+        metadata.book_number,
+        metadata.title.to_lowercase(),
+    )
+}
+
+/**
+ * Creates the pool of destination roots for a run. With `keep-author-together`,
+ * the roots recorded in the library database keep authors on their drive.
+ *
+ * @return The pool.
+ */
+fn new_pool() -> Pool {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let author_roots = match (&cfg.placement, &cfg.database) {
+        (Placement::KeepAuthorTogether, Some(path)) if cfg.pool.len() > 1 => {
+            database::author_roots(path).unwrap_or_default()
+        }
+        _ => HashMap::new(),
+    };
+    Pool::new(&cfg.pool, cfg.placement.clone(), &author_roots)
+}
+
+/**
+ * Reads a book's metadata from the metadata providers.
+ *
+ * @param metadata_path The path of the book's metadata file.
+ * @param summary The summary that collects the results of the run.
+ * @return The book's metadata, or `None` if it could not be read or the book doesn't
+ *         match the filters.
+ */
+fn read_book_metadata(metadata_path: &Path, summary: &mut Summary) -> Option<Metadata> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let book_dir = metadata_path.parent()?;
+    let Some(raw_data) = read_book(&cfg.providers, book_dir) else {
+        summary.problem(
+            Category::Metadata,
+            format!(
+                "'{}': the metadata could not be read",
+                metadata_path.display()
+            ),
+        );
+        return None;
+    };
+    let raw_isbn = raw_data.isbn.clone().filter(|isbn| !isbn.trim().is_empty());
+    let mut metadata = build_metadata(
+        raw_data,
+        book_dir,
+        &cfg.series_patterns,
+        &cfg.series_aliases,
+        &cfg.file_ext,
+    );
+    if let Some(isbn) = raw_isbn
+        && metadata.isbn.is_none()
+    {
+        summary
+            .invalid_isbns
+            .push((book_dir.display().to_string(), isbn));
+    }
+    if let Some(into) = &cfg.author {
+        let merged: Vec<String> = cfg
+            .sources
+            .iter()
+            .filter_map(|dir| Path::new(dir).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .chain(std::iter::once(into.clone()))
+            .collect();
+        // Books naming none of the merged authors keep their author
+        match &metadata.author {
+            Some(author) => {
+                if let Some(author) = authors::replace_merged_author(author, &merged, into) {
+                    metadata.author = Some(author);
+                }
+            }
+            None => metadata.author = Some(into.clone()),
+        }
+    }
+
+    cfg.filters
+        .iter()
+        .all(|filter| filter.matches(&metadata))
+        .then_some(metadata)
+}
+
+/**
+ * Compares an incoming book with the edition that already exists in the destination
+ * for `--on-duplicate keep-better`, and records the decision in the summary.
+ *
+ * @param from The source directory of the incoming book.
+ * @param to The existing destination book directory.
+ * @param rendered The incoming files, as (path, destination, bytes, file number).
+ * @param summary The summary that collects the results.
+ * @return `ReplaceAll` if the incoming book is better, otherwise `KeepExisting`.
+ */
+fn keep_better(
+    from: &str,
+    to: &str,
+    rendered: &[(PathBuf, String, u64, Option<u16>)],
+    summary: &mut Summary,
+) -> MergeDecision {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let incoming_files: Vec<PathBuf> = rendered
+        .iter()
+        .map(|(path, _, _, _)| path.clone())
+        .filter(|path| is_audio_file(path, &cfg.file_ext))
+        .collect();
+    let existing_files: Vec<PathBuf> = get_files(&to.to_string())
+        .into_iter()
+        .filter(|path| is_audio_file(path, &cfg.file_ext))
+        .collect();
+    let incoming = BookQuality::of(&incoming_files);
+    let existing = BookQuality::of(&existing_files);
+
+    let (decision, kept) = match incoming.is_better_than(&existing) {
+        true => (MergeDecision::ReplaceAll, "the incoming book"),
+        false => (MergeDecision::KeepExisting, "the existing book"),
+    };
+    if !cfg.quiet {
+        println!(
+            "{} '{}' ({}) already exists as '{}' ({}), keeping {}",
+            "Duplicate:".yellow(),
+            from,
+            incoming,
+            to,
+            existing,
+            kept
+        );
+    }
+    summary.duplicate_decisions.push(format!(
+        "'{}' ({}) vs '{}' ({}): kept {}",
+        from, incoming, to, existing, kept
+    ));
+    decision
+}
+
+/**
+ * Decides how a book is combined with the existing book at its destination, with
+ * `--on-duplicate` or `--merge-strategy`, one of which must be set.
+ *
+ * @param from The source directory of the incoming book.
+ * @param to The destination directory, which already exists.
+ * @param rendered The incoming files with their destination, size and number.
+ * @param summary The summary that collects the duplicate decisions.
+ * @return The decision.
+ */
+fn decide_merge(
+    from: &str,
+    to: &str,
+    rendered: &[(PathBuf, String, u64, Option<u16>)],
+    summary: &mut Summary,
+) -> MergeDecision {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    match (&cfg.on_duplicate, &cfg.merge_strategy) {
+        (Some(DuplicatePolicy::KeepBetter), _) => keep_better(from, to, rendered, summary),
+        (None, Some(strategy)) => {
+            let incoming_bytes = rendered.iter().map(|(_, _, bytes, _)| bytes).sum();
+            merge_decision(strategy, to, incoming_bytes)
+        }
+        (None, None) => unreachable!("a duplicate policy or merge strategy is set"),
+    }
+}
+
+/**
+ * Checks whether a book was modified after `--since`.
+ *
+ * @param metadata_path The path of the book's metadata file.
+ * @return True if the book was modified recently enough, or `--since` is not set.
+ */
+fn is_recent(metadata_path: &Path) -> bool {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    match cfg.since {
+        Some(since) => {
+            filter::book_modified(metadata_path).is_some_and(|modified| modified > since)
+        }
+        None => true,
+    }
+}
+
+/**
+ * Checks whether a file is the metadata file of a book.
+ *
+ * A book directory can have the metadata files of several providers (e.g. a
+ * `metadata.json` and a `metadata.opf`), only the file of the provider with the
+ * highest precedence marks the book, so each book is planned once.
+ *
+ * @param path The file to check.
+ * @return True if the file is a book's metadata file.
+ */
+fn is_metafile(path: &Path) -> bool {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    is_book_metafile(&cfg.providers, path)
+}
+
+/**
+ * Generate the plan for a single book.
+ *
+ * This renders the destination directory (and runs the naming script), and
+ * resolves the destination and action of every file in the book directory.
+ *
+ * @param schema The schema to use for formatting the new directory and file names.
+ * @param metadata_paths The path of the book's metadata file, followed by the
+ *        metadata files of the book's other parts when they are merged into it. The
+ *        files of each part are added after the files of the previous parts.
+ * @param metadata The book's metadata.
+ * @param all_books The metadata of all books of the run, for the naming script.
+ * @param collisions The destination paths claimed by the books planned so far.
+ * @param pool The destination roots, with the space used by the books planned so far.
+ * @param summary The summary that collects the results of the run.
+ * @return The book's plan, or `None` if the metadata could not be rendered, if the
+ *         naming script failed, or if a conflict was found and the conflict policy
+ *         is `fail`.
+ */
+fn plan_book(
+    schema: &Schema,
+    metadata_paths: &[PathBuf],
+    mut metadata: Metadata,
+    all_books: &ScriptBooks,
+    collisions: &mut CollisionIndex,
+    pool: &mut Pool,
+    summary: &mut Summary,
+) -> Option<Plan> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let metadata_file = metadata_paths[0].display().to_string();
+    let mut source_dirs = metadata_paths
+        .iter()
+        .map(|path| path.parent().unwrap().display().to_string());
+    let from = source_dirs.next().unwrap();
+    let parts: Vec<String> = source_dirs.collect();
+    let source_dirs: Vec<&String> = std::iter::once(&from).chain(&parts).collect();
+
+    // A book that is still downloading is never imported half, it is checked again
+    // in the next run or watch batch
+    if cfg.incomplete != IncompleteDownloads::Import
+        && let Some(file) = source_dirs
+            .iter()
+            .find_map(|dir| fsutil::find_incomplete_download(Path::new(dir)))
+    {
+        let reason = format!("still downloading ('{}')", file.display());
+        defer_book(from, reason, summary);
+        return None;
+    }
+    // A modification time in the future (e.g. from a skewed clock) counts as settled,
+    // since the book would wait for it forever
+    if let Some(settle) = cfg.settle
+        && cfg.incomplete != IncompleteDownloads::Import
+        && let Some(age) = source_dirs
+            .iter()
+            .filter_map(|dir| fsutil::last_modified(Path::new(dir)))
+            .max()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        && age < settle
+    {
+        let reason = format!("still being written (modified {}s ago)", age.as_secs());
+        defer_book(from, reason, summary);
+        return None;
+    }
+
+    let file_total = source_dirs
+        .iter()
+        .flat_map(|dir| get_files(dir))
+        .filter(|path| is_audio_file(path, &cfg.file_ext))
+        .count();
+    metadata.file_total = Some(u16::try_from(file_total).unwrap_or(u16::MAX));
+
+    let (root, to) = match schema.fmt_path(&mut metadata) {
+        // Renaming in place keeps the book directory
+        _ if cfg.rename_only => (String::new(), from.clone()),
+        Ok(value) => {
+            let value = match &cfg.naming_script {
+                Some(script) => match script.apply(&metadata, &value, all_books) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        eprintln!(
+                            "{} '{}'. {}",
+                            "Error: The naming script failed for".red(),
+                            metadata_file.yellow(),
+                            err
+                        );
+                        summary.problem(
+                            Category::Template,
+                            format!("'{}': the naming script failed. {}", metadata_file, err),
+                        );
+                        return None;
+                    }
+                },
+                None => value,
+            };
+            let root = match route::destination(&cfg.routes, &metadata) {
+                Some(root) => root.to_string(),
+                None => {
+                    let bytes = source_dirs
+                        .iter()
+                        .flat_map(|dir| get_files(dir))
+                        .map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+                        .sum();
+                    pool.place(&metadata, &value, bytes).to_string()
+                }
+            };
+            let (to, changed) = collisions.resolve_dir(&root, &value);
+            if changed && !cfg.quiet {
+                println!(
+                    "{} '{}/{}' only differs by case from '{}', using the existing casing",
+                    "Warning:".yellow(),
+                    root,
+                    value,
+                    to
+                );
+            }
+            (root, to)
+        }
+        Err(_) => {
+            eprintln!(
+                "{} '{}' - Schema: {}",
+                "Error: Required field missing in file".red(),
+                metadata_file.yellow(),
+                schema.path_template.yellow()
+            );
+            summary.problem(
+                Category::Template,
+                format!("'{}': a field of the path schema is missing", metadata_file),
+            );
+            return None;
+        }
+    };
+
+    let file_action = cfg.action.file_action();
+
+    let mut rendered = Vec::new();
+    let mut left_out = Vec::new();
+    // The files of each part are numbered after the files of the previous parts
+    let mut offset: u16 = 0;
+    for (part, dir) in source_dirs.iter().enumerate() {
+        let overrides = match BookOverrides::read(Path::new(dir)) {
+            Ok(overrides) => overrides,
+            Err(err) => {
+                eprintln!(
+                    "{} '{}'. {}",
+                    "Error: Could not read the override file of".red(),
+                    dir.yellow(),
+                    err
+                );
+                summary.problem(
+                    Category::Metadata,
+                    format!("'{}': the {} file is invalid. {}", dir, OVERRIDE_FILE, err),
+                );
+                return None;
+            }
+        };
+        let mut next_offset = offset;
+        for path in get_files(dir) {
+            let is_audio = is_audio_file(&path, &cfg.file_ext);
+            // Resolving the number probes the audio tags, so it is only done once per file
+            let file_number = if is_audio {
+                next_offset = next_offset.saturating_add(1);
+                let explanation = explain_track_number(
+                    &path,
+                    &cfg.track_keywords,
+                    &overrides.rename,
+                    metadata.file_total,
+                );
+                if cfg.explain_numbers && !cfg.quiet {
+                    println!(
+                        "{} '{}': {}",
+                        "Number:".blue(),
+                        path.display(),
+                        explanation.describe()
+                    );
+                }
+                explanation
+                    .number
+                    .map(|number| number.saturating_add(offset))
+            } else {
+                None
+            };
+            next_offset = next_offset.max(file_number.unwrap_or(0));
+            if !is_audio
+                && cfg.other_files != OtherFiles::Copy
+                && !is_provider_file(&cfg.providers, &path)
+            {
+                left_out.push(path);
+                continue;
+            }
+
+            let file_name = match schema.fmt_file(&mut metadata, &path, file_number, &cfg.file_ext)
+            {
+                Ok(file_name) => file_name,
+                Err(err) => {
+                    eprintln!(
+                        "{} '{}' - Schema: {} ({})",
+                        "Error: Could not render file name for".red(),
+                        path.display().to_string().yellow(),
+                        schema.file_template.yellow(),
+                        err
+                    );
+                    summary.problem(
+                        Category::Template,
+                        format!("'{}': the file name could not be rendered", path.display()),
+                    );
+                    return None;
+                }
+            };
+
+            let destination = format!("{}/{}", to, file_name);
+            // The other files of later parts, e.g. their metadata file and cover, are
+            // usually copies of the first part's files
+            if part > 0 && !is_audio && rendered.iter().any(|(_, to, _, _)| *to == destination) {
+                continue;
+            }
+            let bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            rendered.push((path, destination, bytes, file_number));
+        }
+        offset = next_offset;
+    }
+    // The file fields only apply to a single file, not to the book
+    metadata.file_number = None;
+    metadata.file_number_with_zeros = None;
+    metadata.original_name = None;
+    metadata.original_dir = None;
+
+    let description_file = match schema.fmt_description_file(&metadata) {
+        Some(Ok(name)) => Some(format!("{}/{}", to, name)),
+        Some(Err(err)) => {
+            eprintln!(
+                "{} '{}' ({})",
+                "Error: Could not render the description file name for".red(),
+                metadata_file.yellow(),
+                err
+            );
+            summary.problem(
+                Category::Template,
+                format!(
+                    "'{}': the description file name could not be rendered",
+                    metadata_file
+                ),
+            );
+            return None;
+        }
+        None => None,
+    };
+
+    // A book that already is in the destination, or that another book of the run
+    // lands on too, is likely a duplicate. A book that is merged into the existing
+    // book on purpose is not
+    let merges = cfg.merge_strategy.is_some() || cfg.on_duplicate.is_some();
+    if !cfg.rename_only {
+        let planned = !collisions.claim_book(&to);
+        if planned || (!merges && Path::new(&to).is_dir()) {
+            summary.duplicates.push(format!("'{}' -> '{}'", from, to));
+        }
+    }
+
+    // Decide how to combine the book with an existing book in the destination
+    let mut merge = None;
+    if !cfg.rename_only && merges && Path::new(&to).is_dir() && collisions.claim_merge(&to) {
+        let decision = decide_merge(&from, &to, &rendered, summary);
+        if decision == MergeDecision::KeepExisting {
+            if !cfg.quiet {
+                println!(
+                    "{} '{}', keeping the existing book '{}'",
+                    "Skipping:".yellow(),
+                    from,
+                    to
+                );
+            }
+            summary
+                .skipped_books
+                .push((from, format!("kept the existing book '{}'", to)));
+            return None;
+        }
+        merge = Some(decision);
+    }
+
+    let playlist_file = match schema.fmt_playlist_file(&metadata) {
+        Some(Ok(name)) => Some(format!("{}/{}", to, name)),
+        Some(Err(err)) => {
+            eprintln!(
+                "{} '{}' ({})",
+                "Error: Could not render the playlist file name for".red(),
+                metadata_file.yellow(),
+                err
+            );
+            summary.problem(
+                Category::Template,
+                format!(
+                    "'{}': the playlist file name could not be rendered",
+                    metadata_file
+                ),
+            );
+            return None;
+        }
+        None => None,
+    };
+
+    // When renaming in place, the old names of renamed files are free to be reused
+    let renamed_from: HashSet<String> = rendered
+        .iter()
+        .filter(|(path, destination, _, _)| cfg.rename_only && *path != Path::new(destination))
+        .map(|(path, _, _, _)| path.display().to_string().to_lowercase())
+        .collect();
+
+    let mut files = Vec::new();
+    let mut tracks = Vec::new();
+    for (path, mut destination, bytes, file_number) in rendered {
+        if cfg.rename_only && path == Path::new(&destination) {
+            if is_audio_file(&path, &cfg.file_ext) {
+                tracks.push((file_number, destination));
+            }
+            continue;
+        }
+        let check_disk = merge != Some(MergeDecision::ReplaceAll)
+            && !renamed_from.contains(&destination.to_lowercase());
+        let mut action = file_action.clone();
+        if let Some(existing) = collisions.claim_file(&destination, check_disk) {
+            // Files are expected to exist when books are merged
+            if merge.is_none() {
+                summary.collisions.push(format!(
+                    "'{}' -> '{}', which is already taken",
+                    path.display(),
+                    existing
+                ));
+            }
+            match (&merge, &cfg.on_conflict) {
+                (Some(MergeDecision::AddMissing), _) => action = FileAction::Skip,
+                (_, ConflictPolicy::Overwrite) => destination = existing,
+                (_, ConflictPolicy::Skip) => action = FileAction::Skip,
+                (_, ConflictPolicy::Rename) => {
+                    destination = collisions.claim_versioned(&destination, &cfg.conflict_suffix);
+                    if !cfg.quiet {
+                        println!(
+                            "{} '{}' already exists, using '{}'",
+                            "Warning:".yellow(),
+                            existing,
+                            destination
+                        );
+                    }
+                    summary.versioned.push(destination.clone());
+                }
+                (_, ConflictPolicy::Fail) => {
+                    eprintln!(
+                        "{} '{}' conflicts with '{}', skipping book '{}'",
+                        "Error:".red(),
+                        destination.yellow(),
+                        existing.yellow(),
+                        from
+                    );
+                    summary.problem(
+                        Category::Conflict,
+                        format!("'{}' conflicts with '{}'", destination, existing),
+                    );
+                    summary.skipped_books.push((
+                        from,
+                        format!("'{}' conflicts with '{}'", destination, existing),
+                    ));
+                    return None;
+                }
+            }
+        }
+
+        if is_audio_file(&path, &cfg.file_ext) {
+            tracks.push((file_number, destination.clone()));
+        }
+        files.push(FileOp {
+            bytes,
+            to: destination,
+            from: path,
+            action,
+        });
+    }
+
+    // A file renamed onto the old name of another file (e.g. two files swapping their
+    // names) is renamed in two steps, the second after all other files are renamed
+    let mut deferred = Vec::new();
+    for op in &mut files {
+        if op.action == FileAction::Move && renamed_from.contains(&op.to.to_lowercase()) {
+            let temporary = format!("{}.{}", op.from.display(), TEMPORARY_EXTENSION);
+            deferred.push(FileOp {
+                from: PathBuf::from(&temporary),
+                to: std::mem::replace(&mut op.to, temporary),
+                bytes: op.bytes,
+                action: FileAction::Move,
+            });
+        }
+    }
+    files.extend(deferred);
+
+    // Files without a number are played last, in name order
+    tracks.sort_by(|a, b| (a.0.is_none(), a.0, &a.1).cmp(&(b.0.is_none(), b.0, &b.1)));
+    let playlist = playlist_file.map(|file| Playlist {
+        file,
+        entries: tracks
+            .into_iter()
+            .map(|(_, track)| {
+                let relative = Path::new(&track).strip_prefix(&to).map(Path::to_path_buf);
+                relative
+                    .unwrap_or(PathBuf::from(&track))
+                    .display()
+                    .to_string()
+            })
+            .collect(),
+    });
+
+    let metafile = Path::new(&from).join(&cfg.metafile);
+    let write_back = if cfg.write_back && metafile.is_file() {
+        writeback::plan_write_back(&metafile, &metadata, &cfg.series_patterns)
+    } else {
+        None
+    };
+
+    let completeness = completeness::score(&metadata, &files);
+    if !completeness.missing.is_empty() {
+        summary
+            .incomplete
+            .push((from.clone(), completeness.clone()));
+    }
+    if cfg.other_files == OtherFiles::List {
+        summary
+            .other_files
+            .extend(left_out.iter().map(|path| path.display().to_string()));
+    }
+
+    Some(Plan {
+        from,
+        source: String::new(),
+        to,
+        root,
+        action: cfg.action.clone(),
+        replace_existing: merge == Some(MergeDecision::ReplaceAll),
+        files,
+        description_file,
+        playlist,
+        write_back,
+        metadata,
+        parts,
+        left_out,
+        completeness,
+    })
+}
+
+/**
+ * Run the migration process.
+ *
+ * This function takes a vector of plans, and executes the migration process.
+ * It creates the necessary directories and copies or moves the files as planned.
+ *
+ * @param actions A vector of `Plan` objects representing the operations to execute.
+ * @param summary The summary that collects the results of the run.
+ * @param database The library database that organized books are recorded in, if any.
+ */
+fn run(actions: Vec<Plan>, summary: &mut Summary, database: Option<Database>) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    // Books on different drives are processed side by side, so no drive waits on
+    // another. With a single job, every book is processed in order.
+    let total = actions.len();
+    let database = Mutex::new(database);
+    let results = Mutex::new(std::mem::take(summary));
+    let started = AtomicUsize::new(0);
+    let process = |(index, action): (usize, Plan)| {
+        if shutdown::requested() {
+            return;
+        }
+        started.fetch_add(1, Ordering::Relaxed);
+        let mut book_summary = Summary::default();
+        run_book(action, index, total, &mut book_summary, &database);
+        results
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .merge(book_summary);
+    };
+
+    match cfg.jobs_per_device {
+        // A book keeps both the drive it is read from and the drive it is written
+        // to busy
+        Some(per_device) => {
+            let books = actions.into_iter().enumerate().map(|(index, action)| {
+                let mut devices: Vec<u64> = [&action.from, &action.to]
+                    .into_iter()
+                    .filter_map(|dir| fsutil::device_id(Path::new(dir)))
+                    .collect();
+                devices.dedup();
+                (devices, (index, action))
+            });
+            pipeline::run_limited(books.collect(), cfg.jobs, per_device, process);
+        }
+        None => {
+            let books = actions.into_iter().enumerate().map(|(index, action)| {
+                let device = match cfg.jobs {
+                    1 => None,
+                    _ => fsutil::device_id(Path::new(&action.root)),
+                };
+                (device, (index, action))
+            });
+            pipeline::run_grouped(books.collect(), cfg.jobs, process);
+        }
+    }
+    *summary = results.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    let started = started.into_inner();
+    if started < total {
+        println!(
+            "{} {} books were not processed",
+            "Stopped:".yellow(),
+            total - started
+        );
+    }
+}
+
+/**
+ * Processes a single book of the run.
+ *
+ * @param action The plan of the book.
+ * @param index The position of the book in the run, starting at 0.
+ * @param total The number of books in the run.
+ * @param summary The summary that collects the results of the book.
+ * @param database The library database that organized books are recorded in, if any.
+ */
+fn run_book(
+    action: Plan,
+    index: usize,
+    total: usize,
+    summary: &mut Summary,
+    database: &Mutex<Option<Database>>,
+) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    println!("--\n");
+    if cfg.check_audio && !check_book(&action, summary) {
+        return;
+    }
+    summary.books += 1;
+    emit_book_started(&action, index, total);
+    let errors = summary.errors.len();
+    write_back_metadata(&action, summary);
+
+    // The existing book is only removed once the incoming book has arrived in full
+    let replaced = if action.replace_existing {
+        match set_aside_book(&action.to) {
+            Ok(aside) => Some(aside),
+            Err(err) => {
+                eprintln!("{} {}", "Error setting aside existing book:".red(), err);
+                summary.error(
+                    Category::Io,
+                    format!("'{}' was not replaced: {}", action.to, err),
+                );
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    // Renaming the whole directory is near-instant, the files are renamed after
+    let renamed = can_rename_book(&action) && rename_book(&action);
+
+    let dde = fs::exists(&action.to);
+    if !dde.unwrap_or(false) {
+        match fs::create_dir_all(&action.to) {
+            Ok(_) => println!("{} {}", "Created Directory:".green(), action.to),
+            Err(err) => eprintln!("{} {}", "Error creating directory:".red(), err),
+        }
+    }
+
+    let mut checksums = HashMap::new();
+    // The audio files without a complete copy in the destination
+    let mut unverified = Vec::new();
+    // The source and destination of every moved file, to move them back if the book is restored
+    let mut moved = Vec::new();
+    let transfer_errors = summary.errors.len();
+    for op in &action.files {
+        let op_errors = summary.errors.len();
+        match op.action {
+            FileAction::Move => {
+                let from = if renamed {
+                    renamed_path(&action, &op.from)
+                } else {
+                    op.from.clone()
+                };
+                if move_file(&from, &op.to, &mut checksums, summary) {
+                    moved.push((from, op.to.as_str()));
+                    if !cfg.rename_only {
+                        summary.freed_bytes += op.bytes;
+                    }
+                }
+            }
+            FileAction::Copy => {
+                copy_file(&op.from, &op.to, &mut checksums, summary);
+            }
+            FileAction::Symlink => {
+                symlink_file(&op.from, &op.to, summary);
+            }
+            FileAction::Hardlink => {
+                hardlink_file(&op.from, &op.to, summary);
+            }
+            FileAction::Skip => skip_file(&op.from, &op.to, summary),
+        }
+        let error = summary.errors[op_errors..].last().cloned();
+        if is_audio_file(&op.from, &cfg.file_ext) && !has_counterpart(op, error.is_some()) {
+            unverified.push(op.from.display().to_string());
+        }
+        record_operation(op, error, summary);
+        if op.action == FileAction::Skip {
+            continue;
+        }
+        summary.files += 1;
+        summary.bytes += op.bytes;
+    }
+
+    if renamed {
+        remove_empty_dirs(Path::new(&action.to));
+    }
+    if let Some(aside) = replaced {
+        if summary.errors.len() > transfer_errors || !unverified.is_empty() {
+            restore_book(&action.to, &aside, &moved, summary);
+            return;
+        }
+        let result = retry("delete", || fs::remove_dir_all(&aside)).map_err(|e| e.to_string());
+        audit::record(
+            "delete",
+            Path::new(&aside),
+            Path::new(""),
+            result.as_ref().err().map(String::as_str),
+        );
+        match result {
+            Ok(_) => println!("{} {}", "Replaced Existing Book:".yellow(), action.to),
+            Err(err) => eprintln!(
+                "{} {}: {}",
+                "Error removing replaced book:".red(),
+                aside.yellow(),
+                err
+            ),
+        }
+    }
+    let book_bytes = action
+        .files
+        .iter()
+        .filter(|op| op.action != FileAction::Skip)
+        .map(|op| op.bytes)
+        .sum();
+    summary.count_source(&action.source, 1, book_bytes);
+
+    write_description(&action, summary);
+    write_playlist(&action, summary);
+    if cfg.checksums {
+        write_checksums(&action, &checksums, summary);
+    }
+
+    if summary.errors.len() == errors {
+        summary.organized.push(action.from.clone());
+    }
+    let recorded = database.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+    if summary.errors.len() == errors && recorded {
+        // The files are hashed before locking, so the other books keep going meanwhile
+        let files = database::book_files(&action.to, &checksums, cfg.buffer_size);
+        if let Some(database) = database.lock().unwrap_or_else(|e| e.into_inner()).as_mut()
+            && let Err(err) =
+                database.record_book(&action.metadata, &action.to, &action.root, &files)
+        {
+            eprintln!("{} {}", "Error recording book in the database:".red(), err);
+            summary.error(
+                Category::Io,
+                format!("'{}' was not recorded: {}", action.to, err),
+            );
+        }
+    }
+
+    if action.action == ActionOpt::All {
+        let linked = std::iter::once(&action.from)
+            .chain(&action.parts)
+            .find(|dir| fsutil::through_symlink(Path::new(dir), Path::new(&action.source)));
+        let safe = if let Some(dir) = linked {
+            Err(format!(
+                "'{}' is reached through a symbolic link, deleting it would delete the linked data",
+                dir
+            ))
+        } else if let Some(file) = unverified.first() {
+            Err(format!(
+                "{} audio files have no verified copy in the destination, e.g. '{}'",
+                unverified.len(),
+                file
+            ))
+        } else if cfg.keep_source_on_error && summary.errors.len() > errors {
+            Err(format!(
+                "{} errors occurred while processing the book",
+                summary.errors.len() - errors
+            ))
+        } else {
+            verify_destination(&action)
+        };
+        if let Err(err) = safe {
+            eprintln!("{} {}", "Not deleting source directory:".red(), err);
+            audit::record("delete", Path::new(&action.from), Path::new(""), Some(&err));
+            summary.error(
+                Category::Verification,
+                format!("'{}' was not deleted: {}", action.from, err),
+            );
+            return;
+        }
+
+        // A renamed book directory is already gone from the source
+        let sources = std::iter::once(&action.from).filter(|_| !renamed);
+        for source in sources.chain(&action.parts) {
+            if action.left_out.iter().any(|file| file.starts_with(source)) {
+                println!(
+                    "{} {} (other files were left in it)",
+                    "Kept:".yellow(),
+                    source
+                );
+                continue;
+            }
+            let result = retry("delete", || fs::remove_dir_all(source)).map_err(|e| e.to_string());
+            audit::record(
+                "delete",
+                Path::new(source),
+                Path::new(""),
+                result.as_ref().err().map(String::as_str),
+            );
+            match result {
+                Ok(_) => println!("{} {}", "Deleted:".yellow(), source),
+                Err(err) => eprintln!("{} {}", "Error deleting old directory:".red(), err),
+            }
+        }
+
+        for dir in std::iter::once(&action.from).chain(&action.parts) {
+            prune_empty_parents(Path::new(dir), &action.source);
+        }
+    }
+}
+
+/**
+ * Renames an existing book directory aside, so it can be put back if replacing it
+ * fails.
+ *
+ * @param to The directory of the existing book.
+ * @return The path the book was renamed to, or the error.
+ */
+fn set_aside_book(to: &str) -> Result<String, String> {
+    let aside = format!("{}.{}", to, REPLACED_EXTENSION);
+    if fs::exists(&aside).unwrap_or(true) {
+        return Err(format!("'{}' is in the way", aside));
+    }
+    let result = retry("move", || fs::rename(to, &aside)).map_err(|e| e.to_string());
+    audit::record(
+        "move",
+        Path::new(to),
+        Path::new(&aside),
+        result.as_ref().err().map(String::as_str),
+    );
+    result.map(|_| aside)
+}
+
+/**
+ * Puts a book that was set aside back in place after its replacement failed. The
+ * files that were moved in are moved back to the source first, and the rest of
+ * the incomplete replacement is removed.
+ *
+ * @param to The directory of the book.
+ * @param aside The path the existing book was renamed to.
+ * @param moved The source and destination of every file moved into the book.
+ * @param summary The summary that collects the results of the run.
+ */
+fn restore_book(to: &str, aside: &str, moved: &[(PathBuf, &str)], summary: &mut Summary) {
+    eprintln!(
+        "{} {}",
+        "Replacing the existing book failed, restoring:".red(),
+        to.yellow()
+    );
+    let mut checksums = HashMap::new();
+    let mut kept = false;
+    for (from, dest) in moved {
+        kept |= !move_file(
+            Path::new(dest),
+            &from.to_string_lossy(),
+            &mut checksums,
+            summary,
+        );
+    }
+    if kept {
+        // Deleting the directory would lose the files that could not be moved back
+        let err = format!("files moved into '{}' could not be moved back", to);
+        eprintln!(
+            "{} {}, the old book is kept in {}",
+            "Error:".red(),
+            err,
+            aside.yellow()
+        );
+        summary.error(Category::Io, format!("'{}' was not restored: {}", to, err));
+        return;
+    }
+
+    let result = retry("delete", || fs::remove_dir_all(to))
+        .and_then(|_| retry("move", || fs::rename(aside, to)))
+        .map_err(|e| e.to_string());
+    audit::record(
+        "move",
+        Path::new(aside),
+        Path::new(to),
+        result.as_ref().err().map(String::as_str),
+    );
+    match result {
+        Ok(_) => println!("{} {}", "Restored Existing Book:".yellow(), to),
+        Err(err) => {
+            eprintln!("{} {}", "Error restoring existing book:".red(), err);
+            summary.error(
+                Category::Io,
+                format!("'{}' was not restored from '{}': {}", to, aside, err),
+            );
+        }
+    }
+}
+
+/**
+ * Removes the directories left empty above a deleted book directory, up to (but
+ * not including) the source root the book was found in. Without a known source
+ * root, only the parent directory of the book is removed.
+ *
+ * @param book_dir The deleted book directory.
+ * @param source The source root the book was found in, or an empty string.
+ */
+fn prune_empty_parents(book_dir: &Path, source: &str) {
+    let source = Path::new(source);
+    let mut parent = book_dir.parent();
+    while let Some(p) = parent
+        && !p.as_os_str().is_empty()
+        && p.exists()
+    {
+        if !source.as_os_str().is_empty() && (p == source || !p.starts_with(source)) {
+            break;
+        }
+        // Never delete the directories a link points to
+        if fsutil::through_symlink(p, source) {
+            break;
+        }
+
+        // Remove junk files before atempting to delete the directory
+        fsutil::remove_junk(p, CONFIG.get().is_some_and(|cfg| cfg.skip_hidden));
+
+        match fs::remove_dir(p) {
+            Ok(_) => {
+                audit::record("delete", p, Path::new(""), None);
+                println!("{} '{:?}'", "Deleted:".yellow(), p)
+            }
+            Err(_) => {
+                eprintln!("{} {:?}", "Unempty directory, not deleting:".yellow(), p);
+                break;
+            }
+        }
+        if source.as_os_str().is_empty() {
+            break;
+        }
+        parent = p.parent();
+    }
+}
+
+/**
+ * Emits the progress event for a book that is about to be processed.
+ *
+ * @param action The plan of the book.
+ * @param index The position of the book in the run, starting at 0.
+ * @param total The number of books in the run.
+ */
+fn emit_book_started(action: &Plan, index: usize, total: usize) {
+    progress::emit(
+        "book_started",
+        json!({
+            "src": action.from,
+            "dst": action.to,
+            "files": action.files.len(),
+            "bytes": action.files.iter().map(|op| op.bytes).sum::<u64>(),
+            "index": index,
+            "total": total,
+        }),
+    );
+}
+
+/**
+ * Checks whether a book can be moved by renaming its whole directory, instead of
+ * moving its files one by one.
+ *
+ * This is the case when all files of the book are moved, the destination doesn't
+ * exist yet and is on the same filesystem, the book directory is not reached
+ * through a link, and it contains no other books and no files that are left out.
+ * The files are renamed to their final names afterwards, so no file may be renamed
+ * onto another file of the book that is still to be renamed.
+ *
+ * @param action The plan of the book.
+ * @return True if the book directory can be renamed.
+ */
+fn can_rename_book(action: &Plan) -> bool {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let (from, to) = (Path::new(&action.from), Path::new(&action.to));
+
+    if !matches!(action.action, ActionOpt::Move | ActionOpt::All)
+        || action.replace_existing
+        || !action.parts.is_empty()
+        || action.files.iter().any(|op| op.action != FileAction::Move)
+        || to.exists()
+        || to.starts_with(from)
+        || std::path::absolute(from).ok() == std::path::absolute(&cfg.from).ok()
+        || !fsutil::same_filesystem(from, to)
+        || fsutil::through_symlink(from, Path::new(&action.source))
+    {
+        return false;
+    }
+
+    // Files that are left out, such as hidden files, would be carried into the library
+    let planned: HashSet<&Path> = action.files.iter().map(|op| op.from.as_path()).collect();
+    let has_other_files = WalkDir::new(from)
+        .min_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .any(|e| {
+            (e.depth() > 1 && is_metafile(e.path()))
+                || is_skipped(&e)
+                || (e.file_type().is_file() && !planned.contains(e.path()))
+        });
+    if has_other_files {
+        return false;
+    }
+
+    let renamed: Vec<PathBuf> = action
+        .files
+        .iter()
+        .map(|op| renamed_path(action, &op.from))
+        .collect();
+    action.files.iter().enumerate().all(|(index, op)| {
+        let to = Path::new(&op.to);
+        renamed
+            .iter()
+            .enumerate()
+            .all(|(other, path)| other == index || path != to)
+    })
+}
+
+/**
+ * Renames the book directory to its destination directory.
+ *
+ * @param action The plan of the book.
+ * @return True if the directory was renamed. If not, nothing was changed and the
+ *         files are moved one by one instead.
+ */
+fn rename_book(action: &Plan) -> bool {
+    let to = Path::new(&action.to);
+    if let Some(parent) = to.parent()
+        && fs::create_dir_all(parent).is_err()
+    {
+        return false;
+    }
+    if fs::rename(&action.from, to).is_err() {
+        return false;
+    }
+
+    audit::record("rename", Path::new(&action.from), to, None);
+    println!(
+        "{} '{}' to '{}'",
+        "Renamed Directory:".green(),
+        action.from,
+        action.to.green()
+    );
+
+    // Moving the files keeps the (now empty) source directory
+    if action.action == ActionOpt::Move
+        && let Err(err) = fs::create_dir(&action.from)
+    {
+        eprintln!("{} {}", "Error recreating the source directory:".red(), err);
+    }
+    true
+}
+
+/**
+ * Gets the path a file of the book has after its directory was renamed.
+ *
+ * @param action The plan of the book.
+ * @param file The original path of the file.
+ * @return The path of the file in the renamed directory.
+ */
+fn renamed_path(action: &Plan, file: &Path) -> PathBuf {
+    let relative = file.strip_prefix(&action.from).unwrap_or(file);
+    Path::new(&action.to).join(relative)
+}
+
+/**
+ * Removes the empty subdirectories of a directory, deepest first.
+ *
+ * @param dir The directory to clean up. It is kept, even if it is empty.
+ */
+fn remove_empty_dirs(dir: &Path) {
+    for entry in WalkDir::new(dir)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        if entry.file_type().is_dir() {
+            fs::remove_dir(entry.path()).unwrap_or(());
+        }
+    }
+}
+
+/**
+ * Writes the book description to the description file, if enabled.
+ *
+ * @param action The plan of the book.
+ * @param summary The summary that collects the results of the run.
+ */
+fn write_description(action: &Plan, summary: &mut Summary) {
+    let (Some(file), Some(description)) = (&action.description_file, &action.metadata.description)
+    else {
+        return;
+    };
+
+    print!("{} '{}'...", "Writing:".blue(), file.green());
+    let result = fs::write(file, format!("{}\n", description.trim())).map_err(|e| e.to_string());
+    audit::record(
+        "write",
+        Path::new(""),
+        Path::new(file),
+        result.as_ref().err().map(String::as_str),
+    );
+    match result {
+        Ok(_) => println!(" Done"),
+        Err(err) => {
+            eprintln!("{} {}", "Error writing the description:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", file, err));
+        }
+    }
+}
+
+/**
+ * Writes the book's playlist into its destination directory, if enabled.
+ *
+ * @param action The plan of the book.
+ * @param summary The summary that collects the results of the run.
+ */
+fn write_playlist(action: &Plan, summary: &mut Summary) {
+    let Some(playlist) = &action.playlist else {
+        return;
+    };
+
+    print!("{} '{}'...", "Writing:".blue(), playlist.file.green());
+    let contents: String = playlist
+        .entries
+        .iter()
+        .map(|entry| format!("{}\n", entry))
+        .collect();
+    let result =
+        fs::write(&playlist.file, format!("#EXTM3U\n{}", contents)).map_err(|e| e.to_string());
+    audit::record(
+        "write",
+        Path::new(""),
+        Path::new(&playlist.file),
+        result.as_ref().err().map(String::as_str),
+    );
+    match result {
+        Ok(_) => println!(" Done"),
+        Err(err) => {
+            eprintln!("{} {}", "Error writing the playlist:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", playlist.file, err));
+        }
+    }
+}
+
+/**
+ * Writes the corrected series and book number back to the book's source metadata
+ * file, if planned. This is done before the files are transferred, so the library
+ * gets the corrected file, while the backup of the original stays in the source.
+ *
+ * @param action The plan of the book.
+ * @param summary The summary that collects the results of the run.
+ */
+fn write_back_metadata(action: &Plan, summary: &mut Summary) {
+    let Some(write_back) = &action.write_back else {
+        return;
+    };
+    let file = &write_back.file;
+
+    print!(
+        "{} '{}'...",
+        "Correcting:".blue(),
+        file.display().to_string().green()
+    );
+    let result = writeback::write_back(file, &write_back.series).map_err(|e| e.to_string());
+    audit::record(
+        "write",
+        Path::new(""),
+        file,
+        result.as_ref().err().map(String::as_str),
+    );
+    match result {
+        Ok(_) => println!(" Done"),
+        Err(err) => {
+            eprintln!("{} {}", "Error correcting the metadata file:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", file.display(), err));
+        }
+    }
+}
+
+/**
+ * Writes the checksum manifest into the book's destination directory.
+ *
+ * @param action The plan of the book.
+ * @param checksums The SHA-256 of the files hashed while they were copied.
+ * @param summary The summary that collects the results of the run.
+ */
+fn write_checksums(action: &Plan, checksums: &HashMap<PathBuf, String>, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let manifest = Path::new(&action.to).join(checksum::MANIFEST_NAME);
+
+    print!(
+        "{} '{}'...",
+        "Writing:".blue(),
+        manifest.display().to_string().green()
+    );
+    let result = checksum::write_manifest(Path::new(&action.to), checksums, cfg.buffer_size)
+        .map_err(|e| e.to_string());
+    audit::record(
+        "write",
+        Path::new(""),
+        &manifest,
+        result.as_ref().err().map(String::as_str),
+    );
+    match result {
+        Ok(_) => println!(" Done"),
+        Err(err) => {
+            eprintln!("{} {}", "Error writing the checksums:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", manifest.display(), err));
+        }
+    }
+}
+
+/**
+ * Adds a file operation to the summary, so it shows up in the report.
+ *
+ * @param op The file operation.
+ * @param error The error message if the operation failed.
+ * @param summary The summary that collects the results of the run.
+ */
+fn record_operation(op: &FileOp, error: Option<String>, summary: &mut Summary) {
+    let event = match (&error, &op.action) {
+        (Some(_), _) => "file_failed",
+        (None, FileAction::Copy) => "file_copied",
+        (None, FileAction::Move) => "file_moved",
+        (None, FileAction::Symlink | FileAction::Hardlink) => "file_linked",
+        (None, FileAction::Skip) => "file_skipped",
+    };
+    progress::emit(
+        event,
+        json!({ "src": op.from, "dst": op.to, "bytes": op.bytes, "error": error }),
+    );
+    if error.is_none() && op.action != FileAction::Skip {
+        seed::record(&op.from, Path::new(&op.to));
+    }
+
+    summary.operations.push(Operation {
+        action: op.action.name(),
+        from: op.from.display().to_string(),
+        to: op.to.clone(),
+        bytes: op.bytes,
+        error,
+    });
+}
+
+/**
+ * Copy a file from one location to another.
+ *
+ * @param file The path of the file to copy.
+ * @param destination_path The path to copy the file to.
+ * @param checksums Collects the SHA-256 computed while copying, keyed by destination.
+ * @param summary The summary that collects the results of the run.
+ * @return True if the file was copied.
+ */
+fn copy_file(
+    file: &Path,
+    destination_path: &str,
+    checksums: &mut HashMap<PathBuf, String>,
+    summary: &mut Summary,
+) -> bool {
+    print!(
+        "\n{} '{}' to '{}'...",
+        "Copying:".blue(),
+        file.to_str().unwrap(),
+        destination_path.green()
+    );
+    let result = transfer_file(file, Path::new(destination_path));
+    if let Ok(Some(sha256)) = &result {
+        checksums.insert(PathBuf::from(destination_path), sha256.clone());
+    }
+    audit::record(
+        "copy",
+        file,
+        Path::new(destination_path),
+        result.as_ref().err().map(String::as_str),
+    );
+
+    match result {
+        Ok(_) => {
+            println!(" Done");
+            true
+        }
+        Err(err) => {
+            eprintln!("{} {}", "Error copying file:".red(), err);
+            summary.error(
+                transfer_category(&err),
+                format!("'{}': {}", file.display(), err),
+            );
+            false
+        }
+    }
+}
+
+/**
+ * Move a file from one location to another.
+ *
+ * If the destination is on another filesystem, the file is copied (and verified
+ * when enabled) before the source is removed.
+ *
+ * @param file The path of the file to move.
+ * @param destination_path The path to move the file to.
+ * @param checksums Collects the SHA-256 computed while copying, keyed by destination.
+ * @param summary The summary that collects the results of the run.
+ * @return True if the file was moved.
+ */
+fn move_file(
+    file: &Path,
+    destination_path: &str,
+    checksums: &mut HashMap<PathBuf, String>,
+    summary: &mut Summary,
+) -> bool {
+    print!(
+        "{} '{}' to '{}'...",
+        "Moving:".blue(),
+        file.to_str().unwrap(),
+        destination_path.green()
+    );
+    let result = match retry("move", || fs::rename(file, destination_path)) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            transfer_file(file, Path::new(destination_path)).and_then(|sha256| {
+                if let Some(sha256) = sha256 {
+                    checksums.insert(PathBuf::from(destination_path), sha256);
+                }
+                retry("delete", || fs::remove_file(file)).map_err(|e| e.to_string())
+            })
+        }
+        result => result.map_err(|e| e.to_string()),
+    };
+    audit::record(
+        "move",
+        file,
+        Path::new(destination_path),
+        result.as_ref().err().map(String::as_str),
+    );
+
+    match result {
+        Ok(_) => {
+            println!(" Done");
+            true
+        }
+        Err(err) => {
+            eprintln!("{} {}", "Error moving file:".red(), err);
+            summary.error(
+                transfer_category(&err),
+                format!("'{}': {}", file.display(), err),
+            );
+            false
+        }
+    }
+}
+
+/**
+ * Create a symlink in the destination pointing to the source file.
+ *
+ * @param file The path of the file to link to.
+ * @param destination_path The path of the link.
+ * @param summary The summary that collects the results of the run.
+ * @return True if the link was created.
+ */
+fn symlink_file(file: &Path, destination_path: &str, summary: &mut Summary) -> bool {
+    print!(
+        "{} '{}' to '{}'...",
+        "Linking:".blue(),
+        file.to_str().unwrap(),
+        destination_path.green()
+    );
+    let result = retry("symlink", || {
+        link::symlink(file, Path::new(destination_path))
+    })
+    .map_err(|e| e.to_string());
+    audit::record(
+        "symlink",
+        file,
+        Path::new(destination_path),
+        result.as_ref().err().map(String::as_str),
+    );
+
+    match result {
+        Ok(_) => {
+            println!(" Done");
+            true
+        }
+        Err(err) => {
+            eprintln!("{} {}", "Error linking file:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", file.display(), err));
+            false
+        }
+    }
+}
+
+/**
+ * Create a hard link in the destination for the source file.
+ *
+ * @param file The path of the file to link to.
+ * @param destination_path The path of the link.
+ * @param summary The summary that collects the results of the run.
+ * @return True if the link was created.
+ */
+fn hardlink_file(file: &Path, destination_path: &str, summary: &mut Summary) -> bool {
+    print!(
+        "{} '{}' to '{}'...",
+        "Linking:".blue(),
+        file.to_str().unwrap(),
+        destination_path.green()
+    );
+    let result = retry("hardlink", || {
+        link::hardlink(file, Path::new(destination_path))
+    })
+    .map_err(|e| e.to_string());
+    audit::record(
+        "hardlink",
+        file,
+        Path::new(destination_path),
+        result.as_ref().err().map(String::as_str),
+    );
+
+    match result {
+        Ok(_) => {
+            println!(" Done");
+            true
+        }
+        Err(err) => {
+            eprintln!("{} {}", "Error linking file:".red(), err);
+            summary.error(Category::Io, format!("'{}': {}", file.display(), err));
+            false
+        }
+    }
+}
+
+/**
+ * Report a file that is skipped because its destination already exists.
+ *
+ * @param file The path of the skipped file.
+ * @param destination_path The conflicting destination path.
+ * @param summary The summary that collects the results of the run.
+ */
+fn skip_file(file: &Path, destination_path: &str, summary: &mut Summary) {
+    println!(
+        "{} '{}', '{}' already exists",
+        "Skipping:".yellow(),
+        file.to_str().unwrap(),
+        destination_path.yellow()
+    );
+    audit::record(
+        "skip",
+        file,
+        Path::new(destination_path),
+        Some("already exists"),
+    );
+    let conflict = format!("'{}' -> '{}'", file.display(), destination_path);
+    summary.problem(Category::Conflict, conflict.clone());
+    summary.conflicts.push(conflict);
+}
+
+/**
+ * Copies a file with the copy backend and verifies the written file when enabled.
+ *
+ * The data is hashed while copying when `--verify` or `--checksums` is set.
+ *
+ * @param file The path of the file to copy.
+ * @param destination The path to copy the file to.
+ * @return The SHA-256 of the copied data, if it was hashed, or an error message.
+ */
+fn transfer_file(file: &Path, destination: &Path) -> Result<Option<String>, String> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    let source_bytes = fs::metadata(file).map_err(|e| e.to_string())?.len();
+    let hash = cfg.verify || cfg.checksums;
+    let result = retry("copy", || {
+        copy::copy_file(file, destination, cfg.buffer_size, hash)
+    })
+    .map_err(|e| e.to_string())?;
+    if result.bytes != source_bytes {
+        return Err(format!(
+            "incomplete copy, wrote {} of {} bytes",
+            result.bytes, source_bytes
+        ));
+    }
+    if cfg.verify
+        && let Some(expected) = &result.sha256
+    {
+        let actual = copy::hash_file(destination, cfg.buffer_size).map_err(|e| e.to_string())?;
+        if &actual != expected {
+            return Err(format!(
+                "verification failed, checksum mismatch for '{}'",
+                destination.display()
+            ));
+        }
+    }
+
+    Ok(result.sha256)
+}
+
+/**
+ * Gets the category of an error of `transfer_file`: a copy that was written but
+ * doesn't match its source is a verification mismatch, anything else is an IO
+ * failure.
+ *
+ * @param err The error message.
+ * @return The category of the error.
+ */
+fn transfer_category(err: &str) -> Category {
+    if err.starts_with("incomplete copy") || err.starts_with("verification failed") {
+        Category::Verification
+    } else {
+        Category::Io
+    }
+}
+
+/**
+ * Simulates the actions that would be performed during the process.
+ *
+ * This function prints the planned operations (e.g., file moves, deletions) without executing them.
+ *
+ * @param actions A vector of `Plan` objects representing the operations to simulate.
+ * @param summary The summary that collects the results of the run.
+ */
+fn dry_run(actions: Vec<Plan>, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    let total = actions.len();
+    for (index, action) in actions.iter().enumerate() {
+        println!("--\n");
+        if cfg.check_audio && !check_book(action, summary) {
+            continue;
+        }
+        summary.books += 1;
+        emit_book_started(action, index, total);
+
+        if action.replace_existing {
+            println!("{} {}", "Replaced Existing Book:".yellow(), action.to);
+        }
+
+        if can_rename_book(action) {
+            println!(
+                "{} '{}' to '{}'",
+                "Renamed Directory:".green(),
+                action.from,
+                action.to.green()
+            );
+        } else if !fs::exists(&action.to).unwrap_or(false) {
+            println!("{} {}", "Created Directory:".green(), action.to);
+        }
+
+        // The renames are shown side by side, files that keep their name are only
+        // counted, so the actual renames stand out
+        let mut book_bytes: u64 = 0;
+        let mut label = None;
+        let mut renames: Vec<(String, String)> = Vec::new();
+        let mut unchanged = 0;
+        for op in &action.files {
+            let op_label = match op.action {
+                FileAction::Move if cfg.rename_only => "Moving:",
+                FileAction::Move => {
+                    summary.freed_bytes += op.bytes;
+                    "Moving:"
+                }
+                FileAction::Copy => "Copying:",
+                FileAction::Symlink | FileAction::Hardlink => "Linking:",
+                FileAction::Skip => {
+                    skip_file(&op.from, &op.to, summary);
+                    record_operation(op, None, summary);
+                    continue;
+                }
+            };
+            record_operation(op, None, summary);
+            label = Some(op_label);
+            book_bytes += op.bytes;
+            summary.files += 1;
+
+            let old = std::iter::once(&action.from)
+                .chain(&action.parts)
+                .find_map(|dir| op.from.strip_prefix(dir).ok())
+                .unwrap_or(&op.from)
+                .display()
+                .to_string();
+            let new = Path::new(&op.to)
+                .strip_prefix(&action.to)
+                .unwrap_or(Path::new(&op.to))
+                .display()
+                .to_string();
+            // A file renamed through a temporary name is shown as a single rename
+            let temporary = op.from.extension() == Some(TEMPORARY_EXTENSION.as_ref());
+            if temporary
+                && let Some(rename) = renames
+                    .iter_mut()
+                    .find(|(_, to)| op.from.file_name() == Some(to.as_ref()))
+            {
+                rename.1 = new;
+            } else if old == new {
+                unchanged += 1;
+            } else {
+                renames.push((old, new));
+            }
+        }
+
+        if let Some(label) = label {
+            println!(
+                "{} '{}' to '{}'",
+                label.blue(),
+                action.from,
+                action.to.green()
+            );
+        }
+        let width = renames
+            .iter()
+            .map(|(old, _)| old.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (old, new) in &renames {
+            println!("{}", diff::rename_line(old, new, width));
+        }
+        match unchanged {
+            0 => {}
+            1 => println!("  1 file unchanged"),
+            _ => println!("  {} files unchanged", unchanged),
+        }
+
+        if let Some(description_file) = &action.description_file {
+            println!("{} '{}'", "Writing:".blue(), description_file.green());
+        }
+        if let Some(playlist) = &action.playlist {
+            println!("{} '{}'", "Writing:".blue(), playlist.file.green());
+        }
+        if let Some(write_back) = &action.write_back {
+            println!(
+                "{} '{}' (series '{}')",
+                "Correcting:".blue(),
+                write_back.file.display().to_string().green(),
+                write_back.series
+            );
+        }
+        if cfg.checksums {
+            println!(
+                "{} '{}/{}'",
+                "Writing:".blue(),
+                action.to.green(),
+                checksum::MANIFEST_NAME.green()
+            );
+        }
+
+        if !action.completeness.missing.is_empty() {
+            println!(
+                "{} {}% (missing {})",
+                "Metadata:".blue(),
+                action.completeness.score,
+                action.completeness.missing.join(", ")
+            );
+        }
+        println!("{} {}", "Book size:".blue(), format_size(book_bytes));
+        summary.bytes += book_bytes;
+        summary.count_source(&action.source, 1, book_bytes);
+
+        if action.action == ActionOpt::All {
+            println!("{} {:?}", "Deleted:".yellow(), action.from);
+        }
+    }
+
+    summary.destination_free = cfg
+        .pool
+        .iter()
+        .map(|root| fsutil::available_space(Path::new(root)))
+        .sum();
+    // Suggest what to replace rather than only reporting that the import doesn't fit
+    if summary
+        .destination_free
+        .is_some_and(|free| summary.bytes > free)
+    {
+        summary.prune_suggestions = prune::suggest_pruning(&actions);
+    }
+}
+
+/**
+ * Verifies that the destination of a book holds at least as many audio files,
+ * totaling at least as many bytes, as the source had before it was moved.
+ *
+ * @param action The plan of the book to verify.
+ * @return An error describing the mismatch if the destination is incomplete.
+ */
+fn verify_destination(action: &Plan) -> Result<(), String> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    let (source_count, source_bytes) = action
+        .files
+        .iter()
+        .filter(|op| is_audio_file(&op.from, &cfg.file_ext))
+        .fold((0, 0), |(count, bytes), op| (count + 1, bytes + op.bytes));
+
+    let (dest_count, dest_bytes) = get_files(&action.to)
+        .iter()
+        .filter(|file| is_audio_file(file, &cfg.file_ext))
+        .fold((0, 0), |(count, bytes), file| {
+            let size = fs::metadata(file).map(|m| m.len()).unwrap_or(0);
+            (count + 1, bytes + size)
+        });
+
+    if dest_count < source_count || dest_bytes < source_bytes {
+        return Err(format!(
+            "destination has {} audio files ({}), but the source had {} ({})",
+            dest_count,
+            format_size(dest_bytes),
+            source_count,
+            format_size(source_bytes)
+        ));
+    }
+
+    Ok(())
+}
+
+/**
+ * Checks whether a file operation left a complete copy of the file at its
+ * destination, so the source file can safely be deleted.
+ *
+ * @param op The file operation.
+ * @param failed Whether the operation reported an error.
+ * @return True if the operation succeeded and the destination file has the size of
+ *         the source file.
+ */
+fn has_counterpart(op: &FileOp, failed: bool) -> bool {
+    !failed && fs::metadata(&op.to).is_ok_and(|m| m.len() == op.bytes)
+}
+
+/**
+ * Retrieves a list of audio files from the specified directory. macOS junk, such
+ * as AppleDouble `._*` files, and hidden files with `--skip-hidden` are left out so
+ * they never end up in the library.
+ *
+ * @param dir The directory to search for files.
+ * @return A vector of `PathBuf` objects representing the audio files found.
+ */
+fn get_files(dir: &String) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let dir = Path::new(dir);
+
+    let follow_symlinks = CONFIG.get().is_some_and(|cfg| cfg.follow_symlinks);
+    for file in WalkDir::new(dir)
+        .follow_links(follow_symlinks)
+        .into_iter()
+        .filter_entry(|e| !is_skipped(e))
+    {
+        // e.g. a link loop, which would otherwise be followed forever
+        let file = match file {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("{} {}", "Warning:".yellow(), err);
+                continue;
+            }
+        };
+        let path = file.path();
+
+        if path.is_file() {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    files
+}
+
+/**
+ * Checks whether a walked file or directory is left out: macOS junk, and hidden
+ * files and directories with `--skip-hidden`. The directory the walk starts from
+ * is never left out.
+ *
+ * @param entry The file or directory.
+ * @return True if the entry and everything below it is skipped.
+ */
+fn is_skipped(entry: &walkdir::DirEntry) -> bool {
+    let skip_hidden = CONFIG.get().is_none_or(|cfg| cfg.skip_hidden);
+    entry.depth() > 0
+        && (fsutil::is_junk(entry.path()) || (skip_hidden && fsutil::is_hidden(entry.path())))
+}
+
+/**
+ * Leaves a book that is still being downloaded out of the run, so it is checked
+ * again in the next run or watch batch. It is listed as still downloading, or with
+ * the skipped books with `--incomplete skip`.
+ *
+ * @param from The source directory of the book.
+ * @param reason Why the book is not complete, e.g. "still downloading ('a.part')".
+ * @param summary The summary that collects the results of the run.
+ */
+fn defer_book(from: String, reason: String, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    if cfg.incomplete == IncompleteDownloads::Skip {
+        if !cfg.quiet {
+            println!(
+                "{} '{}' is {}, skipping it",
+                "Warning:".yellow(),
+                from,
+                reason
+            );
+        }
+        summary.skipped_books.push((from, reason));
+    } else {
+        if !cfg.quiet {
+            println!(
+                "{} '{}' is {}, it is left for a later run",
+                "Deferred:".blue(),
+                from,
+                reason
+            );
+        }
+        summary.downloading.push(from);
+    }
+}
+
+/**
+ * Checks whether a file has one of the configured audio file extensions, ignoring case.
+ * With `--detect-types`, files with another extension or none are checked by their
+ * content.
+ *
+ * @param file The file to check.
+ * @param file_ext The list of audio file extensions.
+ * @return True if the file is an audio file.
+ */
+fn is_audio_file(file: &Path, file_ext: &[String]) -> bool {
+    file.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| file_ext.iter().any(|ext| ext.eq_ignore_ascii_case(e)))
+        || (CONFIG.get().is_some_and(|cfg| cfg.detect_types)
+            && sniff::audio_extension(file, file_ext).is_some())
+}
+
+/**
+ * Checks every audio file of a book for corruption before it is processed.
+ *
+ * If any file fails, the book is recorded in the summary and, when a quarantine
+ * directory is configured, the book's source directory is moved into it.
+ *
+ * @param action The plan of the book to check.
+ * @param summary The summary that collects the results of the run.
+ * @return True if all audio files passed the check.
+ */
+fn check_book(action: &Plan, summary: &mut Summary) -> bool {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+
+    let mut failures = Vec::new();
+    for op in &action.files {
+        let file = &op.from;
+        if !is_audio_file(file, &cfg.file_ext) {
+            continue;
+        }
+        print!("{} '{}'...", "Checking:".blue(), file.display());
+        match integrity::check_audio(file, cfg.deep_check) {
+            Ok(_) => println!(" OK"),
+            Err(err) => {
+                println!(" {}", "Failed".red());
+                failures.push(format!("'{}': {}", file.display(), err));
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        return true;
+    }
+
+    eprintln!(
+        "{} '{}'",
+        "Error: Corrupt audio found, skipping book".red(),
+        action.from.yellow()
+    );
+    if let Some(quarantine) = &cfg.quarantine {
+        for source in std::iter::once(&action.from).chain(&action.parts) {
+            let dir_name = Path::new(source).file_name().unwrap_or_default();
+            let destination = Path::new(quarantine).join(dir_name);
+            if cfg.dry_run {
+                println!("{} '{}'", "Quarantined:".yellow(), destination.display());
+                continue;
+            }
+            let result = fs::create_dir_all(quarantine)
+                .and_then(|_| fs::rename(source, &destination))
+                .map_err(|e| e.to_string());
+            audit::record(
+                "quarantine",
+                Path::new(source),
+                &destination,
+                result.as_ref().err().map(String::as_str),
+            );
+            match result {
+                Ok(_) => println!("{} '{}'", "Quarantined:".yellow(), destination.display()),
+                Err(err) => eprintln!("{} {}", "Error quarantining book:".red(), err),
+            }
+        }
+    }
+
+    summary
+        .quarantined
+        .push((action.from.clone(), failures.join(", ")));
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_only() {
+        let _lock = TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-plan-only-{}", std::process::id()));
+        let book = dir.join("src/Mistborn");
+        let unreadable = dir.join("src/Broken");
+        fs::create_dir_all(&book).unwrap();
+        fs::create_dir_all(&unreadable).unwrap();
+        fs::write(
+            book.join("metadata.json"),
+            r#"{"title":"Mistborn","authors":["Brandon Sanderson"]}"#,
+        )
+        .unwrap();
+        fs::write(book.join("01.mp3"), "audio").unwrap();
+        // Not valid UTF-8, so the file can't be read
+        fs::write(unreadable.join("metadata.json"), [0xff, 0xfe, 0xfd]).unwrap();
+        fs::write(unreadable.join("01.mp3"), "audio").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let args = Args::try_parse_from(["aborg", "-s", "/previous", "-d", "/library"]).unwrap();
+        CONFIG.replace(build_config(&args, &FileConfig::default(), ActionOpt::None).unwrap());
+
+        let plans = plan_only(["aborg", "-s", &path("src"), "-d", &path("lib")]).unwrap();
+        // The book with the unreadable metadata file is left out
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].to, path("lib/Brandon Sanderson/Mistborn"));
+        assert!(
+            plans[0]
+                .files
+                .iter()
+                .any(|file| file.from == book.join("01.mp3"))
+        );
+        // The config of the caller is restored
+        assert_eq!(CONFIG.get().unwrap().from, "/previous");
+
+        let result = plan_only(["aborg", "-s", &path("src"), "--merge-strategy", "prompt"]);
+        assert!(result.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_settle() {
+        let _lock = TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-settle-{}", std::process::id()));
+        let (fresh, future) = (dir.join("src/Fresh"), dir.join("src/Future"));
+        for (book, title) in [(&fresh, "Fresh"), (&future, "Future")] {
+            fs::create_dir_all(book).unwrap();
+            fs::write(
+                book.join("metadata.json"),
+                format!(r#"{{"title":"{}","authors":["A"]}}"#, title),
+            )
+            .unwrap();
+            fs::write(book.join("01.mp3"), "audio").unwrap();
+        }
+        // A clock that is ahead dated the files of this book into the future
+        let tomorrow = SystemTime::now() + Duration::from_secs(24 * 60 * 60);
+        for name in ["metadata.json", "01.mp3"] {
+            let file = fs::File::options()
+                .write(true)
+                .open(future.join(name))
+                .unwrap();
+            file.set_modified(tomorrow).unwrap();
+        }
+        let path = |name: &str| dir.join(name).display().to_string();
+        let titles = |plans: Vec<Plan>| -> Vec<String> {
+            let mut titles: Vec<String> = plans.into_iter().map(|p| p.metadata.title).collect();
+            titles.sort();
+            titles
+        };
+
+        let args = [
+            "aborg",
+            "-s",
+            &path("src"),
+            "-d",
+            &path("lib"),
+            "--settle",
+            "600",
+        ];
+        assert_eq!(titles(plan_only(args).unwrap()), vec!["Future"]);
+        // Importing incomplete downloads imports unsettled books too
+        let mut import = args.to_vec();
+        import.extend(["--incomplete", "import"]);
+        assert_eq!(titles(plan_only(import).unwrap()), vec!["Fresh", "Future"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_existing_restored() {
+        let _lock = TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-replace-{}", std::process::id()));
+        let (book, existing) = (dir.join("src/Mistborn"), dir.join("lib/A/Mistborn"));
+        fs::create_dir_all(&book).unwrap();
+        fs::create_dir_all(&existing).unwrap();
+        fs::write(
+            book.join("metadata.json"),
+            r#"{"title":"Mistborn","authors":["A"]}"#,
+        )
+        .unwrap();
+        fs::write(book.join("01.mp3"), "new audio").unwrap();
+        fs::write(book.join("02.mp3"), "new audio").unwrap();
+        fs::write(existing.join("01.mp3"), "old audio").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let args = [
+            "aborg",
+            "-s",
+            &path("src"),
+            "-d",
+            &path("lib"),
+            "--action",
+            "1",
+            "--merge-strategy",
+            "replace-all",
+        ];
+        let config = build_config(
+            &Args::try_parse_from(args).unwrap(),
+            &FileConfig::default(),
+            ActionOpt::Move,
+        )
+        .unwrap();
+        CONFIG.replace(config);
+        let plans = plan_only(args).unwrap();
+        assert!(plans[0].replace_existing);
+
+        // A file that went missing after planning fails the replacement
+        fs::remove_file(book.join("02.mp3")).unwrap();
+        let mut summary = Summary::default();
+        execute(plans, &[path("src")], &mut summary);
+
+        assert!(!summary.errors.is_empty());
+        assert!(summary.organized.is_empty());
+        assert_eq!(
+            fs::read_to_string(existing.join("01.mp3")).unwrap(),
+            "old audio"
+        );
+        assert!(!existing.join("metadata.json").exists());
+        assert!(!dir.join("lib/A/Mistborn.aborg-replaced").exists());
+        // The files that were moved in are back in the source
+        assert_eq!(
+            fs::read_to_string(book.join("01.mp3")).unwrap(),
+            "new audio"
+        );
+        assert!(book.join("metadata.json").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
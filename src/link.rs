@@ -0,0 +1,95 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/**
+ * Creates a symbolic link at `link` pointing to `target`.
+ *
+ * On Windows, directories are linked with a junction, which doesn't need any
+ * special privileges, and files are linked with a file symlink. A missing
+ * `SeCreateSymbolicLinkPrivilege` is reported with an explanation of how to fix it.
+ *
+ * @param target The existing file or directory the link points to.
+ * @param link The path of the link to create.
+ * @return `Ok(())` if the link was created.
+ */
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    // Relative targets would be resolved from the link's directory, so always link to
+    // the absolute path.
+    let target = target.canonicalize()?;
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&target, link)
+    }
+
+    #[cfg(windows)]
+    {
+        if target.is_dir() {
+            return junction(&target, link);
+        }
+
+        // ERROR_PRIVILEGE_NOT_HELD
+        std::os::windows::fs::symlink_file(&target, link).map_err(|err| {
+            if err.raw_os_error() == Some(1314) {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "creating file symlinks requires the SeCreateSymbolicLinkPrivilege, \
+                     enable Developer Mode in the Windows settings or run aborg as an administrator",
+                )
+            } else {
+                err
+            }
+        })
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, link);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symlinks are not supported on this platform",
+        ))
+    }
+}
+
+/**
+ * Creates a directory junction on Windows using `mklink /J`.
+ *
+ * @param target The existing directory the junction points to.
+ * @param link The path of the junction to create.
+ * @return `Ok(())` if the junction was created.
+ */
+#[cfg(windows)]
+fn junction(target: &Path, link: &Path) -> io::Result<()> {
+    let output = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(target)
+        .output()?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "mklink /J failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/**
+ * Creates a hard link at `link` for the file `target`.
+ *
+ * Both paths must be on the same filesystem. Hard links keep working when the
+ * original file is moved or deleted, so a torrent client can keep seeding it.
+ *
+ * @param target The existing file to link to.
+ * @param link The path of the link to create.
+ * @return `Ok(())` if the link was created.
+ */
+pub fn hardlink(target: &Path, link: &Path) -> io::Result<()> {
+    fs::hard_link(target, link)
+}
@@ -0,0 +1,117 @@
+use crate::summary::{Operation, Summary};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The header line of the mapping file.
+const HEADER: &str = "original_path,new_path,action";
+
+/**
+ * Writes a CSV file that maps the original path of every file the run touched to
+ * its new path, so other tools can update their references to the files.
+ *
+ * Failed and skipped files are left out. A file that was moved more than once
+ * (e.g. through a temporary name) is listed once, with its final path. Paths are
+ * absolute.
+ *
+ * @param path The path of the CSV file to write.
+ * @param summary The results of the run.
+ * @return `Ok(())` if the mapping was written, or an error message.
+ */
+pub fn write_mapping(path: &str, summary: &Summary) -> Result<(), String> {
+    let mut csv = String::from(HEADER);
+    csv.push('\n');
+    for (from, to, action) in mapping(&summary.operations) {
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            csv_field(&absolute(&from)),
+            csv_field(&absolute(&to)),
+            action
+        ));
+    }
+    fs::write(path, csv).map_err(|e| e.to_string())
+}
+
+/**
+ * Builds the mapping rows from the file operations of a run.
+ *
+ * @param operations The file operations, in the order they were performed.
+ * @return The original path, new path and action of each file.
+ */
+fn mapping(operations: &[Operation]) -> Vec<(String, String, &'static str)> {
+    let mut rows: Vec<(String, String, &'static str)> = Vec::new();
+    // The row of each current path, so a file that is moved again is found at once
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for op in operations {
+        if op.error.is_some() || op.action == "skip" {
+            continue;
+        }
+        match index.remove(&op.from) {
+            Some(row) => {
+                rows[row].1 = op.to.clone();
+                index.insert(op.to.clone(), row);
+            }
+            None => {
+                index.entry(op.to.clone()).or_insert(rows.len());
+                rows.push((op.from.clone(), op.to.clone(), op.action));
+            }
+        }
+    }
+    rows
+}
+
+/**
+ * Makes a path absolute, so the mapping doesn't depend on the working directory.
+ */
+fn absolute(path: &str) -> String {
+    std::path::absolute(Path::new(path))
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/**
+ * Quotes a CSV field if it contains a comma, quote or line break.
+ */
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapping() {
+        let op = |action, from: &str, to: &str, error: Option<&str>| Operation {
+            action,
+            from: from.to_string(),
+            to: to.to_string(),
+            bytes: 0,
+            error: error.map(String::from),
+        };
+        let operations = vec![
+            op("move", "a/1.mp3", "b/1.mp3", None),
+            op("move", "a/2.mp3", "a/2.mp3.aborg-rename", None),
+            op("move", "a/3.mp3", "b/3.mp3", Some("disk full")),
+            op("skip", "a/4.mp3", "b/4.mp3", None),
+            op("move", "a/2.mp3.aborg-rename", "b/2.mp3", None),
+        ];
+        assert_eq!(
+            mapping(&operations),
+            vec![
+                (String::from("a/1.mp3"), String::from("b/1.mp3"), "move"),
+                (String::from("a/2.mp3"), String::from("b/2.mp3"), "move"),
+            ]
+        );
+
+        assert_eq!(csv_field("/books/Title.mp3"), "/books/Title.mp3");
+        assert_eq!(
+            csv_field("/books/Dune, \"Part 1\".mp3"),
+            "\"/books/Dune, \"\"Part 1\"\".mp3\""
+        );
+    }
+}
@@ -1,24 +1,147 @@
+use crate::fsutil::is_junk;
+use crate::isbn;
+use crate::track::{get_movement, get_release_date};
+use crate::{CONFIG, is_audio_file};
 use colored::Colorize;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::process::exit;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Debug, Serialize, Default)]
+/// The default rules used to split a series entry into a series name and book number.
+///
+/// These are tried in order and the first match wins.
+pub const DEFAULT_SERIES_PATTERNS: [&str; 3] = [
+    r"(?i)^book\s+(?P<number>\d+)\s+of\s+(?:the\s+)?(?P<series>.+)$",
+    r"(?i)^(?P<series>.+?),?\s+(?:vol(?:ume)?|bk|book)\.?\s*#?(?P<number>\d+|[IVXLCDM]+)$",
+    r"^(?P<series>.+)\s+#?(?P<number>\d+)$",
+];
+
+#[derive(Deserialize, Debug, Serialize, Default, Clone)]
 /// Represents the raw metadata structure parsed from a JSON file.
 ///
 /// This struct is used as an intermediate representation of metadata
-/// before it is converted into the `Metadata` struct.
-struct RawMetadata {
+/// before it is converted into the `Metadata` struct. Metadata providers
+/// return it, so the metadata of several providers can be merged.
+pub struct RawMetadata {
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub series: Option<Vec<String>>,
+    pub authors: Option<Vec<String>>,
+    pub published_year: Option<String>,
+    pub published_date: Option<String>,
+    pub genres: Option<Vec<String>>,
+    pub language: Option<String>,
+    pub abridged: Option<bool>,
+    pub asin: Option<String>,
+    pub isbn: Option<String>,
+    pub narrators: Option<Vec<String>>,
+    pub description: Option<String>,
+    #[serde(skip)]
+    pub purchase_date: Option<String>,
+}
+
+impl RawMetadata {
+    /**
+     * Fills the fields that are not set with the fields of other metadata.
+     *
+     * @param other The metadata of a provider with a lower precedence.
+     */
+    pub fn merge(&mut self, other: RawMetadata) {
+        fn fill<T>(field: &mut Option<T>, other: Option<T>) {
+            if field.is_none() {
+                *field = other;
+            }
+        }
+
+        if self.title.trim().is_empty() {
+            self.title = other.title;
+        }
+        fill(&mut self.subtitle, other.subtitle);
+        fill(&mut self.series, other.series.filter(|s| !s.is_empty()));
+        fill(&mut self.authors, other.authors.filter(|a| !a.is_empty()));
+        fill(&mut self.published_year, other.published_year);
+        fill(&mut self.published_date, other.published_date);
+        fill(&mut self.genres, other.genres.filter(|g| !g.is_empty()));
+        fill(&mut self.language, other.language);
+        fill(&mut self.abridged, other.abridged);
+        fill(&mut self.asin, other.asin);
+        fill(&mut self.isbn, other.isbn);
+        fill(
+            &mut self.narrators,
+            other.narrators.filter(|n| !n.is_empty()),
+        );
+        fill(&mut self.description, other.description);
+        fill(&mut self.purchase_date, other.purchase_date);
+    }
+}
+
+/// Represents the metadata file written by Libation (an Audible downloader).
+///
+/// Libation stores lists as comma-separated strings, and the series number in a
+/// separate field. It is converted into `RawMetadata` before further processing.
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "PascalCase", default)]
+struct LibationMetadata {
+    audible_product_id: String,
     title: String,
     subtitle: Option<String>,
-    series: Option<Vec<String>>,
-    authors: Option<Vec<String>>,
-    published_year: Option<String>,
-    published_date: Option<String>,
-    genres: Option<Vec<String>>,
+    author_names: Option<String>,
+    narrator_names: Option<String>,
+    series_names: Option<String>,
+    series_order: Option<String>,
+    date_published: Option<String>,
+    date_added: Option<String>,
+    categories_names: Option<String>,
     language: Option<String>,
-    abridged: Option<bool>,
+    is_abridged: Option<bool>,
+    description: Option<String>,
+}
+
+impl From<LibationMetadata> for RawMetadata {
+    fn from(libation: LibationMetadata) -> Self {
+        let split = |list: Option<String>| -> Option<Vec<String>> {
+            let items: Vec<String> = list?
+                .split(", ")
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if items.is_empty() { None } else { Some(items) }
+        };
+        // Dates look like "2011-11-08T00:00:00", only the date is kept
+        let date = |date: Option<String>| date.map(|d| d.chars().take(10).collect::<String>());
+
+        let order = split(libation.series_order).and_then(|o| o.first().cloned());
+        let series = split(libation.series_names).map(|series| {
+            series
+                .into_iter()
+                .take(1)
+                .map(|name| match &order {
+                    Some(order) => format!("{} #{}", name, order),
+                    None => name,
+                })
+                .collect()
+        });
+        let published_date = date(libation.date_published);
+
+        RawMetadata {
+            title: libation.title,
+            subtitle: libation.subtitle.filter(|s| !s.is_empty()),
+            series,
+            authors: split(libation.author_names),
+            published_year: published_date.as_ref().map(|d| d.chars().take(4).collect()),
+            published_date,
+            genres: split(libation.categories_names),
+            language: libation.language,
+            abridged: libation.is_abridged,
+            asin: Some(libation.audible_product_id).filter(|s| !s.is_empty()),
+            isbn: None,
+            narrators: split(libation.narrator_names),
+            description: libation.description,
+            purchase_date: date(libation.date_added),
+        }
+    }
 }
 
 /// Represents the processed metadata for an audiobook.
@@ -26,7 +149,7 @@ struct RawMetadata {
 /// This struct contains detailed information about an audiobook, including
 /// its title, author, series, and other attributes. It is derived from
 /// the `RawMetadata` struct.
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Metadata {
     pub title: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -47,76 +170,589 @@ pub struct Metadata {
     pub genre: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub language: Option<String>,
+    /// The ISO 639-1 code of the language, e.g. `en` or `de`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub abridged: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub asin: Option<String>,
+    /// The ISBN-13, without hyphens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub isbn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub narrator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purchase_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub file_number: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file_number_with_zeros: Option<String>,
+    /// The number of audio files of the book, counted when the book is planned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_total: Option<u16>,
+    /// The name of the source file, without its extension.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    /// The name of the directory the source file is in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_dir: Option<String>,
 }
 
 /**
- * Parses metadata from a JSON file and converts it into a `Metadata` object.
+ * Reads a JSON metadata file (Audiobookshelf or Libation).
  *
  * @param path The file path to the JSON metadata file.
- * @return An `Option` containing the parsed `Metadata` object, or `None` if parsing fails.
+ * @return The raw metadata, or `None` if the file can't be read or parsed.
  */
-pub fn parse_metadata(path: &str) -> Option<Metadata> {
+pub fn read_metadata_file(path: &Path) -> Option<RawMetadata> {
     let file_contents = match fs::read_to_string(path) {
         Ok(contents) => contents,
         Err(e) => {
             eprintln!(
                 "{} '{}'. {}",
                 "Error: Could not read the file".red(),
-                path.yellow(),
+                path.display().to_string().yellow(),
                 e
             );
-            exit(1);
+            return None;
         }
     };
 
-    match serde_json::from_str::<RawMetadata>(&file_contents) {
+    match parse_raw_metadata(&file_contents) {
         Ok(raw_data) => {
-            println!("Successfully parsed metadata file '{}'", path);
-
-            let author = raw_data
-                .authors
-                .and_then(|authors| authors.first().cloned());
-            let genre = raw_data.genres.and_then(|genres| genres.first().cloned());
-            let full_series = raw_data.series.and_then(|series| series.first().cloned());
-            let (series, book_number) = match full_series {
-                Some(s) => {
-                    let re = Regex::new(r"^(.+)\s+#?(\d+)$").unwrap();
-                    if let Some(results) = re.captures(&s) {
-                        let series = Some(results[1].to_string());
-                        let book_number = results[2].parse::<u16>().ok();
-                        (series, book_number)
-                    } else {
-                        (None, None)
-                    }
-                }
-                None => (None, None),
-            };
-
-            Some(Metadata {
-                title: raw_data.title,
-                subtitle: raw_data.subtitle,
-                series,
-                book_number,
-                book_number_with_zeros: None,
-                author,
-                published_year: raw_data.published_year,
-                published_date: raw_data.published_date,
-                genre,
-                language: raw_data.language,
-                abridged: raw_data.abridged,
-                file_number: None,
-                file_number_with_zeros: None,
-            })
+            if !CONFIG.get().is_some_and(|cfg| cfg.quiet) {
+                println!("Successfully parsed metadata file '{}'", path.display());
+            }
+            Some(raw_data)
         }
         Err(_) => {
-            eprintln!("{} '{}'", "Error: Failed to parse file".red(), path);
+            eprintln!(
+                "{} '{}'",
+                "Error: Failed to parse file".red(),
+                path.display()
+            );
             None
         }
     }
 }
+
+/**
+ * Converts raw metadata into a `Metadata` object.
+ *
+ * @param raw_data The raw metadata of the book.
+ * @param book_dir The book directory, used as a fallback for the book number.
+ * @param series_patterns The ordered rules used to split the series entry.
+ * @param series_aliases The canonical series names, keyed by lowercase alias.
+ * @param file_ext The audio file extensions, used to find the movement tags.
+ * @return The processed metadata. An invalid ISBN is dropped with a warning.
+ */
+pub fn build_metadata(
+    raw_data: RawMetadata,
+    book_dir: &Path,
+    series_patterns: &[Regex],
+    series_aliases: &HashMap<String, String>,
+    file_ext: &[String],
+) -> Metadata {
+    let author = raw_data
+        .authors
+        .and_then(|authors| authors.first().cloned());
+    let genre = raw_data.genres.and_then(|genres| genres.first().cloned());
+    let narrator = raw_data
+        .narrators
+        .and_then(|narrators| narrators.first().cloned());
+    let full_series = raw_data.series.and_then(|series| series.first().cloned());
+    let (series, book_number) = match &full_series {
+        Some(s) => parse_series(s, series_patterns),
+        None => (None, None),
+    };
+
+    // The movement tags of the audio files are only read when they are needed
+    let (tag_series, tag_number) = match (&series, book_number) {
+        (Some(_), Some(_)) => (None, None),
+        _ => first_audio_file(book_dir, file_ext)
+            .map(|file| get_movement(&file))
+            .unwrap_or_default(),
+    };
+    let series = series
+        .or(tag_series)
+        .map(|s| canonical_series(s, series_aliases));
+
+    // Book number precedence: metafile sequence > series string > movement tag >
+    // folder name > title
+    let folder_name = book_dir.file_name().and_then(|n| n.to_str());
+    let book_number = book_number
+        .or_else(|| full_series.as_deref().and_then(find_volume))
+        .or(tag_number)
+        .or_else(|| folder_name.and_then(find_volume))
+        .or_else(|| find_volume(&raw_data.title));
+
+    let isbn = raw_data
+        .isbn
+        .as_deref()
+        .filter(|isbn| !isbn.trim().is_empty())
+        .and_then(|isbn| match isbn::normalize(isbn) {
+            Ok(isbn) => Some(isbn),
+            Err(err) => {
+                eprintln!(
+                    "{} {} in '{}', ignoring it",
+                    "Warning:".yellow(),
+                    err,
+                    book_dir.display()
+                );
+                None
+            }
+        });
+
+    // Books without a year fall back to the date tags of their audio files
+    let (published_year, published_date) = match raw_data.published_year {
+        Some(year) if !year.trim().is_empty() => (Some(year), raw_data.published_date),
+        _ => match first_audio_file(book_dir, file_ext)
+            .and_then(|file| Some((get_release_date(&file)?, file)))
+        {
+            Some((date, file)) => {
+                if CONFIG.get().is_some_and(|cfg| cfg.verbose) {
+                    println!(
+                        "{} Using the published date '{}' from the tags of '{}'",
+                        "Info:".blue(),
+                        date,
+                        file.display()
+                    );
+                }
+                let year = date.chars().take(4).collect();
+                let full_date = (date.len() == 10).then_some(date);
+                (Some(year), raw_data.published_date.or(full_date))
+            }
+            None => (None, raw_data.published_date),
+        },
+    };
+
+    Metadata {
+        title: raw_data.title,
+        subtitle: raw_data.subtitle,
+        series,
+        book_number,
+        book_number_with_zeros: None,
+        author,
+        published_year,
+        published_date,
+        genre,
+        language_code: raw_data.language.as_deref().and_then(language_code),
+        language: raw_data.language,
+        abridged: raw_data.abridged,
+        asin: raw_data.asin,
+        isbn,
+        narrator,
+        purchase_date: raw_data.purchase_date,
+        description: raw_data.description.filter(|d| !d.trim().is_empty()),
+        file_number: None,
+        file_number_with_zeros: None,
+        file_total: None,
+        original_name: None,
+        original_dir: None,
+    }
+}
+
+/**
+ * Parses the contents of a metadata file, detecting whether it was written by
+ * Audiobookshelf or Libation.
+ *
+ * @param contents The JSON contents of the metadata file.
+ * @return The parsed metadata, or the parse error.
+ */
+pub fn parse_raw_metadata(contents: &str) -> serde_json::Result<RawMetadata> {
+    let value: serde_json::Value = serde_json::from_str(contents)?;
+    if value.get("AudibleProductId").is_some() {
+        serde_json::from_value::<LibationMetadata>(value).map(RawMetadata::from)
+    } else {
+        serde_json::from_value(value)
+    }
+}
+
+/**
+ * Checks whether a file name matches the metadata files Libation writes next to
+ * a book, named after the book with the ASIN in brackets (e.g. `Title [B0036I54I6].json`).
+ *
+ * @param file_name The file name to check.
+ * @return True if the file looks like a Libation metadata file.
+ */
+pub fn is_libation_metafile(file_name: &str) -> bool {
+    let re = Regex::new(r"\[[0-9A-Z]{10}\]\.json$").unwrap();
+    re.is_match(file_name)
+}
+
+/// Language names and codes, and the ISO 639-1 code they map to. The codes are the
+/// ISO 639-1 and both ISO 639-2 codes, the names are in English and the language itself.
+const LANGUAGES: [(&str, &[&str]); 22] = [
+    ("en", &["en", "eng", "english"]),
+    ("de", &["de", "deu", "ger", "german", "deutsch"]),
+    (
+        "fr",
+        &["fr", "fra", "fre", "french", "français", "francais"],
+    ),
+    (
+        "es",
+        &["es", "spa", "spanish", "español", "espanol", "castellano"],
+    ),
+    ("it", &["it", "ita", "italian", "italiano"]),
+    ("nl", &["nl", "nld", "dut", "dutch", "nederlands"]),
+    ("pt", &["pt", "por", "portuguese", "português", "portugues"]),
+    ("sv", &["sv", "swe", "swedish", "svenska"]),
+    ("no", &["no", "nor", "nb", "nob", "norwegian", "norsk"]),
+    ("da", &["da", "dan", "danish", "dansk"]),
+    ("fi", &["fi", "fin", "finnish", "suomi"]),
+    ("pl", &["pl", "pol", "polish", "polski"]),
+    ("cs", &["cs", "ces", "cze", "czech", "čeština", "cestina"]),
+    ("hu", &["hu", "hun", "hungarian", "magyar"]),
+    ("ru", &["ru", "rus", "russian", "русский"]),
+    ("uk", &["uk", "ukr", "ukrainian", "українська"]),
+    ("tr", &["tr", "tur", "turkish", "türkçe", "turkce"]),
+    ("el", &["el", "ell", "gre", "greek", "ελληνικά"]),
+    ("ja", &["ja", "jpn", "japanese", "日本語"]),
+    ("zh", &["zh", "zho", "chi", "chinese", "mandarin", "中文"]),
+    ("ko", &["ko", "kor", "korean", "한국어"]),
+    ("ar", &["ar", "ara", "arabic", "العربية"]),
+];
+
+/**
+ * Maps a language name or code to its ISO 639-1 code, so books tagged `German`,
+ * `Deutsch`, `ger` or `de-DE` all get `de`.
+ *
+ * @param language The language of the book.
+ * @return The ISO 639-1 code, or `None` if the language is not known.
+ */
+pub fn language_code(language: &str) -> Option<String> {
+    let language = language.trim().to_lowercase();
+    // Locales like `en-US` or `de_AT` use the code of their language
+    let language = language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or(&language)
+        .trim();
+    LANGUAGES
+        .iter()
+        .find(|(_, names)| names.contains(&language))
+        .map(|(code, _)| code.to_string())
+}
+
+/**
+ * Finds an ASIN in the name of a book directory or its files, e.g. `[B07B8W2KPX]`.
+ *
+ * Audible ASINs (`B0` and 8 letters or digits) are found anywhere as a separate
+ * word, and book ASINs (which are ISBN-10s) only in brackets.
+ *
+ * @param dir The book directory.
+ * @return The ASIN, or `None` if no name contains one.
+ */
+pub fn find_asin(dir: &Path) -> Option<String> {
+    let re = Regex::new(r"\[(\d{9}[\dX])\]|\b(B0[0-9A-Z]{8})\b").unwrap();
+    let find = |name: &str| {
+        re.captures(name)
+            .and_then(|c| c.get(1).or(c.get(2)))
+            .map(|m| m.as_str().to_string())
+    };
+
+    let dir_name = dir.file_name().and_then(|n| n.to_str());
+    if let Some(asin) = dir_name.and_then(find) {
+        return Some(asin);
+    }
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect();
+    files.sort();
+    files.iter().find_map(|name| find(name))
+}
+
+/**
+ * Compiles the series patterns, falling back to `DEFAULT_SERIES_PATTERNS` when none are given.
+ *
+ * @param patterns The user supplied patterns, in order of precedence.
+ * @return The compiled patterns, or an error message if a pattern is invalid or has no `series` group.
+ */
+pub fn compile_series_patterns(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    let patterns: Vec<&str> = if patterns.is_empty() {
+        DEFAULT_SERIES_PATTERNS.to_vec()
+    } else {
+        patterns.iter().map(|p| p.as_str()).collect()
+    };
+
+    patterns
+        .into_iter()
+        .map(|pattern| {
+            let re = Regex::new(pattern).map_err(|e| format!("'{}': {}", pattern, e))?;
+            if !re.capture_names().any(|name| name == Some("series")) {
+                return Err(format!("'{}': missing a 'series' named group", pattern));
+            }
+            Ok(re)
+        })
+        .collect()
+}
+
+/**
+ * Splits a series entry (e.g. "Mistborn #1") into the series name and book number.
+ *
+ * The patterns are tried in order. If none of them match, the whole entry is
+ * used as the series name with no book number.
+ *
+ * @param full_series The series entry from the metadata file.
+ * @param patterns The ordered rules used to split the entry.
+ * @return A tuple of the series name and the book number, if found.
+ */
+fn parse_series(full_series: &str, patterns: &[Regex]) -> (Option<String>, Option<u16>) {
+    let full_series = full_series.trim();
+    if full_series.is_empty() {
+        return (None, None);
+    }
+
+    for re in patterns {
+        if let Some(caps) = re.captures(full_series) {
+            let series = caps
+                .name("series")
+                .map(|m| m.as_str().trim().to_string())
+                .filter(|s| !s.is_empty());
+            let book_number = caps.name("number").and_then(|m| parse_number(m.as_str()));
+            return (series, book_number);
+        }
+    }
+
+    (Some(full_series.to_string()), None)
+}
+
+/**
+ * Gets the book number written in a series entry, like `build_metadata` does, from
+ * the series patterns or else a volume marker such as "Book 2".
+ *
+ * @param full_series The series entry from the metadata file.
+ * @param patterns The ordered rules used to split the entry.
+ * @return The book number, if the entry has one.
+ */
+pub fn series_entry_number(full_series: &str, patterns: &[Regex]) -> Option<u16> {
+    parse_series(full_series, patterns)
+        .1
+        .or_else(|| find_volume(full_series))
+}
+
+/**
+ * Finds the first audio file of a book directory, by name.
+ *
+ * @param dir The book directory.
+ * @param file_ext The audio file extensions.
+ * @return The first audio file, if the directory has any.
+ */
+fn first_audio_file(dir: &Path, file_ext: &[String]) -> Option<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| is_audio_file(path, file_ext) && !is_junk(path))
+        .collect();
+    files.sort();
+    files.into_iter().next()
+}
+
+/**
+ * Builds the lookup table for series aliases.
+ *
+ * @param aliases The aliases of each series, keyed by the canonical series name.
+ * @return The canonical series names, keyed by lowercase alias. Each canonical
+ * name is also an alias of itself, so differently cased entries are merged too.
+ */
+pub fn series_alias_map(aliases: &HashMap<String, Vec<String>>) -> HashMap<String, String> {
+    let mut map = HashMap::new();
+    for (canonical, names) in aliases {
+        map.insert(canonical.trim().to_lowercase(), canonical.clone());
+        for name in names {
+            map.insert(name.trim().to_lowercase(), canonical.clone());
+        }
+    }
+    map
+}
+
+/**
+ * Replaces a series name with its canonical name, if it is a known alias.
+ *
+ * @param series The series name parsed from the metadata.
+ * @param aliases The canonical series names, keyed by lowercase alias.
+ * @return The canonical series name.
+ */
+fn canonical_series(series: String, aliases: &HashMap<String, String>) -> String {
+    aliases
+        .get(&series.trim().to_lowercase())
+        .cloned()
+        .unwrap_or(series)
+}
+
+/**
+ * Finds a volume marker such as "Vol. 3", "Volume III", "Bk 2" or "Book 4" in a string.
+ *
+ * @param text The series entry, folder name, or title to search.
+ * @return The volume number, if a marker was found.
+ */
+fn find_volume(text: &str) -> Option<u16> {
+    let re = Regex::new(r"(?i:\b(?:vol(?:ume)?|bk|book))\.?\s*#?\s*(\d+|[IVXLCDM]+)\b").unwrap();
+    re.captures(text).and_then(|caps| parse_number(&caps[1]))
+}
+
+/**
+ * Parses a book number written either as digits or as an upper case roman numeral.
+ *
+ * @param value The number to parse (e.g. "3" or "III").
+ * @return The parsed number, or `None` if it is not a valid number.
+ */
+fn parse_number(value: &str) -> Option<u16> {
+    if let Ok(num) = value.parse::<u16>() {
+        return Some(num);
+    }
+
+    let mut total: u16 = 0;
+    let mut previous: u16 = 0;
+    for c in value.chars().rev() {
+        let digit = match c {
+            'I' => 1,
+            'V' => 5,
+            'X' => 10,
+            'L' => 50,
+            'C' => 100,
+            'D' => 500,
+            'M' => 1000,
+            _ => return None,
+        };
+        if digit < previous {
+            total = total.checked_sub(digit)?;
+        } else {
+            total = total.checked_add(digit)?;
+            previous = digit;
+        }
+    }
+
+    if total > 0 { Some(total) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_series() {
+        let patterns = compile_series_patterns(&[]).unwrap();
+        let inputs = [
+            ("Mistborn #1", Some("Mistborn"), Some(1)),
+            ("The Mistborn Saga 4", Some("The Mistborn Saga"), Some(4)),
+            (
+                "Book 3 of the Stormlight Archive",
+                Some("Stormlight Archive"),
+                Some(3),
+            ),
+            ("Discworld", Some("Discworld"), None),
+            (
+                "The Wheel of Time, Vol. 3",
+                Some("The Wheel of Time"),
+                Some(3),
+            ),
+            ("Dune Volume IV", Some("Dune"), Some(4)),
+            ("Riftwar Bk 2", Some("Riftwar"), Some(2)),
+            ("  ", None, None),
+        ];
+
+        for (input, series, number) in inputs {
+            let result = parse_series(input, &patterns);
+            assert_eq!(
+                result,
+                (series.map(|s| s.to_string()), number),
+                "Failed on input: '{}'",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_language_code() {
+        assert_eq!(language_code("English").as_deref(), Some("en"));
+        assert_eq!(language_code("deutsch").as_deref(), Some("de"));
+        assert_eq!(language_code("ger").as_deref(), Some("de"));
+        assert_eq!(language_code("de-AT").as_deref(), Some("de"));
+        assert_eq!(language_code(" Français ").as_deref(), Some("fr"));
+        assert_eq!(language_code("Klingon"), None);
+    }
+
+    #[test]
+    fn test_find_volume() {
+        let inputs = [
+            ("Title Vol. 3", Some(3)),
+            ("Title Volume III", Some(3)),
+            ("Title - Bk 2", Some(2)),
+            ("Mistborn Book 4 [B07B8W2KPX]", Some(4)),
+            ("Volume XIV", Some(14)),
+            ("The Book of Dust", None),
+            ("Involved Title", None),
+        ];
+
+        for (input, expected) in inputs {
+            assert_eq!(find_volume(input), expected, "Failed on input: '{}'", input);
+        }
+    }
+
+    #[test]
+    fn test_compile_series_patterns_requires_series_group() {
+        assert!(compile_series_patterns(&[r"^(.+) (\d+)$".to_string()]).is_err());
+        assert!(compile_series_patterns(&[r"^(?P<series>.+)$".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_parse_libation_metadata() {
+        let contents = r#"{
+            "AudibleProductId": "B0036I54I6",
+            "Title": "The Alloy of Law",
+            "AuthorNames": "Brandon Sanderson",
+            "NarratorNames": "Michael Kramer, Kate Reading",
+            "SeriesNames": "Mistborn",
+            "SeriesOrder": "4",
+            "DatePublished": "2011-11-08T00:00:00",
+            "DateAdded": "2020-01-02T10:11:12"
+        }"#;
+        let raw = parse_raw_metadata(contents).unwrap();
+
+        assert_eq!(raw.title, "The Alloy of Law");
+        assert_eq!(raw.series, Some(vec!["Mistborn #4".to_string()]));
+        assert_eq!(raw.authors, Some(vec!["Brandon Sanderson".to_string()]));
+        assert_eq!(
+            raw.narrators,
+            Some(vec![
+                "Michael Kramer".to_string(),
+                "Kate Reading".to_string()
+            ])
+        );
+        assert_eq!(raw.asin.as_deref(), Some("B0036I54I6"));
+        assert_eq!(raw.published_year.as_deref(), Some("2011"));
+        assert_eq!(raw.published_date.as_deref(), Some("2011-11-08"));
+        assert_eq!(raw.purchase_date.as_deref(), Some("2020-01-02"));
+        assert!(is_libation_metafile("The Alloy of Law [B0036I54I6].json"));
+        assert!(!is_libation_metafile("metadata.json"));
+    }
+
+    #[test]
+    fn test_canonical_series() {
+        let aliases = series_alias_map(&HashMap::from([(
+            "The Wheel of Time".to_string(),
+            vec!["Wheel of Time".to_string(), "WoT".to_string()],
+        )]));
+
+        for input in [
+            "Wheel of Time",
+            "wot",
+            "THE WHEEL OF TIME",
+            "The Wheel of Time",
+        ] {
+            assert_eq!(
+                canonical_series(input.to_string(), &aliases),
+                "The Wheel of Time"
+            );
+        }
+        assert_eq!(
+            canonical_series("Mistborn".to_string(), &aliases),
+            "Mistborn"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+use crate::checksum::MANIFEST_NAME;
+use crate::diff::rename_line;
+use crate::overrides::BookOverrides;
+use crate::schema::Schema;
+use crate::summary::{Category, Summary};
+use crate::track::{get_track_number, parse_from_filename};
+use crate::{
+    CONFIG, TEMPORARY_EXTENSION, audit, get_files, is_audio_file, is_metafile, is_skipped,
+    read_book_metadata,
+};
+use colored::Colorize;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/**
+ * Re-evaluates the file numbers of the books in an organized library, and renames
+ * the audio files whose number is wrong, e.g. because an older version took a
+ * date in the title for the file number. Nothing else is changed.
+ *
+ * The numbers are resolved like they are during planning, from the track tag and
+ * else from the file name. Only files whose name has a different number are renamed,
+ * with the file schema.
+ *
+ * @param schema The schema the library was organized with.
+ * @param summary The summary that collects the results.
+ */
+pub fn fix_numbers(schema: &Schema, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    println!(
+        "Searching for '{}' in '{}' and all sub-directories...",
+        cfg.metafile.green(),
+        cfg.from.green()
+    );
+
+    let entries = WalkDir::new(&cfg.from)
+        .into_iter()
+        .filter_entry(|e| !is_skipped(e));
+    for entry in entries {
+        match entry {
+            Ok(entry) if is_metafile(entry.path()) => fix_book(entry.path(), schema, summary),
+            Ok(_) => {}
+            Err(err) => eprintln!("{}{}", "Error: ".red(), err),
+        }
+    }
+}
+
+/**
+ * Renames the audio files of a single book whose number is wrong.
+ *
+ * A book is left unchanged if two of its files would get the same name, or if a
+ * file would be renamed onto a file that keeps its name.
+ *
+ * @param metadata_path The path of the book's metadata file.
+ * @param schema The schema the library was organized with.
+ * @param summary The summary that collects the results.
+ */
+fn fix_book(metadata_path: &Path, schema: &Schema, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let Some(mut metadata) = read_book_metadata(metadata_path, summary) else {
+        return;
+    };
+    let dir = metadata_path.parent().unwrap();
+    let audio_files: Vec<PathBuf> = get_files(&dir.display().to_string())
+        .into_iter()
+        .filter(|file| is_audio_file(file, &cfg.file_ext))
+        .collect();
+    metadata.file_total = Some(u16::try_from(audio_files.len()).unwrap_or(u16::MAX));
+    let overrides = match BookOverrides::read(dir) {
+        Ok(overrides) => overrides,
+        Err(err) => {
+            eprintln!("{} {}", "Error reading the override file:".red(), err);
+            summary.error(
+                Category::Metadata,
+                format!("'{}' was not renumbered: {}", dir.display(), err),
+            );
+            return;
+        }
+    };
+
+    let mut renames = Vec::new();
+    for file in &audio_files {
+        let number = get_track_number(
+            file,
+            &cfg.track_keywords,
+            &overrides.rename,
+            metadata.file_total,
+        );
+        // Files whose name already has the right number keep their name, even if the
+        // schema would render it differently
+        if !number_changed(file, number, &cfg.track_keywords, metadata.file_total) {
+            continue;
+        }
+        let name = match schema.fmt_file(&mut metadata, file, number, &cfg.file_ext) {
+            Ok(name) => name,
+            Err(err) => {
+                eprintln!(
+                    "{} '{}' - Schema: {} ({})",
+                    "Error: Could not render file name for".red(),
+                    file.display().to_string().yellow(),
+                    schema.file_template.yellow(),
+                    err
+                );
+                summary.error(
+                    Category::Template,
+                    format!(
+                        "'{}' was not renumbered: the file name could not be rendered",
+                        file.display()
+                    ),
+                );
+                return;
+            }
+        };
+        let destination = file.with_file_name(&name);
+        if destination != *file {
+            renames.push((file.clone(), destination));
+        }
+    }
+    if renames.is_empty() {
+        return;
+    }
+
+    let book = dir.display().to_string();
+    if let Err(err) = check_renames(&audio_files, &renames) {
+        eprintln!("{} {}, skipping book '{}'", "Error:".red(), err, book);
+        summary.skipped_books.push((book, err));
+        return;
+    }
+
+    println!("--\n");
+    println!("{} '{}'", "Renumbering:".green(), book);
+    let width = renames
+        .iter()
+        .map(|(from, _)| file_name(from).chars().count())
+        .max()
+        .unwrap_or(0);
+    for (from, to) in &renames {
+        println!("{}", rename_line(&file_name(from), &file_name(to), width));
+    }
+    summary.books += 1;
+    summary.files += renames.len();
+    if cfg.dry_run {
+        return;
+    }
+
+    if let Err(err) = rename_files(&renames) {
+        eprintln!("{} {}", "Error renaming files:".red(), err);
+        summary.error(
+            Category::Io,
+            format!("'{}' was not fully renumbered: {}", book, err),
+        );
+        return;
+    }
+    let manifest = dir.join(MANIFEST_NAME);
+    if manifest.exists()
+        && let Err(err) = rename_manifest_entries(&manifest, &renames)
+    {
+        eprintln!("{} {}", "Error updating the checksum manifest:".red(), err);
+        summary.error(
+            Category::Io,
+            format!(
+                "'{}' still lists the old file names: {}",
+                manifest.display(),
+                err
+            ),
+        );
+    }
+}
+
+/**
+ * Checks that the renames of a book don't overwrite any file: no two files may get
+ * the same name, and no file may be renamed onto a file that keeps its name.
+ *
+ * @param files The audio files of the book.
+ * @param renames The renames, as (current path, new path).
+ * @return `Ok(())` if the renames are safe, or the reason they are not.
+ */
+fn check_renames(files: &[PathBuf], renames: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+    let renamed: HashSet<&PathBuf> = renames.iter().map(|(from, _)| from).collect();
+    let mut taken: HashSet<&PathBuf> = files.iter().filter(|f| !renamed.contains(f)).collect();
+    for (from, to) in renames {
+        let other_file = to.exists() && !renamed.contains(to);
+        if other_file || !taken.insert(to) {
+            return Err(format!(
+                "'{}' would be renamed onto '{}', which is taken",
+                from.display(),
+                to.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Renames the files in two steps, first to a temporary name and then to the final
+ * name, so files can swap their names.
+ *
+ * @param renames The renames, as (current path, new path).
+ */
+fn rename_files(renames: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let mut temporary = Vec::new();
+    for (from, to) in renames {
+        let path = PathBuf::from(format!("{}.{}", from.display(), TEMPORARY_EXTENSION));
+        let result = fs::rename(from, &path);
+        audit::record(
+            "rename",
+            from,
+            &path,
+            result.as_ref().err().map(|e| e.to_string()).as_deref(),
+        );
+        result?;
+        temporary.push((path, to));
+    }
+    for (from, to) in temporary {
+        let result = fs::rename(&from, to);
+        audit::record(
+            "rename",
+            &from,
+            to,
+            result.as_ref().err().map(|e| e.to_string()).as_deref(),
+        );
+        result?;
+        println!("{} '{}'", "Renamed:".green(), to.display());
+    }
+    Ok(())
+}
+
+/**
+ * Replaces the old names of renamed files in a book's checksum manifest. The files
+ * keep their content, so their checksums stay valid.
+ *
+ * @param manifest The path of the manifest.
+ * @param renames The renames, as (current path, new path).
+ */
+fn rename_manifest_entries(manifest: &Path, renames: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let names: HashMap<String, String> = renames
+        .iter()
+        .map(|(from, to)| (file_name(from), file_name(to)))
+        .collect();
+    let contents = fs::read_to_string(manifest)?;
+    let lines: String = contents
+        .lines()
+        .map(|line| match line.split_once("  ") {
+            Some((hash, name)) if names.contains_key(name) => {
+                format!("{}  {}\n", hash, names[name])
+            }
+            _ => format!("{}\n", line),
+        })
+        .collect();
+    fs::write(manifest, lines)
+}
+
+/**
+ * Gets the file name of a path as a string.
+ */
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/**
+ * Checks whether the number in the name of a file differs from its re-evaluated
+ * number.
+ *
+ * @param file The audio file.
+ * @param number The re-evaluated number of the file.
+ * @param keywords The compiled track keywords.
+ * @param file_total The number of audio files of the book.
+ * @return True if the file has to be renamed to get the right number.
+ */
+fn number_changed(
+    file: &Path,
+    number: Option<u16>,
+    keywords: &[Regex],
+    file_total: Option<u16>,
+) -> bool {
+    let name = file.file_stem().unwrap_or_default().to_string_lossy();
+    parse_from_filename(&name, keywords, file_total) != number
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::compile_track_keywords;
+
+    #[test]
+    fn test_check_renames() {
+        let path = |name: &str| PathBuf::from(format!("/library/Dune/{}", name));
+        let files = vec![
+            path("Dune (01).mp3"),
+            path("Dune (02).mp3"),
+            path("Dune (2021).mp3"),
+        ];
+
+        // Files swapping their names
+        let swap = vec![
+            (path("Dune (01).mp3"), path("Dune (02).mp3")),
+            (path("Dune (02).mp3"), path("Dune (01).mp3")),
+        ];
+        assert!(check_renames(&files, &swap).is_ok());
+
+        // A file renamed onto a file that keeps its name
+        let onto_kept = vec![(path("Dune (2021).mp3"), path("Dune (01).mp3"))];
+        assert!(check_renames(&files, &onto_kept).is_err());
+
+        // Two files renamed to the same name
+        let same = vec![
+            (path("Dune (01).mp3"), path("Dune (03).mp3")),
+            (path("Dune (2021).mp3"), path("Dune (03).mp3")),
+        ];
+        assert!(check_renames(&files, &same).is_err());
+    }
+
+    #[test]
+    fn test_number_changed() {
+        let keywords = compile_track_keywords(&[]);
+        let total = Some(12);
+        let custom = Path::new("/library/Dune/My Custom Name - Part 001.mp3");
+        assert!(!number_changed(custom, Some(1), &keywords, total));
+        assert!(number_changed(custom, Some(2), &keywords, total));
+        // The day of a date was taken for the number
+        let dated = Path::new("/library/Podcast/Episode 2021-05-04 (04).mp3");
+        assert!(number_changed(dated, Some(7), &keywords, total));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::overrides::BookOverrides;
+use crate::provider::read_book_sources;
+use crate::schema::Schema;
+use crate::summary::Summary;
+use crate::track::explain_track_number;
+use crate::{CONFIG, execute, get_files, is_audio_file, plan, read_book_metadata};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+/**
+ * Plans a single book directory, and explains every decision on the way: the
+ * metadata files that were found, which provider each field came from, which series
+ * pattern matched, and where each file number came from and which numbers were ignored. The book is then planned
+ * and shown like in a dry run, or organized with `--execute`.
+ *
+ * This is meant for debugging books that end up in the wrong place.
+ *
+ * @param schema The schema the book is organized with.
+ * @param summary The summary that collects the results.
+ */
+pub fn one(schema: &Schema, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let dir = Path::new(&cfg.from);
+    if !dir.is_dir() {
+        eprintln!(
+            "{} '{}' is not a directory",
+            "Error:".red(),
+            cfg.from.yellow()
+        );
+        return;
+    }
+    println!("{} '{}'", "Book:".green(), dir.display());
+
+    println!("{}", "Metadata files:".bold());
+    let mut metafile = None;
+    for provider in &cfg.providers {
+        match provider.find_metafile(dir) {
+            Some(path) => {
+                println!("  {:<8} '{}'", provider.name(), path.display());
+                metafile = metafile.or(Some(path));
+            }
+            None => println!("  {:<8} none", provider.name()),
+        }
+    }
+    let Some(metafile) = metafile else {
+        eprintln!(
+            "{} No provider found a metadata file in '{}', it is not planned as a book",
+            "Error:".red(),
+            cfg.from.yellow()
+        );
+        return;
+    };
+
+    let Some((raw, sources)) = read_book_sources(&cfg.providers, dir) else {
+        eprintln!("{} No provider knows the book", "Error:".red());
+        return;
+    };
+    println!("{}", "Metadata sources:".bold());
+    let width = sources
+        .iter()
+        .map(|(field, _)| field.len())
+        .max()
+        .unwrap_or(0);
+    for (field, source) in &sources {
+        println!("  {:<width$} {}", field, source, width = width);
+    }
+
+    match raw.series.as_ref().and_then(|series| series.first()) {
+        Some(entry) => match cfg.series_patterns.iter().find(|re| re.is_match(entry)) {
+            Some(re) => println!(
+                "{} '{}' matched the pattern '{}'",
+                "Series:".bold(),
+                entry,
+                re.as_str()
+            ),
+            None => println!(
+                "{} '{}' matched no pattern, the whole entry is the series",
+                "Series:".bold(),
+                entry
+            ),
+        },
+        None => println!("{} none in the metadata", "Series:".bold()),
+    }
+
+    let Some(metadata) = read_book_metadata(&metafile, summary) else {
+        eprintln!(
+            "{} The book doesn't match the filters, it is not planned",
+            "Error:".red()
+        );
+        return;
+    };
+    println!(
+        "{} {} by {}, series {}, book {}, {}",
+        "Resolved:".bold(),
+        metadata.title,
+        metadata.author.as_deref().unwrap_or("(no author)"),
+        metadata.series.as_deref().unwrap_or("(none)"),
+        metadata
+            .book_number
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| String::from("(none)")),
+        metadata.published_year.as_deref().unwrap_or("(no year)")
+    );
+
+    let mut audio_files: Vec<_> = get_files(&cfg.from)
+        .into_iter()
+        .filter(|file| is_audio_file(file, &cfg.file_ext))
+        .collect();
+    audio_files.sort();
+    // The book is numbered like in planning, where the file count is a hint
+    let file_total = Some(u16::try_from(audio_files.len()).unwrap_or(u16::MAX));
+    // An invalid override file is reported when the book is planned
+    let overrides = BookOverrides::read(Path::new(&cfg.from)).unwrap_or_default();
+    if !overrides.rename.is_empty() {
+        println!(
+            "{} {} rename rules from the override file",
+            "Renames:".bold(),
+            overrides.rename.len()
+        );
+    }
+    println!("{} ({})", "File numbers:".bold(), audio_files.len());
+    for file in &audio_files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let explanation =
+            explain_track_number(file, &cfg.track_keywords, &overrides.rename, file_total);
+        match explanation.number {
+            Some(_) => println!("  '{}' -> {}", name, explanation.describe()),
+            None => println!("  '{}' -> {}", name.yellow(), explanation.describe()),
+        }
+    }
+    println!();
+
+    // Only the book itself is planned, not the books in its sub-directories
+    let canonical = fs::canonicalize(dir).unwrap_or_default();
+    let mut plans = plan(schema, &cfg.sources, summary);
+    plans.retain(|plan| fs::canonicalize(&plan.from).unwrap_or_default() == canonical);
+    execute(plans, &cfg.sources, summary);
+}
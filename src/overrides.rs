@@ -0,0 +1,121 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// The name of the file in a book directory that holds the overrides of the book.
+pub const OVERRIDE_FILE: &str = ".aborg.toml";
+
+/// Represents the settings of a single book, read from the override file in its
+/// directory, for sources the heuristics get wrong.
+#[derive(Debug, Default)]
+pub struct BookOverrides {
+    /// Rules that rewrite the names of the book's files before their numbers are
+    /// parsed, applied in order.
+    pub rename: Vec<RenameRule>,
+}
+
+/// Represents a regex rule that rewrites a file name, e.g. to drop a `CD1-` prefix.
+#[derive(Debug, Clone)]
+pub struct RenameRule {
+    /// The regex matched against the file name without its extension.
+    pub pattern: Regex,
+    /// The replacement of every match, which can use the groups as `$1` or `$name`.
+    pub replace: String,
+}
+
+/// The override file as written, before the rules are compiled.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawOverrides {
+    rename: Vec<RawRenameRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRenameRule {
+    #[serde(rename = "match")]
+    pattern: String,
+    #[serde(default)]
+    replace: String,
+}
+
+impl BookOverrides {
+    /**
+     * Reads the override file of a book directory.
+     *
+     * @param dir The book directory.
+     * @return The overrides, empty if the directory has no override file, or the
+     * reason the file is invalid.
+     */
+    pub fn read(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(OVERRIDE_FILE);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        Self::parse(&contents)
+    }
+
+    /**
+     * Parses the contents of an override file and compiles its rules.
+     *
+     * @param contents The TOML contents.
+     * @return The overrides, or the reason they are invalid.
+     */
+    fn parse(contents: &str) -> Result<Self, String> {
+        let raw: RawOverrides = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let rename = raw
+            .rename
+            .into_iter()
+            .map(|rule| match Regex::new(&rule.pattern) {
+                Ok(pattern) => Ok(RenameRule {
+                    pattern,
+                    replace: rule.replace,
+                }),
+                Err(err) => Err(format!(
+                    "the rename rule '{}' is invalid. {}",
+                    rule.pattern, err
+                )),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rename })
+    }
+}
+
+/**
+ * Rewrites a file name with rename rules, each applied to the result of the one
+ * before it.
+ *
+ * @param name The file name without its extension.
+ * @param rules The rename rules of the book.
+ * @return The rewritten name.
+ */
+pub fn apply_renames(name: &str, rules: &[RenameRule]) -> String {
+    rules.iter().fold(name.to_string(), |name, rule| {
+        rule.pattern
+            .replace_all(&name, rule.replace.as_str())
+            .to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_renames() {
+        let overrides = BookOverrides::parse(
+            r#"rename = [{ match = "^CD(\\d)-", replace = "$1" }, { match = " \\(\\d{4}\\)$" }]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            apply_renames("CD2-14 Chapter Title (2019)", &overrides.rename),
+            "214 Chapter Title"
+        );
+        assert_eq!(apply_renames("07", &overrides.rename), "07");
+
+        assert!(BookOverrides::parse(r#"rename = [{ match = "(" }]"#).is_err());
+        assert!(BookOverrides::parse(r#"renames = []"#).is_err());
+    }
+}
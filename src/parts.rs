@@ -0,0 +1,118 @@
+use crate::metadata::Metadata;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/**
+ * Splits a part marker off the end of a title or directory name, e.g.
+ * `The Stand - Part 2`, `The Stand (Part 1 of 2)` or `The Stand, Pt. 1`.
+ *
+ * @param name The title or directory name.
+ * @return The name without the marker and the part number, or `None` if the name
+ *         has no part marker.
+ */
+pub fn split_part(name: &str) -> Option<(String, u16)> {
+    let re = Regex::new(
+        r"(?i)^(.+?)[\s\-–_.,:;(\[]*\b(?:part|pt\.?)\s*(\d{1,2})(?:\s*of\s*\d{1,2})?\s*[)\]]?$",
+    )
+    .unwrap();
+    let captures = re.captures(name.trim())?;
+    let base = captures[1].trim().to_string();
+    let part = captures[2].parse().ok()?;
+    (!base.is_empty() && part > 0).then_some((base, part))
+}
+
+/**
+ * Finds books that were released as several directories, one per part, each with
+ * its own metadata file.
+ *
+ * The part marker is read from the title, or else from the directory name. Books by
+ * the same author with the same name before the marker belong together, but only
+ * when their parts are numbered 1, 2, ... without gaps or repeats.
+ *
+ * @param books The metadata file and metadata of each book.
+ * @return The indexes of the books of each split book, ordered by part.
+ */
+pub fn find_split_books(books: &[(PathBuf, Metadata)]) -> Vec<Vec<usize>> {
+    let mut groups: HashMap<(String, String), Vec<(u16, usize)>> = HashMap::new();
+    for (index, (path, metadata)) in books.iter().enumerate() {
+        let dir_name = path
+            .parent()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let Some((base, part)) = split_part(&metadata.title).or_else(|| split_part(&dir_name))
+        else {
+            continue;
+        };
+        let author = metadata.author.as_deref().unwrap_or_default();
+        groups
+            .entry((normalize(author), normalize(&base)))
+            .or_default()
+            .push((part, index));
+    }
+
+    let mut split_books: Vec<Vec<usize>> = groups
+        .into_values()
+        .filter_map(|mut parts| {
+            parts.sort();
+            let numbered = parts
+                .iter()
+                .enumerate()
+                .all(|(i, (part, _))| usize::from(*part) == i + 1);
+            (parts.len() > 1 && numbered).then(|| parts.into_iter().map(|(_, i)| i).collect())
+        })
+        .collect();
+    split_books.sort();
+    split_books
+}
+
+/**
+ * Lowercases a name and drops everything but letters and digits.
+ */
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_part() {
+        let split = |base: &str, part| Some((base.to_string(), part));
+        assert_eq!(split_part("The Stand Part 1"), split("The Stand", 1));
+        assert_eq!(split_part("The Stand - Part 2"), split("The Stand", 2));
+        assert_eq!(split_part("The Stand (Part 1 of 2)"), split("The Stand", 1));
+        assert_eq!(split_part("The Stand, Pt. 2"), split("The Stand", 2));
+        assert_eq!(split_part("The Stand [pt2]"), split("The Stand", 2));
+        assert_eq!(split_part("Departure 1"), None);
+        assert_eq!(split_part("Part 1"), None);
+        assert_eq!(split_part("The Stand"), None);
+    }
+
+    #[test]
+    fn test_find_split_books() {
+        let book = |dir: &str, title: &str| {
+            (
+                PathBuf::from(format!("/src/{}/metadata.json", dir)),
+                Metadata {
+                    author: Some(String::from("Stephen King")),
+                    title: title.to_string(),
+                    ..Default::default()
+                },
+            )
+        };
+        let books = vec![
+            book("The Stand Part 2", "The Stand"),
+            book("Misery", "Misery"),
+            book("The Stand Part 1", "The Stand"),
+            book("It", "It, Part 1"),
+            book("It 3", "It, Part 3"),
+        ];
+        assert_eq!(find_split_books(&books), vec![vec![2, 0]]);
+    }
+}
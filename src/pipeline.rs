@@ -0,0 +1,261 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+/**
+ * Applies a function to every item on several threads, e.g. to read the metadata
+ * of many books at once.
+ *
+ * @param items The items.
+ * @param jobs The number of threads. With 1, the items are processed in order on
+ * the current thread.
+ * @param f The function to apply.
+ * @return The results, in the order of the items.
+ */
+pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.min(items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(index) else {
+                            break;
+                        };
+                        results.push((index, f(item)));
+                    }
+                    results
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("a worker thread panicked"))
+            .collect()
+    });
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/**
+ * Processes items in groups, with at most `jobs` items of each group at a time,
+ * while the groups run next to each other. Grouping the books by destination drive
+ * keeps every drive busy without overloading any of them.
+ *
+ * Within a group, items are started in order.
+ *
+ * @param items The items, with the key of their group.
+ * @param jobs The maximum number of items processed at a time in each group.
+ * @param f The function that processes an item.
+ */
+pub fn run_grouped<K, T, F>(items: Vec<(K, T)>, jobs: usize, f: F)
+where
+    K: Eq + Hash,
+    T: Send,
+    F: Fn(T) + Sync,
+{
+    let mut groups: HashMap<K, Vec<T>> = HashMap::new();
+    for (key, item) in items {
+        groups.entry(key).or_default().push(item);
+    }
+    let queues: Vec<Mutex<std::vec::IntoIter<T>>> = groups
+        .into_values()
+        .map(|items| Mutex::new(items.into_iter()))
+        .collect();
+
+    thread::scope(|scope| {
+        for queue in &queues {
+            for _ in 0..jobs.max(1) {
+                scope.spawn(|| {
+                    loop {
+                        let item = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                        match item {
+                            Some(item) => f(item),
+                            None => break,
+                        }
+                    }
+                });
+            }
+        }
+    });
+}
+
+/// The items still to be processed by `run_limited`, and how many running items use
+/// each key.
+type Limited<K, T> = (VecDeque<(Vec<K>, T)>, HashMap<K, usize>);
+
+/**
+ * Processes items on `jobs` threads, with at most `per_key` items using the same key
+ * at a time. Keying the books by their source and destination drives keeps two
+ * books from being copied from or to the same spinning disk at once, while books on
+ * other drives still run next to each other.
+ *
+ * Items are started in order, except that an item whose keys are all busy is passed
+ * over for the next one that can start.
+ *
+ * @param items The items, with their keys.
+ * @param jobs The maximum number of items processed at a time.
+ * @param per_key The maximum number of items processed at a time for each key.
+ * @param f The function that processes an item.
+ */
+pub fn run_limited<K, T, F>(items: Vec<(Vec<K>, T)>, jobs: usize, per_key: usize, f: F)
+where
+    K: Eq + Hash + Clone + Send,
+    T: Send,
+    F: Fn(T) + Sync,
+{
+    let state: Mutex<Limited<K, T>> = Mutex::new((items.into(), HashMap::new()));
+    let released = Condvar::new();
+    let per_key = per_key.max(1);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            scope.spawn(|| {
+                let mut guard = state.lock().unwrap_or_else(|e| e.into_inner());
+                loop {
+                    let (pending, running) = &mut *guard;
+                    if pending.is_empty() {
+                        break;
+                    }
+                    let ready = pending.iter().position(|(keys, _)| {
+                        keys.iter()
+                            .all(|key| running.get(key).copied().unwrap_or(0) < per_key)
+                    });
+                    let Some((keys, item)) = ready.and_then(|index| pending.remove(index)) else {
+                        guard = released.wait(guard).unwrap_or_else(|e| e.into_inner());
+                        continue;
+                    };
+                    for key in &keys {
+                        *running.entry(key.clone()).or_default() += 1;
+                    }
+                    drop(guard);
+
+                    // The keys are released even when `f` panics, so the other
+                    // threads don't wait forever
+                    let release = Release {
+                        state: &state,
+                        released: &released,
+                        keys,
+                    };
+                    f(item);
+                    drop(release);
+                    guard = state.lock().unwrap_or_else(|e| e.into_inner());
+                }
+            });
+        }
+    });
+}
+
+/// Releases the keys of an item processed by `run_limited` when it is dropped.
+struct Release<'a, K: Eq + Hash, T> {
+    state: &'a Mutex<Limited<K, T>>,
+    released: &'a Condvar,
+    keys: Vec<K>,
+}
+
+impl<K: Eq + Hash, T> Drop for Release<'_, K, T> {
+    fn drop(&mut self) {
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        for key in &self.keys {
+            if let Some(count) = guard.1.get_mut(key) {
+                *count = count.saturating_sub(1);
+            }
+        }
+        self.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_map() {
+        let items: Vec<u32> = (0..100).collect();
+        assert_eq!(
+            parallel_map(&items, 4, |n| n * 2),
+            items.iter().map(|n| n * 2).collect::<Vec<_>>()
+        );
+        assert_eq!(parallel_map(&items, 1, |n| n + 1)[99], 100);
+    }
+
+    #[test]
+    fn test_run_grouped() {
+        let running = Mutex::new(HashMap::<char, usize>::new());
+        let max = Mutex::new(HashMap::<char, usize>::new());
+        let done = Mutex::new(Vec::new());
+        let items: Vec<(char, (char, u32))> = (0..30)
+            .map(|n| {
+                let group = if n % 3 == 0 { 'a' } else { 'b' };
+                (group, (group, n))
+            })
+            .collect();
+
+        run_grouped(items, 2, |(group, n)| {
+            {
+                let mut running = running.lock().unwrap();
+                let count = running.entry(group).or_default();
+                *count += 1;
+                let mut max = max.lock().unwrap();
+                let peak = max.entry(group).or_default();
+                *peak = (*peak).max(*count);
+            }
+            thread::sleep(std::time::Duration::from_millis(2));
+            *running.lock().unwrap().get_mut(&group).unwrap() -= 1;
+            done.lock().unwrap().push(n);
+        });
+
+        assert!(max.lock().unwrap().values().all(|&peak| peak <= 2));
+        assert_eq!(done.lock().unwrap().len(), 30);
+    }
+
+    #[test]
+    fn test_run_limited() {
+        let running = Mutex::new(HashMap::<char, usize>::new());
+        let peak = Mutex::new(0);
+        let done = Mutex::new(Vec::new());
+        // Every item reads from drive a or b and writes to drive x or y
+        let items: Vec<(Vec<char>, u32)> = (0..20)
+            .map(|n| {
+                let source = if n % 2 == 0 { 'a' } else { 'b' };
+                let destination = if n % 3 == 0 { 'x' } else { 'y' };
+                (vec![source, destination], n)
+            })
+            .collect();
+        let keys: HashMap<u32, Vec<char>> = items.iter().map(|(k, n)| (*n, k.clone())).collect();
+
+        run_limited(items, 4, 1, |n| {
+            {
+                let mut running = running.lock().unwrap();
+                for key in &keys[&n] {
+                    *running.entry(*key).or_default() += 1;
+                }
+                let mut peak = peak.lock().unwrap();
+                *peak = running.values().copied().max().unwrap_or(0).max(*peak);
+            }
+            thread::sleep(std::time::Duration::from_millis(2));
+            let mut running = running.lock().unwrap();
+            for key in &keys[&n] {
+                *running.get_mut(key).unwrap() -= 1;
+            }
+            done.lock().unwrap().push(n);
+        });
+
+        assert_eq!(*peak.lock().unwrap(), 1);
+        assert_eq!(done.lock().unwrap().len(), 20);
+    }
+}
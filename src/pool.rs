@@ -0,0 +1,181 @@
+use crate::fsutil::available_space;
+use crate::metadata::Metadata;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Represents how a book is assigned to one of several destination roots, e.g. a
+/// library spread across several drives.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum Placement {
+    /// Put each book on the root with the most free space.
+    MostFreeSpace,
+    /// Fill the roots in the order they were given, moving on once a book doesn't fit.
+    FillInOrder,
+    /// Put each book on the root that already holds the author's books, and new
+    /// authors on the root with the most free space.
+    KeepAuthorTogether,
+}
+
+/// Picks the destination root of each book from a pool of roots.
+///
+/// The free space of each root is read once, and reduced by the size of every book
+/// placed on it, so a run spreads its books the same way in a dry run.
+#[derive(Debug)]
+pub struct Pool {
+    roots: Vec<String>,
+    placement: Placement,
+    /// The estimated free space of each root.
+    free: Vec<u64>,
+    /// The root index of each author placed so far, by lowercase author.
+    authors: HashMap<String, usize>,
+}
+
+impl Pool {
+    /**
+     * Creates a pool.
+     *
+     * @param roots The destination roots, in order of preference.
+     * @param placement How books are assigned to the roots.
+     * @param author_roots The roots the library database recorded for each author,
+     * by lowercase author, used by `keep-author-together`.
+     * @return The pool.
+     */
+    pub fn new(
+        roots: &[String],
+        placement: Placement,
+        author_roots: &HashMap<String, String>,
+    ) -> Pool {
+        let free = roots
+            .iter()
+            .map(|root| available_space(Path::new(root)).unwrap_or(0))
+            .collect();
+        let absolute: Vec<Option<PathBuf>> = roots
+            .iter()
+            .map(|root| std::path::absolute(root).ok())
+            .collect();
+        let authors = author_roots
+            .iter()
+            .filter_map(|(author, root)| {
+                let index = absolute
+                    .iter()
+                    .position(|r| r.as_deref() == Some(Path::new(root)))?;
+                Some((author.clone(), index))
+            })
+            .collect();
+
+        Pool {
+            roots: roots.to_vec(),
+            placement,
+            free,
+            authors,
+        }
+    }
+
+    /**
+     * Picks the root of a book. A book that already exists on one of the roots stays
+     * there, so it is merged or replaced instead of duplicated.
+     *
+     * @param metadata The metadata of the book.
+     * @param book_dir The book directory, relative to the root.
+     * @param bytes The size of the book.
+     * @return The destination root.
+     */
+    pub fn place(&mut self, metadata: &Metadata, book_dir: &str, bytes: u64) -> &str {
+        let author = metadata.author.as_ref().map(|a| a.trim().to_lowercase());
+        let existing = self
+            .roots
+            .iter()
+            .position(|root| Path::new(root).join(book_dir).is_dir());
+        let index = existing.unwrap_or_else(|| match self.placement {
+            Placement::MostFreeSpace => self.most_free(),
+            Placement::FillInOrder => self
+                .free
+                .iter()
+                .position(|&free| free >= bytes)
+                .unwrap_or_else(|| self.most_free()),
+            Placement::KeepAuthorTogether => author
+                .as_ref()
+                .and_then(|author| self.authors.get(author).copied())
+                .unwrap_or_else(|| self.most_free()),
+        });
+
+        self.free[index] = self.free[index].saturating_sub(bytes);
+        if let Some(author) = author {
+            self.authors.entry(author).or_insert(index);
+        }
+        &self.roots[index]
+    }
+
+    /**
+     * Gets the index of the root with the most free space. Ties go to the earlier root.
+     */
+    fn most_free(&self) -> usize {
+        self.free
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, free)| **free)
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(placement: Placement, free: &[u64]) -> Pool {
+        Pool {
+            roots: (0..free.len())
+                .map(|i| format!("/nonexistent/drive{}", i))
+                .collect(),
+            placement,
+            free: free.to_vec(),
+            authors: HashMap::new(),
+        }
+    }
+
+    fn book(author: &str) -> Metadata {
+        Metadata {
+            author: Some(author.to_string()),
+            title: String::from("Title"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_place() {
+        let mut most_free = pool(Placement::MostFreeSpace, &[100, 150]);
+        assert_eq!(
+            most_free.place(&book("A"), "A/1", 80),
+            "/nonexistent/drive1"
+        );
+        assert_eq!(
+            most_free.place(&book("A"), "A/2", 10),
+            "/nonexistent/drive0"
+        );
+
+        let mut in_order = pool(Placement::FillInOrder, &[100, 150]);
+        assert_eq!(in_order.place(&book("A"), "A/1", 80), "/nonexistent/drive0");
+        assert_eq!(in_order.place(&book("B"), "B/1", 30), "/nonexistent/drive1");
+        assert_eq!(in_order.place(&book("C"), "C/1", 20), "/nonexistent/drive0");
+
+        let mut together = pool(Placement::KeepAuthorTogether, &[100, 150]);
+        together
+            .authors
+            .insert(String::from("brandon sanderson"), 0);
+        assert_eq!(
+            together.place(&book("Brandon Sanderson"), "B/1", 50),
+            "/nonexistent/drive0"
+        );
+        assert_eq!(
+            together.place(&book("Andy Weir"), "A/1", 10),
+            "/nonexistent/drive1"
+        );
+        assert_eq!(
+            together.place(&book("andy weir "), "A/2", 10),
+            "/nonexistent/drive1"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+
+static PROGRESS: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Collects the events of the current run in memory, used by the server to stream
+/// the progress of an approved plan.
+static CAPTURE: Mutex<Option<Arc<Mutex<Vec<String>>>>> = Mutex::new(None);
+
+/**
+ * Opens the stream that progress events are written to.
+ *
+ * @param path The file, pipe or device (e.g. `/dev/fd/3`) to write the events to.
+ */
+pub fn init(path: &str) -> io::Result<()> {
+    let file = File::create(path)?;
+    PROGRESS.get_or_init(|| Mutex::new(file));
+    Ok(())
+}
+
+/**
+ * Sets (or clears) the list that the events are collected in, next to the
+ * progress stream.
+ *
+ * @param events The list to collect the events in, or `None` to stop collecting.
+ */
+pub fn capture(events: Option<Arc<Mutex<Vec<String>>>>) {
+    if let Ok(mut capture) = CAPTURE.lock() {
+        *capture = events;
+    }
+}
+
+/**
+ * Writes a progress event as a single JSON line, if a progress stream was opened
+ * or the events are being collected.
+ *
+ * The event name is added to the fields as `event`.
+ *
+ * @param event The name of the event (e.g. `file_copied`).
+ * @param fields The fields of the event, as a JSON object.
+ */
+pub fn emit(event: &str, fields: Value) {
+    let capture = CAPTURE.lock().ok().and_then(|c| c.clone());
+    let stream = PROGRESS.get();
+    if capture.is_none() && stream.is_none() {
+        return;
+    }
+
+    let mut line = json!({ "event": event });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+
+    if let Some(events) = capture
+        && let Ok(mut events) = events.lock()
+    {
+        events.push(line.to_string());
+    }
+
+    if let Some(stream) = stream
+        && let Ok(mut stream) = stream.lock()
+    {
+        // Progress is best effort, a closed reader shouldn't stop the run
+        writeln!(stream, "{}", line).unwrap_or(());
+        stream.flush().unwrap_or(());
+    }
+}
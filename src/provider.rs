@@ -0,0 +1,723 @@
+use crate::cache::ResponseCache;
+use crate::fsutil::is_junk;
+use crate::isbn;
+use crate::metadata::{
+    RawMetadata, find_asin, is_libation_metafile, parse_raw_metadata, read_metadata_file,
+};
+use crate::sniff;
+use crate::{CONFIG, is_audio_file};
+use colored::Colorize;
+use lofty::file::TaggedFileExt;
+use lofty::tag::{Accessor, ItemKey};
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// The providers used when the config file doesn't set `providers`.
+pub const DEFAULT_PROVIDERS: [&str; 2] = ["json", "opf"];
+
+/// The metadata files found in the directory that was checked last, so checking
+/// every file of a directory only looks for the metadata files once.
+static LAST_DIR: Mutex<Option<DirMetafiles>> = Mutex::new(None);
+
+/// Represents the metadata files the providers found in a directory.
+struct DirMetafiles {
+    /// The address of the providers. Configs are never freed, so the address of
+    /// their providers is not reused by other providers.
+    providers: usize,
+    dir: PathBuf,
+    /// The modification time of the directory, which changes when a file is added,
+    /// removed or renamed.
+    modified: SystemTime,
+    /// The metadata file of each provider that has one, in order of precedence.
+    metafiles: Vec<PathBuf>,
+}
+
+/// A source of book metadata.
+///
+/// Providers are asked in the configured order, and each provider only fills in
+/// the fields the providers before it didn't set. New sources (e.g. online lookups)
+/// are added by implementing this trait and adding them to `build_providers`.
+pub trait MetadataProvider: Debug + Send + Sync {
+    /**
+     * Gets the name of the provider, as it is set in the config file.
+     */
+    fn name(&self) -> &'static str;
+
+    /**
+     * Finds this provider's metadata file in a book directory.
+     *
+     * Directories with a metadata file are treated as books. Providers that don't
+     * use a metadata file (e.g. embedded tags) never mark a directory as a book.
+     *
+     * @param dir The directory to look in.
+     * @return The metadata file, if the directory has one.
+     */
+    fn find_metafile(&self, _dir: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /**
+     * Reads what this provider knows about a book.
+     *
+     * @param dir The book directory.
+     * @param known The metadata read by the providers before this one, e.g. to look
+     * the book up by its ASIN.
+     * @return The metadata, or `None` if the provider has nothing for this book.
+     */
+    fn read(&self, dir: &Path, known: &RawMetadata) -> Option<RawMetadata>;
+}
+
+/// Reads the Audiobookshelf `metadata.json` (or the configured metadata file), or
+/// the metadata file Libation writes next to a book.
+#[derive(Debug)]
+pub struct JsonProvider {
+    pub metafile: String,
+}
+
+impl MetadataProvider for JsonProvider {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn find_metafile(&self, dir: &Path) -> Option<PathBuf> {
+        let metafile = dir.join(&self.metafile);
+        if metafile.is_file() {
+            return Some(metafile);
+        }
+        files_in(dir).into_iter().find(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(is_libation_metafile)
+        })
+    }
+
+    fn read(&self, dir: &Path, _known: &RawMetadata) -> Option<RawMetadata> {
+        read_metadata_file(&self.find_metafile(dir)?)
+    }
+}
+
+/// Reads an OPF package file (e.g. `metadata.opf` written by Calibre or Audiobookshelf).
+#[derive(Debug)]
+pub struct OpfProvider;
+
+impl MetadataProvider for OpfProvider {
+    fn name(&self) -> &'static str {
+        "opf"
+    }
+
+    fn find_metafile(&self, dir: &Path) -> Option<PathBuf> {
+        let metafile = dir.join("metadata.opf");
+        if metafile.is_file() {
+            return Some(metafile);
+        }
+        files_in(dir)
+            .into_iter()
+            .find(|path| path.extension().is_some_and(|e| e == "opf"))
+    }
+
+    fn read(&self, dir: &Path, _known: &RawMetadata) -> Option<RawMetadata> {
+        let path = self.find_metafile(dir)?;
+        let contents = fs::read_to_string(&path).ok()?;
+        match parse_opf(&contents) {
+            Ok(raw) => {
+                if !CONFIG.get().is_some_and(|cfg| cfg.quiet) {
+                    println!("Successfully parsed metadata file '{}'", path.display());
+                }
+                Some(raw)
+            }
+            Err(err) => {
+                eprintln!(
+                    "{} '{}'. {}",
+                    "Error: Failed to parse file".red(),
+                    path.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Reads the tags embedded in the book's first audio file.
+#[derive(Debug)]
+pub struct TagsProvider {
+    pub file_ext: Vec<String>,
+}
+
+impl MetadataProvider for TagsProvider {
+    fn name(&self) -> &'static str {
+        "tags"
+    }
+
+    fn read(&self, dir: &Path, _known: &RawMetadata) -> Option<RawMetadata> {
+        let file = files_in(dir)
+            .into_iter()
+            .find(|path| is_audio_file(path, &self.file_ext))?;
+        let tagged_file = sniff::open(&file).and_then(|p| p.read()).ok()?;
+        let tag = tagged_file.primary_tag().or(tagged_file.first_tag())?;
+
+        let text = |key: &ItemKey| {
+            tag.get_string(key)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        let list = |value: Option<String>| value.map(|v| vec![v]);
+        let series = text(&ItemKey::Movement).map(|name| {
+            match text(&ItemKey::MovementNumber).and_then(|n| n.split('/').next().map(String::from))
+            {
+                Some(number) => format!("{} #{}", name, number.trim()),
+                None => name,
+            }
+        });
+
+        Some(RawMetadata {
+            title: text(&ItemKey::AlbumTitle).unwrap_or_default(),
+            authors: list(text(&ItemKey::AlbumArtist).or(text(&ItemKey::TrackArtist))),
+            // Audiobook taggers put the narrator in the composer field
+            narrators: list(text(&ItemKey::Composer)),
+            series: list(series),
+            genres: list(tag.genre().map(|g| g.to_string())),
+            published_year: tag.year().map(|y| y.to_string()),
+            description: text(&ItemKey::Comment),
+            ..Default::default()
+        })
+    }
+}
+
+/// Runs a user command with the book directory as its last argument, and reads the
+/// metadata it prints on stdout, in the same JSON format as the metadata file.
+///
+/// The command usually looks the book up online, so its output is cached by what
+/// identifies the book (its ASIN, ISBN, or its title and author).
+#[derive(Debug)]
+pub struct CommandProvider {
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    pub cache: Option<ResponseCache>,
+}
+
+impl MetadataProvider for CommandProvider {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    fn read(&self, dir: &Path, known: &RawMetadata) -> Option<RawMetadata> {
+        let program = self.command.first()?;
+        let key = format!(
+            "command\n{}\n{}",
+            self.command.join(" "),
+            lookup_key(dir, known)
+        );
+        let stdout = match self.cache.as_ref().and_then(|cache| cache.get(&key)) {
+            Some(cached) => cached,
+            None => {
+                let stdout = self.run(dir)?;
+                if let Some(cache) = &self.cache
+                    && let Err(err) = cache.put(&key, &stdout)
+                {
+                    eprintln!("{} {}", "Error writing the lookup cache:".red(), err);
+                }
+                stdout
+            }
+        };
+
+        // A command that knows nothing about the book may print nothing
+        if stdout.trim().is_empty() {
+            return None;
+        }
+        match parse_raw_metadata(&stdout) {
+            Ok(raw) => {
+                if !CONFIG.get().is_some_and(|cfg| cfg.quiet) {
+                    println!(
+                        "Successfully read metadata of '{}' from '{}'",
+                        dir.display(),
+                        program
+                    );
+                }
+                Some(raw)
+            }
+            Err(err) => {
+                eprintln!(
+                    "{} '{}' for '{}'. {}",
+                    "Error: Failed to parse the output of the metadata command".red(),
+                    program.yellow(),
+                    dir.display(),
+                    err
+                );
+                None
+            }
+        }
+    }
+}
+
+impl CommandProvider {
+    /**
+     * Runs the command for a book.
+     *
+     * @param dir The book directory.
+     * @return The output of the command, or `None` if it could not be run or failed.
+     */
+    fn run(&self, dir: &Path) -> Option<String> {
+        let (program, args) = self.command.split_first()?;
+        let output = Command::new(program)
+            .args(args)
+            .arg(dir)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output();
+        let output = match output {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!(
+                    "{} '{}'. {}",
+                    "Error: Could not run the metadata command".red(),
+                    program.yellow(),
+                    err
+                );
+                return None;
+            }
+        };
+        if !output.status.success() {
+            eprintln!(
+                "{} '{}' {} for '{}'",
+                "Error: The metadata command".red(),
+                program.yellow(),
+                output.status,
+                dir.display()
+            );
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/**
+ * Builds the key a book is looked up by, from what the providers before found.
+ *
+ * @param dir The book directory, used when nothing identifies the book yet.
+ * @param known The metadata read so far.
+ * @return The key.
+ */
+fn lookup_key(dir: &Path, known: &RawMetadata) -> String {
+    if let Some(asin) = known.asin.as_deref().filter(|a| !a.trim().is_empty()) {
+        return format!("asin:{}", asin.trim().to_uppercase());
+    }
+    if let Some(isbn) = known.isbn.as_deref().and_then(|i| isbn::normalize(i).ok()) {
+        return format!("isbn:{}", isbn);
+    }
+    let author = known.authors.iter().flatten().next();
+    if !known.title.trim().is_empty() {
+        return format!(
+            "title:{}|author:{}",
+            known.title.trim().to_lowercase(),
+            author.map(|a| a.trim().to_lowercase()).unwrap_or_default()
+        );
+    }
+    let dir = std::path::absolute(dir).unwrap_or(dir.to_path_buf());
+    format!("dir:{}", dir.display())
+}
+
+/**
+ * Creates the metadata providers from their names.
+ *
+ * @param names The provider names in order of precedence (`json`, `opf`, `tags` or
+ * `command`).
+ * @param metafile The name of the JSON metadata file.
+ * @param file_ext The audio file extensions.
+ * @param command The program and arguments of the `command` provider.
+ * @param cache The cache for the responses of online lookups, if enabled.
+ * @return The providers, or an error message if a name is unknown or the `command`
+ * provider has no command.
+ */
+pub fn build_providers(
+    names: &[String],
+    metafile: &str,
+    file_ext: &[String],
+    command: &[String],
+    cache: Option<&ResponseCache>,
+) -> Result<Vec<Box<dyn MetadataProvider>>, String> {
+    names
+        .iter()
+        .map(|name| -> Result<Box<dyn MetadataProvider>, String> {
+            match name.as_str() {
+                "json" => Ok(Box::new(JsonProvider {
+                    metafile: metafile.to_string(),
+                })),
+                "opf" => Ok(Box::new(OpfProvider)),
+                "tags" => Ok(Box::new(TagsProvider {
+                    file_ext: file_ext.to_vec(),
+                })),
+                "command" if command.is_empty() => Err(String::from(
+                    "the command provider needs a metadata_command",
+                )),
+                "command" => Ok(Box::new(CommandProvider {
+                    command: command.to_vec(),
+                    cache: cache.cloned(),
+                })),
+                _ => Err(format!(
+                    "unknown metadata provider '{}', use json, opf, tags or command",
+                    name
+                )),
+            }
+        })
+        .collect()
+}
+
+/**
+ * Checks whether a file is the metadata file that marks its directory as a book.
+ *
+ * A directory can hold metadata files of several providers, but only the file of
+ * the first provider that has one counts, so each book is planned once.
+ *
+ * @param providers The providers in order of precedence.
+ * @param path The file to check.
+ * @return True if the file marks a book.
+ */
+pub fn is_book_metafile(providers: &[Box<dyn MetadataProvider>], path: &Path) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    dir_metafiles(providers, dir)
+        .first()
+        .is_some_and(|metafile| metafile == path)
+}
+
+/**
+ * Checks whether a file is the metadata file of any provider, e.g. both the
+ * `metadata.json` and the `metadata.opf` of a book.
+ *
+ * @param providers The providers.
+ * @param path The file to check.
+ * @return True if a provider reads the file.
+ */
+pub fn is_provider_file(providers: &[Box<dyn MetadataProvider>], path: &Path) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    dir_metafiles(providers, dir)
+        .iter()
+        .any(|metafile| metafile == path)
+}
+
+/**
+ * Finds the metadata file of each provider in a directory. The files found in the
+ * directory that was checked last are reused until the directory changes.
+ *
+ * @param providers The providers in order of precedence.
+ * @param dir The directory.
+ * @return The metadata file of each provider that has one, in order of precedence.
+ */
+fn dir_metafiles(providers: &[Box<dyn MetadataProvider>], dir: &Path) -> Vec<PathBuf> {
+    let find = || {
+        providers
+            .iter()
+            .filter_map(|provider| provider.find_metafile(dir))
+            .collect::<Vec<_>>()
+    };
+    let Ok(modified) = fs::metadata(dir).and_then(|m| m.modified()) else {
+        return find();
+    };
+    let mut last = LAST_DIR.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(last) = last.as_ref()
+        && last.providers == providers.as_ptr() as usize
+        && last.dir == dir
+        && last.modified == modified
+    {
+        return last.metafiles.clone();
+    }
+    let metafiles = find();
+    *last = Some(DirMetafiles {
+        providers: providers.as_ptr() as usize,
+        dir: dir.to_path_buf(),
+        modified,
+        metafiles: metafiles.clone(),
+    });
+    metafiles
+}
+
+/**
+ * Reads a book's metadata from all providers, merged in order of precedence.
+ *
+ * @param providers The providers in order of precedence.
+ * An ASIN in the name of the book directory or its files is used when no provider
+ * found one, and is passed on to the providers, so online lookups can use it.
+ *
+ * @param dir The book directory.
+ * @return The merged metadata, or `None` if no provider knows the book.
+ */
+pub fn read_book(providers: &[Box<dyn MetadataProvider>], dir: &Path) -> Option<RawMetadata> {
+    read_book_sources(providers, dir).map(|(metadata, _)| metadata)
+}
+
+/**
+ * Reads a book's metadata like `read_book`, and records which provider each field
+ * came from, e.g. for `aborg one`.
+ *
+ * @param providers The providers in order of precedence.
+ * @param dir The book directory.
+ * @return The merged metadata and the source of each field that is set, as
+ *         (field, provider name), or `None` if no provider knows the book.
+ */
+pub fn read_book_sources(
+    providers: &[Box<dyn MetadataProvider>],
+    dir: &Path,
+) -> Option<(RawMetadata, Vec<(String, String)>)> {
+    let from_names = RawMetadata {
+        asin: find_asin(dir),
+        ..Default::default()
+    };
+    let mut merged: Option<RawMetadata> = None;
+    let mut sources = Vec::new();
+    for provider in providers {
+        let mut known = merged.clone().unwrap_or_default();
+        known.merge(from_names.clone());
+        let Some(raw) = provider.read(dir, &known) else {
+            continue;
+        };
+        let merged = match merged.as_mut() {
+            Some(merged) => {
+                merged.merge(raw);
+                merged
+            }
+            None => merged.insert(raw),
+        };
+        record_sources(&mut sources, merged, provider.name());
+    }
+
+    let mut merged = merged?;
+    merged.merge(from_names);
+    record_sources(&mut sources, &merged, "directory name");
+    Some((merged, sources))
+}
+
+/**
+ * Records the source of the fields of merged metadata that have no source yet.
+ *
+ * @param sources The sources recorded so far, as (field, source).
+ * @param metadata The metadata merged so far.
+ * @param source The source of the fields that were just set.
+ */
+fn record_sources(sources: &mut Vec<(String, String)>, metadata: &RawMetadata, source: &str) {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(metadata) else {
+        return;
+    };
+    for (field, value) in fields {
+        let is_set = !value.is_null()
+            && !value.as_str().is_some_and(|s| s.trim().is_empty())
+            && !value.as_array().is_some_and(|a| a.is_empty());
+        if is_set && !sources.iter().any(|(known, _)| *known == field) {
+            sources.push((field, source.to_string()));
+        }
+    }
+}
+
+/**
+ * Lists the files of a directory (not its subdirectories), sorted by name.
+ */
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && !is_junk(path))
+        .collect();
+    files.sort();
+    files
+}
+
+/**
+ * Parses the Dublin Core metadata of an OPF package file.
+ *
+ * Creators with the `nrt` role are narrators, other creators are authors. The
+ * series is read from Calibre's `calibre:series` and `calibre:series_index` entries.
+ *
+ * @param contents The contents of the OPF file.
+ * @return The raw metadata, or an error message if the file is not valid XML.
+ */
+fn parse_opf(contents: &str) -> Result<RawMetadata, String> {
+    let document = roxmltree::Document::parse(contents).map_err(|e| e.to_string())?;
+    let elements: Vec<_> = document
+        .descendants()
+        .filter(|node| node.is_element())
+        .collect();
+
+    let texts = |name: &str| -> Vec<(roxmltree::Node, String)> {
+        elements
+            .iter()
+            .filter(|node| node.tag_name().name() == name)
+            .filter_map(|node| {
+                let text = node.text()?.trim().to_string();
+                (!text.is_empty()).then_some((*node, text))
+            })
+            .collect()
+    };
+    let first = |name: &str| texts(name).into_iter().next().map(|(_, text)| text);
+    let meta = |name: &str| {
+        elements
+            .iter()
+            .find(|node| node.tag_name().name() == "meta" && node.attribute("name") == Some(name))
+            .and_then(|node| node.attribute("content"))
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    };
+    let role = |node: &roxmltree::Node| {
+        node.attributes()
+            .find(|attribute| attribute.name() == "role")
+            .map(|attribute| attribute.value().to_string())
+    };
+    let non_empty = |list: Vec<String>| if list.is_empty() { None } else { Some(list) };
+
+    let creators = texts("creator");
+    let authors = creators
+        .iter()
+        .filter(|(node, _)| role(node).is_none_or(|r| r == "aut"))
+        .map(|(_, name)| name.clone())
+        .collect();
+    let narrators = creators
+        .iter()
+        .filter(|(node, _)| role(node).is_some_and(|r| r == "nrt"))
+        .map(|(_, name)| name.clone())
+        .collect();
+
+    // Calibre stores the index as a float, e.g. "4.0"
+    let series = meta("calibre:series").map(|name| {
+        match meta("calibre:series_index").map(|i| i.trim_end_matches(".0").to_string()) {
+            Some(index) => format!("{} #{}", name, index),
+            None => name,
+        }
+    });
+    let identifier = |scheme: &str| {
+        texts("identifier")
+            .into_iter()
+            .find(|(node, _)| {
+                node.attributes()
+                    .any(|a| a.name() == "scheme" && a.value().eq_ignore_ascii_case(scheme))
+            })
+            .map(|(_, id)| id)
+    };
+    let published_date = first("date");
+
+    Ok(RawMetadata {
+        title: first("title").unwrap_or_default(),
+        subtitle: None,
+        series: series.map(|s| vec![s]),
+        authors: non_empty(authors),
+        published_year: published_date.as_ref().map(|d| d.chars().take(4).collect()),
+        published_date: published_date.map(|d| d.chars().take(10).collect()),
+        genres: non_empty(texts("subject").into_iter().map(|(_, s)| s).collect()),
+        language: first("language"),
+        abridged: None,
+        asin: identifier("asin"),
+        isbn: identifier("isbn"),
+        narrators: non_empty(narrators),
+        description: first("description"),
+        purchase_date: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_opf() {
+        let contents = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+              <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+                <dc:title>The Alloy of Law</dc:title>
+                <dc:creator opf:role="aut">Brandon Sanderson</dc:creator>
+                <dc:creator opf:role="nrt">Michael Kramer</dc:creator>
+                <dc:date>2011-11-08T00:00:00+00:00</dc:date>
+                <dc:language>eng</dc:language>
+                <dc:subject>Fantasy</dc:subject>
+                <dc:identifier opf:scheme="ASIN">B0036I54I6</dc:identifier>
+                <dc:identifier opf:scheme="ISBN">978-0-7653-6854-6</dc:identifier>
+                <meta name="calibre:series" content="Mistborn"/>
+                <meta name="calibre:series_index" content="4.0"/>
+              </metadata>
+            </package>"#;
+        let raw = parse_opf(contents).unwrap();
+
+        assert_eq!(raw.title, "The Alloy of Law");
+        assert_eq!(raw.authors, Some(vec!["Brandon Sanderson".to_string()]));
+        assert_eq!(raw.narrators, Some(vec!["Michael Kramer".to_string()]));
+        assert_eq!(raw.series, Some(vec!["Mistborn #4".to_string()]));
+        assert_eq!(raw.published_year.as_deref(), Some("2011"));
+        assert_eq!(raw.published_date.as_deref(), Some("2011-11-08"));
+        assert_eq!(raw.genres, Some(vec!["Fantasy".to_string()]));
+        assert_eq!(raw.asin.as_deref(), Some("B0036I54I6"));
+        assert_eq!(raw.isbn.as_deref(), Some("978-0-7653-6854-6"));
+    }
+
+    #[test]
+    fn test_merge_precedence() {
+        let mut raw = RawMetadata {
+            title: "The Alloy of Law".to_string(),
+            authors: Some(vec!["Brandon Sanderson".to_string()]),
+            ..Default::default()
+        };
+        raw.merge(RawMetadata {
+            title: "Alloy of Law".to_string(),
+            authors: Some(vec!["B. Sanderson".to_string()]),
+            series: Some(vec!["Mistborn #4".to_string()]),
+            ..Default::default()
+        });
+
+        assert_eq!(raw.title, "The Alloy of Law");
+        assert_eq!(raw.authors, Some(vec!["Brandon Sanderson".to_string()]));
+        assert_eq!(raw.series, Some(vec!["Mistborn #4".to_string()]));
+    }
+
+    #[test]
+    fn test_record_sources() {
+        let mut sources = Vec::new();
+        let mut raw = RawMetadata {
+            title: "The Alloy of Law".to_string(),
+            series: Some(Vec::new()),
+            ..Default::default()
+        };
+        record_sources(&mut sources, &raw, "opf");
+        raw.series = Some(vec!["Mistborn #4".to_string()]);
+        raw.asin = Some("B005OEVIZM".to_string());
+        record_sources(&mut sources, &raw, "directory name");
+
+        let field = |name: &str| {
+            sources
+                .iter()
+                .find(|(f, _)| f == name)
+                .map(|(_, s)| s.as_str())
+        };
+        assert_eq!(field("title"), Some("opf"));
+        assert_eq!(field("series"), Some("directory name"));
+        assert_eq!(field("asin"), Some("directory name"));
+        assert_eq!(field("narrators"), None);
+    }
+
+    #[test]
+    fn test_is_book_metafile() {
+        let dir = std::env::temp_dir().join(format!("aborg-metafile-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (json, opf) = (dir.join("metadata.json"), dir.join("metadata.opf"));
+        fs::write(&json, "{}").unwrap();
+        fs::write(&opf, "<package/>").unwrap();
+        let names = DEFAULT_PROVIDERS.map(String::from);
+        let providers = build_providers(&names, "metadata.json", &[], &[], None).unwrap();
+
+        // Only the file of the first provider marks the book
+        assert!(is_book_metafile(&providers, &json));
+        assert!(!is_book_metafile(&providers, &opf));
+        assert!(is_provider_file(&providers, &opf));
+        assert!(!is_provider_file(&providers, &dir.join("cover.jpg")));
+
+        // A change of the directory is noticed
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        fs::remove_file(&json).unwrap();
+        assert!(is_book_metafile(&providers, &opf));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
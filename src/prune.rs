@@ -0,0 +1,125 @@
+use crate::dupes::title_key;
+use crate::quality::average_bitrate;
+use crate::summary::Summary;
+use crate::{CONFIG, Plan, get_files, is_audio_file, is_metafile, read_book_metadata};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Represents an existing book in the destination that an incoming book could
+/// replace, to make room for an import that doesn't fit.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PruneSuggestion {
+    /// The directory of the existing book.
+    pub existing: String,
+    /// The source directory of the incoming book with the same author and title.
+    pub incoming: String,
+    /// The size of the existing book, freed by replacing it.
+    pub bytes: u64,
+    /// The average bitrate of the existing book in kb/s, if known.
+    pub existing_bitrate: Option<u32>,
+    /// The average bitrate of the incoming book in kb/s, if known.
+    pub incoming_bitrate: Option<u32>,
+}
+
+impl PruneSuggestion {
+    /**
+     * Checks whether the existing book has a lower bitrate than the incoming one.
+     */
+    pub fn is_lower_quality(&self) -> bool {
+        matches!(
+            (self.existing_bitrate, self.incoming_bitrate),
+            (Some(existing), Some(incoming)) if existing < incoming
+        )
+    }
+}
+
+/**
+ * Finds the existing books in the destination that are the same book as an incoming
+ * book (by author and title), so they can be replaced when the destination is low
+ * on space. Books with a higher bitrate than the incoming book are not suggested.
+ *
+ * Only the top directories the incoming books are planned into (e.g. the author
+ * directories) are searched, since that is where another copy of a book ends up.
+ *
+ * @param plans The plans of the run.
+ * @return The suggestions, the largest existing books first.
+ */
+pub fn suggest_pruning(plans: &[Plan]) -> Vec<PruneSuggestion> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let mut incoming: HashMap<String, &Plan> = HashMap::new();
+    let mut search_dirs: Vec<PathBuf> = Vec::new();
+    for plan in plans {
+        incoming.entry(title_key(&plan.metadata)).or_insert(plan);
+        let top = Path::new(&plan.to)
+            .strip_prefix(&plan.root)
+            .ok()
+            .and_then(|path| path.iter().next())
+            .map(|name| Path::new(&plan.root).join(name));
+        if let Some(top) = top
+            && top.is_dir()
+            && !search_dirs.contains(&top)
+        {
+            search_dirs.push(top);
+        }
+    }
+
+    let sources: HashSet<&str> = plans.iter().map(|plan| plan.from.as_str()).collect();
+    let mut suggestions = Vec::new();
+    let metadata_paths = search_dirs.iter().flat_map(|dir| {
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| is_metafile(entry.path()))
+    });
+    for entry in metadata_paths {
+        let Some(dir) = entry.path().parent() else {
+            continue;
+        };
+        let existing = dir.display().to_string();
+        if sources.contains(existing.as_str()) {
+            continue;
+        }
+        let Some(metadata) = read_book_metadata(entry.path(), &mut Summary::default()) else {
+            continue;
+        };
+        let Some(plan) = incoming.get(&title_key(&metadata)) else {
+            continue;
+        };
+
+        let files = get_files(&existing);
+        let audio: Vec<PathBuf> = files
+            .iter()
+            .filter(|file| is_audio_file(file, &cfg.file_ext))
+            .cloned()
+            .collect();
+        let incoming_audio: Vec<PathBuf> = plan
+            .files
+            .iter()
+            .map(|op| op.from.clone())
+            .filter(|file| is_audio_file(file, &cfg.file_ext))
+            .collect();
+        let suggestion = PruneSuggestion {
+            existing,
+            incoming: plan.from.clone(),
+            bytes: files
+                .iter()
+                .filter_map(|file| fs::metadata(file).ok())
+                .map(|metadata| metadata.len())
+                .sum(),
+            existing_bitrate: average_bitrate(&audio),
+            incoming_bitrate: average_bitrate(&incoming_audio),
+        };
+        let higher_quality = matches!(
+            (suggestion.existing_bitrate, suggestion.incoming_bitrate),
+            (Some(existing), Some(incoming)) if existing > incoming
+        );
+        if !higher_quality {
+            suggestions.push(suggestion);
+        }
+    }
+    suggestions.sort_by_key(|suggestion| std::cmp::Reverse(suggestion.bytes));
+    suggestions
+}
@@ -0,0 +1,134 @@
+use crate::dupes::audio_duration;
+use crate::fsutil::format_size;
+use clap::ValueEnum;
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The extensions of lossless audio formats, which beat any lossy edition.
+const LOSSLESS_EXTENSIONS: [&str; 6] = ["flac", "wav", "aiff", "aif", "ape", "wv"];
+
+/// Represents what happens when an incoming book already exists in the destination.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Keep the edition with the better quality: lossless audio first, then the
+    /// higher bitrate, then the larger size. Ties keep the existing book.
+    KeepBetter,
+}
+
+/// Represents the quality of an edition of a book, measured from its audio files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookQuality {
+    /// The codec of the audio files, from their extension, e.g. `MP3`.
+    pub codec: String,
+    /// True if the audio files are in a lossless format, e.g. FLAC.
+    pub lossless: bool,
+    /// The average bitrate in kb/s, if the length of the audio is known.
+    pub bitrate: Option<u32>,
+    /// The total size of the audio files.
+    pub bytes: u64,
+}
+
+impl BookQuality {
+    /**
+     * Measures the quality of a book from its audio files.
+     *
+     * @param files The audio files of the book.
+     * @return The quality of the book.
+     */
+    pub fn of(files: &[PathBuf]) -> Self {
+        let mut codecs: Vec<String> = files
+            .iter()
+            .filter_map(|file| file.extension())
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .collect();
+        codecs.sort();
+        codecs.dedup();
+        BookQuality {
+            lossless: !codecs.is_empty()
+                && codecs
+                    .iter()
+                    .all(|codec| LOSSLESS_EXTENSIONS.contains(&codec.as_str())),
+            codec: codecs.join("/").to_uppercase(),
+            bitrate: average_bitrate(files),
+            bytes: files
+                .iter()
+                .filter_map(|file| fs::metadata(file).ok())
+                .map(|metadata| metadata.len())
+                .sum(),
+        }
+    }
+
+    /**
+     * Checks whether this edition is better than another one: lossless audio beats
+     * lossy audio, then the higher bitrate wins, and the larger size when the
+     * bitrates are equal or unknown.
+     *
+     * @param other The edition to compare with.
+     * @return True if this edition is strictly better.
+     */
+    pub fn is_better_than(&self, other: &BookQuality) -> bool {
+        if self.lossless != other.lossless {
+            return self.lossless;
+        }
+        match (self.bitrate, other.bitrate) {
+            (Some(a), Some(b)) if a != b => a > b,
+            _ => self.bytes > other.bytes,
+        }
+    }
+}
+
+impl Display for BookQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bitrate = self
+            .bitrate
+            .map(|kbps| format!("{} kb/s", kbps))
+            .unwrap_or_else(|| String::from("unknown bitrate"));
+        write!(f, "{} {}, {}", bitrate, self.codec, format_size(self.bytes))
+    }
+}
+
+/**
+ * Computes the average bitrate of audio files from their size and length.
+ *
+ * @param files The audio files of a book.
+ * @return The bitrate in kb/s, or `None` if the length of the files is unknown.
+ */
+pub fn average_bitrate(files: &[PathBuf]) -> Option<u32> {
+    let bytes: u64 = files
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let duration: Duration = files.iter().map(|file| audio_duration(file)).sum();
+    if duration.is_zero() {
+        return None;
+    }
+    u32::try_from(bytes * 8 / 1000 / duration.as_secs().max(1)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_better_than() {
+        let quality = |codec: &str, bitrate: Option<u32>, bytes: u64| BookQuality {
+            codec: codec.to_uppercase(),
+            lossless: LOSSLESS_EXTENSIONS.contains(&codec),
+            bitrate,
+            bytes,
+        };
+        let mp3_64 = quality("mp3", Some(64), 300);
+        let mp3_128 = quality("mp3", Some(128), 200);
+        assert!(mp3_128.is_better_than(&mp3_64));
+        assert!(!mp3_64.is_better_than(&mp3_128));
+        assert!(quality("flac", Some(64), 100).is_better_than(&mp3_128));
+
+        // Without known bitrates the larger edition wins, and ties keep the existing one
+        assert!(quality("m4b", None, 500).is_better_than(&mp3_64));
+        assert!(!mp3_64.is_better_than(&mp3_64.clone()));
+        assert_eq!(mp3_128.to_string(), "128 kb/s MP3, 200 B");
+    }
+}
@@ -0,0 +1,81 @@
+use crate::database::{BookQuery, BookRecord, find_books};
+use clap::ValueEnum;
+use colored::Colorize;
+use std::process::exit;
+
+/// Represents the output formats of `aborg query`.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum QueryFormat {
+    /// One book directory per line.
+    Paths,
+    /// A JSON array of the matching books, including their metadata.
+    Json,
+    /// A CSV table with the path, author, series, book number and title.
+    Csv,
+}
+
+/**
+ * Prints the books in the library database that match the query.
+ *
+ * @param database The path of the SQLite library database.
+ * @param query The filters to apply.
+ * @param format The output format.
+ */
+pub fn query(database: &str, query: &BookQuery, format: &QueryFormat) {
+    let books = match find_books(database, query) {
+        Ok(books) => books,
+        Err(err) => {
+            eprintln!(
+                "{} '{}'. {}",
+                "Error: Could not query the library database".red(),
+                database.yellow(),
+                err
+            );
+            exit(1)
+        }
+    };
+
+    match format {
+        QueryFormat::Paths => {
+            for book in &books {
+                println!("{}", book.path);
+            }
+        }
+        QueryFormat::Json => match serde_json::to_string_pretty(&books) {
+            Ok(json) => println!("{}", json),
+            Err(err) => eprintln!("{} {}", "Error:".red(), err),
+        },
+        QueryFormat::Csv => {
+            println!("path,author,series,book_number,title");
+            for book in &books {
+                println!("{}", csv_row(book));
+            }
+        }
+    }
+}
+
+/**
+ * Formats a book as a CSV row, quoting fields that need it.
+ *
+ * @param book The book to format.
+ * @return The CSV row.
+ */
+fn csv_row(book: &BookRecord) -> String {
+    [
+        book.path.clone(),
+        book.author.clone().unwrap_or_default(),
+        book.series.clone().unwrap_or_default(),
+        book.book_number.map(|n| n.to_string()).unwrap_or_default(),
+        book.title.clone(),
+    ]
+    .iter()
+    .map(|field| {
+        if field.contains([',', '"', '\n']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.clone()
+        }
+    })
+    .collect::<Vec<String>>()
+    .join(",")
+}
@@ -0,0 +1,146 @@
+use crate::summary::Summary;
+use crate::{CONFIG, FileAction, FileOp, Plan, database};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+/**
+ * Loads the plans of a past run from the library database and checks which of
+ * their files are already in place, so the run can be verified (with `--dry-run`)
+ * or completed by executing the plans again.
+ *
+ * Books whose files are all in place are reported as done and left out. The files
+ * that are in place are skipped in the other books. A book is left out with an
+ * error if one of its missing files is gone from the source too.
+ *
+ * @param run_id The ID of the run, as printed when it started.
+ * @param summary The summary that collects the books that can't be redone.
+ * @return The plans of the books that are not done, or an error message.
+ */
+pub fn redo_plans(run_id: i64, summary: &mut Summary) -> Result<Vec<Plan>, String> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let Some(path) = &cfg.database else {
+        return Err(String::from(
+            "--redo needs the library database the run was recorded in, use --database",
+        ));
+    };
+    let plans = database::run_plans(path, run_id)?;
+    if plans.is_empty() {
+        return Err(format!("No plans were recorded for run {}", run_id));
+    }
+    println!(
+        "{} {} books of run {}...",
+        "Checking".green(),
+        plans.len(),
+        run_id
+    );
+
+    let mut remaining = Vec::new();
+    for mut plan in plans {
+        let done: Vec<bool> = plan
+            .files
+            .iter()
+            .map(|op| is_done(op, &plan.files))
+            .collect();
+        let missing = done.iter().filter(|done| !**done).count();
+        if missing == 0 {
+            println!("{} '{}'", "Done:".green(), plan.to);
+            continue;
+        }
+
+        let lost = plan
+            .files
+            .iter()
+            .zip(&done)
+            .find(|(op, done)| !**done && !op.from.exists());
+        if let Some((op, _)) = lost {
+            eprintln!(
+                "{} '{}' is neither in the source nor in the destination, skipping book '{}'",
+                "Error:".red(),
+                op.from.display().to_string().yellow(),
+                plan.from
+            );
+            summary.skipped_books.push((
+                plan.from.clone(),
+                format!("'{}' is missing", op.from.display()),
+            ));
+            continue;
+        }
+
+        println!(
+            "{} '{}' ({} of {} files missing)",
+            "Incomplete:".yellow(),
+            plan.to,
+            missing,
+            plan.files.len()
+        );
+        // The files already in place must not be removed again
+        plan.replace_existing &= missing == plan.files.len();
+        for (op, done) in plan.files.iter_mut().zip(done) {
+            if done {
+                op.action = FileAction::Skip;
+            }
+        }
+        remaining.push(plan);
+    }
+    Ok(remaining)
+}
+
+/**
+ * Checks whether a file operation of a plan is complete. A file renamed through a
+ * temporary name is complete once the rename to its final name is.
+ *
+ * @param op The file operation.
+ * @param files All file operations of the plan.
+ * @return True if the file is in place.
+ */
+fn is_done(op: &FileOp, files: &[FileOp]) -> bool {
+    op.action == FileAction::Skip
+        || in_place(op)
+        || files
+            .iter()
+            .any(|next| next.from == Path::new(&op.to) && in_place(next))
+}
+
+/**
+ * Checks whether the destination of a file operation exists with the planned size.
+ * Links are only checked for existence.
+ */
+fn in_place(op: &FileOp) -> bool {
+    match op.action {
+        FileAction::Symlink => fs::symlink_metadata(&op.to).is_ok(),
+        _ => fs::metadata(&op.to).is_ok_and(|m| m.len() == op.bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_is_done() {
+        let dir = std::env::temp_dir().join(format!("aborg-redo-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.mp3"), "abc").unwrap();
+        fs::write(dir.join("b.mp3"), "abc").unwrap();
+        let op = |from: &str, to: &str| FileOp {
+            from: PathBuf::from(from),
+            to: dir.join(to).display().to_string(),
+            bytes: 3,
+            action: FileAction::Move,
+        };
+        let files = vec![
+            op("src/a.mp3", "a.mp3"),
+            op("src/b.mp3", "b.mp3.aborg-rename"),
+            op(
+                &dir.join("b.mp3.aborg-rename").display().to_string(),
+                "b.mp3",
+            ),
+            op("src/c.mp3", "c.mp3"),
+        ];
+        let done: Vec<bool> = files.iter().map(|op| is_done(op, &files)).collect();
+        assert_eq!(done, vec![true, true, true, false]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+use crate::schema::Schema;
+use crate::{CONFIG, Config};
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The result of reloading the config file and templates.
+pub struct Reloaded {
+    pub config: Config,
+    pub schema: Schema,
+    /// The files the new config and schema were read from.
+    pub files: Vec<PathBuf>,
+}
+
+/// Reloads the config and templates of a long running watch when their files
+/// change, so edits apply to the next batch without restarting the service.
+pub struct Reloader {
+    load: Box<dyn Fn() -> Result<Reloaded, String>>,
+    /// The watched files and their modification times when they were last loaded.
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Reloader {
+    /**
+     * Creates a reloader.
+     *
+     * @param files The files the current config and schema were read from.
+     * @param load Reads the config and schema again, validating them.
+     */
+    pub fn new(files: Vec<PathBuf>, load: Box<dyn Fn() -> Result<Reloaded, String>>) -> Self {
+        Reloader {
+            load,
+            stamps: stamp(files),
+        }
+    }
+
+    /**
+     * Reloads the config and schema if one of their files changed since the last
+     * check.
+     *
+     * The new config only replaces the current one if it is valid and all its
+     * templates compile, otherwise the error is logged and the current config is kept.
+     *
+     * @return The new schema, or `None` if nothing changed or the reload failed.
+     */
+    pub fn check(&mut self) -> Option<Schema> {
+        let changed = self
+            .stamps
+            .iter()
+            .find(|(path, modified)| &modified_time(path) != modified)?;
+        println!(
+            "{} '{}' changed, reloading the config and templates...",
+            "Reload:".blue(),
+            changed.0.display()
+        );
+
+        match (self.load)() {
+            Ok(reloaded) => {
+                CONFIG.replace(reloaded.config);
+                self.stamps = stamp(reloaded.files);
+                println!("{}", "Reloaded the config and templates.".green());
+                Some(reloaded.schema)
+            }
+            Err(err) => {
+                eprintln!(
+                    "{} {}. Keeping the previous config.",
+                    "Error: Reload failed:".red(),
+                    err
+                );
+                // Only retry once the files change again
+                let files = self.stamps.drain(..).map(|(path, _)| path).collect();
+                self.stamps = stamp(files);
+                None
+            }
+        }
+    }
+}
+
+/**
+ * Records the modification time of each file. Missing files are recorded too, so
+ * creating them counts as a change.
+ */
+fn stamp(files: Vec<PathBuf>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    files
+        .into_iter()
+        .map(|path| {
+            let modified = modified_time(&path);
+            (path, modified)
+        })
+        .collect()
+}
+
+/**
+ * Gets the modification time of a file, or `None` if it doesn't exist.
+ */
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,147 @@
+use crate::fsutil::format_size;
+use crate::summary::Summary;
+use chrono::Local;
+use handlebars::Handlebars;
+use serde_json::json;
+use std::fs;
+
+const TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>aborg report - {{generated}}</title>
+<style>
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.5em; }
+.dry-run { color: #b36b00; font-weight: bold; }
+.stats td { padding: 0.2em 1em 0.2em 0; }
+table.operations { border-collapse: collapse; width: 100%; font-size: 0.9em; }
+table.operations th, table.operations td { border: 1px solid #ccc; padding: 0.3em 0.5em; text-align: left; }
+table.operations th { background: #eee; cursor: pointer; user-select: none; }
+tr.error td { background: #fdd; }
+tr.skip td { background: #ffd; }
+ul.errors li { color: #b00; }
+</style>
+</head>
+<body>
+<h1>aborg report</h1>
+<p>Generated {{generated}}{{#if dry_run}} <span class="dry-run">(dry run, no changes were made)</span>{{/if}}</p>
+<table class="stats">
+<tr><td>Books processed</td><td>{{books}}</td></tr>
+<tr><td>Files processed</td><td>{{files}}</td></tr>
+<tr><td>Total size</td><td>{{size}}</td></tr>
+<tr><td>Space freed in source</td><td>{{freed}}</td></tr>
+<tr><td>Errors</td><td>{{error_count}}</td></tr>
+</table>
+{{#if errors}}
+<h2>Errors</h2>
+<ul class="errors">
+{{#each errors}}<li>{{this}}</li>
+{{/each}}</ul>
+{{/if}}
+{{#if skipped_books}}
+<h2>Skipped books</h2>
+<ul>
+{{#each skipped_books}}<li>{{this.[0]}} - {{this.[1]}}</li>
+{{/each}}</ul>
+{{/if}}
+{{#if invalid_isbns}}
+<h2>Invalid ISBNs</h2>
+<ul>
+{{#each invalid_isbns}}<li>{{this.[0]}} - {{this.[1]}}</li>
+{{/each}}</ul>
+{{/if}}
+{{#if incomplete}}
+<h2>Incomplete metadata</h2>
+<ul>
+{{#each incomplete}}<li>{{this.[1].score}}% {{this.[0]}} - missing {{#each this.[1].missing}}{{#unless @first}}, {{/unless}}{{this}}{{/each}}</li>
+{{/each}}</ul>
+{{/if}}
+{{#if quarantined}}
+<h2>Quarantined books</h2>
+<ul class="errors">
+{{#each quarantined}}<li>{{this.[0]}} - {{this.[1]}}</li>
+{{/each}}</ul>
+{{/if}}
+<h2>Operations</h2>
+<table class="operations">
+<thead><tr><th>Action</th><th>From</th><th>To</th><th>Size</th><th>Outcome</th></tr></thead>
+<tbody>
+{{#each operations}}<tr class="{{#if error}}error{{else}}{{action}}{{/if}}"><td>{{action}}</td><td>{{from}}</td><td>{{to}}</td><td data-sort="{{bytes}}">{{size}}</td><td>{{outcome}}</td></tr>
+{{/each}}</tbody>
+</table>
+<script>
+document.querySelectorAll("table.operations th").forEach(function (th, column) {
+  var ascending = true;
+  th.addEventListener("click", function () {
+    var body = th.closest("table").tBodies[0];
+    var rows = Array.from(body.rows);
+    var key = function (row) {
+      var cell = row.cells[column];
+      var sort = cell.getAttribute("data-sort");
+      return sort === null ? cell.textContent.toLowerCase() : Number(sort);
+    };
+    rows.sort(function (a, b) {
+      var x = key(a), y = key(b);
+      return (x < y ? -1 : x > y ? 1 : 0) * (ascending ? 1 : -1);
+    });
+    ascending = !ascending;
+    rows.forEach(function (row) { body.appendChild(row); });
+  });
+});
+</script>
+</body>
+</html>
+"#;
+
+/**
+ * Writes a self-contained HTML report of a run, with the summary, a sortable
+ * table of every file operation, and the errors highlighted.
+ *
+ * @param path The path of the HTML file to write.
+ * @param summary The results of the run.
+ * @param dry_run True if the run was a dry run.
+ * @return `Ok(())` if the report was written, or an error message.
+ */
+pub fn write_report(path: &str, summary: &Summary, dry_run: bool) -> Result<(), String> {
+    let operations: Vec<_> = summary
+        .operations
+        .iter()
+        .map(|op| {
+            json!({
+                "action": op.action,
+                "from": op.from,
+                "to": op.to,
+                "bytes": op.bytes,
+                "size": format_size(op.bytes),
+                "error": op.error.is_some(),
+                "outcome": match (&op.error, op.action) {
+                    (Some(err), _) => err.as_str(),
+                    (None, "skip") => "already exists",
+                    (None, _) => "ok",
+                },
+            })
+        })
+        .collect();
+
+    let data = json!({
+        "generated": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "dry_run": dry_run,
+        "books": summary.books,
+        "files": summary.files,
+        "size": format_size(summary.bytes),
+        "freed": format_size(summary.freed_bytes),
+        "errors": summary.errors,
+        "error_count": summary.errors.len(),
+        "skipped_books": summary.skipped_books,
+        "invalid_isbns": summary.invalid_isbns,
+        "incomplete": summary.incomplete_by_score(),
+        "quarantined": summary.quarantined,
+        "operations": operations,
+    });
+
+    let html = Handlebars::new()
+        .render_template(TEMPLATE, &data)
+        .map_err(|e| e.to_string())?;
+    fs::write(path, html).map_err(|e| e.to_string())
+}
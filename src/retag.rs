@@ -0,0 +1,167 @@
+use crate::audit;
+use crate::metadata::{Metadata, build_metadata};
+use crate::provider::read_book;
+use crate::track::parse_from_filename;
+use crate::{CONFIG, get_files, is_audio_file, is_metafile};
+use colored::Colorize;
+use lofty::config::WriteOptions;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::{Accessor, ItemKey, Tag, TagExt};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/**
+ * Updates the embedded tags of an already organized library from its metadata files.
+ *
+ * Every directory containing a metadata file is treated as a book, and each audio
+ * file inside it gets its album, artist, genre, year, series and track number tags
+ * rewritten, along with the track and disc totals. No files are moved or renamed.
+ *
+ * @param movement If true, also write the series into the movement tags.
+ */
+pub fn retag(movement: bool) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let target_file = &cfg.metafile;
+
+    println!(
+        "Searching for '{}' in '{}' and all sub-directories...",
+        target_file.green(),
+        cfg.from.green()
+    );
+
+    for entry in WalkDir::new(&cfg.from) {
+        match entry {
+            Ok(entry) => {
+                if !is_metafile(entry.path()) {
+                    continue;
+                }
+
+                let dir = entry.path().parent().unwrap();
+                let Some(raw_data) = read_book(&cfg.providers, dir) else {
+                    continue;
+                };
+                let metadata = build_metadata(
+                    raw_data,
+                    dir,
+                    &cfg.series_patterns,
+                    &cfg.series_aliases,
+                    &cfg.file_ext,
+                );
+
+                println!("--\n");
+                let book_dir = entry.path().parent().unwrap().display().to_string();
+                let audio_files: Vec<PathBuf> = get_files(&book_dir)
+                    .into_iter()
+                    .filter(|file| is_audio_file(file, &cfg.file_ext))
+                    .collect();
+                // A track number above the file count (e.g. a missing file) must not
+                // show as "7/5"
+                let track_total = audio_files
+                    .iter()
+                    .filter_map(|file| track_from_name(file, audio_files.len() as u32))
+                    .fold(audio_files.len() as u32, u32::max);
+                for file in &audio_files {
+                    tag_file(file, &metadata, track_total, movement, cfg.dry_run);
+                }
+            }
+            Err(err) => {
+                eprintln!("{}{}", "Error: ".red(), err);
+            }
+        }
+    }
+}
+
+/**
+ * Writes the book metadata into the primary tag of an audio file.
+ *
+ * The track number is taken from the (already organized) file name first,
+ * falling back to the track number that is already in the tag. Since the files of
+ * a book are numbered through, the book is tagged as a single disc.
+ *
+ * @param file The audio file to tag.
+ * @param metadata The book metadata to write.
+ * @param track_total The number of tracks of the book.
+ * @param movement If true, also write the series and book number into the movement tags.
+ * @param dry_run If true, only print what would be tagged.
+ */
+fn tag_file(file: &Path, metadata: &Metadata, track_total: u32, movement: bool, dry_run: bool) {
+    print!("{} '{}'...", "Tagging:".blue(), file.display());
+    if dry_run {
+        println!(" Done");
+        return;
+    }
+
+    let mut tagged_file = match Probe::open(file).and_then(|p| p.read()) {
+        Ok(tagged_file) => tagged_file,
+        Err(err) => {
+            eprintln!("{} {}", "Error reading tags:".red(), err);
+            return;
+        }
+    };
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged_file.primary_tag_mut().unwrap();
+
+    tag.set_album(metadata.title.clone());
+    if let Some(author) = &metadata.author {
+        tag.set_artist(author.clone());
+        tag.insert_text(ItemKey::AlbumArtist, author.clone());
+    }
+    if let Some(genre) = &metadata.genre {
+        tag.set_genre(genre.clone());
+    }
+    if let Some(year) = metadata
+        .published_year
+        .as_ref()
+        .and_then(|y| y.parse::<u32>().ok())
+    {
+        tag.set_year(year);
+    }
+    if let Some(series) = &metadata.series {
+        let grouping = match metadata.book_number {
+            Some(num) => format!("{} #{}", series, num),
+            None => series.clone(),
+        };
+        tag.insert_text(ItemKey::ContentGroup, grouping);
+        if movement {
+            tag.insert_text(ItemKey::Movement, series.clone());
+            if let Some(num) = metadata.book_number {
+                tag.insert_text(ItemKey::MovementNumber, num.to_string());
+            }
+        }
+    }
+
+    if let Some(track) = track_from_name(file, track_total) {
+        tag.set_track(track);
+    }
+    tag.set_track_total(track_total);
+    tag.set_disk(1);
+    tag.set_disk_total(1);
+
+    let result = tag.save_to_path(file, WriteOptions::default());
+    audit::record(
+        "retag",
+        file,
+        file,
+        result.as_ref().err().map(|e| e.to_string()).as_deref(),
+    );
+    match result {
+        Ok(_) => println!(" Done"),
+        Err(err) => eprintln!("{} {}", "Error writing tags:".red(), err),
+    }
+}
+
+/**
+ * Reads the track number from the name of an organized audio file, with the number
+ * of tracks of the book as a hint.
+ */
+fn track_from_name(file: &Path, file_total: u32) -> Option<u32> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let file_total = u16::try_from(file_total).ok();
+    parse_from_filename(stem, &cfg.track_keywords, file_total).map(u32::from)
+}
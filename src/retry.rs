@@ -0,0 +1,142 @@
+use crate::CONFIG;
+use crate::config::RetryConfig;
+use colored::Colorize;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+/// Represents how file operations are retried after transient errors.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    attempts: u32,
+    delay: Duration,
+    /// The raw OS error codes that are retried.
+    errors: Vec<i32>,
+}
+
+impl RetryPolicy {
+    /**
+     * Creates the retry policy from the config file settings.
+     *
+     * @param config The retry settings.
+     * @return The retry policy, or an error message if an error name is unknown.
+     */
+    pub fn from_config(config: &RetryConfig) -> Result<Self, String> {
+        let mut errors = Vec::new();
+        for name in &config.errors {
+            match error_code(name) {
+                Some(code) => errors.push(code),
+                // Other platforms don't have these error codes, so nothing is retried
+                None if cfg!(not(unix)) => {}
+                None => return Err(format!("unknown error '{}'", name)),
+            }
+        }
+
+        Ok(RetryPolicy {
+            attempts: config.attempts.max(1),
+            delay: Duration::from_millis(config.delay_ms),
+            errors,
+        })
+    }
+
+    /**
+     * Checks whether an error is transient, and the operation should be tried again.
+     */
+    fn is_transient(&self, err: &io::Error) -> bool {
+        err.raw_os_error()
+            .is_some_and(|code| self.errors.contains(&code))
+    }
+}
+
+/**
+ * Runs a file operation, retrying it with an exponential backoff if it fails with
+ * a transient error.
+ *
+ * @param operation The name of the operation, shown when it is retried.
+ * @param f The operation. It must be safe to run again after it failed.
+ * @return The result of the last attempt. If all attempts failed, the error notes
+ *         how many attempts were made.
+ */
+pub fn retry<T>(operation: &str, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let Some(policy) = CONFIG.get().map(|cfg| &cfg.retry) else {
+        return f();
+    };
+
+    let mut delay = policy.delay;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(err) if policy.is_transient(&err) => {
+                if attempt >= policy.attempts {
+                    return Err(io::Error::new(
+                        err.kind(),
+                        format!("{} (gave up after {} attempts)", err, attempt),
+                    ));
+                }
+                eprintln!(
+                    "{} {} failed ({}), retrying in {:?}",
+                    "Warning:".yellow(),
+                    operation,
+                    err,
+                    delay
+                );
+                thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/**
+ * Gets the OS error code of an error name like `ESTALE`.
+ *
+ * @param name The error name, ignoring case.
+ * @return The error code, or `None` if the name is unknown.
+ */
+#[cfg(unix)]
+fn error_code(name: &str) -> Option<i32> {
+    let code = match name.to_uppercase().as_str() {
+        "EAGAIN" | "EWOULDBLOCK" => libc::EAGAIN,
+        "EBUSY" => libc::EBUSY,
+        "ESTALE" => libc::ESTALE,
+        "ETIMEDOUT" => libc::ETIMEDOUT,
+        "ECONNRESET" => libc::ECONNRESET,
+        "ECONNABORTED" => libc::ECONNABORTED,
+        "ENOTCONN" => libc::ENOTCONN,
+        "EHOSTDOWN" => libc::EHOSTDOWN,
+        "EHOSTUNREACH" => libc::EHOSTUNREACH,
+        "ENETDOWN" => libc::ENETDOWN,
+        "ENETUNREACH" => libc::ENETUNREACH,
+        "EINTR" => libc::EINTR,
+        "EIO" => libc::EIO,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(not(unix))]
+fn error_code(_name: &str) -> Option<i32> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_transient() {
+        let policy = RetryPolicy::from_config(&RetryConfig::default()).unwrap();
+        assert!(policy.is_transient(&io::Error::from_raw_os_error(libc::ESTALE)));
+        assert!(policy.is_transient(&io::Error::from_raw_os_error(libc::EAGAIN)));
+        assert!(!policy.is_transient(&io::Error::from_raw_os_error(libc::ENOENT)));
+        assert!(!policy.is_transient(&io::Error::other("not an OS error")));
+
+        let config = RetryConfig {
+            errors: vec!["ENOPE".to_string()],
+            ..RetryConfig::default()
+        };
+        assert!(RetryPolicy::from_config(&config).is_err());
+    }
+}
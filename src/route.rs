@@ -0,0 +1,87 @@
+use crate::config::RouteConfig;
+use crate::filter::{Filter, parse_filter};
+use crate::metadata::Metadata;
+
+/// Represents a rule that organizes the books matching its filters into another
+/// destination root, e.g. nonfiction into `/library/nonfiction`.
+#[derive(Debug)]
+pub struct Route {
+    pub filters: Vec<Filter>,
+    pub destination: String,
+}
+
+/**
+ * Compiles the routing rules of the config file.
+ *
+ * @param routes The rules, in order of precedence.
+ * @return The compiled rules, or an error message if a filter is not valid.
+ */
+pub fn compile_routes(routes: &[RouteConfig]) -> Result<Vec<Route>, String> {
+    routes
+        .iter()
+        .map(|route| {
+            let filters = route
+                .when
+                .iter()
+                .map(|filter| parse_filter(filter).map_err(|e| format!("'{}': {}", filter, e)))
+                .collect::<Result<_, _>>()?;
+            Ok(Route {
+                filters,
+                destination: route.destination.clone(),
+            })
+        })
+        .collect()
+}
+
+/**
+ * Picks the destination root of a book. The first rule whose filters all match
+ * wins.
+ *
+ * @param routes The rules, in order of precedence.
+ * @param metadata The metadata of the book.
+ * @return The destination root, or `None` if no rule matches.
+ */
+pub fn destination<'a>(routes: &'a [Route], metadata: &Metadata) -> Option<&'a str> {
+    routes
+        .iter()
+        .find(|route| route.filters.iter().all(|filter| filter.matches(metadata)))
+        .map(|route| route.destination.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let route = |when: &[&str], destination: &str| RouteConfig {
+            when: when.iter().map(|w| w.to_string()).collect(),
+            destination: destination.to_string(),
+        };
+        let routes = compile_routes(&[
+            route(
+                &["genre~=nonfiction|biograph|history"],
+                "/library/nonfiction",
+            ),
+            route(
+                &["genre~=children|kids", "language=English"],
+                "/library/kids",
+            ),
+            route(&[], "/library/fiction"),
+        ])
+        .unwrap();
+        let book = |genre: &str| Metadata {
+            genre: Some(genre.to_string()),
+            language: Some("English".to_string()),
+            ..Default::default()
+        };
+
+        let pick = |genre: &str| destination(&routes, &book(genre));
+        assert_eq!(pick("Biographies & Memoirs"), Some("/library/nonfiction"));
+        assert_eq!(pick("Children's Audiobooks"), Some("/library/kids"));
+        assert_eq!(pick("Fantasy"), Some("/library/fiction"));
+        assert_eq!(destination(&routes[..2], &book("Fantasy")), None);
+
+        assert!(compile_routes(&[route(&["genre"], "/library")]).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use crate::config::RunsConfig;
+use crate::summary::Summary;
+use crate::{mapping, report};
+use chrono::{Local, NaiveDateTime};
+use colored::Colorize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The format of the names of the run directories, e.g. `2024-06-01T03-00-00`. It
+/// has no colons, which are not allowed in names on Windows.
+const RUN_NAME_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+
+/// The name of the journal of the file operations in a run directory.
+const JOURNAL_NAME: &str = "journal.log";
+
+/// The directory of the run that is in progress, if run directories are enabled.
+static CURRENT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/**
+ * Starts a new run, so the file operations are written to its journal. The
+ * directory is named after the current local time, and is created when the first
+ * file is written into it.
+ *
+ * @param config The settings of the run directories.
+ */
+pub fn start(config: &RunsConfig) {
+    let base = match &config.dir {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => default_dir(),
+    };
+    let dir = base.map(|base| base.join(Local::now().format(RUN_NAME_FORMAT).to_string()));
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = dir;
+    }
+}
+
+/**
+ * Gets the journal of the run that is in progress.
+ *
+ * @return The path of the journal, or `None` if no run was started.
+ */
+pub fn journal() -> Option<PathBuf> {
+    let current = CURRENT.lock().ok()?;
+    current.as_ref().map(|dir| dir.join(JOURNAL_NAME))
+}
+
+/**
+ * Finishes the run that is in progress: writes the report, the mapping and the
+ * summary into its directory, next to the journal, and removes the oldest run
+ * directories beyond the retention limit.
+ *
+ * @param config The settings of the run directories.
+ * @param summary The results of the run.
+ */
+pub fn finish(config: &RunsConfig, summary: &Summary) {
+    let Some(dir) = CURRENT.lock().ok().and_then(|mut current| current.take()) else {
+        return;
+    };
+    match write_run(&dir, summary) {
+        Ok(_) => println!("{} '{}'", "Run written to".green(), dir.display()),
+        Err(err) => eprintln!("{} {}", "Error writing the run directory:".red(), err),
+    }
+    let Some(base) = dir.parent() else {
+        return;
+    };
+    // The run that was just written is always kept
+    match remove_old_runs(base, config.keep.max(1)) {
+        Ok(removed) if !removed.is_empty() => println!(
+            "{} Removed {} old run directories",
+            "Info:".blue(),
+            removed.len()
+        ),
+        Ok(_) => {}
+        Err(err) => eprintln!(
+            "{} '{}'. {}",
+            "Error: Could not remove old run directories in".red(),
+            base.display(),
+            err
+        ),
+    }
+}
+
+/**
+ * Removes the oldest run directories, so only the newest `keep` runs are left.
+ * Only directories named like a run are removed.
+ *
+ * @param base The directory of the run directories.
+ * @param keep The number of runs to keep.
+ * @return The removed run directories.
+ */
+fn remove_old_runs(base: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let mut runs: Vec<PathBuf> = match fs::read_dir(base) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir() && is_run_name(path))
+            .collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    // The names sort by time
+    runs.sort();
+    let old = runs.len().saturating_sub(keep);
+    runs.truncate(old);
+    for run in &runs {
+        fs::remove_dir_all(run)?;
+    }
+    Ok(runs)
+}
+
+/**
+ * Writes the report, the mapping and the summary of a run into its directory.
+ *
+ * @param dir The run directory.
+ * @param summary The results of the run.
+ * @return `Ok(())` if everything was written, or an error message.
+ */
+fn write_run(dir: &Path, summary: &Summary) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let path = |name: &str| dir.join(name).display().to_string();
+    report::write_report(&path("report.html"), summary, false)?;
+    mapping::write_mapping(&path("mapping.csv"), summary)?;
+    let json = serde_json::to_string_pretty(summary).map_err(|e| e.to_string())?;
+    fs::write(path("summary.json"), json).map_err(|e| e.to_string())
+}
+
+/**
+ * Checks whether a directory is named like a run directory.
+ */
+fn is_run_name(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| NaiveDateTime::parse_from_str(name, RUN_NAME_FORMAT).is_ok())
+}
+
+/**
+ * Gets the user's data directory for the runs of aborg.
+ */
+fn default_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })?;
+    Some(base.join("aborg").join("runs"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_old_runs() {
+        let base = env::temp_dir().join(format!("aborg-runs-test-{}", std::process::id()));
+        let names = [
+            "2024-06-01T03-00-00",
+            "2024-06-02T03-00-00",
+            "2024-06-03T03-00-00",
+            "notes",
+        ];
+        for name in names {
+            fs::create_dir_all(base.join(name)).unwrap();
+        }
+
+        let removed = remove_old_runs(&base, 2).unwrap();
+        assert_eq!(removed, vec![base.join("2024-06-01T03-00-00")]);
+        assert!(base.join("2024-06-03T03-00-00").exists());
+        // Directories that are not runs are never removed
+        assert!(base.join("notes").exists());
+        fs::remove_dir_all(base).unwrap();
+    }
+}
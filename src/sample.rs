@@ -0,0 +1,249 @@
+use crate::CONFIG;
+use crate::summary::{Category, Summary};
+use colored::Colorize;
+use serde_json::{Value, json};
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+/// A silent MPEG-1 Layer III frame (128 kbit/s, 44.1 kHz), so the sample audio files
+/// can be probed and tagged like real files.
+const MP3_FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+const MP3_FRAME_SIZE: usize = 417;
+const MP3_FRAMES: usize = 20;
+
+/// Represents a book of the sample source tree.
+struct SampleBook {
+    /// The book directory, relative to the sample root.
+    dir: &'static str,
+    /// The metadata file name and contents.
+    metafile: &'static str,
+    metadata: fn() -> Value,
+    /// The audio files, with the messy names downloads tend to have.
+    files: &'static [&'static str],
+    cover: bool,
+}
+
+/// The sample books. They cover the series formats, numbering styles and file names
+/// aborg has to deal with, and are always the same so the output is reproducible.
+const BOOKS: [SampleBook; 8] = [
+    SampleBook {
+        dir: "Brandon Sanderson - The Final Empire (2006)",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "The Final Empire",
+                "authors": ["Brandon Sanderson"],
+                "narrators": ["Michael Kramer"],
+                "series": ["Mistborn #1"],
+                "genres": ["Fantasy"],
+                "published_year": "2006",
+                "language": "English",
+                "description": "For a thousand years the ash fell and no flowers bloomed."
+            })
+        },
+        files: &[
+            "01 - The Final Empire.mp3",
+            "02 - The Final Empire.mp3",
+            "03 - The Final Empire.mp3",
+        ],
+        cover: true,
+    },
+    SampleBook {
+        dir: "Sanderson/Mistborn/Well of Ascension [Unabridged]",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "The Well of Ascension",
+                "authors": ["Brandon Sanderson"],
+                "narrators": ["Michael Kramer"],
+                "series": ["Mistborn, Book 2"],
+                "genres": ["Fantasy"],
+                "published_year": "2007"
+            })
+        },
+        files: &["Track1.mp3", "Track2.mp3", "Track10.mp3"],
+        cover: false,
+    },
+    SampleBook {
+        dir: "Way of Kings GraphicAudio",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "The Way of Kings",
+                "subtitle": "Part 1 of 5",
+                "authors": ["Brandon Sanderson"],
+                "narrators": ["GraphicAudio"],
+                "series": ["Book 1 of the Stormlight Archive"],
+                "genres": ["Fantasy"],
+                "published_year": "2010"
+            })
+        },
+        files: &["Chapter 01 - Prelude.mp3", "Chapter 02 - Prologue.mp3"],
+        cover: true,
+    },
+    SampleBook {
+        dir: "The Eye of the World",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "The Eye of the World",
+                "authors": ["Robert Jordan"],
+                "narrators": ["Kate Reading", "Michael Kramer"],
+                "series": ["WoT Vol. I"],
+                "genres": ["Fantasy"],
+                "published_year": "1990"
+            })
+        },
+        files: &[
+            "Eye of the World Part 1 of 3.m4b",
+            "Eye of the World Part 2 of 3.m4b",
+            "Eye of the World Part 3 of 3.m4b",
+        ],
+        cover: false,
+    },
+    SampleBook {
+        dir: "Project Hail Mary",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "Project Hail Mary",
+                "authors": ["Andy Weir"],
+                "narrators": ["Ray Porter"],
+                "genres": ["Science Fiction"],
+                "published_year": "2021",
+                "abridged": false
+            })
+        },
+        files: &["Project Hail Mary.m4b"],
+        cover: true,
+    },
+    SampleBook {
+        dir: "Dune.Frank.Herbert.2007.Audiobook",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "Dune",
+                "authors": ["Frank Herbert"],
+                "narrators": ["Scott Brick", "Orlagh Cassidy"],
+                "series": ["Dune 1"],
+                "genres": ["Science Fiction"],
+                "published_year": "2007"
+            })
+        },
+        files: &[
+            "dune_disc1_track01.mp3",
+            "dune_disc1_track02.mp3",
+            "dune_disc2_track01.mp3",
+        ],
+        cover: false,
+    },
+    SampleBook {
+        dir: "Libation/The Hobbit",
+        metafile: "The Hobbit [B0099SNJPK].json",
+        metadata: || {
+            json!({
+                "AudibleProductId": "B0099SNJPK",
+                "Title": "The Hobbit",
+                "AuthorNames": "J. R. R. Tolkien",
+                "NarratorNames": "Andy Serkis",
+                "SeriesNames": "The Lord of the Rings",
+                "SeriesOrder": "0",
+                "DatePublished": "2020-09-17",
+                "CategoriesNames": "Fantasy",
+                "Language": "English",
+                "IsAbridged": false
+            })
+        },
+        files: &["The Hobbit [B0099SNJPK].m4b"],
+        cover: true,
+    },
+    SampleBook {
+        dir: "incomplete/Untitled Download",
+        metafile: "metadata.json",
+        metadata: || {
+            json!({
+                "title": "Untitled Download",
+                "series": ["Unknown Series #3"]
+            })
+        },
+        files: &["audio.mp3"],
+        cover: false,
+    },
+];
+
+/**
+ * Generates a sample source tree, with books in the metadata formats aborg reads
+ * and audio files with realistic messy names, for trying out schemas safely.
+ *
+ * MP3 files contain a few silent frames so they can be probed and tagged, and the
+ * other audio files are empty.
+ *
+ * @param target The directory to create the sample tree in. It must be empty or
+ * not exist yet.
+ * @param summary The summary that collects the results of the run.
+ */
+pub fn generate(target: &str, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let root = Path::new(target);
+    let not_empty = fs::read_dir(root).is_ok_and(|mut entries| entries.next().is_some());
+    if not_empty {
+        eprintln!(
+            "{} '{}'",
+            "Error: The sample directory must be empty:".red(),
+            target.yellow()
+        );
+        exit(1)
+    }
+
+    for book in &BOOKS {
+        let dir = root.join(book.dir);
+        println!("{} '{}'", "Creating Book:".green(), dir.display());
+        summary.books += 1;
+
+        let mut files = vec![(book.metafile, metadata_contents(book))];
+        files.extend(book.files.iter().map(|file| (*file, audio_contents(file))));
+        if book.cover {
+            files.push(("cover.jpg", Vec::new()));
+        }
+
+        for (name, contents) in files {
+            let path = dir.join(name);
+            summary.files += 1;
+            summary.bytes += contents.len() as u64;
+            if cfg.dry_run {
+                println!("  {} '{}'", "Writing:".blue(), name);
+                continue;
+            }
+
+            let result = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, contents));
+            if let Err(err) = result {
+                eprintln!("{} {}", "Error writing sample file:".red(), err);
+                summary.error(Category::Io, format!("'{}': {}", path.display(), err));
+            }
+        }
+    }
+}
+
+/**
+ * Renders the metadata file of a sample book.
+ */
+fn metadata_contents(book: &SampleBook) -> Vec<u8> {
+    serde_json::to_vec_pretty(&(book.metadata)()).unwrap_or_default()
+}
+
+/**
+ * Creates the contents of a sample audio file.
+ *
+ * @param name The file name.
+ * @return A few silent frames for MP3 files, and nothing for other formats.
+ */
+fn audio_contents(name: &str) -> Vec<u8> {
+    if !name.ends_with(".mp3") {
+        return Vec::new();
+    }
+
+    let mut frame = vec![0; MP3_FRAME_SIZE];
+    frame[..MP3_FRAME_HEADER.len()].copy_from_slice(&MP3_FRAME_HEADER);
+    frame.repeat(MP3_FRAMES)
+}
@@ -1,7 +1,72 @@
 use crate::metadata::Metadata;
-use crate::track::get_track_number;
-use handlebars::{Handlebars, RenderError, no_escape};
-use std::path::PathBuf;
+use crate::sniff;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use clap::ValueEnum;
+use handlebars::template::{Parameter, Template, TemplateElement};
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+    RenderErrorReason, no_escape,
+};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The compiled regexes of the `re_replace` helper, by pattern, so each pattern is
+/// only compiled once.
+static REGEXES: Mutex<Option<HashMap<String, Regex>>> = Mutex::new(None);
+
+/// The default template for the destination directory of a book.
+pub const DEFAULT_PATH_SCHEMA: &str = "{{author}}/{{#if series}}{{series}}/{{/if}}{{title}}{{#if book_number_with_zeros}} - Book {{book_number_with_zeros}}{{/if}}";
+
+/// The default template for the audio file names.
+pub const DEFAULT_FILE_SCHEMA: &str = "{{#if series}}{{series}} - {{/if}}{{title}}{{#if file_number_with_zeros}} ({{file_number_with_zeros}}){{/if}}";
+
+/// Represents the built-in directory layouts that can be used instead of `--path-schema`.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum Preset {
+    /// The default layout.
+    Default,
+    /// Adds the narrator to the book directory, so editions read by different
+    /// narrators (e.g. GraphicAudio and the standard edition) are kept apart.
+    Editions,
+    /// Puts each language into its own directory named after the ISO 639-1 code,
+    /// e.g. `de/` and `en/`, one per Audiobookshelf library.
+    Language,
+}
+
+impl Preset {
+    /**
+     * Gets the directory template of the preset.
+     */
+    pub fn path_schema(&self) -> String {
+        match self {
+            Preset::Default => DEFAULT_PATH_SCHEMA.to_string(),
+            Preset::Editions => format!(
+                "{}{{{{#if narrator}}}} [{{{{narrator}}}}]{{{{/if}}}}",
+                DEFAULT_PATH_SCHEMA
+            ),
+            Preset::Language => format!(
+                "{{{{#if language_code}}}}{{{{language_code}}}}{{{{else}}}}unknown{{{{/if}}}}/{}",
+                DEFAULT_PATH_SCHEMA
+            ),
+        }
+    }
+}
+
+/// Represents the templates that `--lenient` applies to.
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum Lenient {
+    /// Only the path schema.
+    Path,
+    /// Only the file schema and the file templates of the config file.
+    File,
+    /// Both.
+    All,
+}
 
 /// Represents the schema used for formatting file paths and names.
 ///
@@ -11,6 +76,28 @@ use std::path::PathBuf;
 pub struct Schema {
     pub path_template: String,
     pub file_template: String,
+    /// Templates for non-audio files, as (lowercase extensions, template).
+    pub extension_templates: Vec<(Vec<String>, String)>,
+    /// Template for the name of the file the book description is written to.
+    pub description_template: Option<String>,
+    /// Template for the name of the book's playlist file.
+    pub playlist_template: Option<String>,
+    /// Partial templates usable as `{{> name}}`, as (name, template).
+    pub partials: Vec<(String, String)>,
+    /// Computed variables usable in all templates like a field, as (name, template).
+    pub variables: Vec<(String, String)>,
+    /// If true, the extensions of renamed files are lowercased.
+    pub lowercase_extensions: bool,
+    /// Extensions renamed to another extension, keyed by the lowercase extension
+    /// (e.g. `m4a` to `m4b`).
+    pub extension_aliases: HashMap<String, String>,
+    /// If true, audio files without an audio extension are detected by their
+    /// content, and get the canonical extension of their format.
+    pub detect_types: bool,
+    /// If true, missing fields render as empty in the path template.
+    pub lenient_path: bool,
+    /// If true, missing fields render as empty in the file templates.
+    pub lenient_file: bool,
 }
 
 impl Schema {
@@ -18,7 +105,238 @@ impl Schema {
         Schema {
             path_template: path,
             file_template: file,
+            extension_templates: Vec::new(),
+            description_template: None,
+            playlist_template: None,
+            partials: Vec::new(),
+            variables: Vec::new(),
+            lowercase_extensions: false,
+            extension_aliases: HashMap::new(),
+            detect_types: false,
+            lenient_path: false,
+            lenient_file: false,
+        }
+    }
+
+    /**
+     * Registers every `.hbs` file in a directory as a partial, named after the file
+     * (e.g. `series.hbs` is used as `{{> series}}`).
+     *
+     * @param dir The templates directory.
+     * @return The schema, or an error message if the directory could not be read.
+     */
+    pub fn with_partials(mut self, dir: &Path) -> Result<Self, String> {
+        let entries = fs::read_dir(dir).map_err(|e| format!("'{}': {}", dir.display(), e))?;
+        let mut files: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == "hbs"))
+            .collect();
+        files.sort();
+
+        for file in files {
+            let Some(name) = file.file_stem().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let template = read_template_file(&file)?;
+            self.partials.push((name.to_string(), template));
+        }
+        Ok(self)
+    }
+
+    /**
+     * Checks that all templates and partials compile, so a broken template is
+     * reported before any book is planned.
+     *
+     * @return An error message naming the broken template.
+     */
+    pub fn validate(&self) -> Result<(), String> {
+        let mut reg = self.registry()?;
+        for (name, partial) in reg.get_templates() {
+            check_regexes(&partial.elements)
+                .map_err(|e| format!("the partial '{}' is invalid. {}", name, e))?;
+        }
+        let mut templates = vec![("path", &self.path_template), ("file", &self.file_template)];
+        templates.extend(
+            self.extension_templates
+                .iter()
+                .map(|(_, t)| ("file template", t)),
+        );
+        templates.extend(
+            self.description_template
+                .iter()
+                .map(|t| ("description file", t)),
+        );
+        templates.extend(self.playlist_template.iter().map(|t| ("playlist file", t)));
+        templates.extend(self.variables.iter().map(|(_, t)| ("variable", t)));
+
+        for (name, template) in templates {
+            reg.register_template_string(name, template)
+                .map_err(|e| format!("the {} schema is invalid. {}", name, e))?;
+            if let Some(compiled) = reg.get_template(name) {
+                check_regexes(&compiled.elements)
+                    .map_err(|e| format!("the {} schema is invalid. {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Sets the computed variables, which are rendered from the book's metadata and
+     * can then be used in all templates like a field, e.g. `{{decade}}`.
+     *
+     * @param variables The templates of the variables, keyed by name.
+     */
+    pub fn with_variables(mut self, variables: &HashMap<String, String>) -> Self {
+        self.variables = variables
+            .iter()
+            .map(|(name, template)| (name.clone(), template.clone()))
+            .collect();
+        self.variables.sort();
+        self
+    }
+
+    /**
+     * Sets the template for the name of the playlist written into each book directory.
+     *
+     * @param template The file name template (e.g. `{{title}}.m3u8`), or `None` to
+     * not write a playlist.
+     */
+    pub fn with_playlist_file(mut self, template: Option<String>) -> Self {
+        self.playlist_template = template;
+        self
+    }
+
+    /**
+     * Sets the template for the name of the file the book description is written to.
+     *
+     * @param template The file name template (e.g. `desc.txt` or `{{title}}.txt`),
+     * or `None` to not write the description.
+     */
+    pub fn with_description_file(mut self, template: Option<String>) -> Self {
+        self.description_template = template;
+        self
+    }
+
+    /**
+     * Sets the templates used for non-audio files.
+     *
+     * @param templates The templates, keyed by a comma-separated list of extensions
+     * (e.g. `"jpg,jpeg,png"`).
+     */
+    pub fn with_extension_templates(mut self, templates: &HashMap<String, String>) -> Self {
+        self.extension_templates = templates
+            .iter()
+            .map(|(extensions, template)| {
+                let extensions = extensions
+                    .split(',')
+                    .map(|e| e.trim().trim_start_matches('.').to_lowercase())
+                    .filter(|e| !e.is_empty())
+                    .collect();
+                (extensions, template.clone())
+            })
+            .collect();
+        self
+    }
+
+    /**
+     * Sets how the extensions of renamed files are normalized.
+     *
+     * @param lowercase If true, extensions are lowercased (e.g. `.MP3` to `.mp3`).
+     * @param aliases Extensions renamed to another extension, as (from, to).
+     */
+    pub fn with_extension_rules(mut self, lowercase: bool, aliases: &[(String, String)]) -> Self {
+        self.lowercase_extensions = lowercase;
+        self.extension_aliases = aliases
+            .iter()
+            .map(|(from, to)| (from.to_lowercase(), to.clone()))
+            .collect();
+        self
+    }
+
+    /**
+     * Sets whether audio files with a wrong or missing extension are detected by
+     * their content, see `--detect-types`.
+     *
+     * @param detect If true, the content of files without an audio extension is checked.
+     */
+    pub fn with_type_detection(mut self, detect: bool) -> Self {
+        self.detect_types = detect;
+        self
+    }
+
+    /**
+     * Sets which templates render missing fields as empty instead of failing the
+     * book.
+     *
+     * @param lenient The lenient templates, or `None` for strict rendering.
+     */
+    pub fn with_lenient(mut self, lenient: Option<&Lenient>) -> Self {
+        self.lenient_path = matches!(lenient, Some(Lenient::Path | Lenient::All));
+        self.lenient_file = matches!(lenient, Some(Lenient::File | Lenient::All));
+        self
+    }
+
+    /**
+     * Normalizes the extension of a renamed file, following `--lowercase-extensions`
+     * and `--extension-alias`.
+     *
+     * @param extension The original extension, without the dot.
+     * @return The extension to use for the new file name.
+     */
+    fn normalize_extension(&self, extension: &str) -> String {
+        match self.extension_aliases.get(&extension.to_lowercase()) {
+            Some(alias) => alias.clone(),
+            None if self.lowercase_extensions => extension.to_lowercase(),
+            None => extension.to_string(),
+        }
+    }
+
+    /**
+     * Creates a Handlebars registry with the settings, helpers and partials shared
+     * by all templates.
+     */
+    fn registry(&self) -> Result<Handlebars<'static>, String> {
+        let mut reg = Handlebars::new();
+        reg.register_escape_fn(no_escape);
+        reg.register_helper("description_short", Box::new(description_short));
+        reg.register_helper("slice", Box::new(slice));
+        reg.register_helper("date", Box::new(date));
+        reg.register_helper("re_replace", Box::new(re_replace));
+        for (name, template) in &self.partials {
+            reg.register_partial(name, template)
+                .map_err(|e| format!("the partial '{}' is invalid. {}", name, e))?;
+        }
+        Ok(reg)
+    }
+
+    /**
+     * Builds the data the templates are rendered with: the metadata fields and the
+     * computed variables. A variable that can't be rendered (e.g. because it uses a
+     * missing field) or renders empty is left out, like a missing field.
+     *
+     * @param reg The registry, left in strict mode.
+     * @param metadata The metadata of the book.
+     * @return The template data.
+     */
+    fn context(&self, reg: &mut Handlebars<'static>, metadata: &Metadata) -> Value {
+        let mut context = serde_json::to_value(metadata).unwrap_or_default();
+        if self.variables.is_empty() {
+            return context;
+        }
+
+        reg.set_strict_mode(true);
+        let fields = context.clone();
+        if let Value::Object(map) = &mut context {
+            for (name, template) in &self.variables {
+                match reg.render_template(template, &fields) {
+                    Ok(value) if !value.is_empty() => {
+                        map.insert(name.clone(), Value::String(value));
+                    }
+                    _ => {}
+                }
+            }
         }
+        context
     }
 
     /**
@@ -28,48 +346,791 @@ impl Schema {
      * @return A `Result` containing the formatted path as a `String` or a `RenderError`.
      */
     pub fn fmt_path(&self, metadata: &mut Metadata) -> Result<String, RenderError> {
-        let mut reg = Handlebars::new();
-        reg.register_escape_fn(no_escape);
+        let mut reg = self.registry().map_err(RenderErrorReason::Other)?;
         metadata.book_number_with_zeros = metadata.book_number.map(|num| format!("{:02}", num));
         reg.register_template_string("path", &self.path_template)
             .unwrap();
+        let context = self.context(&mut reg, metadata);
+        reg.set_strict_mode(!self.lenient_path);
+        let path = reg.render("path", &context)?;
+
+        // A missing field can leave a directory without a name
+        let path: Vec<String> = path
+            .split('/')
+            .map(clean_separators)
+            .filter(|dir| !dir.is_empty())
+            .collect();
+        if path.is_empty() {
+            return Err(RenderErrorReason::Other(String::from("the path is empty")).into());
+        }
+        Ok(path.join("/"))
+    }
+
+    /**
+     * Renders a file template, with the dangling separators of missing fields
+     * cleaned up.
+     *
+     * @param reg The registry with the template registered as `file`.
+     * @param metadata The metadata of the book and file.
+     * @return The file name without its extension.
+     */
+    fn render_file(
+        &self,
+        mut reg: Handlebars<'static>,
+        metadata: &Metadata,
+    ) -> Result<String, RenderError> {
+        let context = self.context(&mut reg, metadata);
+        reg.set_strict_mode(!self.lenient_file);
+        let name = clean_separators(&reg.render("file", &context)?);
+        if name.is_empty() {
+            return Err(RenderErrorReason::Other(String::from("the file name is empty")).into());
+        }
+        Ok(name)
+    }
+
+    /**
+     * Formats the name of the file the book description is written to.
+     *
+     * @param metadata The metadata object containing information for formatting.
+     * @return The file name, or `None` if no description file is configured or the
+     * book has no description.
+     */
+    pub fn fmt_description_file(&self, metadata: &Metadata) -> Option<Result<String, RenderError>> {
+        let template = self.description_template.as_ref()?;
+        metadata.description.as_ref()?;
+
+        let mut reg = match self.registry() {
+            Ok(reg) => reg,
+            Err(err) => return Some(Err(RenderErrorReason::Other(err).into())),
+        };
+        let context = self.context(&mut reg, metadata);
+        reg.set_strict_mode(true);
+        Some(reg.render_template(template, &context))
+    }
+
+    /**
+     * Formats the name of the book's playlist file.
+     *
+     * @param metadata The metadata object containing information for formatting.
+     * @return The file name, or `None` if no playlist is configured.
+     */
+    pub fn fmt_playlist_file(&self, metadata: &Metadata) -> Option<Result<String, RenderError>> {
+        let template = self.playlist_template.as_ref()?;
+
+        let mut reg = match self.registry() {
+            Ok(reg) => reg,
+            Err(err) => return Some(Err(RenderErrorReason::Other(err).into())),
+        };
+        let context = self.context(&mut reg, metadata);
         reg.set_strict_mode(true);
-        reg.render("path", metadata)
+        Some(reg.render_template(template, &context))
     }
 
     /**
      * Formats a file name based on the provided schema, metadata, and file path.
      *
+     * Audio files use the file template. Other files use the template configured
+     * for their extension, or keep their original name if there is none.
+     *
      * @param metadata A mutable reference to the metadata object for formatting.
      * @param file_path The path of the file to format.
+     * @param file_number The file number resolved for this file during planning.
      * @param file_ext A vector of allowed file extensions.
      * @return A `Result` containing the formatted file name as a `String` or a `RenderError`.
      */
     pub fn fmt_file(
         &self,
         metadata: &mut Metadata,
-        file_path: &PathBuf,
-        file_ext: &Vec<String>,
+        file_path: &Path,
+        file_number: Option<u16>,
+        file_ext: &[String],
     ) -> Result<String, RenderError> {
-        let mut reg = Handlebars::new();
-        reg.register_escape_fn(no_escape);
+        let mut reg = self.registry().map_err(RenderErrorReason::Other)?;
         let full_file_name = file_path.file_name().unwrap().to_str().unwrap();
-        let file_name = file_path.file_stem().unwrap().to_str().unwrap();
-        let extension = file_path.extension().unwrap().to_str().unwrap();
-        if file_ext.contains(&extension.to_string()) {
-            let file_number = get_track_number(&file_name);
+        let name = |path: Option<&std::ffi::OsStr>| path.map(|p| p.to_string_lossy().to_string());
+        metadata.original_name = name(file_path.file_stem());
+        metadata.original_dir = name(file_path.parent().and_then(Path::file_name));
+        let extension = file_path.extension().and_then(|e| e.to_str());
+        let is_audio =
+            extension.is_some_and(|ext| file_ext.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+        // An audio file with a wrong or missing extension gets the one of its format
+        let detected = match self.detect_types && !is_audio {
+            true => sniff::audio_extension(file_path, file_ext),
+            false => None,
+        };
+        let Some(extension) = detected.or(extension) else {
+            return Ok(full_file_name.to_string());
+        };
+        if is_audio || detected.is_some() {
             metadata.file_number = file_number;
-            metadata.file_number_with_zeros = file_number.map(|num| format!("{:03}", num));
+            let width = metadata.file_total.map_or(3, number_width);
+            // A padded number in the original name (e.g. `0007 - Title`) keeps its padding
+            let width = file_number
+                .and_then(|num| original_width(file_path, num))
+                .map_or(width, |original| original.max(width));
+            metadata.file_number_with_zeros =
+                file_number.map(|num| format!("{:0width$}", num, width = width));
             reg.register_template_string("file", &self.file_template)
                 .unwrap();
-            reg.set_strict_mode(true);
             return Ok(format!(
                 "{}.{}",
-                reg.render("file", metadata).unwrap(),
-                extension
+                self.render_file(reg, metadata)?,
+                self.normalize_extension(extension)
+            ));
+        }
+
+        let lowercase = extension.to_lowercase();
+        if let Some((_, template)) = self
+            .extension_templates
+            .iter()
+            .find(|(extensions, _)| extensions.contains(&lowercase))
+        {
+            metadata.file_number = None;
+            metadata.file_number_with_zeros = None;
+            reg.register_template_string("file", template).unwrap();
+            return Ok(format!(
+                "{}.{}",
+                self.render_file(reg, metadata)?,
+                self.normalize_extension(extension)
             ));
         }
 
-        Ok(full_file_name.to_string())
+        let stem = &full_file_name[..full_file_name.len() - extension.len()];
+        Ok(format!("{}{}", stem, self.normalize_extension(extension)))
+    }
+}
+
+/**
+ * Reads a schema argument, which is either the template itself or `@` followed by
+ * the path of a template file, e.g. `@/etc/aborg/path.hbs`.
+ *
+ * @param value The schema argument.
+ * @return The template, or an error message if the template file could not be read.
+ */
+pub fn read_schema_arg(value: &str) -> Result<String, String> {
+    match value.strip_prefix('@') {
+        Some(path) => read_template_file(Path::new(path)),
+        None => Ok(value.to_string()),
+    }
+}
+
+/**
+ * Cleans up the separators a missing field leaves behind in a rendered name: empty
+ * brackets (`()`, `[]`), repeated dashes (`Series - - Title`), dashes and commas at
+ * the start or end, and repeated spaces. This runs on every rendered name, so
+ * templates don't need an `{{#if}}` around every optional field.
+ *
+ * @param name The rendered directory or file name.
+ * @return The cleaned up name.
+ */
+fn clean_separators(name: &str) -> String {
+    let brackets = Regex::new(r"\(\s*\)|\[\s*\]|\{\s*\}").unwrap();
+    let dashes = Regex::new(r"\s+([-–])(?:\s+[-–])*\s+").unwrap();
+    let spaces = Regex::new(r"\s{2,}").unwrap();
+    let ends = Regex::new(r"^[\s\-–,]+|[\s\-–,]+$").unwrap();
+
+    let name = brackets.replace_all(name, "");
+    let name = dashes.replace_all(&name, " $1 ");
+    let name = spaces.replace_all(&name, " ");
+    ends.replace_all(&name, "").to_string()
+}
+
+/**
+ * Gets the number of digits file numbers are padded to: 2 for books with fewer
+ * than 100 files, 3 for fewer than 1000, and so on.
+ *
+ * @param file_total The number of audio files of the book.
+ * @return The width of `file_number_with_zeros`.
+ */
+fn number_width(file_total: u16) -> usize {
+    file_total.to_string().len().max(2)
+}
+
+/**
+ * Gets the width of a file number in the original file name, if it is padded with
+ * zeros, e.g. 4 for file 7 in `0007 - Title`.
+ *
+ * @param file_path The path of the file.
+ * @param file_number The file number.
+ * @return The width of the padded number, or `None` if the name has no padded number.
+ */
+fn original_width(file_path: &Path, file_number: u16) -> Option<usize> {
+    let stem = file_path.file_stem()?.to_string_lossy();
+    let re = Regex::new(r"\d+").unwrap();
+    re.find_iter(&stem)
+        .map(|m| m.as_str())
+        .filter(|digits| digits.len() > 1 && digits.starts_with('0'))
+        .filter(|digits| digits.parse::<u64>() == Ok(u64::from(file_number)))
+        .map(str::len)
+        .max()
+}
+
+/**
+ * Parses an `--extension-alias` argument, e.g. `m4a=m4b`.
+ *
+ * @param value The argument.
+ * @return The extension and its alias, without dots, or an error message.
+ */
+pub fn parse_extension_alias(value: &str) -> Result<(String, String), String> {
+    let Some((from, to)) = value.split_once('=') else {
+        return Err(format!(
+            "'{}' is not in the form FROM=TO, e.g. m4a=m4b",
+            value
+        ));
+    };
+    let (from, to) = (
+        from.trim().trim_start_matches('.'),
+        to.trim().trim_start_matches('.'),
+    );
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("'{}' is missing an extension", value));
+    }
+    Ok((from.to_string(), to.to_string()))
+}
+
+/**
+ * Reads a template file. Only the line break at the end of the file is removed,
+ * everything else (including spaces at the end of a line) is kept as written.
+ *
+ * @param path The path of the template file.
+ * @return The template, or an error message if the file could not be read.
+ */
+fn read_template_file(path: &Path) -> Result<String, String> {
+    let mut contents =
+        fs::read_to_string(path).map_err(|e| format!("'{}': {}", path.display(), e))?;
+    if contents.ends_with('\n') {
+        contents.pop();
+        if contents.ends_with('\r') {
+            contents.pop();
+        }
+    }
+    Ok(contents)
+}
+
+/**
+ * Handlebars helper that renders the book description shortened to at most `n`
+ * characters, e.g. `{{description_short 200}}`.
+ */
+fn description_short(
+    h: &Helper,
+    _: &Handlebars,
+    ctx: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let length = h.param(0).and_then(|p| p.value().as_u64()).ok_or(
+        RenderErrorReason::ParamNotFoundForIndex("description_short", 0),
+    )?;
+    if let Some(description) = ctx.data().get("description").and_then(|d| d.as_str()) {
+        out.write(&shorten(description, length as usize))?;
+    }
+    Ok(())
+}
+
+/**
+ * Gets the value a helper works on, its first parameter, as text.
+ *
+ * @param h The helper.
+ * @param r The registry, for its strict mode.
+ * @param name The name of the helper, for the error message.
+ * @return The text of the value, `None` if the value is missing, or an error if a
+ *         strict template uses a missing value.
+ */
+fn helper_text(
+    h: &Helper,
+    r: &Handlebars,
+    name: &'static str,
+) -> Result<Option<String>, RenderError> {
+    let param = h
+        .param(0)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, 0))?;
+    // A missing field fails a strict template, like when it is used directly
+    if (param.is_value_missing() || param.value().is_null()) && r.strict_mode() {
+        return Err(RenderErrorReason::MissingVariable(param.relative_path().cloned()).into());
+    }
+    Ok(match param.value() {
+        Value::String(text) => Some(text.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    })
+}
+
+/**
+ * Handlebars helper that renders the characters of a value from `start` up to, but
+ * not including, `end`, e.g. `{{slice published_year 0 3}}` renders `198` for 1984.
+ * Without `end`, the rest of the value is rendered.
+ */
+fn slice(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let text = helper_text(h, r, "slice")?;
+    let start = h
+        .param(1)
+        .and_then(|p| p.value().as_u64())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("slice", 1))? as usize;
+    let end = h
+        .param(2)
+        .and_then(|p| p.value().as_u64())
+        .map(|e| e as usize);
+    let Some(text) = text else {
+        return Ok(());
+    };
+    let length = end.map_or(usize::MAX, |end| end.saturating_sub(start));
+    out.write(&text.chars().skip(start).take(length).collect::<String>())?;
+    Ok(())
+}
+
+/**
+ * Handlebars helper that formats a date with a chrono format, e.g.
+ * `{{date published_date "%Y-%m"}}` renders `2007-03` for `2007-03-27`. Dates
+ * with a time, and partial dates like `2007` or `2007-03` (which count as the first
+ * day of the year or month) are understood too.
+ */
+fn date(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let text = helper_text(h, r, "date")?;
+    let format = h
+        .param(1)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("date", 1))?;
+    let Some(text) = text else {
+        return Ok(());
+    };
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(RenderErrorReason::Other(format!("Invalid date format '{}'", format)).into());
+    }
+    let parsed = parse_date(&text)
+        .ok_or_else(|| RenderErrorReason::Other(format!("Invalid date '{}'", text)))?;
+    out.write(&parsed.format(format).to_string())?;
+    Ok(())
+}
+
+/**
+ * Handlebars helper that replaces every match of a regex in a value, e.g.
+ * `{{re_replace title "\\s*\\(Unabridged\\)" ""}}` drops the `(Unabridged)` of a
+ * title. The replacement can use the groups of the regex as `$1` or `$name`.
+ */
+fn re_replace(
+    h: &Helper,
+    r: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let text = helper_text(h, r, "re_replace")?;
+    let pattern = h
+        .param(1)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("re_replace", 1))?;
+    let replace = h
+        .param(2)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("re_replace", 2))?;
+    let Some(text) = text else {
+        return Ok(());
+    };
+    let mut regexes = REGEXES.lock().unwrap_or_else(|e| e.into_inner());
+    let regexes = regexes.get_or_insert_with(HashMap::new);
+    let regex = match regexes.get(pattern) {
+        Some(regex) => regex,
+        None => {
+            let regex = Regex::new(pattern).map_err(|err| {
+                RenderErrorReason::Other(format!("Invalid regex '{}'. {}", pattern, err))
+            })?;
+            regexes.entry(pattern.to_string()).or_insert(regex)
+        }
+    };
+    out.write(&regex.replace_all(&text, replace))?;
+    Ok(())
+}
+
+/**
+ * Checks the regexes `re_replace` is called with in a compiled template, so an
+ * invalid regex is reported when the schema is loaded rather than for every book.
+ *
+ * @param elements The elements of the template.
+ * @return An error message naming the invalid regex.
+ */
+fn check_regexes(elements: &[TemplateElement]) -> Result<(), String> {
+    let nested = |template: &Option<Template>| {
+        template
+            .as_ref()
+            .map_or(Ok(()), |template| check_regexes(&template.elements))
+    };
+    for element in elements {
+        match element {
+            TemplateElement::Expression(helper)
+            | TemplateElement::HtmlExpression(helper)
+            | TemplateElement::HelperBlock(helper) => {
+                if helper.name == Parameter::Name(String::from("re_replace"))
+                    && let Some(Parameter::Literal(Value::String(pattern))) = helper.params.get(1)
+                {
+                    Regex::new(pattern)
+                        .map_err(|err| format!("Invalid regex '{}'. {}", pattern, err))?;
+                }
+                for param in helper.params.iter().chain(helper.hash.values()) {
+                    if let Parameter::Subexpression(subexpression) = param {
+                        check_regexes(std::slice::from_ref(&subexpression.element))?;
+                    }
+                }
+                nested(&helper.template)?;
+                nested(&helper.inverse)?;
+            }
+            TemplateElement::PartialBlock(decorator)
+            | TemplateElement::DecoratorBlock(decorator) => {
+                nested(&decorator.template)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Parses a full or partial ISO date, with or without a time.
+ *
+ * @param text The date, e.g. `2007-03-27T00:00:00Z`, `2007-03-27`, `2007-03` or `2007`.
+ * @return The date and time, or `None` if the text is not a date.
+ */
+fn parse_date(text: &str) -> Option<NaiveDateTime> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.naive_local());
+    }
+    if let Ok(date) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S") {
+        return Some(date);
+    }
+    let full = match text.len() {
+        4 => format!("{}-01-01", text),
+        7 => format!("{}-01", text),
+        _ => text.chars().take(10).collect(),
+    };
+    NaiveDate::parse_from_str(&full, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+}
+
+/**
+ * Shortens a text to at most `length` characters, cutting at a word boundary and
+ * adding an ellipsis if anything was cut.
+ *
+ * @param text The text to shorten.
+ * @param length The maximum number of characters, including the ellipsis.
+ * @return The shortened text.
+ */
+fn shorten(text: &str, length: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.chars().count() <= length {
+        return text;
+    }
+
+    let keep = length.saturating_sub(1);
+    let cut: String = text.chars().take(keep).collect();
+    // Only drop the last word if it was cut in the middle
+    let at_boundary = text.chars().nth(keep) == Some(' ');
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > 0 && !at_boundary => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches([',', '.', ';', ':']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("A short text", 20), "A short text");
+        assert_eq!(shorten("A  text\nwith  spaces", 30), "A text with spaces");
+        assert_eq!(shorten("The quick brown fox jumps", 12), "The quick…");
+        assert_eq!(shorten("One, two, three", 10), "One, two…");
+        assert_eq!(shorten("Supercalifragilistic", 6), "Super…");
+    }
+
+    #[test]
+    fn test_read_template_file() {
+        let path = std::env::temp_dir().join(format!("aborg-template-{}.hbs", std::process::id()));
+        fs::write(&path, "{{title}} \n{{~author}}\r\n").unwrap();
+        // Only the final line break is removed
+        assert_eq!(
+            read_template_file(&path).unwrap(),
+            "{{title}} \n{{~author}}"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_fmt_file_original_name() {
+        let schema = Schema::new(
+            String::from("{{title}}"),
+            String::from(
+                "{{#if file_number}}{{title}} {{file_number}}{{else}}{{original_dir}} - {{original_name}}{{/if}}",
+            ),
+        );
+        let mut metadata = Metadata {
+            title: String::from("Dune"),
+            ..Default::default()
+        };
+        let file_ext = vec![String::from("mp3")];
+        let path = Path::new("/src/Dune 2007/Intro.mp3");
+        assert_eq!(
+            schema
+                .fmt_file(&mut metadata, path, Some(3), &file_ext)
+                .unwrap(),
+            "Dune 3.mp3"
+        );
+        assert_eq!(
+            schema
+                .fmt_file(&mut metadata, path, None, &file_ext)
+                .unwrap(),
+            "Dune 2007 - Intro.mp3"
+        );
+    }
+
+    #[test]
+    fn test_fmt_file_extension_rules() {
+        let schema = Schema::new(String::from("{{title}}"), String::from("{{title}}"))
+            .with_extension_rules(true, &[parse_extension_alias("m4a=m4b").unwrap()]);
+        let mut metadata = Metadata {
+            title: String::from("Dune"),
+            ..Default::default()
+        };
+        let file_ext = vec![String::from("mp3"), String::from("m4a")];
+        let fmt = |metadata: &mut Metadata, path: &str| {
+            schema
+                .fmt_file(metadata, Path::new(path), Some(1), &file_ext)
+                .unwrap()
+        };
+        assert_eq!(fmt(&mut metadata, "/src/Dune/01.MP3"), "Dune.mp3");
+        assert_eq!(fmt(&mut metadata, "/src/Dune/01.m4a"), "Dune.m4b");
+        assert_eq!(fmt(&mut metadata, "/src/Dune/Cover.JPG"), "Cover.jpg");
+        assert_eq!(fmt(&mut metadata, "/src/Dune/README"), "README");
+        assert!(parse_extension_alias("m4a").is_err());
+        assert_eq!(
+            parse_extension_alias(".M4A=.m4b"),
+            Ok((String::from("M4A"), String::from("m4b")))
+        );
+    }
+
+    #[test]
+    fn test_fmt_file_number_width() {
+        let schema = Schema::new(
+            String::from("{{title}}"),
+            String::from("{{title}} {{file_number_with_zeros}} of {{file_total}}"),
+        );
+        let file_ext = vec![String::from("mp3")];
+        let path = Path::new("/src/Dune/01.mp3");
+        let fmt = |file_total| {
+            let mut metadata = Metadata {
+                title: String::from("Dune"),
+                file_total,
+                ..Default::default()
+            };
+            schema
+                .fmt_file(&mut metadata, path, Some(7), &file_ext)
+                .unwrap()
+        };
+        assert_eq!(fmt(Some(54)), "Dune 07 of 54.mp3");
+        assert_eq!(fmt(Some(120)), "Dune 007 of 120.mp3");
+        assert_eq!(fmt(Some(1200)), "Dune 0007 of 1200.mp3");
+
+        // The padding of the original name is kept
+        let mut metadata = Metadata {
+            title: String::from("Dune"),
+            file_total: Some(54),
+            ..Default::default()
+        };
+        let padded = Path::new("/src/Dune/Dune 0007.mp3");
+        let name = schema.fmt_file(&mut metadata, padded, Some(7), &file_ext);
+        assert_eq!(name.unwrap(), "Dune 0007 of 54.mp3");
+    }
+
+    #[test]
+    fn test_lenient() {
+        assert_eq!(clean_separators("Dune -  - Book 1"), "Dune - Book 1");
+        assert_eq!(
+            clean_separators("Dune () [Unabridged]"),
+            "Dune [Unabridged]"
+        );
+        assert_eq!(clean_separators(" - Dune - "), "Dune");
+        assert_eq!(
+            clean_separators("Spider-Man - Homecoming"),
+            "Spider-Man - Homecoming"
+        );
+
+        let schema = Schema::new(
+            String::from("{{author}}/{{series}}/{{title}} ({{published_year}})"),
+            String::from("{{series}} - {{title}} - {{file_number_with_zeros}}"),
+        )
+        .with_lenient(Some(&Lenient::All));
+        let mut metadata = Metadata {
+            author: Some(String::from("Frank Herbert")),
+            title: String::from("Dune"),
+            ..Default::default()
+        };
+        assert_eq!(
+            schema.fmt_path(&mut metadata).unwrap(),
+            "Frank Herbert/Dune"
+        );
+        let file_ext = vec![String::from("mp3")];
+        assert_eq!(
+            schema
+                .fmt_file(
+                    &mut metadata,
+                    Path::new("/src/Dune/01.mp3"),
+                    None,
+                    &file_ext
+                )
+                .unwrap(),
+            "Dune.mp3"
+        );
+
+        let strict = schema.with_lenient(None);
+        assert!(strict.fmt_path(&mut metadata).is_err());
+    }
+
+    #[test]
+    fn test_variables() {
+        let variables = HashMap::from([
+            (
+                String::from("decade"),
+                String::from("{{slice published_year 0 3}}0s"),
+            ),
+            (
+                String::from("initial"),
+                String::from("{{slice author 0 1}}"),
+            ),
+        ]);
+        let schema = Schema::new(
+            String::from("{{initial}}/{{author}}/{{decade}}/{{title}}"),
+            String::from("{{title}} ({{decade}})"),
+        )
+        .with_variables(&variables);
+        let mut metadata = Metadata {
+            author: Some(String::from("Frank Herbert")),
+            title: String::from("Dune"),
+            published_year: Some(String::from("1965")),
+            ..Default::default()
+        };
+        assert_eq!(
+            schema.fmt_path(&mut metadata).unwrap(),
+            "F/Frank Herbert/1960s/Dune"
+        );
+        let file_ext = vec![String::from("mp3")];
+        let path = Path::new("/src/Dune/01.mp3");
+        assert_eq!(
+            schema
+                .fmt_file(&mut metadata, path, None, &file_ext)
+                .unwrap(),
+            "Dune (1960s).mp3"
+        );
+
+        // A variable using a missing field is missing too
+        metadata.published_year = None;
+        assert!(schema.fmt_path(&mut metadata).is_err());
+    }
+
+    #[test]
+    fn test_date() {
+        let schema = Schema::new(
+            String::from("{{date published_date \"%Y\"}}/{{date published_date \"%Y-%m\"}}"),
+            String::from("{{title}}"),
+        );
+        let mut metadata = Metadata {
+            title: String::from("Dune"),
+            published_date: Some(String::from("2007-03-27T00:00:00Z")),
+            ..Default::default()
+        };
+        assert_eq!(schema.fmt_path(&mut metadata).unwrap(), "2007/2007-03");
+        metadata.published_date = Some(String::from("2007"));
+        assert_eq!(schema.fmt_path(&mut metadata).unwrap(), "2007/2007-01");
+
+        metadata.published_date = Some(String::from("March 2007"));
+        assert!(schema.fmt_path(&mut metadata).is_err());
+        metadata.published_date = None;
+        assert!(schema.fmt_path(&mut metadata).is_err());
+    }
+
+    #[test]
+    fn test_re_replace() {
+        let schema = Schema::new(
+            String::from(r#"{{author}}/{{re_replace title "\\s*\\(Unabridged\\)" ""}}"#),
+            String::from(r#"{{re_replace title "^(\\w+) (\\w+)$" "$2, $1"}}"#),
+        );
+        let mut metadata = Metadata {
+            author: Some(String::from("Brandon Sanderson")),
+            title: String::from("Mistborn (Unabridged)"),
+            ..Default::default()
+        };
+        assert_eq!(
+            schema.fmt_path(&mut metadata).unwrap(),
+            "Brandon Sanderson/Mistborn"
+        );
+        metadata.title = String::from("Well Ascension");
+        let file_ext = vec![String::from("mp3")];
+        let path = Path::new("/src/Mistborn/01.mp3");
+        assert_eq!(
+            schema
+                .fmt_file(&mut metadata, path, None, &file_ext)
+                .unwrap(),
+            "Ascension, Well.mp3"
+        );
+
+        let invalid = Schema::new(
+            String::from(r#"{{re_replace title "(" ""}}"#),
+            String::from("{{title}}"),
+        );
+        assert!(invalid.fmt_path(&mut metadata).is_err());
+        // An invalid regex is found when the schema is loaded, also inside blocks
+        let error = invalid.validate().unwrap_err();
+        assert!(error.contains("Invalid regex '('"), "{}", error);
+        let nested = Schema::new(
+            String::from(r#"{{#if series}}{{re_replace series "[" ""}}{{/if}}{{title}}"#),
+            String::from("{{title}}"),
+        );
+        assert!(nested.validate().is_err());
+        assert!(schema.validate().is_ok());
+    }
+
+    #[test]
+    fn test_clean_separators_when_strict() {
+        let schema = Schema::new(
+            String::from("{{author}}/{{title}} - {{series}}"),
+            String::from("{{title}} ({{narrator}})"),
+        );
+        let mut metadata = Metadata {
+            author: Some(String::from("Frank Herbert")),
+            title: String::from("Dune"),
+            series: Some(String::new()),
+            narrator: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(
+            schema.fmt_path(&mut metadata).unwrap(),
+            "Frank Herbert/Dune"
+        );
+        let file_ext = vec![String::from("mp3")];
+        assert_eq!(
+            schema
+                .fmt_file(
+                    &mut metadata,
+                    Path::new("/src/Dune/01.mp3"),
+                    None,
+                    &file_ext
+                )
+                .unwrap(),
+            "Dune.mp3"
+        );
+        assert_eq!(clean_separators("_unsorted"), "_unsorted");
     }
 }
@@ -0,0 +1,214 @@
+use crate::metadata::Metadata;
+use rhai::{AST, Array, Dynamic, Engine, Scope};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path};
+
+/// The most operations a script may run for one book, so a script that never ends
+/// fails instead of hanging the run.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+/// The deepest a script may nest its function calls.
+const MAX_CALL_LEVELS: usize = 64;
+
+/// A Rhai script that can change the destination directory of each book, for naming
+/// rules Handlebars can't express.
+///
+/// The script sees these variables:
+/// - `metadata`: the book's metadata, with the same fields as the schemas.
+/// - `path`: the destination directory rendered by the path schema.
+/// - `books`: the metadata of every book found in this run.
+///
+/// The destination is the string the script returns, or `path` if the script
+/// returns nothing.
+pub struct NamingScript {
+    path: String,
+    engine: Engine,
+    ast: AST,
+}
+
+impl fmt::Debug for NamingScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NamingScript")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// The metadata of every book found in a run, converted once for all runs of the
+/// script. The books are shared, so passing them to the script doesn't copy them.
+pub struct ScriptBooks(Result<Dynamic, String>);
+
+impl ScriptBooks {
+    /**
+     * Converts the metadata of the books of a run for the naming script.
+     *
+     * @param books The metadata of all books found in this run.
+     * @return The converted books. A conversion error is reported when the script runs.
+     */
+    pub fn new(books: &[Metadata]) -> ScriptBooks {
+        let books = books
+            .iter()
+            .map(|metadata| rhai::serde::to_dynamic(metadata).map_err(|e| e.to_string()))
+            .collect::<Result<Array, _>>();
+        ScriptBooks(books.map(|books| Dynamic::from_array(books).into_shared()))
+    }
+}
+
+impl Default for ScriptBooks {
+    fn default() -> Self {
+        ScriptBooks::new(&[])
+    }
+}
+
+impl NamingScript {
+    /**
+     * Reads and compiles a naming script, so syntax errors are reported before any
+     * book is planned.
+     *
+     * @param path The path of the script.
+     * @return The compiled script, or an error message.
+     */
+    pub fn load(path: &str) -> Result<NamingScript, String> {
+        let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let engine = engine();
+        let ast = engine.compile(&source).map_err(|e| e.to_string())?;
+
+        Ok(NamingScript {
+            path: path.to_string(),
+            engine,
+            ast,
+        })
+    }
+
+    /**
+     * Runs the script for a book.
+     *
+     * @param metadata The book's metadata.
+     * @param path The destination directory rendered by the path schema.
+     * @param books The metadata of all books found in this run.
+     * @return The destination directory, relative to the destination, or an error
+     * message if the script failed or returned an invalid path.
+     */
+    pub fn apply(
+        &self,
+        metadata: &Metadata,
+        path: &str,
+        books: &ScriptBooks,
+    ) -> Result<String, String> {
+        let metadata = rhai::serde::to_dynamic(metadata).map_err(|e| e.to_string())?;
+        let books = books.0.clone()?;
+
+        let mut scope = Scope::new();
+        scope.push_constant("metadata", metadata);
+        scope.push_constant("books", books);
+        scope.push("path", path.to_string());
+
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| e.to_string())?;
+        let path = if result.is_unit() {
+            scope.get_value::<String>("path")
+        } else {
+            result.into_string().ok()
+        }
+        .ok_or("the script must return a string or leave one in `path`")?;
+
+        let path = path.trim().trim_end_matches('/').to_string();
+        let escapes = Path::new(&path)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+        if path.is_empty() || escapes {
+            return Err(format!(
+                "'{}' is not a directory inside the destination",
+                path
+            ));
+        }
+
+        Ok(path)
+    }
+}
+
+/**
+ * Creates the engine the naming scripts run on, with limits that stop runaway
+ * scripts.
+ */
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(source: &str) -> NamingScript {
+        let engine = engine();
+        let ast = engine.compile(source).unwrap();
+        NamingScript {
+            path: String::from("test.rhai"),
+            engine,
+            ast,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let book = |title: &str, series: Option<&str>| Metadata {
+            author: Some("Brandon Sanderson".to_string()),
+            title: title.to_string(),
+            series: series.map(String::from),
+            ..Default::default()
+        };
+        let metadata = vec![
+            book("The Final Empire", Some("Mistborn")),
+            book("The Well of Ascension", Some("Mistborn")),
+            book("The Way of Kings", Some("Stormlight Archive")),
+        ];
+        let books = ScriptBooks::new(&metadata);
+
+        // Group by series only when the author has more than one book in it
+        let grouped = script(
+            r#"
+            let count = books.filter(|b| b.author == metadata.author && b.series == metadata.series).len();
+            if count > 1 { `${metadata.author}/${metadata.series}/${metadata.title}` }
+            else { `${metadata.author}/${metadata.title}` }
+            "#,
+        );
+        let apply =
+            |script: &NamingScript, metadata: &Metadata| script.apply(metadata, "unused", &books);
+        assert_eq!(
+            apply(&grouped, &metadata[0]).as_deref(),
+            Ok("Brandon Sanderson/Mistborn/The Final Empire")
+        );
+        assert_eq!(
+            apply(&grouped, &metadata[2]).as_deref(),
+            Ok("Brandon Sanderson/The Way of Kings")
+        );
+
+        let modified = script(r#"path = path.to_upper();"#);
+        assert_eq!(
+            modified.apply(&metadata[0], "a/b", &books).as_deref(),
+            Ok("A/B")
+        );
+
+        assert!(
+            script(r#""../outside""#)
+                .apply(&metadata[0], "a", &books)
+                .is_err()
+        );
+        assert!(
+            script(r#""/absolute""#)
+                .apply(&metadata[0], "a", &books)
+                .is_err()
+        );
+        assert!(script("42").apply(&metadata[0], "a", &books).is_err());
+        // Scripts that never end, or recurse without end, fail
+        for source in ["loop {}", "fn f(x) { f(x) } f(1)"] {
+            assert!(script(source).apply(&metadata[0], "a", &books).is_err());
+        }
+    }
+}
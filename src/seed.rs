@@ -0,0 +1,156 @@
+use crate::summary::{Category, Summary};
+use crate::{CONFIG, audit, transfer_file};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::exit;
+
+/// Represents a line of the seed manifest, mapping an original (torrent) path to
+/// its path in the library.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    source: String,
+    library: String,
+}
+
+/**
+ * Appends the original and library path of a file to the seed manifest, if one
+ * is configured. Nothing is written during a dry run.
+ *
+ * @param source The original path of the file.
+ * @param library The path of the file in the library.
+ */
+pub fn record(source: &Path, library: &Path) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let Some(manifest) = &cfg.seed_manifest else {
+        return;
+    };
+    if cfg.dry_run {
+        return;
+    }
+
+    let entry = Entry {
+        source: absolute(source),
+        library: absolute(library),
+    };
+    let result = serde_json::to_string(&entry)
+        .map_err(io::Error::other)
+        .and_then(|line| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(manifest)
+                .and_then(|mut file| writeln!(file, "{}", line))
+        });
+    if let Err(err) = result {
+        eprintln!(
+            "{} '{}'. {}",
+            "Error: Could not write to the seed manifest".red(),
+            manifest.display(),
+            err
+        );
+    }
+}
+
+/**
+ * Rebuilds the original structure recorded in a seed manifest from the library,
+ * so a torrent client can re-check the files.
+ *
+ * Files are hardlinked back to their original path, or copied if the original
+ * path is on another filesystem. Original paths that still exist are left alone.
+ *
+ * @param manifest The path of the seed manifest.
+ * @param summary The summary that collects the results of the run.
+ */
+pub fn restore(manifest: &str, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let file = match fs::File::open(manifest) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!(
+                "{} '{}'. {}",
+                "Error: Could not read the seed manifest".red(),
+                manifest.yellow(),
+                err
+            );
+            exit(1)
+        }
+    };
+
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                summary.error(
+                    Category::Metadata,
+                    format!("Invalid manifest line '{}': {}", line, err),
+                );
+                continue;
+            }
+        };
+
+        let (source, library) = (Path::new(&entry.source), Path::new(&entry.library));
+        if source.exists() {
+            continue;
+        }
+
+        print!(
+            "{} '{}' to '{}'...",
+            "Restoring:".blue(),
+            entry.library,
+            entry.source.green()
+        );
+        if cfg.dry_run {
+            println!(" Done");
+            summary.files += 1;
+            continue;
+        }
+
+        let result = source
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| match fs::hard_link(library, source) {
+                Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                    transfer_file(library, source).map(|_| ())
+                }
+                result => result.map_err(|e| e.to_string()),
+            });
+        audit::record(
+            "restore",
+            library,
+            source,
+            result.as_ref().err().map(String::as_str),
+        );
+
+        match result {
+            Ok(_) => {
+                println!(" Done");
+                summary.files += 1;
+                summary.bytes += fs::metadata(source).map(|m| m.len()).unwrap_or(0);
+            }
+            Err(err) => {
+                eprintln!("{} {}", "Error restoring file:".red(), err);
+                summary.error(Category::Io, format!("'{}': {}", entry.source, err));
+            }
+        }
+    }
+}
+
+/**
+ * Makes a path absolute, so the manifest can be used from any directory.
+ *
+ * @param path The path to make absolute.
+ * @return The absolute path.
+ */
+fn absolute(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
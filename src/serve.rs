@@ -0,0 +1,780 @@
+use crate::conflict::{CollisionIndex, MergeDecision};
+use crate::progress;
+use crate::schema::Schema;
+use crate::shutdown;
+use crate::summary::Summary;
+use crate::{CONFIG, FileAction, Plan, decide_merge, execute, plan};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::path::{Component, Path};
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+/// Only one plan is executed at a time, so the progress events belong to one plan.
+static EXECUTION: Mutex<()> = Mutex::new(());
+
+/// Represents the state of a submitted source directory.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    /// The plan is waiting to be approved.
+    Planned,
+    Running,
+    Finished,
+}
+
+/// Represents a source directory submitted to the server, with its plan.
+#[derive(Debug, Serialize)]
+struct Submission {
+    id: u64,
+    source: String,
+    status: Status,
+    books: Vec<Plan>,
+    summary: Summary,
+    #[serde(skip)]
+    events: Arc<Mutex<Vec<String>>>,
+    #[serde(skip)]
+    finished: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    submissions: HashMap<u64, Submission>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitRequest {
+    source: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct EditRequest {
+    to: String,
+}
+
+const UI: &str = include_str!("ui.html");
+
+/// The header that carries the token of the server in API requests.
+const TOKEN_HEADER: &str = "X-Aborg-Token";
+
+/// Represents what a request must match to be handled: the address the server
+/// listens on, and the token of the server.
+struct Access {
+    listen: String,
+    token: String,
+}
+
+/**
+ * Runs the HTTP server that plans and executes source directories on request.
+ *
+ * Endpoints:
+ * - `GET /` serves the web UI for reviewing and approving plans.
+ * - `POST /plans` with `{"source": "..."}` plans the directory and returns the plan.
+ * - `GET /plans` lists the submitted plans and their status.
+ * - `GET /plans/{id}` returns a plan, its status and its summary.
+ * - `POST /plans/{id}/books/{index}` with `{"to": "..."}` changes a book's destination.
+ * - `POST /plans/{id}/books/{index}/skip` removes a book from the plan.
+ * - `POST /plans/{id}/approve` executes the plan in the background.
+ * - `GET /plans/{id}/events` streams the progress events of the plan as JSON lines.
+ *
+ * The API requests must send the token in the `X-Aborg-Token` header, and POST
+ * requests must be JSON, so other web pages can't make a browser send them.
+ *
+ * @param listen The address to listen on, e.g. `127.0.0.1:8080`.
+ * @param token The token of the API requests, or `None` to generate one.
+ * @param schema The schema used to plan the submitted directories.
+ */
+pub fn serve(listen: &str, token: Option<String>, schema: Schema) {
+    let server = match Server::http(listen) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!(
+                "{} '{}'. {}",
+                "Error: Could not listen on".red(),
+                listen.yellow(),
+                err
+            );
+            exit(1)
+        }
+    };
+    println!("Listening on {}", format!("http://{}", listen).green());
+    let token = token.unwrap_or_else(|| {
+        let token = generate_token();
+        println!("{} {}", "API token:".blue(), token);
+        token
+    });
+
+    let access = Arc::new(Access {
+        listen: listen.to_string(),
+        token,
+    });
+    let state = Arc::new(Mutex::new(State::default()));
+    let schema = Arc::new(schema);
+    shutdown::install();
+    while !shutdown::requested() {
+        let request = match server.recv_timeout(Duration::from_millis(250)) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(err) => {
+                eprintln!("{} {}", "Error receiving request:".red(), err);
+                continue;
+            }
+        };
+        let state = Arc::clone(&state);
+        let schema = Arc::clone(&schema);
+        let access = Arc::clone(&access);
+        thread::spawn(move || handle(request, &access, &state, &schema));
+    }
+
+    // Wait until the running plan finished its current book
+    shutdown::announce();
+    drop(EXECUTION.lock().unwrap_or_else(|e| e.into_inner()));
+    println!("{}", "Server stopped.".yellow());
+}
+
+/**
+ * Routes a request to its endpoint and sends the response.
+ *
+ * @param request The request to handle.
+ * @param access What the request must match to be handled.
+ * @param state The submitted plans.
+ * @param schema The schema used to plan the submitted directories.
+ */
+fn handle(mut request: Request, access: &Access, state: &Arc<Mutex<State>>, schema: &Schema) {
+    let url = request.url().to_string();
+    let path: Vec<&str> = url
+        .split('?')
+        .next()
+        .unwrap_or("")
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let id = path.get(1).and_then(|id| id.parse::<u64>().ok());
+
+    let index = path.get(3).and_then(|index| index.parse::<usize>().ok());
+
+    let result = match (request.method(), path.as_slice(), id) {
+        _ if let Err(err) = check_request(&request, access, path.is_empty()) => Err(err),
+        (Method::Get, [], _) => {
+            // Other web pages can't read the page, so the token in it stays secret
+            let ui = UI.replace("{{token}}", &access.token);
+            let response = Response::from_string(ui).with_header(content_type("text/html"));
+            request.respond(response).unwrap_or(());
+            return;
+        }
+        (Method::Post, ["plans"], _) => {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => submit(&body, state, schema),
+                Err(err) => Err((400, err.to_string())),
+            }
+        }
+        (Method::Get, ["plans"], _) => Ok(list(state)),
+        (Method::Get, ["plans", _], Some(id)) => get(id, state),
+        (Method::Post, ["plans", _, "approve"], Some(id)) => approve(id, state),
+        (Method::Post, ["plans", _, "books", _], Some(id)) => {
+            let mut body = String::new();
+            match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => edit_book(id, index, &body, state),
+                Err(err) => Err((400, err.to_string())),
+            }
+        }
+        (Method::Post, ["plans", _, "books", _, "skip"], Some(id)) => skip_book(id, index, state),
+        (Method::Get, ["plans", _, "events"], Some(id)) => match events(id, state) {
+            Ok(stream) => {
+                let response = Response::new(
+                    StatusCode(200),
+                    vec![content_type("application/x-ndjson")],
+                    stream,
+                    None,
+                    None,
+                );
+                request.respond(response).unwrap_or(());
+                return;
+            }
+            Err(err) => Err(err),
+        },
+        _ => Err((404, String::from("not found"))),
+    };
+
+    let (status, body) = match result {
+        Ok(body) => (200, body),
+        Err((status, err)) => (status, json!({ "error": err }).to_string()),
+    };
+    let response = Response::from_string(body)
+        .with_status_code(status)
+        .with_header(content_type("application/json"));
+    request.respond(response).unwrap_or(());
+}
+
+/**
+ * Checks that a request is for this server, and was sent by a client that knows
+ * its token rather than by another web page in the browser.
+ *
+ * @param request The request to check.
+ * @param access What the request must match to be handled.
+ * @param page If true, the request is for the web UI, which is served without a token.
+ * @return `Ok(())`, or the status and the reason the request is rejected.
+ */
+fn check_request(request: &Request, access: &Access, page: bool) -> Result<(), (u16, String)> {
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv(name))
+            .map(|header| header.value.as_str())
+    };
+
+    // A page of another site can point its own host name at the server
+    let host = header("Host").unwrap_or("");
+    if !is_listen_host(host, &access.listen) {
+        return Err((403, format!("the host '{}' is not served here", host)));
+    }
+    if let Some(origin) = header("Origin")
+        && !origin
+            .strip_prefix("http://")
+            .is_some_and(|origin| is_listen_host(origin, &access.listen))
+    {
+        return Err((403, format!("requests from '{}' are not allowed", origin)));
+    }
+    if page && *request.method() == Method::Get {
+        return Ok(());
+    }
+
+    if header(TOKEN_HEADER) != Some(access.token.as_str()) {
+        return Err((
+            401,
+            format!("the {} header is missing or wrong", TOKEN_HEADER),
+        ));
+    }
+    let json = header("Content-Type")
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("application/json"));
+    if *request.method() == Method::Post && !json {
+        return Err((415, String::from("the request must be application/json")));
+    }
+    Ok(())
+}
+
+/**
+ * Checks whether a `Host` header names the address the server listens on. When
+ * listening on all interfaces only the port is compared, and `localhost` matches
+ * a loopback address.
+ *
+ * @param host The host and port of the request.
+ * @param listen The address the server listens on.
+ * @return True if the host is the listen address.
+ */
+fn is_listen_host(host: &str, listen: &str) -> bool {
+    if host.eq_ignore_ascii_case(listen) {
+        return true;
+    }
+    let Ok(addr) = listen.parse::<SocketAddr>() else {
+        return false;
+    };
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => (name, port.parse::<u16>().ok()),
+        _ => (host, Some(80)),
+    };
+    if port != Some(addr.port()) {
+        return false;
+    }
+    let ip = addr.ip();
+    ip.is_unspecified()
+        || name
+            .trim_matches(['[', ']'])
+            .parse()
+            .is_ok_and(|name: std::net::IpAddr| name == ip)
+        || (ip.is_loopback() && name.eq_ignore_ascii_case("localhost"))
+}
+
+/**
+ * Generates a random token for the API requests.
+ *
+ * @return 32 hex digits.
+ */
+fn generate_token() -> String {
+    // The keys of `RandomState` come from the random source of the system
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().hash_one(0)))
+        .collect()
+}
+
+/**
+ * Plans a submitted source directory.
+ *
+ * @param body The request body, `{"source": "..."}`.
+ * @return The new submission as JSON.
+ */
+fn submit(body: &str, state: &Mutex<State>, schema: &Schema) -> Result<String, (u16, String)> {
+    let request: SubmitRequest = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+    if !Path::new(&request.source).is_dir() {
+        return Err((400, format!("'{}' is not a directory", request.source)));
+    }
+
+    let mut summary = Summary::default();
+    let books = plan(schema, std::slice::from_ref(&request.source), &mut summary);
+
+    let mut state = state.lock().map_err(|e| (500, e.to_string()))?;
+    state.next_id += 1;
+    let submission = Submission {
+        id: state.next_id,
+        source: request.source,
+        status: Status::Planned,
+        books,
+        summary,
+        events: Arc::default(),
+        finished: Arc::default(),
+    };
+    let body = serde_json::to_string(&submission).map_err(|e| (500, e.to_string()))?;
+    state.submissions.insert(submission.id, submission);
+    Ok(body)
+}
+
+/**
+ * Lists the submitted plans.
+ *
+ * @return The id, source, status and book count of every submission as JSON.
+ */
+fn list(state: &Mutex<State>) -> String {
+    let Ok(state) = state.lock() else {
+        return String::from("[]");
+    };
+
+    let mut submissions: Vec<_> = state.submissions.values().collect();
+    submissions.sort_by_key(|s| s.id);
+    let list: Vec<_> = submissions
+        .iter()
+        .map(|s| {
+            json!({
+                "id": s.id,
+                "source": s.source,
+                "status": s.status,
+                "books": s.books.len(),
+            })
+        })
+        .collect();
+    json!(list).to_string()
+}
+
+/**
+ * Gets a submitted plan.
+ *
+ * @param id The id of the submission.
+ * @return The submission as JSON.
+ */
+fn get(id: u64, state: &Mutex<State>) -> Result<String, (u16, String)> {
+    let state = state.lock().map_err(|e| (500, e.to_string()))?;
+    let submission = state
+        .submissions
+        .get(&id)
+        .ok_or((404, format!("plan {} not found", id)))?;
+    serde_json::to_string(submission).map_err(|e| (500, e.to_string()))
+}
+
+/**
+ * Changes the destination directory of a book in a plan that is not approved yet.
+ *
+ * @param id The id of the submission.
+ * @param index The position of the book in the plan.
+ * @param body The request body, `{"to": "..."}`.
+ * @return The updated submission as JSON.
+ */
+fn edit_book(
+    id: u64,
+    index: Option<usize>,
+    body: &str,
+    state: &Mutex<State>,
+) -> Result<String, (u16, String)> {
+    let request: EditRequest = serde_json::from_str(body).map_err(|e| (400, e.to_string()))?;
+    let to = request.to.trim_end_matches('/').to_string();
+    if to.is_empty() {
+        return Err((400, String::from("the destination can't be empty")));
+    }
+
+    let root = destination_root(&to).ok_or_else(|| {
+        (
+            400,
+            format!("'{}' is not inside a destination of the library", to),
+        )
+    })?;
+
+    let mut state = state.lock().map_err(|e| (500, e.to_string()))?;
+    let mut book = pending_book(&mut state, id, index)?.clone();
+    let old = std::mem::replace(&mut book.to, to);
+    let destinations = book
+        .files
+        .iter_mut()
+        .map(|file| &mut file.to)
+        .chain(book.description_file.as_mut())
+        .chain(book.playlist.as_mut().map(|playlist| &mut playlist.file));
+    for destination in destinations {
+        if let Some(name) = destination.strip_prefix(&old) {
+            *destination = format!("{}{}", book.to, name);
+        }
+    }
+    book.root = root;
+
+    let submission = state.submissions.get_mut(&id).expect("book was found");
+    let index = index.expect("book was found");
+    let mut collisions = planned_collisions(&submission.books, index);
+    if !collisions.claim_book(&book.to) {
+        return Err((
+            409,
+            format!("another book of the plan is organized into '{}'", book.to),
+        ));
+    }
+    merge_edited(&mut book, &mut collisions, &mut submission.summary)?;
+    submission.books[index] = book;
+    serde_json::to_string(submission).map_err(|e| (500, e.to_string()))
+}
+
+/**
+ * Finds the destination root a directory edited in review is inside of.
+ *
+ * @param to The edited destination directory of a book.
+ * @return The root, or `None` if the directory is outside of every root, or is a
+ *         root itself.
+ */
+fn destination_root(to: &str) -> Option<String> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let path = Path::new(to);
+    if path.components().any(|c| c == Component::ParentDir) {
+        return None;
+    }
+    let path = std::path::absolute(path).ok()?;
+    let routes = cfg.routes.iter().map(|route| &route.destination);
+    std::iter::once(&cfg.to)
+        .chain(&cfg.pool)
+        .chain(routes)
+        .find(|root| {
+            std::path::absolute(root).is_ok_and(|root| path.starts_with(&root) && path != root)
+        })
+        .cloned()
+}
+
+/**
+ * Collects the destinations of the other books of a plan, so an edited book can
+ * be checked against them like when the plan was made.
+ *
+ * @param books The books of the plan. Skipped books are already removed.
+ * @param edited The position of the edited book, which is left out.
+ * @return The index of the planned book directories and files.
+ */
+fn planned_collisions(books: &[Plan], edited: usize) -> CollisionIndex {
+    let mut collisions = CollisionIndex::default();
+    let others = books
+        .iter()
+        .enumerate()
+        .filter(|(position, _)| *position != edited);
+    for (_, book) in others {
+        collisions.claim_book(&book.to);
+        for op in book.files.iter().filter(|op| op.action != FileAction::Skip) {
+            collisions.claim_file(&op.to, false);
+        }
+    }
+    collisions
+}
+
+/**
+ * Decides again how an edited book is combined with an existing book at its new
+ * destination, like when it was planned.
+ *
+ * @param book The plan of the book with its new destination.
+ * @param collisions The destinations of the other books of the plan.
+ * @param summary The summary of the submission.
+ * @return `Ok(())`, or the reason the edit is rejected.
+ */
+fn merge_edited(
+    book: &mut Plan,
+    collisions: &mut CollisionIndex,
+    summary: &mut Summary,
+) -> Result<(), (u16, String)> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let exists = Path::new(&book.to).is_dir();
+    let merge = if exists && (cfg.merge_strategy.is_some() || cfg.on_duplicate.is_some()) {
+        let rendered: Vec<_> = book
+            .files
+            .iter()
+            .map(|op| (op.from.clone(), op.to.clone(), op.bytes, None))
+            .collect();
+        Some(decide_merge(&book.from, &book.to, &rendered, summary))
+    } else {
+        None
+    };
+    if merge == Some(MergeDecision::KeepExisting) {
+        return Err((
+            409,
+            format!(
+                "'{}' already exists and is kept, skip the book instead",
+                book.to
+            ),
+        ));
+    }
+
+    book.replace_existing = merge == Some(MergeDecision::ReplaceAll);
+    let file_action = book.action.file_action();
+    for op in &mut book.files {
+        if let Some(planned) = collisions.claim_file(&op.to, false) {
+            return Err((
+                409,
+                format!("'{}' is already planned as '{}'", op.to, planned),
+            ));
+        }
+        let taken = Path::new(&op.to).exists() && !book.replace_existing;
+        op.action = match (taken, &merge) {
+            (false, _) => file_action.clone(),
+            (true, Some(MergeDecision::AddMissing)) => FileAction::Skip,
+            (true, _) => return Err((409, format!("'{}' already exists", op.to))),
+        };
+    }
+    Ok(())
+}
+
+/**
+ * Removes a book from a plan that is not approved yet.
+ *
+ * @param id The id of the submission.
+ * @param index The position of the book in the plan.
+ * @return The updated submission as JSON.
+ */
+fn skip_book(id: u64, index: Option<usize>, state: &Mutex<State>) -> Result<String, (u16, String)> {
+    let mut state = state.lock().map_err(|e| (500, e.to_string()))?;
+    let from = pending_book(&mut state, id, index)?.from.clone();
+
+    let submission = state.submissions.get_mut(&id).expect("book was found");
+    submission.books.retain(|book| book.from != from);
+    submission
+        .summary
+        .skipped_books
+        .push((from, String::from("skipped in review")));
+    serde_json::to_string(submission).map_err(|e| (500, e.to_string()))
+}
+
+/**
+ * Finds a book of a plan that can still be changed.
+ *
+ * @param id The id of the submission.
+ * @param index The position of the book in the plan.
+ * @return The book's plan.
+ */
+fn pending_book(
+    state: &mut State,
+    id: u64,
+    index: Option<usize>,
+) -> Result<&mut Plan, (u16, String)> {
+    let submission = state
+        .submissions
+        .get_mut(&id)
+        .ok_or((404, format!("plan {} not found", id)))?;
+    if submission.status != Status::Planned {
+        return Err((409, format!("plan {} was already approved", id)));
+    }
+    index
+        .and_then(|index| submission.books.get_mut(index))
+        .ok_or((404, String::from("book not found")))
+}
+
+/**
+ * Approves a plan and executes it in the background.
+ *
+ * @param id The id of the submission.
+ * @return The new status as JSON.
+ */
+fn approve(id: u64, state: &Arc<Mutex<State>>) -> Result<String, (u16, String)> {
+    let (books, source, events, finished) = {
+        let mut state = state.lock().map_err(|e| (500, e.to_string()))?;
+        let submission = state
+            .submissions
+            .get_mut(&id)
+            .ok_or((404, format!("plan {} not found", id)))?;
+        if submission.status != Status::Planned {
+            return Err((409, format!("plan {} was already approved", id)));
+        }
+        submission.status = Status::Running;
+        (
+            submission.books.clone(),
+            submission.source.clone(),
+            Arc::clone(&submission.events),
+            Arc::clone(&submission.finished),
+        )
+    };
+
+    let state = Arc::clone(state);
+    thread::spawn(move || {
+        let _execution = EXECUTION.lock().unwrap_or_else(|e| e.into_inner());
+        let mut summary = state
+            .lock()
+            .ok()
+            .and_then(|mut s| {
+                s.submissions
+                    .get_mut(&id)
+                    .map(|s| std::mem::take(&mut s.summary))
+            })
+            .unwrap_or_default();
+
+        progress::capture(Some(events));
+        execute(books, std::slice::from_ref(&source), &mut summary);
+        progress::capture(None);
+
+        if let Ok(mut state) = state.lock()
+            && let Some(submission) = state.submissions.get_mut(&id)
+        {
+            submission.summary = summary;
+            submission.status = Status::Finished;
+        }
+        finished.store(true, Ordering::SeqCst);
+    });
+
+    Ok(json!({ "id": id, "status": Status::Running }).to_string())
+}
+
+/**
+ * Opens the progress event stream of a plan.
+ *
+ * @param id The id of the submission.
+ * @return A reader that yields the events as JSON lines until the plan finishes.
+ */
+fn events(id: u64, state: &Mutex<State>) -> Result<EventStream, (u16, String)> {
+    let state = state.lock().map_err(|e| (500, e.to_string()))?;
+    let submission = state
+        .submissions
+        .get(&id)
+        .ok_or((404, format!("plan {} not found", id)))?;
+    Ok(EventStream {
+        events: Arc::clone(&submission.events),
+        finished: Arc::clone(&submission.finished),
+        position: 0,
+        buffer: Vec::new(),
+    })
+}
+
+/// Streams the progress events of a plan, waiting for new events until the plan
+/// has finished.
+struct EventStream {
+    events: Arc<Mutex<Vec<String>>>,
+    finished: Arc<AtomicBool>,
+    position: usize,
+    buffer: Vec<u8>,
+}
+
+impl Read for EventStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.buffer.is_empty() {
+            // Check before reading the events, so no events are missed at the end
+            let finished = self.finished.load(Ordering::SeqCst);
+            if let Ok(events) = self.events.lock() {
+                for event in &events[self.position..] {
+                    self.buffer.extend_from_slice(event.as_bytes());
+                    self.buffer.push(b'\n');
+                }
+                self.position = events.len();
+            }
+            if self.buffer.is_empty() {
+                if finished {
+                    return Ok(0);
+                }
+                thread::sleep(Duration::from_millis(250));
+            }
+        }
+
+        let count = buf.len().min(self.buffer.len());
+        buf[..count].copy_from_slice(&self.buffer[..count]);
+        self.buffer.drain(..count);
+        Ok(count)
+    }
+}
+
+/**
+ * Builds a `Content-Type` header.
+ *
+ * @param value The content type.
+ */
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).expect("valid header")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActionOpt, Args, FileConfig, build_config, plan_only};
+    use clap::Parser;
+    use std::fs;
+
+    #[test]
+    fn test_edit_book_collision() {
+        let _lock = crate::TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-serve-edit-{}", std::process::id()));
+        for title in ["Coraline", "Stardust"] {
+            let book = dir.join("src").join(title);
+            fs::create_dir_all(&book).unwrap();
+            fs::write(
+                book.join("metadata.json"),
+                format!(r#"{{"title":"{}","authors":["Neil Gaiman"]}}"#, title),
+            )
+            .unwrap();
+            fs::write(book.join("01.mp3"), title).unwrap();
+        }
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let args = ["aborg", "-s", &path("src"), "-d", &path("lib")];
+        let config = build_config(
+            &Args::try_parse_from(args).unwrap(),
+            &FileConfig::default(),
+            ActionOpt::None,
+        )
+        .unwrap();
+        CONFIG.replace(config);
+        let books = plan_only(args).unwrap();
+        let state = Mutex::new(State::default());
+        state.lock().unwrap().submissions.insert(
+            1,
+            Submission {
+                id: 1,
+                source: path("src"),
+                status: Status::Planned,
+                books,
+                summary: Summary::default(),
+                events: Arc::default(),
+                finished: Arc::default(),
+            },
+        );
+        let edit = |index: usize, to: &str| {
+            edit_book(1, Some(index), &json!({ "to": to }).to_string(), &state)
+        };
+
+        let taken = state.lock().unwrap().submissions[&1].books[0].to.clone();
+        // Both books would be organized into the same directory
+        assert_eq!(edit(1, &taken).unwrap_err().0, 409);
+        let other_case = path("lib/neil gaiman/coraline");
+        assert_eq!(edit(1, &other_case).unwrap_err().0, 409);
+        assert!(edit(1, &path("lib/Neil Gaiman/Stardust (2)")).is_ok());
+        // Editing a book onto its own directory is no collision
+        assert!(edit(0, &taken).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_listen_host() {
+        assert!(is_listen_host("127.0.0.1:8080", "127.0.0.1:8080"));
+        assert!(is_listen_host("localhost:8080", "127.0.0.1:8080"));
+        assert!(is_listen_host("[::1]:8080", "[::1]:8080"));
+        assert!(is_listen_host("nas.local:8080", "0.0.0.0:8080"));
+        assert!(is_listen_host("nas.local", "0.0.0.0:80"));
+        // A page of another site that resolves its own name to the server
+        assert!(!is_listen_host("evil.example:8080", "127.0.0.1:8080"));
+        assert!(!is_listen_host("127.0.0.1:9090", "127.0.0.1:8080"));
+        assert!(!is_listen_host("nas.local:9090", "0.0.0.0:8080"));
+        assert!(!is_listen_host("", "127.0.0.1:8080"));
+    }
+}
@@ -0,0 +1,70 @@
+use colored::Colorize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Set once SIGTERM or SIGINT was received.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/**
+ * Installs the SIGTERM and SIGINT handlers used by the long running modes.
+ *
+ * The first signal requests a stop, so the book that is being processed is
+ * finished before exiting and no book is left half moved. A second signal exits
+ * immediately.
+ */
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handle(_signal: libc::c_int) {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            // SAFETY: `_exit` is async-signal-safe.
+            unsafe { libc::_exit(130) };
+        }
+    }
+
+    let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches an atomic and calls async-signal-safe functions.
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+/**
+ * Checks whether a stop was requested.
+ */
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/**
+ * Prints that aborg is stopping, and why.
+ */
+pub fn announce() {
+    println!(
+        "{}",
+        "Stop requested, exiting after the current book. Send the signal again to exit immediately."
+            .yellow()
+    );
+}
+
+/**
+ * Sleeps for the given duration, waking up early if a stop is requested.
+ *
+ * @param duration How long to sleep.
+ * @return False if the sleep was cut short because a stop was requested.
+ */
+pub fn sleep(duration: Duration) -> bool {
+    let end = Instant::now() + duration;
+    while !requested() {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        thread::sleep(left.min(Duration::from_millis(250)));
+    }
+    false
+}
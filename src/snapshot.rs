@@ -0,0 +1,95 @@
+use crate::copy::hash_file;
+use crate::{CONFIG, Plan};
+use chrono::Local;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+/// Represents the source tree of a run, as it was before anything was changed.
+#[derive(Debug, Serialize)]
+struct Snapshot {
+    /// When the snapshot was taken, in RFC 3339 format.
+    created: String,
+    books: Vec<BookSnapshot>,
+}
+
+/// Represents the source directory of a single book.
+#[derive(Debug, Serialize)]
+struct BookSnapshot {
+    dir: String,
+    files: Vec<FileSnapshot>,
+}
+
+/// Represents a single file of a source directory.
+#[derive(Debug, Serialize)]
+struct FileSnapshot {
+    /// The path of the file, relative to the book directory.
+    path: String,
+    size: u64,
+    /// The SHA-256 of the file, only with `--verify`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+}
+
+/**
+ * Writes a JSON record of the source directories of the planned books, with every
+ * file they contain, so there is a record of exactly what the input looked like
+ * before the run changed it. The files are hashed with `--verify`.
+ *
+ * All files are listed, including the ones the run leaves out, such as hidden
+ * files and macOS junk. Paths are absolute.
+ *
+ * @param path The path of the JSON file to write.
+ * @param plans The plans of the books.
+ * @return The number of files recorded, or an error message.
+ */
+pub fn write_snapshot(path: &str, plans: &[Plan]) -> Result<usize, String> {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let mut books = Vec::new();
+    let mut count = 0;
+    for dir in plans
+        .iter()
+        .flat_map(|plan| std::iter::once(&plan.from).chain(&plan.parts))
+    {
+        let dir = std::path::absolute(dir).map_err(|e| format!("'{}': {}", dir, e))?;
+        let mut files = Vec::new();
+        for entry in WalkDir::new(&dir).sort_by_file_name() {
+            let entry = entry.map_err(|e| e.to_string())?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let size = entry.metadata().map_err(|e| e.to_string())?.len();
+            let sha256 = cfg
+                .verify
+                .then(|| hash_file(entry.path(), cfg.buffer_size))
+                .transpose()
+                .map_err(|e| format!("'{}': {}", entry.path().display(), e))?;
+            files.push(FileSnapshot {
+                path: relative(entry.path(), &dir),
+                size,
+                sha256,
+            });
+        }
+        count += files.len();
+        books.push(BookSnapshot {
+            dir: dir.display().to_string(),
+            files,
+        });
+    }
+
+    let snapshot = Snapshot {
+        created: Local::now().to_rfc3339(),
+        books,
+    };
+    let json = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/**
+ * Gets the path of a file relative to its book directory.
+ */
+fn relative(file: &Path, dir: &Path) -> String {
+    file.strip_prefix(dir).unwrap_or(file).display().to_string()
+}
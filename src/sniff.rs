@@ -0,0 +1,111 @@
+use crate::CONFIG;
+use lofty::error::Result as LoftyResult;
+use lofty::file::FileType;
+use lofty::probe::Probe;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/**
+ * Detects the audio format of a file from its content, ignoring its extension.
+ *
+ * @param path The file to check.
+ * @return The canonical extension of the format (e.g. `mp3` or `m4b`), or `None` if
+ *         the file is not audio or can't be read.
+ */
+pub fn detect_extension(path: &Path) -> Option<&'static str> {
+    let file = File::open(path).ok()?;
+    let probe = Probe::new(BufReader::new(file)).guess_file_type().ok()?;
+    let extension = match probe.file_type()? {
+        FileType::Aac => "aac",
+        FileType::Aiff => "aiff",
+        FileType::Ape => "ape",
+        FileType::Flac => "flac",
+        FileType::Mpeg => "mp3",
+        FileType::Mp4 => mp4_extension(path),
+        FileType::Mpc => "mpc",
+        FileType::Opus => "opus",
+        FileType::Speex => "spx",
+        FileType::Vorbis => "ogg",
+        FileType::Wav => "wav",
+        FileType::WavPack => "wv",
+        _ => return None,
+    };
+    Some(extension)
+}
+
+/**
+ * Gets the extension of an MP4 file from the major brand of its `ftyp` box:
+ * `m4b` for audiobooks, `m4p` for protected files and `m4a` for everything else.
+ */
+fn mp4_extension(path: &Path) -> &'static str {
+    let mut header = [0; 12];
+    let read = File::open(path).and_then(|mut file| file.read_exact(&mut header));
+    match (read, &header[4..]) {
+        (Ok(_), b"ftypM4B ") => "m4b",
+        (Ok(_), b"ftypM4P ") => "m4p",
+        _ => "m4a",
+    }
+}
+
+/**
+ * Detects the audio format of a file whose extension is not an audio extension,
+ * e.g. `Chapter 1.tmp` or `Chapter 1`, with `--detect-types`.
+ *
+ * @param path The file to check.
+ * @param file_ext The audio file extensions.
+ * @return The canonical extension, if the file is audio in one of the configured
+ *         formats.
+ */
+pub fn audio_extension(path: &Path, file_ext: &[String]) -> Option<&'static str> {
+    detect_extension(path).filter(|ext| file_ext.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+/**
+ * Opens an audio file to read its tags and properties. The format is taken from the
+ * extension, and with `--detect-types` from the content when the extension doesn't
+ * tell it.
+ *
+ * @param path The audio file.
+ * @return The probe, ready to be read.
+ */
+pub fn open(path: &Path) -> LoftyResult<Probe<BufReader<File>>> {
+    let probe = Probe::open(path)?;
+    if probe.file_type().is_none() && CONFIG.get().is_some_and(|cfg| cfg.detect_types) {
+        return Ok(probe.guess_file_type()?);
+    }
+    Ok(probe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_detect_extension() {
+        let dir = std::env::temp_dir().join(format!("aborg-sniff-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, content: &[u8]| {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            path
+        };
+
+        let m4b = file(
+            "Chapter 1.tmp",
+            b"\0\0\0\x20ftypM4B \0\0\0\0M4B mp42isom\0\0\0\0",
+        );
+        assert_eq!(detect_extension(&m4b), Some("m4b"));
+        let m4a = file("Chapter 2", b"\0\0\0\x20ftypmp42\0\0\0\0mp42isom\0\0\0\0");
+        assert_eq!(detect_extension(&m4a), Some("m4a"));
+        let flac = file("Chapter 3.mp3", b"fLaC\0\0\0\x22");
+        assert_eq!(detect_extension(&flac), Some("flac"));
+        assert_eq!(audio_extension(&flac, &[String::from("mp3")]), None);
+
+        let text = file("notes.tmp", b"Not an audio file");
+        assert_eq!(detect_extension(&text), None);
+        assert_eq!(detect_extension(&dir.join("missing")), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,479 @@
+use crate::authors::AuthorGroup;
+use crate::completeness::Completeness;
+use crate::fsutil::format_size;
+use crate::prune::PruneSuggestion;
+use colored::Colorize;
+use serde::Serialize;
+
+/// The category of a problem of a run, so the problems of a large run can be
+/// triaged by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Category {
+    /// A metadata file or manifest could not be read or parsed.
+    Metadata,
+    /// A schema, template or naming script could not be rendered for a book.
+    Template,
+    /// A file could not be read, written, moved, linked or deleted.
+    Io,
+    /// A file or book was skipped because its destination is taken.
+    Conflict,
+    /// A file does not match its source or checksum, or the source was kept because
+    /// the destination could not be verified.
+    Verification,
+}
+
+impl Category {
+    /// The categories in the order they are printed.
+    const ALL: [Category; 5] = [
+        Category::Metadata,
+        Category::Template,
+        Category::Io,
+        Category::Conflict,
+        Category::Verification,
+    ];
+
+    /**
+     * Gets the heading the problems of the category are printed under.
+     */
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Metadata => "Metadata parse failures",
+            Category::Template => "Template render failures",
+            Category::Io => "IO failures",
+            Category::Conflict => "Skipped conflicts",
+            Category::Verification => "Verification mismatches",
+        }
+    }
+}
+
+/// Collects the results of a run so they can be reported once it finishes.
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub books: usize,
+    pub files: usize,
+    /// Total size of the files copied or moved.
+    pub bytes: u64,
+    /// Size of the files moved out of the source.
+    pub freed_bytes: u64,
+    /// Free space left on the destination, only checked during a dry run.
+    pub destination_free: Option<u64>,
+    /// Existing books that incoming books could replace, found when the import
+    /// doesn't fit on the destination.
+    pub prune_suggestions: Vec<PruneSuggestion>,
+    /// Files that failed to copy, move, or verify.
+    pub errors: Vec<String>,
+    /// Every problem of the run, including the errors and conflicts, by category.
+    pub problems: Vec<(Category, String)>,
+    /// Files that were skipped because their destination already exists.
+    pub conflicts: Vec<String>,
+    /// Destination files that got a version suffix because of a conflict.
+    pub versioned: Vec<String>,
+    /// Destination files that are taken by an existing file or another planned file,
+    /// whatever the conflict policy does with them.
+    pub collisions: Vec<String>,
+    /// Books whose destination directory already exists, or is the destination of
+    /// another book of the run.
+    pub duplicates: Vec<String>,
+    /// Which edition was kept of each book that already existed, with the quality
+    /// of both, for `--on-duplicate keep-better`.
+    pub duplicate_decisions: Vec<String>,
+    /// Books that were held back because of corrupt audio, as (directory, reason).
+    pub quarantined: Vec<(String, String)>,
+    /// Books that were not processed, as (directory, reason).
+    pub skipped_books: Vec<(String, String)>,
+    /// The source directories of the books whose files were all organized without
+    /// errors.
+    pub organized: Vec<String>,
+    /// Books that were left out because they are still downloading.
+    pub downloading: Vec<String>,
+    /// Books whose metadata has an invalid ISBN, as (directory, ISBN).
+    pub invalid_isbns: Vec<(String, String)>,
+    /// Books whose metadata is missing scored fields, as (directory, completeness).
+    pub incomplete: Vec<(String, Completeness)>,
+    /// Non-audio files left in the source with `--other-files list`.
+    pub other_files: Vec<String>,
+    /// Every file operation of the run, in the order they were performed.
+    pub operations: Vec<Operation>,
+    /// The books and size processed from each source root, as (root, books, bytes).
+    pub sources: Vec<(String, usize, u64)>,
+    /// Author directories in the destination that are likely the same author.
+    pub similar_authors: Vec<AuthorGroup>,
+}
+
+/// Represents a single file operation of a run, used for the HTML report.
+#[derive(Debug, Serialize)]
+pub struct Operation {
+    pub action: &'static str,
+    pub from: String,
+    pub to: String,
+    pub bytes: u64,
+    /// The error message if the operation failed.
+    pub error: Option<String>,
+}
+
+impl Summary {
+    /**
+     * Adds the results of another summary, e.g. of a book processed on another
+     * thread.
+     *
+     * @param other The results to add.
+     */
+    pub fn merge(&mut self, other: Summary) {
+        self.books += other.books;
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.freed_bytes += other.freed_bytes;
+        self.destination_free = self.destination_free.or(other.destination_free);
+        self.prune_suggestions.extend(other.prune_suggestions);
+        self.errors.extend(other.errors);
+        self.problems.extend(other.problems);
+        self.conflicts.extend(other.conflicts);
+        self.versioned.extend(other.versioned);
+        self.collisions.extend(other.collisions);
+        self.duplicates.extend(other.duplicates);
+        self.duplicate_decisions.extend(other.duplicate_decisions);
+        self.quarantined.extend(other.quarantined);
+        self.skipped_books.extend(other.skipped_books);
+        self.organized.extend(other.organized);
+        self.downloading.extend(other.downloading);
+        self.invalid_isbns.extend(other.invalid_isbns);
+        self.incomplete.extend(other.incomplete);
+        self.other_files.extend(other.other_files);
+        self.operations.extend(other.operations);
+        self.similar_authors.extend(other.similar_authors);
+        for (source, books, bytes) in other.sources {
+            self.count_source(&source, books, bytes);
+        }
+    }
+
+    /**
+     * Records an error, e.g. a file that failed to copy.
+     *
+     * @param category The kind of error.
+     * @param message The error, starting with the affected path.
+     */
+    pub fn error(&mut self, category: Category, message: String) {
+        self.problems.push((category, message.clone()));
+        self.errors.push(message);
+    }
+
+    /**
+     * Records a problem that is not an error of a file operation, e.g. a book whose
+     * path could not be rendered.
+     *
+     * @param category The kind of problem.
+     * @param message The problem, starting with the affected path.
+     */
+    pub fn problem(&mut self, category: Category, message: String) {
+        self.problems.push((category, message));
+    }
+
+    /**
+     * Groups the problems of the run by category, in the order they are printed.
+     * Categories without problems are left out.
+     */
+    pub fn problems_by_category(&self) -> Vec<(Category, Vec<&String>)> {
+        Category::ALL
+            .into_iter()
+            .filter_map(|category| {
+                let messages: Vec<&String> = self
+                    .problems
+                    .iter()
+                    .filter(|(c, _)| *c == category)
+                    .map(|(_, message)| message)
+                    .collect();
+                (!messages.is_empty()).then_some((category, messages))
+            })
+            .collect()
+    }
+
+    /**
+     * Describes what a dry run found that would likely go wrong in the real run:
+     * books whose templates could not be rendered, collisions and duplicate books.
+     *
+     * @return The findings, e.g. `2 render failures`, empty if there are none.
+     */
+    pub fn potential_problems(&self) -> Vec<String> {
+        let render_failures = self
+            .problems
+            .iter()
+            .filter(|(category, _)| *category == Category::Template)
+            .count();
+        [
+            (render_failures, "render failure"),
+            (self.collisions.len(), "collision"),
+            (self.duplicates.len(), "duplicate book"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| match count {
+            1 => format!("1 {}", label),
+            _ => format!("{} {}s", count, label),
+        })
+        .collect()
+    }
+
+    /**
+     * Adds processed books to the totals of their source root.
+     *
+     * @param source The source root.
+     * @param books The number of books.
+     * @param bytes The size of the books' files.
+     */
+    pub fn count_source(&mut self, source: &str, books: usize, bytes: u64) {
+        match self.sources.iter_mut().find(|(root, _, _)| root == source) {
+            Some((_, total_books, total_bytes)) => {
+                *total_books += books;
+                *total_bytes += bytes;
+            }
+            None => self.sources.push((source.to_string(), books, bytes)),
+        }
+    }
+
+    /**
+     * Gets the books with incomplete metadata, the least complete first, so the books
+     * that need the most fixing come first.
+     */
+    pub fn incomplete_by_score(&self) -> Vec<&(String, Completeness)> {
+        let mut books: Vec<_> = self.incomplete.iter().collect();
+        books.sort_by_key(|(dir, completeness)| (completeness.score, dir));
+        books
+    }
+
+    /**
+     * Prints the collected results.
+     */
+    pub fn print(&self) {
+        println!("\n{}", "Summary:".bold());
+        println!("Books processed: {}", self.books);
+        println!("Files processed: {}", self.files);
+        println!("Total size: {}", format_size(self.bytes));
+        if self.sources.len() > 1 {
+            for (source, books, bytes) in &self.sources {
+                println!(
+                    "  '{}': {} books, {}",
+                    source.yellow(),
+                    books,
+                    format_size(*bytes)
+                );
+            }
+        }
+        if self.freed_bytes > 0 {
+            println!("Space freed in source: {}", format_size(self.freed_bytes));
+        }
+        if let Some(free) = self.destination_free {
+            println!("Destination free space: {}", format_size(free));
+            if self.bytes > free {
+                println!(
+                    "{}",
+                    "Warning: The destination does not have enough free space for this import!"
+                        .yellow()
+                );
+            }
+        }
+        if !self.prune_suggestions.is_empty() {
+            let freed = self.prune_suggestions.iter().map(|s| s.bytes).sum();
+            println!(
+                "{}",
+                format!(
+                    "Replacing these existing books would free {}:",
+                    format_size(freed)
+                )
+                .yellow()
+            );
+            for suggestion in &self.prune_suggestions {
+                let bitrate = |bitrate: Option<u32>| {
+                    bitrate
+                        .map(|kbps| format!("{} kb/s", kbps))
+                        .unwrap_or_else(|| String::from("unknown bitrate"))
+                };
+                let kind = match suggestion.is_lower_quality() {
+                    true => "lower bitrate",
+                    false => "duplicate",
+                };
+                println!(
+                    "  '{}' ({}, {}, {}) <- '{}' ({})",
+                    suggestion.existing.yellow(),
+                    format_size(suggestion.bytes),
+                    bitrate(suggestion.existing_bitrate),
+                    kind,
+                    suggestion.incoming,
+                    bitrate(suggestion.incoming_bitrate)
+                );
+            }
+        }
+
+        if !self.problems.is_empty() {
+            println!("{}", format!("Problems ({}):", self.problems.len()).red());
+            for (category, messages) in self.problems_by_category() {
+                println!("  {} ({}):", category.label(), messages.len());
+                for message in messages {
+                    println!("    {}", message);
+                }
+            }
+        }
+
+        if !self.versioned.is_empty() {
+            println!(
+                "{}",
+                format!("Versioned files ({}):", self.versioned.len()).yellow()
+            );
+            for file in &self.versioned {
+                println!("  '{}'", file);
+            }
+        }
+
+        if !self.collisions.is_empty() {
+            println!(
+                "{}",
+                format!("Collisions ({}):", self.collisions.len()).yellow()
+            );
+            for collision in &self.collisions {
+                println!("  {}", collision);
+            }
+        }
+
+        if !self.duplicates.is_empty() {
+            println!(
+                "{}",
+                format!("Duplicate books ({}):", self.duplicates.len()).yellow()
+            );
+            for duplicate in &self.duplicates {
+                println!("  {}", duplicate);
+            }
+        }
+
+        if !self.duplicate_decisions.is_empty() {
+            println!(
+                "{}",
+                format!("Duplicate decisions ({}):", self.duplicate_decisions.len()).blue()
+            );
+            for decision in &self.duplicate_decisions {
+                println!("  {}", decision);
+            }
+        }
+
+        if !self.skipped_books.is_empty() {
+            println!(
+                "{}",
+                format!("Skipped books ({}):", self.skipped_books.len()).yellow()
+            );
+            for (dir, reason) in &self.skipped_books {
+                println!("  '{}' - {}", dir.yellow(), reason);
+            }
+        }
+
+        if !self.downloading.is_empty() {
+            println!(
+                "{}",
+                format!("Still downloading ({}):", self.downloading.len()).blue()
+            );
+            for dir in &self.downloading {
+                println!("  '{}'", dir);
+            }
+        }
+
+        if !self.invalid_isbns.is_empty() {
+            println!(
+                "{}",
+                format!("Invalid ISBNs ({}):", self.invalid_isbns.len()).yellow()
+            );
+            for (dir, isbn) in &self.invalid_isbns {
+                println!("  '{}' - {}", dir.yellow(), isbn);
+            }
+        }
+
+        if !self.incomplete.is_empty() {
+            println!(
+                "{}",
+                format!("Incomplete metadata ({}):", self.incomplete.len()).yellow()
+            );
+            for (dir, completeness) in self.incomplete_by_score() {
+                println!(
+                    "  {:>3}% '{}' - missing {}",
+                    completeness.score,
+                    dir.yellow(),
+                    completeness.missing.join(", ")
+                );
+            }
+        }
+
+        if !self.other_files.is_empty() {
+            println!(
+                "{}",
+                format!(
+                    "Other files left in the source ({}):",
+                    self.other_files.len()
+                )
+                .yellow()
+            );
+            for file in &self.other_files {
+                println!("  '{}'", file);
+            }
+        }
+
+        if !self.similar_authors.is_empty() {
+            println!(
+                "{}",
+                format!(
+                    "Similar author directories ({}):",
+                    self.similar_authors.len()
+                )
+                .yellow()
+            );
+            for group in &self.similar_authors {
+                let others: Vec<String> = group
+                    .others
+                    .iter()
+                    .map(|dir| format!("'{}'", dir.display()))
+                    .collect();
+                println!(
+                    "  '{}' <- {}",
+                    group.canonical.display().to_string().yellow(),
+                    others.join(", ")
+                );
+                println!("    {}", group.remediation());
+            }
+        }
+
+        if !self.quarantined.is_empty() {
+            println!(
+                "{}",
+                format!("Quarantined books ({}):", self.quarantined.len()).red()
+            );
+            for (dir, reason) in &self.quarantined {
+                println!("  '{}' - {}", dir.yellow(), reason);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_potential_problems() {
+        let mut summary = Summary::default();
+        assert!(summary.potential_problems().is_empty());
+
+        summary
+            .problems
+            .push((Category::Template, String::from("'a': missing title")));
+        summary
+            .problems
+            .push((Category::Io, String::from("'b': disk full")));
+        summary.collisions.push(String::from("'c' and 'd'"));
+        summary.collisions.push(String::from("'e' and 'f'"));
+        assert_eq!(
+            summary.potential_problems(),
+            vec!["1 render failure", "2 collisions"]
+        );
+
+        summary.duplicates.push(String::from("'g' -> 'h'"));
+        assert_eq!(
+            summary.potential_problems(),
+            vec!["1 render failure", "2 collisions", "1 duplicate book"]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use crate::audit;
+use crate::fsutil::is_junk;
+use crate::retry::retry;
+use crate::summary::{Category, Summary};
+use crate::{CONFIG, confirm, transfer_file};
+use colored::Colorize;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/**
+ * Makes the mirror directory structurally match the source library.
+ *
+ * Files that are missing from the mirror, or differ in size or modification
+ * time, are copied over. With `delete`, files in the mirror that no longer exist
+ * in the source are removed. The deletions are always listed before anything is
+ * removed, and must be confirmed unless `yes` is set.
+ *
+ * @param delete If true, remove mirror files whose source was removed.
+ * @param yes If true, don't ask for confirmation before deleting.
+ * @param summary The summary that collects the results of the run.
+ */
+pub fn sync(delete: bool, yes: bool, summary: &mut Summary) {
+    let cfg = CONFIG.get().expect("CONFIG was not set");
+    let source = Path::new(&cfg.from);
+    let mirror = Path::new(&cfg.to);
+
+    println!("Syncing '{}' to '{}'...", cfg.from.green(), cfg.to.green());
+
+    // A partial listing of the source would delete the files it missed from the mirror
+    let source_files = match relative_files(source) {
+        Ok(files) => files,
+        Err(err) => {
+            eprintln!("{} {}", "Error reading the source library:".red(), err);
+            summary.error(
+                Category::Io,
+                format!("'{}' was not synced: {}", cfg.from, err),
+            );
+            return;
+        }
+    };
+    let source_set: HashSet<&PathBuf> = source_files.iter().collect();
+
+    // Preview the deletions first, so they can be reviewed before anything changes
+    let mut deletions = Vec::new();
+    if delete && mirror.is_dir() {
+        let mirror_files = match relative_files(mirror) {
+            Ok(files) => files,
+            Err(err) => {
+                eprintln!("{} {}", "Error reading the mirror:".red(), err);
+                summary.error(
+                    Category::Io,
+                    format!("'{}' was not synced: {}", cfg.to, err),
+                );
+                return;
+            }
+        };
+        deletions = mirror_files
+            .into_iter()
+            .filter(|file| !source_set.contains(file))
+            .collect();
+
+        if !deletions.is_empty() {
+            println!(
+                "{}",
+                format!(
+                    "The following {} files will be deleted from the mirror:",
+                    deletions.len()
+                )
+                .yellow()
+            );
+            for file in &deletions {
+                println!("  {}", mirror.join(file).display());
+            }
+        }
+    }
+
+    for file in &source_files {
+        let from = source.join(file);
+        let to = mirror.join(file);
+        if is_up_to_date(&from, &to) {
+            continue;
+        }
+
+        let bytes = fs::metadata(&from).map(|m| m.len()).unwrap_or(0);
+        print!(
+            "{} '{}' to '{}'...",
+            "Syncing:".blue(),
+            from.display(),
+            to.display().to_string().green()
+        );
+        if cfg.dry_run {
+            println!(" Done");
+            summary.files += 1;
+            summary.bytes += bytes;
+            continue;
+        }
+
+        let result = to
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| transfer_file(&from, &to))
+            .and_then(|_| copy_modified_time(&from, &to).map_err(|e| e.to_string()));
+        audit::record(
+            "sync",
+            &from,
+            &to,
+            result.as_ref().err().map(String::as_str),
+        );
+        match result {
+            Ok(_) => {
+                println!(" Done");
+                summary.files += 1;
+                summary.bytes += bytes;
+            }
+            Err(err) => {
+                eprintln!("{} {}", "Error syncing file:".red(), err);
+                summary.error(Category::Io, format!("'{}': {}", from.display(), err));
+            }
+        }
+    }
+
+    if deletions.is_empty() {
+        return;
+    }
+
+    if cfg.dry_run {
+        for file in &deletions {
+            println!("{} '{}'", "Deleted:".yellow(), mirror.join(file).display());
+        }
+        return;
+    }
+
+    let question = format!(
+        "Type 'yes' to delete {} files from the mirror: ",
+        deletions.len()
+    );
+    if !yes && !confirm(&question) {
+        println!("{}", "Not deleting any files from the mirror.".yellow());
+        return;
+    }
+
+    for file in &deletions {
+        let path = mirror.join(file);
+        let result = retry("delete", || fs::remove_file(&path));
+        audit::record(
+            "delete",
+            &path,
+            Path::new(""),
+            result.as_ref().err().map(|e| e.to_string()).as_deref(),
+        );
+        match result {
+            Ok(_) => println!("{} '{}'", "Deleted:".yellow(), path.display()),
+            Err(err) => {
+                eprintln!("{} {}", "Error deleting file:".red(), err);
+                summary.error(Category::Io, format!("'{}': {}", path.display(), err));
+            }
+        }
+    }
+
+    remove_empty_dirs(mirror);
+}
+
+/**
+ * Lists all files in a directory tree, relative to the root, leaving out macOS junk.
+ *
+ * @param root The directory to list.
+ * @return The relative file paths, or the error if the root or any directory
+ *         below it can't be read.
+ */
+fn relative_files(root: &Path) -> Result<Vec<PathBuf>, String> {
+    if !root.is_dir() {
+        return Err(format!("'{}' is not a directory", root.display()));
+    }
+    let mut files = Vec::new();
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| !is_junk(entry.path()))
+    {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.file_type().is_file()
+            && let Ok(file) = entry.path().strip_prefix(root)
+        {
+            files.push(file.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/**
+ * Checks whether the mirror file has the same size and modification time as the source.
+ *
+ * @param from The source file.
+ * @param to The mirror file.
+ * @return True if the mirror file doesn't need to be copied again.
+ */
+fn is_up_to_date(from: &Path, to: &Path) -> bool {
+    let (Ok(source), Ok(mirror)) = (fs::metadata(from), fs::metadata(to)) else {
+        return false;
+    };
+
+    source.len() == mirror.len()
+        && match (source.modified(), mirror.modified()) {
+            (Ok(a), Ok(b)) => a
+                .duration_since(b)
+                .or_else(|_| b.duration_since(a))
+                .is_ok_and(|d| d.as_secs() < 2),
+            _ => false,
+        }
+}
+
+/**
+ * Copies the modification time of the source file to the mirror file, so the
+ * next sync can tell that the file is up to date.
+ *
+ * @param from The source file.
+ * @param to The mirror file.
+ */
+fn copy_modified_time(from: &Path, to: &Path) -> io::Result<()> {
+    let modified = fs::metadata(from)?.modified()?;
+    File::options().write(true).open(to)?.set_modified(modified)
+}
+
+/**
+ * Removes all empty directories below the root, deepest first.
+ *
+ * @param root The directory to clean up. The root itself is kept.
+ */
+fn remove_empty_dirs(root: &Path) {
+    for entry in WalkDir::new(root)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        if fs::remove_dir(entry.path()).is_ok() {
+            audit::record("delete", entry.path(), Path::new(""), None);
+            println!("{} '{}'", "Deleted:".yellow(), entry.path().display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ActionOpt, Args, FileConfig, build_config};
+    use clap::Parser;
+
+    #[test]
+    fn test_sync_missing_source() {
+        let _lock = crate::TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-sync-{}", std::process::id()));
+        fs::create_dir_all(dir.join("mirror/Author/Book")).unwrap();
+        fs::write(dir.join("mirror/Author/Book/01.mp3"), "audio").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let args = Args::try_parse_from([
+            "aborg",
+            "sync",
+            "--delete",
+            "--yes",
+            &path("missing"),
+            &path("mirror"),
+        ])
+        .unwrap();
+        let config = build_config(&args, &FileConfig::default(), ActionOpt::None).unwrap();
+        CONFIG.replace(config);
+        let mut summary = Summary::default();
+        sync(true, true, &mut summary);
+
+        assert_eq!(summary.errors.len(), 1);
+        assert!(dir.join("mirror/Author/Book/01.mp3").is_file());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,33 +1,286 @@
+use crate::CONFIG;
+use crate::overrides::{RenameRule, apply_renames};
+use crate::sniff;
+use colored::Colorize;
 use lofty::file::TaggedFileExt;
-use lofty::probe::Probe;
-use lofty::tag::Accessor;
+use lofty::tag::{Accessor, ItemKey};
 use regex::Regex;
+use std::fmt::Display;
+use std::num::IntErrorKind;
+use std::path::Path;
+
+/// The keywords that mark the file number in a file name (e.g. `Chapter 5`), with
+/// their German, French, Spanish and Dutch counterparts.
+/// The disc is no keyword, so `Title - CD 2 - 05` is file 5 rather than file 2.
+pub const DEFAULT_TRACK_KEYWORDS: [&str; 20] = [
+    "track",
+    "chapter",
+    "section",
+    "part",
+    "episode",
+    "kapitel",
+    "teil",
+    "abschnitt",
+    "folge",
+    "chapitre",
+    "partie",
+    "piste",
+    "épisode",
+    "capítulo",
+    "capitulo",
+    "parte",
+    "pista",
+    "episodio",
+    "hoofdstuk",
+    "deel",
+];
+
+/// Describes how the number of a file was found, for `--explain-numbers`.
+#[derive(Debug, Default, PartialEq)]
+pub struct NumberExplanation {
+    /// The file number, if one was found.
+    pub number: Option<u16>,
+    /// Where the number came from, e.g. "the track tag".
+    pub source: String,
+    /// The numbers that were passed over, and why, e.g. "2024 (date)".
+    pub ignored: Vec<String>,
+}
+
+impl NumberExplanation {
+    /**
+     * Describes the explanation in one line, e.g. "file 5, from the track tag".
+     */
+    pub fn describe(&self) -> String {
+        let found = match self.number {
+            Some(number) => format!("file {}, from {}", number, self.source),
+            None => format!("no number, {}", self.source),
+        };
+        match self.ignored.is_empty() {
+            true => found,
+            false => format!("{} (ignored: {})", found, self.ignored.join(", ")),
+        }
+    }
+
+    /**
+     * Records a number that was passed over, once.
+     */
+    fn ignore(&mut self, reason: String) {
+        if !self.ignored.contains(&reason) {
+            self.ignored.push(reason);
+        }
+    }
+}
 
 /**
  * Get the track number from a file's metadata.
  *
  * This function attempts to extract the track number from the file's metadata.
- * If the track number is not found or is invalid, it returns None.
+ * If the track number is not found or is invalid, the file name is parsed instead.
+ * If neither has a valid track number, it returns None.
+ *
+ * @param path The full path of the audio file.
+ * @param keywords The compiled track keywords, see `compile_track_keywords`.
+ * @param renames The rename rules of the book, applied to the name before it is parsed.
+ * @param file_total The number of audio files of the book, if known.
+ */
+pub fn get_track_number(
+    path: &Path,
+    keywords: &[Regex],
+    renames: &[RenameRule],
+    file_total: Option<u16>,
+) -> Option<u16> {
+    explain_track_number(path, keywords, renames, file_total).number
+}
+
+/**
+ * Gets the track number of a file, like `get_track_number`, and describes where it
+ * came from and what was ignored on the way.
+ *
+ * @param path The full path of the audio file.
+ * @param keywords The compiled track keywords, see `compile_track_keywords`.
+ * @param renames The rename rules of the book, applied to the name before it is parsed.
+ * @param file_total The number of audio files of the book, if known.
+ * @return The explanation, with the track number if one was found.
  */
-pub fn get_track_number(path: &str) -> Option<u16> {
+pub fn explain_track_number(
+    path: &Path,
+    keywords: &[Regex],
+    renames: &[RenameRule],
+    file_total: Option<u16>,
+) -> NumberExplanation {
     // 1. Try to read internal metadata (ID3, etc.)
-    //    Probe::open checks the file extension and content to figure out the format.
+    //    The format is taken from the file extension, or detected with --detect-types.
     //    We return Result or Option at every step to ensure safe fallthrough.
-    if let Ok(tagged_file) = Probe::open(path).and_then(|p| p.read()) {
-        if let Some(tag) = tagged_file.primary_tag() {
-            if let Some(track) = tag.track() {
-                // Some files might have a tag set to 0, which is usually invalid.
-                // We treat 0 as "missing" so we fall back to filename parsing.
-                if track > 0 {
-                    return Some(track as u16);
-                }
+    let mut tag_ignored = None;
+    if let Ok(tagged_file) = sniff::open(path).and_then(|p| p.read())
+        && let Some(tag) = tagged_file.primary_tag()
+        && let Some(track) = tag.track()
+    {
+        // Some files might have a tag set to 0, which is usually invalid.
+        // We treat 0 as "missing" so we fall back to filename parsing.
+        match u16::try_from(track) {
+            Ok(0) => tag_ignored = Some(String::from("the track tag 0")),
+            Ok(track) => {
+                return NumberExplanation {
+                    number: Some(track),
+                    source: String::from("the track tag"),
+                    ignored: Vec::new(),
+                };
+            }
+            Err(_) => {
+                warn_overflow(path, track);
+                tag_ignored = Some(format!("the track tag {} (too large)", track));
             }
         }
     }
 
     // 2. Fallback: If no internal tag (or track was 0), parse the filename
     //    This part runs if ANY step above fails or returns None.
-    return parse_from_filename(path);
+    let mut explanation = match path.file_stem().and_then(|s| s.to_str()) {
+        Some(file_name) if !renames.is_empty() => {
+            let renamed = apply_renames(file_name, renames);
+            let mut explanation = explain_from_filename(&renamed, keywords, file_total);
+            if renamed != file_name {
+                explanation.source = format!("{} (renamed to '{}')", explanation.source, renamed);
+            }
+            explanation
+        }
+        Some(file_name) => explain_from_filename(file_name, keywords, file_total),
+        None => NumberExplanation {
+            source: String::from("the file has no name"),
+            ..Default::default()
+        },
+    };
+    if let Some(reason) = tag_ignored {
+        explanation.ignored.insert(0, reason);
+    }
+    explanation
+}
+
+/**
+ * Parses the digits of a file number. Numbers too large for a file number (above
+ * 65535) are reported, since they are more likely an ID than a file number.
+ *
+ * @param file_name The name of the file the digits were found in.
+ * @param digits The digits of the number.
+ * @return The number, or `None` if it is too large.
+ */
+fn parse_number(file_name: &str, digits: &str) -> Option<u16> {
+    match digits.parse::<u16>() {
+        Ok(number) => Some(number),
+        Err(err) if *err.kind() == IntErrorKind::PosOverflow => {
+            warn_overflow(Path::new(file_name), digits);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+/**
+ * Warns that a file number is too large and is ignored.
+ */
+fn warn_overflow(file: &Path, number: impl Display) {
+    if CONFIG.get().is_some_and(|cfg| cfg.quiet) {
+        return;
+    }
+    println!(
+        "{} '{}' has the file number {}, which is too large and is ignored",
+        "Warning:".yellow(),
+        file.display(),
+        number
+    );
+}
+
+/**
+ * Compiles the track keywords, falling back to `DEFAULT_TRACK_KEYWORDS` when none
+ * are given. A keyword matches when it is followed by a number, e.g. `Kapitel 5`,
+ * `Teil7` or `Episode #3`, ignoring case.
+ *
+ * @param keywords The user supplied keywords.
+ * @return The compiled keywords, each capturing the number.
+ */
+pub fn compile_track_keywords(keywords: &[String]) -> Vec<Regex> {
+    let keywords: Vec<&str> = if keywords.is_empty() {
+        DEFAULT_TRACK_KEYWORDS.to_vec()
+    } else {
+        keywords.iter().map(|k| k.trim()).collect()
+    };
+
+    keywords
+        .into_iter()
+        .filter(|keyword| !keyword.is_empty())
+        .map(|keyword| {
+            Regex::new(&format!(r"(?i)\b{}\s*#?\s*(\d+)\b", regex::escape(keyword))).unwrap()
+        })
+        .collect()
+}
+
+/**
+ * Get the series name and book number from a file's movement tags.
+ *
+ * Apple Books and some taggers store the series in the movement name (MP4 `©mvn`,
+ * ID3 `MVNM`) and the book number in the movement number (MP4 `©mvi`, ID3 `MVIN`).
+ *
+ * @param path The full path of the audio file.
+ * @return The movement name and number, if set.
+ */
+pub fn get_movement(path: &Path) -> (Option<String>, Option<u16>) {
+    let Ok(tagged_file) = sniff::open(path).and_then(|p| p.read()) else {
+        return (None, None);
+    };
+
+    let mut series = None;
+    let mut number = None;
+    for tag in tagged_file.tags() {
+        series = series.or_else(|| {
+            tag.get_string(&ItemKey::Movement)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        });
+        // ID3 stores the number as "number/total"
+        number = number.or_else(|| {
+            tag.get_string(&ItemKey::MovementNumber)
+                .and_then(|n| n.split('/').next()?.trim().parse::<u16>().ok())
+                .filter(|n| *n > 0)
+        });
+    }
+
+    (series, number)
+}
+
+/**
+ * Get the release date from a file's date tags (ID3 `TDRL`/`TDRC`, MP4 `©day`,
+ * Vorbis `DATE`/`YEAR`, etc.).
+ *
+ * @param path The full path of the audio file.
+ * @return The date as `YYYY-MM-DD`, or only the year as `YYYY` if the tag has no
+ *         full date.
+ */
+pub fn get_release_date(path: &Path) -> Option<String> {
+    let tagged_file = sniff::open(path).and_then(|p| p.read()).ok()?;
+    let keys = [
+        ItemKey::ReleaseDate,
+        ItemKey::RecordingDate,
+        ItemKey::Year,
+        ItemKey::OriginalReleaseDate,
+    ];
+    tagged_file.tags().iter().find_map(|tag| {
+        keys.iter()
+            .filter_map(|key| tag.get_string(key))
+            .find_map(parse_tag_date)
+    })
+}
+
+/**
+ * Parses the value of a date tag, e.g. `2010`, `2010-08-31` or
+ * `2010-08-31T07:00:00Z`.
+ *
+ * @param value The tag value.
+ * @return The date as `YYYY-MM-DD`, or the year as `YYYY`.
+ */
+fn parse_tag_date(value: &str) -> Option<String> {
+    let re = Regex::new(r"^\d{4}(?:-\d{2}-\d{2})?").unwrap();
+    re.find(value.trim()).map(|date| date.as_str().to_string())
 }
 
 /**
@@ -35,125 +288,187 @@ pub fn get_track_number(path: &str) -> Option<u16> {
  *
  * The file number is typically a whole number found at the start of the file name.
  * If multiple numbers are present, context-based rules are applied to determine the correct one.
+ * When the book's file count is known and the rules find no number within it, a name
+ * with several numbers (e.g. "2024 - 07 - Title") uses the only one that fits.
  *
  * @param file_name The name of the file to analyze.
+ * @param keywords The compiled track keywords.
+ * @param file_total The number of audio files of the book, if known.
  * @return An `Option<u16>` containing the extracted file number, or `None` if no valid number is found.
  */
-fn parse_from_filename(file_name: &str) -> Option<u16> {
-    // We will collect numbers to IGNORE here.
-    let mut ignore_list: Vec<u16> = Vec::new();
+pub fn parse_from_filename(
+    file_name: &str,
+    keywords: &[Regex],
+    file_total: Option<u16>,
+) -> Option<u16> {
+    explain_from_filename(file_name, keywords, file_total).number
+}
 
-    // 1. Identify "Book" number to ignore (e.g., "Book 3")
-    let re_book = Regex::new(r"(?i)\bbook\s*#?\s*(\d+)\b").unwrap();
-    if let Some(caps) = re_book.captures(file_name) {
-        if let Ok(num) = caps[1].parse::<u16>() {
-            ignore_list.push(num);
-        }
+/**
+ * Extracts the file number from a file name, like `parse_from_filename`, and
+ * describes the rule that found it and the numbers that were ignored.
+ *
+ * @param file_name The name of the file to analyze.
+ * @param keywords The compiled track keywords.
+ * @param file_total The number of audio files of the book, if known.
+ * @return The explanation, with the file number if a valid one was found.
+ */
+pub fn explain_from_filename(
+    file_name: &str,
+    keywords: &[Regex],
+    file_total: Option<u16>,
+) -> NumberExplanation {
+    let mut explanation = NumberExplanation::default();
+    let ignored = ignored_numbers(file_name);
+    for (number, reason) in &ignored {
+        explanation.ignore(format!("{} ({})", number, reason));
     }
+    let ignore_list: Vec<u16> = ignored.iter().map(|(number, _)| *number).collect();
+    let found = parse_with_rules(file_name, keywords, &ignore_list);
 
-    // 2. Identify Dates (YYYY-MM-DD) to ignore
-    let re_date_iso = Regex::new(r"\b(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})\b").unwrap();
-    for caps in re_date_iso.captures_iter(file_name) {
-        if let Ok(y) = caps[1].parse::<u16>() {
-            ignore_list.push(y);
-        }
-        if let Ok(m) = caps[2].parse::<u16>() {
-            ignore_list.push(m);
-        }
-        if let Ok(d) = caps[3].parse::<u16>() {
-            ignore_list.push(d);
+    if let Some(total) = file_total
+        && !found.as_ref().is_some_and(|(n, _)| (1..=total).contains(n))
+    {
+        let re_number = Regex::new(r"\d+").unwrap();
+        let candidates: Vec<u16> = re_number
+            .find_iter(file_name)
+            .filter_map(|m| m.as_str().parse().ok())
+            .filter(|n| !ignore_list.contains(n))
+            .collect();
+        let fitting: Vec<u16> = candidates
+            .iter()
+            .copied()
+            .filter(|n| (1..=total).contains(n))
+            .collect();
+        // A year is never the file number, but it still is a number of the name
+        let years = ignored
+            .iter()
+            .filter(|(_, reason)| *reason == "year")
+            .count();
+        if let [n] = fitting[..]
+            && candidates.len() + years > 1
+        {
+            if let Some((other, rule)) = &found {
+                explanation.ignore(format!(
+                    "{} from {}, not within the book's {} files",
+                    other, rule, total
+                ));
+            }
+            explanation.number = Some(n);
+            explanation.source = format!("the only number within the book's {} files", total);
+            return explanation;
         }
     }
 
-    // 3. Identify Dates (MM/DD/YYYY or DD.MM.YYYY) to ignore
-    let re_date_common = Regex::new(r"\b(\d{1,2})[-/.](\d{1,2})[-/.](\d{4})\b").unwrap();
-    for caps in re_date_common.captures_iter(file_name) {
-        if let Ok(d1) = caps[1].parse::<u16>() {
-            ignore_list.push(d1);
-        }
-        if let Ok(d2) = caps[2].parse::<u16>() {
-            ignore_list.push(d2);
-        }
-        if let Ok(y) = caps[3].parse::<u16>() {
-            ignore_list.push(y);
+    match found {
+        Some((number, rule)) => {
+            explanation.number = Some(number);
+            explanation.source = rule;
         }
+        None => explanation.source = String::from("no rule matched the file name"),
     }
+    explanation
+}
 
-    // 4. Identify Short Dates (MM/DD/YY or DD.MM.YY) to ignore
-    //    We strictly look for 2 digits at the end to catch "11/27/25"
-    let re_date_short = Regex::new(r"\b(\d{1,2})[-/.](\d{1,2})[-/.](\d{2})\b").unwrap();
-    for caps in re_date_short.captures_iter(file_name) {
-        if let Ok(d1) = caps[1].parse::<u16>() {
-            ignore_list.push(d1);
-        }
-        if let Ok(d2) = caps[2].parse::<u16>() {
-            ignore_list.push(d2);
-        }
-        if let Ok(y) = caps[3].parse::<u16>() {
-            ignore_list.push(y);
-        }
-    }
+/**
+ * Finds the numbers in a file name that are not file numbers: book numbers
+ * (e.g. "Book 3") and the parts of dates.
+ *
+ * @param file_name The name of the file to analyze.
+ * @return The numbers to ignore, with what they are ("book number" or "date").
+ */
+fn ignored_numbers(file_name: &str) -> Vec<(u16, &'static str)> {
+    // We will collect numbers to IGNORE here.
+    let mut ignore_list: Vec<(u16, &'static str)> = Vec::new();
 
-    // 5. Explicit Context (Section, Chapter, Part, Track) - Highest Priority
-    let re_context = Regex::new(r"(?i)\b(section|chapter|part|track)\s*#?\s*(\d+)\b").unwrap();
-    if let Some(caps) = re_context.captures(file_name) {
-        return caps[2].parse().ok();
+    // 1. Identify "Book" number to ignore (e.g., "Book 3")
+    let re_book = Regex::new(r"(?i)\bbook\s*#?\s*(\d+)\b").unwrap();
+    if let Some(caps) = re_book.captures(file_name)
+        && let Ok(num) = caps[1].parse::<u16>()
+    {
+        ignore_list.push((num, "book number"));
     }
 
-    // 6. "X of Y" Pattern (e.g. "2 of 13")
-    let re_of = Regex::new(r"(?i)\b(\d+)\s*of\s*\d+").unwrap();
-    if let Some(caps) = re_of.captures(file_name) {
-        let num = caps[1].parse().ok();
-        if let Some(n) = num {
-            if !ignore_list.contains(&n) {
-                return Some(n);
+    let dates = [
+        // 2. Identify Dates (YYYY-MM-DD) to ignore
+        r"\b(\d{4})[-/.](\d{1,2})[-/.](\d{1,2})\b",
+        // 3. Identify Dates (MM/DD/YYYY or DD.MM.YYYY) to ignore
+        r"\b(\d{1,2})[-/.](\d{1,2})[-/.](\d{4})\b",
+        // 4. Identify Short Dates (MM/DD/YY or DD.MM.YY) to ignore
+        //    We strictly look for 2 digits at the end to catch "11/27/25"
+        r"\b(\d{1,2})[-/.](\d{1,2})[-/.](\d{2})\b",
+    ];
+    for pattern in dates {
+        let re_date = Regex::new(pattern).unwrap();
+        for caps in re_date.captures_iter(file_name) {
+            for part in [&caps[1], &caps[2], &caps[3]] {
+                if let Ok(n) = part.parse::<u16>() {
+                    ignore_list.push((n, "date"));
+                }
             }
         }
     }
-
-    // 7. Start Pattern (e.g. "02 -", "01. Song", "BH_19-")
-    //    Modified to include `.` in separator class `[-_.]` to handle "01. Title"
-    let re_start = Regex::new(r"^(?:[a-zA-Z]+[_\s-]*)?(\d{1,3})\s*[-_.]").unwrap();
-    if let Some(caps) = re_start.captures(file_name) {
-        let num = caps[1].parse().ok();
-        if let Some(n) = num {
-            if !ignore_list.contains(&n) {
-                return Some(n);
-            }
+    // A year followed by a month (e.g. "2024 - 07") looks like a four digit file
+    // number otherwise
+    let re_year = Regex::new(r"\b((?:19|20)\d{2})\s*[-_.]\s*\d{1,2}\b").unwrap();
+    for caps in re_year.captures_iter(file_name) {
+        if let Ok(n) = caps[1].parse::<u16>()
+            && !ignore_list.iter().any(|(ignored, _)| *ignored == n)
+        {
+            ignore_list.push((n, "year"));
         }
     }
 
-    // 8. Track-Total Pattern anywhere (e.g. "19-37", "01/12")
-    let re_track_total = Regex::new(r"\b(\d{1,3})[-/_]\d+\b").unwrap();
-    if let Some(caps) = re_track_total.captures(file_name) {
-        let num = caps[1].parse().ok();
-        if let Some(n) = num {
-            if !ignore_list.contains(&n) {
-                return Some(n);
-            }
-        }
-    }
+    ignore_list
+}
 
-    // 9. Delimited Suffix (e.g. "- 02", "_2", "_02")
-    let re_suffix = Regex::new(r"[-_]\s*(\d+)$").unwrap();
-    if let Some(caps) = re_suffix.captures(file_name) {
-        let num = caps[1].parse().ok();
-        if let Some(n) = num {
-            if !ignore_list.contains(&n) {
-                return Some(n);
-            }
-        }
+/**
+ * Applies the context-based rules to find the file number in a file name.
+ *
+ * @param file_name The name of the file to analyze.
+ * @param keywords The compiled track keywords.
+ * @param ignore_list The numbers that are not file numbers.
+ * @return The file number, or `None` if no rule matches.
+ */
+fn parse_with_rules(
+    file_name: &str,
+    keywords: &[Regex],
+    ignore_list: &[u16],
+) -> Option<(u16, String)> {
+    // 5. Explicit Context (Track, Chapter, Kapitel, ...) - Highest Priority
+    //    The keyword that comes first in the name wins, e.g. 2 in "Part 2 - Chapter 05"
+    let leftmost = keywords
+        .iter()
+        .filter_map(|re| re.captures(file_name))
+        .min_by_key(|caps| caps.get(0).map_or(0, |m| m.start()));
+    if let Some(caps) = leftmost {
+        let rule = format!("the keyword in '{}'", &caps[0]);
+        return parse_number(file_name, &caps[1]).map(|n| (n, rule));
     }
 
-    // 10. Solo Number Pattern (e.g. "02", "2")
-    //    Only accept if the ENTIRE string is just the number.
-    let re_solo = Regex::new(r"^\s*(\d+)\s*$").unwrap();
-    if let Some(caps) = re_solo.captures(file_name) {
-        let num = caps[1].parse().ok();
-        if let Some(n) = num {
-            if !ignore_list.contains(&n) {
-                return Some(n);
-            }
+    let rules = [
+        // 6. "X of Y" Pattern (e.g. "2 of 13")
+        ("X of Y", r"(?i)\b(\d+)\s*of\s*\d+"),
+        // 7. Start Pattern (e.g. "02 -", "01. Song", "BH_19-")
+        //    Modified to include `.` in separator class `[-_.]` to handle "01. Title"
+        ("start", r"^(?:[a-zA-Z]+[_\s-]*)?(\d{1,4})\s*[-_.]"),
+        // 8. Track-Total Pattern anywhere (e.g. "19-37", "01/12")
+        ("track-total", r"\b(\d{1,4})[-/_]\d+\b"),
+        // 9. Delimited Suffix (e.g. "- 02", "_2", "_02")
+        ("delimited suffix", r"[-_]\s*(\d+)$"),
+        // 10. Parenthesized Suffix (e.g. "Title (002)"), the default file schema output
+        ("parenthesized suffix", r"\((\d+)\)\s*$"),
+        // 11. Solo Number Pattern (e.g. "02", "2")
+        //    Only accept if the ENTIRE string is just the number.
+        ("solo number", r"^\s*(\d+)\s*$"),
+    ];
+    for (rule, pattern) in rules {
+        let re = Regex::new(pattern).unwrap();
+        if let Some(caps) = re.captures(file_name)
+            && let Some(n) = parse_number(file_name, &caps[1])
+            && !ignore_list.contains(&n)
+        {
+            return Some((n, format!("the {} pattern in '{}'", rule, caps[0].trim())));
         }
     }
 
@@ -166,6 +481,7 @@ mod tests {
 
     #[test]
     fn test_parse_from_filename() {
+        let keywords = compile_track_keywords(&[]);
         // Tuple format: (input_filename, expected_track_number)
         let inputs = [
             ("02 - book title", Some(2)),
@@ -191,10 +507,25 @@ mod tests {
             ("author - title 11/27/2025 with date", None),
             ("author - title 11/27/25 with date", None),
             ("author - title 11.27.2025 with date", None),
+            ("Mistborn - The Alloy of Law (002)", Some(2)),
+            ("Der Hobbit - Kapitel 05", Some(5)),
+            ("Der Hobbit Teil3", Some(3)),
+            ("Disc 2 - Track 05 - title", Some(5)),
+            ("Title - CD 2 - 05", Some(5)),
+            ("Title - Disc 2 - 05", Some(5)),
+            ("Part 2 - Chapter 05", Some(2)),
+            ("Chapter 05 - Part 2", Some(5)),
+            ("Capítulo 12 - title", Some(12)),
+            ("LibriVox Compilation - Track 1200 - title", Some(1200)),
+            ("0999 - Title", Some(999)),
+            ("1000 - Title", Some(1000)),
+            ("1001 - Title", Some(1001)),
+            ("1001-1200 title", Some(1001)),
+            ("Chapter 70000 - title", None),
         ];
 
         for (input, expected) in inputs {
-            let result = parse_from_filename(input);
+            let result = parse_from_filename(input, &keywords, None);
             assert_eq!(
                 result, expected,
                 "Failed on input: '{}'. Expected {:?}, got {:?}",
@@ -202,4 +533,67 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_custom_track_keywords() {
+        let keywords = compile_track_keywords(&[String::from("Hoofdstuk")]);
+        assert_eq!(
+            parse_from_filename("Boek 2 - hoofdstuk 7", &keywords, None),
+            Some(7)
+        );
+        assert_eq!(
+            parse_from_filename("Book 3 - chapter 7 - title", &keywords, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_file_total_hint() {
+        let keywords = compile_track_keywords(&[]);
+        let parse = |name, total| parse_from_filename(name, &keywords, total);
+        assert_eq!(parse("2024 - 07 - Title", None), None);
+        assert_eq!(parse("2024 - 07 - Title", Some(12)), Some(7));
+        // A number the rules found within the file count is kept
+        assert_eq!(parse("02 - Title 7", Some(12)), Some(2));
+        // Several fitting numbers are ambiguous, a single number is not a hint
+        assert_eq!(parse("The 3 Kings 2024 07 Title", Some(12)), None);
+        assert_eq!(parse("Title with number 4 in it", Some(12)), None);
+        // Book numbers and dates are never file numbers
+        assert_eq!(parse("Book 3 - 2024 - 07 - Title", Some(12)), Some(7));
+    }
+
+    #[test]
+    fn test_explain_from_filename() {
+        let keywords = compile_track_keywords(&[]);
+        let explanation =
+            explain_from_filename("Book 2 - Chapter 05 - 2021-03-14", &keywords, None);
+        assert_eq!(explanation.number, Some(5));
+        assert_eq!(explanation.source, "the keyword in 'Chapter 05'");
+        assert_eq!(
+            explanation.ignored,
+            vec!["2 (book number)", "2021 (date)", "3 (date)", "14 (date)"]
+        );
+
+        let explanation = explain_from_filename("Podcast 145 - 07 Title", &keywords, Some(12));
+        assert_eq!(
+            explanation.describe(),
+            "file 7, from the only number within the book's 12 files (ignored: 145 from the start pattern in 'Podcast 145 -', not within the book's 12 files)"
+        );
+        assert_eq!(
+            explain_from_filename("Title", &keywords, None).describe(),
+            "no number, no rule matched the file name"
+        );
+    }
+
+    #[test]
+    fn test_parse_tag_date() {
+        assert_eq!(parse_tag_date("2010").as_deref(), Some("2010"));
+        assert_eq!(parse_tag_date("2010-08-31").as_deref(), Some("2010-08-31"));
+        assert_eq!(
+            parse_tag_date(" 2010-08-31T07:00:00Z").as_deref(),
+            Some("2010-08-31")
+        );
+        assert_eq!(parse_tag_date("2010-08").as_deref(), Some("2010"));
+        assert_eq!(parse_tag_date("August 2010"), None);
+    }
 }
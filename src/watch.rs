@@ -0,0 +1,180 @@
+use crate::reload::Reloader;
+use crate::schema::Schema;
+use crate::shutdown;
+use crate::summary::Summary;
+use crate::{CONFIG, execute, plan, runs};
+use chrono::{Local, NaiveTime, TimeDelta};
+use colored::Colorize;
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+/// Represents when the accumulated books are processed.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Process a batch every interval.
+    Every(Duration),
+    /// Process a batch once a day at this local time.
+    DailyAt(NaiveTime),
+}
+
+/**
+ * Watches the source directory and processes the books that accumulated in it
+ * in batches, on a schedule, instead of immediately on arrival.
+ *
+ * Books that were organized without errors in an earlier batch are not processed
+ * again, so copying (`--action 0`) doesn't repeat the same books every batch. Books
+ * that failed, or are still downloading, are checked again in the next batch.
+ *
+ * On SIGTERM or SIGINT, the book that is being processed is finished before
+ * exiting, so a restart never leaves a half moved book behind.
+ *
+ * Before each batch, the config file and templates are reloaded if they changed.
+ *
+ * @param schema The schema used to plan the books.
+ * @param schedule When to process the batches.
+ * @param reloader Reloads the config and templates when their files change.
+ */
+pub fn watch(mut schema: Schema, schedule: &Schedule, mut reloader: Reloader) {
+    let mut processed = HashSet::new();
+    shutdown::install();
+
+    loop {
+        let wait = until_next_batch(schedule);
+        let next = Local::now() + TimeDelta::from_std(wait).unwrap_or_default();
+        println!(
+            "Next batch at {}",
+            next.format("%Y-%m-%d %H:%M:%S").to_string().green()
+        );
+        if !shutdown::sleep(wait) {
+            break;
+        }
+
+        if let Some(reloaded) = reloader.check() {
+            schema = reloaded;
+        }
+        let cfg = CONFIG.get().expect("CONFIG was not set");
+
+        // Books that left the source can't be planned again
+        processed.retain(|from: &String| Path::new(from).exists());
+        let mut summary = Summary::default();
+        let books: Vec<_> = plan(&schema, std::slice::from_ref(&cfg.from), &mut summary)
+            .into_iter()
+            .filter(|book| !processed.contains(&book.from))
+            .collect();
+        if books.is_empty() {
+            println!("No new books found in '{}'", cfg.from);
+            continue;
+        }
+
+        println!("{} {} new books", "Processing:".blue(), books.len());
+        if cfg.dry_run {
+            processed.extend(books.iter().map(|book| book.from.clone()));
+        }
+        if let Some(config) = &cfg.runs {
+            runs::start(config);
+        }
+        execute(books, std::slice::from_ref(&cfg.from), &mut summary);
+        // A book that failed is tried again in the next batch
+        processed.extend(summary.organized.iter().cloned());
+        if let Some(config) = &cfg.runs {
+            runs::finish(config, &summary);
+        }
+        if shutdown::requested() {
+            break;
+        }
+    }
+
+    println!("{}", "Stopped watching.".yellow());
+}
+
+/**
+ * Calculates how long to wait until the next batch.
+ *
+ * @param schedule When to process the batches.
+ * @return The time to wait.
+ */
+fn until_next_batch(schedule: &Schedule) -> Duration {
+    match schedule {
+        Schedule::Every(interval) => *interval,
+        Schedule::DailyAt(time) => {
+            let now = Local::now().naive_local();
+            let mut next = now.date().and_time(*time);
+            if next <= now {
+                next += TimeDelta::days(1);
+            }
+            (next - now).to_std().unwrap_or_default()
+        }
+    }
+}
+
+/**
+ * Parses an interval like `90s`, `30m`, `2h` or `1d`.
+ *
+ * @param value The interval to parse. A number without a unit is in seconds.
+ * @return The interval, or an error message.
+ */
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid interval", value))?;
+
+    let unit_seconds: u64 = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        _ => {
+            return Err(format!(
+                "unknown interval unit '{}', use s, m, h or d",
+                unit
+            ));
+        }
+    };
+    let seconds = number
+        .checked_mul(unit_seconds)
+        .ok_or_else(|| format!("'{}' is too long an interval", value))?;
+    if seconds == 0 {
+        return Err(String::from("the interval must be greater than zero"));
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/**
+ * Parses a local time of day like `03:00`.
+ *
+ * @param value The time to parse, as `HH:MM`.
+ * @return The time, or an error message.
+ */
+pub fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| format!("'{}' is not a valid time, use HH:MM", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30m"), Ok(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_interval("1d"), Ok(Duration::from_secs(24 * 60 * 60)));
+        assert!(parse_interval("0m").is_err());
+        assert!(parse_interval("2w").is_err());
+        assert!(parse_interval("h").is_err());
+        assert!(parse_interval("213503982334602d").is_err());
+        assert_eq!(
+            parse_interval("18446744073709551615s"),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+    }
+}
@@ -0,0 +1,225 @@
+use crate::metadata::{Metadata, series_entry_number};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The extension added to the name of a metadata file's backup.
+const BACKUP_EXTENSION: &str = "bak";
+
+/// Represents a correction written back to a book's source metadata file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WriteBack {
+    /// The metadata file in the source directory.
+    pub file: PathBuf,
+    /// The series entry written to the file, e.g. `Mistborn #2`.
+    pub series: String,
+}
+
+/**
+ * Checks whether the resolved series or book number of a book is missing from its
+ * metadata file, e.g. because the book number came from the folder name.
+ *
+ * Only Audiobookshelf `metadata.json` files are corrected, and only books with a
+ * series, since the book number is written as part of the series entry.
+ *
+ * @param file The book's metadata file.
+ * @param metadata The resolved metadata of the book.
+ * @param patterns The ordered rules used to split the series entry.
+ * @return The correction, or `None` if the file already has the series and number.
+ */
+pub fn plan_write_back(file: &Path, metadata: &Metadata, patterns: &[Regex]) -> Option<WriteBack> {
+    let contents = fs::read_to_string(file).ok()?;
+    let value: Value = serde_json::from_str(&contents).ok()?;
+    let series = corrected_series(&value, metadata, patterns)?;
+    Some(WriteBack {
+        file: file.to_path_buf(),
+        series,
+    })
+}
+
+/**
+ * Builds the series entry of a metadata file that lacks the resolved series or book
+ * number.
+ *
+ * @param value The contents of the metadata file.
+ * @param metadata The resolved metadata of the book.
+ * @param patterns The ordered rules used to split the series entry.
+ * @return The new series entry, or `None` if the file needs no correction.
+ */
+fn corrected_series(value: &Value, metadata: &Metadata, patterns: &[Regex]) -> Option<String> {
+    let series = metadata.series.as_deref()?;
+    let entry = match value.as_object()?.get("series") {
+        None | Some(Value::Null) => None,
+        Some(Value::Array(entries)) => entries.first().and_then(Value::as_str),
+        // A series in an unknown format is left alone
+        Some(_) => return None,
+    };
+    let lacks_number = metadata.book_number.is_some()
+        && entry.is_none_or(|entry| series_entry_number(entry, patterns).is_none());
+    if entry.is_some_and(|entry| !entry.trim().is_empty()) && !lacks_number {
+        return None;
+    }
+
+    Some(match metadata.book_number {
+        Some(number) => format!("{} #{}", series, number),
+        None => series.to_string(),
+    })
+}
+
+/**
+ * Writes the corrected series entry into a metadata file. The original file is
+ * copied to a backup first, unless a backup from an earlier run exists.
+ *
+ * The series entry replaces the first entry of `series`, every other field of the
+ * file is kept.
+ *
+ * @param file The metadata file to correct.
+ * @param series The series entry to write.
+ * @return The path of the backup.
+ */
+pub fn write_back(file: &Path, series: &str) -> io::Result<PathBuf> {
+    let contents = fs::read_to_string(file)?;
+    let mut value: Value = serde_json::from_str(&contents).map_err(io::Error::other)?;
+    let Some(object) = value.as_object_mut() else {
+        return Err(io::Error::other("the metadata file is not a JSON object"));
+    };
+    match object.get_mut("series") {
+        Some(Value::Array(entries)) if !entries.is_empty() => entries[0] = json!(series),
+        _ => {
+            object.insert(String::from("series"), json!([series]));
+        }
+    }
+
+    let backup = PathBuf::from(format!("{}.{}", file.display(), BACKUP_EXTENSION));
+    if !backup.exists() {
+        fs::copy(file, &backup)?;
+    }
+    let json = serde_json::to_string_pretty(&value).map_err(io::Error::other)?;
+    fs::write(file, format!("{}\n", json))?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_corrected_series() {
+        let patterns = vec![Regex::new(r"^(?P<series>.+?)\s*#(?P<number>\d+)$").unwrap()];
+        let metadata = Metadata {
+            title: String::from("The Well of Ascension"),
+            series: Some(String::from("Mistborn")),
+            book_number: Some(2),
+            ..Default::default()
+        };
+        let corrected = |value: Value| corrected_series(&value, &metadata, &patterns);
+
+        assert_eq!(
+            corrected(json!({ "title": "The Well of Ascension" })),
+            Some(String::from("Mistborn #2"))
+        );
+        assert_eq!(
+            corrected(json!({ "series": ["Mistborn"] })),
+            Some(String::from("Mistborn #2"))
+        );
+        assert_eq!(corrected(json!({ "series": ["Mistborn #2"] })), None);
+        assert_eq!(corrected(json!({ "series": ["Mistborn, Book 2"] })), None);
+        assert_eq!(corrected(json!({ "series": "Mistborn" })), None);
+
+        let standalone = Metadata {
+            book_number: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(corrected_series(&json!({}), &standalone, &patterns), None);
+    }
+
+    #[test]
+    fn test_write_back() {
+        let dir = std::env::temp_dir().join(format!("aborg-writeback-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("metadata.json");
+        fs::write(
+            &file,
+            r#"{"title":"Dune","series":["Dune"],"tags":["Sci-Fi"]}"#,
+        )
+        .unwrap();
+
+        let backup = write_back(&file, "Dune #1").unwrap();
+        let value: Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!({ "title": "Dune", "series": ["Dune #1"], "tags": ["Sci-Fi"] })
+        );
+        assert!(
+            fs::read_to_string(&backup)
+                .unwrap()
+                .contains(r#""series":["Dune"]"#)
+        );
+
+        // The backup of the original file is kept
+        write_back(&file, "Dune #2").unwrap();
+        assert!(
+            fs::read_to_string(&backup)
+                .unwrap()
+                .contains(r#""series":["Dune"]"#)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_back_before_transfer() {
+        use crate::config::FileConfig;
+        use crate::schema::Schema;
+        use crate::summary::Summary;
+        use crate::{ActionOpt, Args, CONFIG, build_config, execute, plan};
+        use clap::Parser;
+
+        let _lock = crate::TEST_CONFIG.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = std::env::temp_dir().join(format!("aborg-writeback-run-{}", std::process::id()));
+        let book = dir.join("src/Mistborn Book 2");
+        fs::create_dir_all(&book).unwrap();
+        fs::write(
+            book.join("metadata.json"),
+            r#"{"title":"The Well of Ascension","authors":["Brandon Sanderson"],"series":["Mistborn"]}"#,
+        )
+        .unwrap();
+        fs::write(book.join("01.mp3"), "audio").unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+
+        let args = Args::try_parse_from([
+            "aborg",
+            "-s",
+            &path("src"),
+            "-d",
+            &path("lib"),
+            "--write-back",
+            "--checksums",
+        ])
+        .unwrap();
+        CONFIG.replace(build_config(&args, &FileConfig::default(), ActionOpt::None).unwrap());
+        let cfg = CONFIG.get().unwrap();
+        let schema = Schema::new(
+            String::from("{{author}}/{{series}}/{{title}}"),
+            String::from("{{title}}"),
+        );
+        let mut summary = Summary::default();
+        let plans = plan(&schema, &cfg.sources, &mut summary);
+        execute(plans, &cfg.sources, &mut summary);
+        assert!(summary.errors.is_empty());
+
+        // Both the source and its copy in the library are corrected
+        let library = dir.join("lib/Brandon Sanderson/Mistborn/The Well of Ascension");
+        for file in [book.join("metadata.json"), library.join("metadata.json")] {
+            assert!(fs::read_to_string(file).unwrap().contains("Mistborn #2"));
+        }
+        // The backup stays in the source, and isn't in the manifest
+        assert!(book.join("metadata.json.bak").is_file());
+        assert!(!library.join("metadata.json.bak").exists());
+        let manifest = fs::read_to_string(library.join("checksums.sha256")).unwrap();
+        assert!(!manifest.contains(".bak"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}